@@ -0,0 +1,96 @@
+//! Where keygen computes
+//!
+//! [`keygen_vk_with`](crate::plonk::keygen_vk_with) hands the permutation
+//! commitments, the expensive part of the verifying key, to an
+//! [`ExecutionBackend`]. [`LocalBackend`] commits on this machine, which is
+//! what [`keygen_vk`](crate::plonk::keygen_vk) uses, and [`RemoteBackend`]
+//! commits through the workers of a [`Dispatcher`].
+use futures::future::LocalBoxFuture;
+
+use super::{dispatcher::Dispatcher, net::SerdeParams};
+use crate::{
+    arithmetic::CurveAffine,
+    helpers::SerdeCurveAffine,
+    plonk::{
+        permutation::{self, keygen::build_vk},
+        Error,
+    },
+    poly::{commitment::Params, EvaluationDomain},
+};
+
+/// Computes the parts of keygen that can run away from the caller.
+pub trait ExecutionBackend<C: CurveAffine, P> {
+    /// Returns the verifying key of the permutation argument `p`, whose
+    /// copies are described by `mapping`, which takes a column and a row to
+    /// the cell they map to.
+    fn permutation_vk<'a>(
+        &'a mut self,
+        params: &'a P,
+        domain: &'a EvaluationDomain<C::Scalar>,
+        p: &'a permutation::Argument,
+        mapping: &'a (dyn Fn(usize, usize) -> (usize, usize) + Sync),
+    ) -> LocalBoxFuture<'a, Result<permutation::VerifyingKey<C>, Error>>;
+}
+
+/// Runs keygen in this process. Its futures are ready as soon as they are
+/// polled, so they can be driven with `block_on` from synchronous code.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalBackend;
+
+impl<'params, C, P> ExecutionBackend<C, P> for LocalBackend
+where
+    C: CurveAffine,
+    P: Params<'params, C>,
+{
+    fn permutation_vk<'a>(
+        &'a mut self,
+        params: &'a P,
+        domain: &'a EvaluationDomain<C::Scalar>,
+        p: &'a permutation::Argument,
+        mapping: &'a (dyn Fn(usize, usize) -> (usize, usize) + Sync),
+    ) -> LocalBoxFuture<'a, Result<permutation::VerifyingKey<C>, Error>> {
+        Box::pin(async move { Ok(build_vk(params, domain, p, mapping)) })
+    }
+}
+
+/// Runs keygen on the workers of a [`Dispatcher`], which falls back to this
+/// process for the tasks no worker could run.
+#[allow(missing_debug_implementations)]
+pub struct RemoteBackend<'a> {
+    pub dispatcher: &'a Dispatcher,
+}
+
+impl<'a> RemoteBackend<'a> {
+    pub fn new(dispatcher: &'a Dispatcher) -> Self {
+        RemoteBackend { dispatcher }
+    }
+}
+
+impl<'d, 'params, C, P> ExecutionBackend<C, P> for RemoteBackend<'d>
+where
+    C: SerdeCurveAffine,
+    P: Params<'params, C> + SerdeParams,
+{
+    fn permutation_vk<'a>(
+        &'a mut self,
+        params: &'a P,
+        domain: &'a EvaluationDomain<C::Scalar>,
+        p: &'a permutation::Argument,
+        mapping: &'a (dyn Fn(usize, usize) -> (usize, usize) + Sync),
+    ) -> LocalBoxFuture<'a, Result<permutation::VerifyingKey<C>, Error>> {
+        Box::pin(async move {
+            // The tasks carry the mapping of their columns, so it is only
+            // laid out as a table here.
+            let n = params.n() as usize;
+            let mapping = (0..p.ncolumns())
+                .map(|i| (0..n).map(|j| mapping(i, j)).collect())
+                .collect::<Vec<Vec<_>>>();
+            let params_hash = self.dispatcher.upload_params(params).await;
+            let commitments = self
+                .dispatcher
+                .keygen(params, params_hash, domain, p, &mapping)
+                .await?;
+            Ok(permutation::VerifyingKey::from_commitments(commitments))
+        })
+    }
+}
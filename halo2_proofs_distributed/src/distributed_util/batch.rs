@@ -0,0 +1,614 @@
+//! Whole proofs run on the workers
+//!
+//! [`Dispatcher::prove_batch`](super::dispatcher::Dispatcher::prove_batch)
+//! proves independent circuits sharing a proving key, each on a single
+//! worker. A circuit proven this way implements [`BatchCircuit`], which names
+//! it and encodes its witness, and is registered with the worker together
+//! with the [`Multiopen`] argument its proofs use.
+//!
+//! The proving key is sent to each worker once, as an `UploadKey` request,
+//! and kept in its [`KeyCache`] under its [`KeyHash`]. Each circuit then goes
+//! out as a `Prove` request, a [`ProveTask`] naming the params, the key and
+//! the circuit, and carrying the instances and the witness.
+use std::{
+    collections::HashMap,
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+
+use ff::{Field, FromUniformBytes, WithSmallOrderMulGroup};
+use halo2curves::{
+    bn256::{Bn256, Fr, G1Affine},
+    CurveAffine,
+};
+use rand_core::OsRng;
+
+use super::{
+    net::{
+        invalid_data, read_bytes, read_header, read_scalars, read_u32, write_bytes, write_header,
+        write_scalars, write_u32,
+    },
+    params::{ContentHash, ParamsHash},
+};
+use crate::{
+    helpers::{SerdeCurveAffine, SerdePrimeField},
+    plonk::{create_proof, Circuit, Error, ProvingKey},
+    poly::{
+        commitment::CommitmentScheme,
+        ipa::{
+            commitment::{IPACommitmentScheme, ParamsIPA},
+            multiopen::ProverIPA,
+        },
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::{ProverGWC, ProverSHPLONK},
+        },
+    },
+    transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+    SerdeFormat,
+};
+
+/// A circuit whose witness can be sent to the worker proving it.
+pub trait BatchCircuit<F: Field>: Circuit<F> + 'static {
+    /// Name the circuit is registered and sent under, unique among the
+    /// circuits of a worker. Prefixing it with the name of the crate defining
+    /// the circuit keeps it so.
+    const NAME: &'static str;
+
+    /// Writes the witness of the circuit, as sent to the worker.
+    fn write_witness<W: io::Write>(&self, writer: &mut W) -> io::Result<()>;
+
+    /// Builds the circuit from a witness written with
+    /// [`BatchCircuit::write_witness`].
+    fn read_witness<R: io::Read>(reader: &mut R) -> io::Result<Self>;
+}
+
+/// How proofs over the scheme `S` are opened, with a Blake2b transcript.
+/// The dispatcher and the workers must agree on it, so it is sent along with
+/// each circuit.
+pub trait Multiopen<S: CommitmentScheme>: 'static {
+    /// Name the argument is sent under.
+    const NAME: &'static str;
+
+    /// Proves `circuits` and returns the transcript.
+    fn create_proof<C: Circuit<S::Scalar>>(
+        params: &S::ParamsProver,
+        pk: &ProvingKey<S::Curve>,
+        circuits: &[C],
+        instances: &[&[&[S::Scalar]]],
+    ) -> Result<Vec<u8>, Error>;
+}
+
+/// The GWC multiopen argument of KZG.
+#[derive(Clone, Copy, Debug)]
+pub struct Gwc;
+
+/// The SHPLONK multiopen argument of KZG.
+#[derive(Clone, Copy, Debug)]
+pub struct Shplonk;
+
+/// The multiopen argument of IPA.
+#[derive(Clone, Copy, Debug)]
+pub struct Ipa;
+
+impl Multiopen<KZGCommitmentScheme<Bn256>> for Gwc {
+    const NAME: &'static str = "gwc";
+
+    fn create_proof<C: Circuit<Fr>>(
+        params: &ParamsKZG<Bn256>,
+        pk: &ProvingKey<G1Affine>,
+        circuits: &[C],
+        instances: &[&[&[Fr]]],
+    ) -> Result<Vec<u8>, Error> {
+        let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        create_proof::<KZGCommitmentScheme<Bn256>, ProverGWC<'_, Bn256>, _, _, _, _>(
+            params,
+            pk,
+            circuits,
+            instances,
+            OsRng,
+            &mut transcript,
+        )?;
+        Ok(transcript.finalize())
+    }
+}
+
+impl Multiopen<KZGCommitmentScheme<Bn256>> for Shplonk {
+    const NAME: &'static str = "shplonk";
+
+    fn create_proof<C: Circuit<Fr>>(
+        params: &ParamsKZG<Bn256>,
+        pk: &ProvingKey<G1Affine>,
+        circuits: &[C],
+        instances: &[&[&[Fr]]],
+    ) -> Result<Vec<u8>, Error> {
+        let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+            params,
+            pk,
+            circuits,
+            instances,
+            OsRng,
+            &mut transcript,
+        )?;
+        Ok(transcript.finalize())
+    }
+}
+
+impl<G: CurveAffine> Multiopen<IPACommitmentScheme<G>> for Ipa
+where
+    G::Scalar: WithSmallOrderMulGroup<3> + FromUniformBytes<64>,
+{
+    const NAME: &'static str = "ipa";
+
+    fn create_proof<C: Circuit<G::Scalar>>(
+        params: &ParamsIPA<G>,
+        pk: &ProvingKey<G>,
+        circuits: &[C],
+        instances: &[&[&[G::Scalar]]],
+    ) -> Result<Vec<u8>, Error> {
+        let mut transcript = Blake2bWrite::<_, G, Challenge255<_>>::init(vec![]);
+        create_proof::<IPACommitmentScheme<G>, ProverIPA<'_, G>, _, _, _, _>(
+            params,
+            pk,
+            circuits,
+            instances,
+            OsRng,
+            &mut transcript,
+        )?;
+        Ok(transcript.finalize())
+    }
+}
+
+/// The hash a proving key is referred to by, that of its encoding as sent
+/// to the workers.
+pub type KeyHash = ContentHash;
+
+/// Writes the payload of an `UploadKey` request: the task header and the
+/// proving key, and returns the hash of the key.
+pub fn write_key_upload<W: io::Write, C: SerdeCurveAffine>(
+    writer: &mut W,
+    pk: &ProvingKey<C>,
+    format: SerdeFormat,
+) -> io::Result<KeyHash>
+where
+    C::Scalar: SerdePrimeField + FromUniformBytes<64>,
+{
+    let encoded = pk.to_bytes(format);
+    write_header::<_, C::Scalar>(writer, format)?;
+    writer.write_all(&encoded)?;
+    Ok(KeyHash::of(&encoded))
+}
+
+/// A proving key held by a worker, decoded the first time a circuit is
+/// proven with it, as decoding needs the circuit.
+enum Key<C: CurveAffine> {
+    Encoded(SerdeFormat, Vec<u8>),
+    Decoded(Arc<ProvingKey<C>>),
+}
+
+/// Proving keys a worker holds, by hash.
+///
+/// Keys are never evicted: a dispatcher uploads a key once per connection
+/// and counts on the worker holding it for as long as the connection lasts.
+/// A worker thus keeps every key it was sent in memory until it restarts,
+/// encoded until it first proves a circuit with it and decoded from then
+/// on, so the memory it uses grows with the number of distinct keys it is
+/// sent.
+pub struct KeyCache<C: CurveAffine> {
+    keys: Mutex<HashMap<KeyHash, Arc<Mutex<Key<C>>>>>,
+}
+
+impl<C: CurveAffine> Default for KeyCache<C> {
+    fn default() -> Self {
+        KeyCache {
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<C: CurveAffine> fmt::Debug for KeyCache<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys = self.keys.lock().unwrap();
+        f.debug_struct("KeyCache")
+            .field("keys", &keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<C: SerdeCurveAffine> KeyCache<C>
+where
+    C::Scalar: SerdePrimeField + FromUniformBytes<64>,
+{
+    /// Adds the key of an `UploadKey` request written with
+    /// [`write_key_upload`], returning its hash.
+    pub fn insert(&self, upload: &[u8]) -> io::Result<KeyHash> {
+        let mut reader = upload;
+        let format = read_header::<_, C::Scalar>(&mut reader)?;
+        let hash = KeyHash::of(reader);
+        self.keys
+            .lock()
+            .unwrap()
+            .entry(hash)
+            .or_insert_with(|| Arc::new(Mutex::new(Key::Encoded(format, reader.to_vec()))));
+        Ok(hash)
+    }
+
+    /// Returns whether the cache holds the key hashing to `hash`.
+    pub fn contains(&self, hash: &KeyHash) -> bool {
+        self.keys.lock().unwrap().contains_key(hash)
+    }
+
+    /// Returns the key hashing to `hash` as the key of `circuit`, or a
+    /// `NotFound` error when the cache does not hold it.
+    pub fn get<ConcreteCircuit: Circuit<C::Scalar>>(
+        &self,
+        hash: &KeyHash,
+        circuit: &ConcreteCircuit,
+    ) -> io::Result<Arc<ProvingKey<C>>> {
+        #[cfg(not(feature = "circuit-params"))]
+        let _ = circuit;
+
+        let key = self.keys.lock().unwrap().get(hash).cloned();
+        let key = key.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("proving key {} is not cached", hash),
+            )
+        })?;
+
+        // Decoding takes a while, so only this key is locked meanwhile: the
+        // other keys stay available, and requests for this one wait for it
+        // to be decoded once.
+        let mut key = key.lock().unwrap();
+        let pk = match &*key {
+            Key::Decoded(pk) => return Ok(pk.clone()),
+            Key::Encoded(format, encoded) => Arc::new(
+                ProvingKey::read::<_, ConcreteCircuit>(
+                    &mut &encoded[..],
+                    *format,
+                    #[cfg(feature = "circuit-params")]
+                    circuit.params(),
+                )
+                .map_err(|e| invalid_data(format!("invalid proving key {}: {}", hash, e)))?,
+            ),
+        };
+        *key = Key::Decoded(pk.clone());
+        Ok(pk)
+    }
+}
+
+/// A circuit to prove, as sent in a `Prove` request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProveTask<F> {
+    /// The params to prove with.
+    pub params: ParamsHash,
+    /// The proving key of the circuit.
+    pub key: KeyHash,
+    /// The name of the circuit, see [`BatchCircuit::NAME`].
+    pub circuit: String,
+    /// The name of the multiopen argument, see [`Multiopen::NAME`].
+    pub multiopen: String,
+    /// The values of the instance columns of the circuit.
+    pub instances: Vec<Vec<F>>,
+    /// The witness, as [`BatchCircuit::write_witness`] writes it.
+    pub witness: Vec<u8>,
+}
+
+impl<F: SerdePrimeField> ProveTask<F> {
+    pub fn write<W: io::Write>(&self, writer: &mut W, format: SerdeFormat) -> io::Result<()> {
+        write_header::<_, F>(writer, format)?;
+        self.params.write(writer)?;
+        self.key.write(writer)?;
+        write_bytes(writer, self.circuit.as_bytes())?;
+        write_bytes(writer, self.multiopen.as_bytes())?;
+        write_u32(writer, self.instances.len() as u32)?;
+        for column in &self.instances {
+            write_scalars(writer, column, format)?;
+        }
+        write_bytes(writer, &self.witness)
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let format = read_header::<_, F>(reader)?;
+        let params = ParamsHash::read(reader)?;
+        let key = KeyHash::read(reader)?;
+        let name = |bytes: Vec<u8>| {
+            String::from_utf8(bytes).map_err(|_| invalid_data("invalid name in prove task"))
+        };
+        let circuit = name(read_bytes(reader)?)?;
+        let multiopen = name(read_bytes(reader)?)?;
+        let columns = read_u32(reader)?;
+        let instances = (0..columns)
+            .map(|_| read_scalars(reader, format))
+            .collect::<io::Result<_>>()?;
+        let witness = read_bytes(reader)?;
+        Ok(ProveTask {
+            params,
+            key,
+            circuit,
+            multiopen,
+            instances,
+            witness,
+        })
+    }
+}
+
+/// Proves a task with the params it names, taking its key from the cache.
+type Prove<S> = Arc<
+    dyn Fn(
+            &<S as CommitmentScheme>::ParamsProver,
+            &KeyCache<<S as CommitmentScheme>::Curve>,
+            &ProveTask<<S as CommitmentScheme>::Scalar>,
+        ) -> io::Result<Vec<u8>>
+        + Send
+        + Sync,
+>;
+
+/// The circuits a worker proves over the scheme `S`, by name and multiopen
+/// argument.
+pub struct ProverRegistry<S: CommitmentScheme> {
+    provers: HashMap<(&'static str, &'static str), Prove<S>>,
+}
+
+impl<S: CommitmentScheme> Clone for ProverRegistry<S> {
+    fn clone(&self) -> Self {
+        ProverRegistry {
+            provers: self.provers.clone(),
+        }
+    }
+}
+
+impl<S: CommitmentScheme> Default for ProverRegistry<S> {
+    fn default() -> Self {
+        ProverRegistry {
+            provers: HashMap::new(),
+        }
+    }
+}
+
+impl<S: CommitmentScheme> ProverRegistry<S> {
+    /// Returns whether `circuit` is registered with the argument `multiopen`.
+    pub fn contains(&self, circuit: &str, multiopen: &str) -> bool {
+        self.provers
+            .keys()
+            .any(|&(name, argument)| name == circuit && argument == multiopen)
+    }
+
+    /// Returns the names of the registered circuits, each followed by its
+    /// multiopen argument.
+    pub fn names(&self) -> impl Iterator<Item = String> + '_ {
+        self.provers
+            .keys()
+            .map(|(circuit, multiopen)| format!("{}/{}", circuit, multiopen))
+    }
+}
+
+impl<S: CommitmentScheme> fmt::Debug for ProverRegistry<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.names().collect::<Vec<_>>();
+        names.sort_unstable();
+        f.debug_struct("ProverRegistry")
+            .field("circuits", &names)
+            .finish()
+    }
+}
+
+impl<S> ProverRegistry<S>
+where
+    S: CommitmentScheme + 'static,
+    S::Curve: SerdeCurveAffine,
+    S::Scalar: SerdePrimeField + FromUniformBytes<64>,
+{
+    /// Proves the circuits of type `C` with the argument `M` from now on,
+    /// replacing any circuit registered under the same names.
+    pub fn register<M: Multiopen<S>, C: BatchCircuit<S::Scalar>>(&mut self) {
+        let prove: Prove<S> = Arc::new(
+            |params: &S::ParamsProver, keys: &KeyCache<S::Curve>, task: &ProveTask<S::Scalar>| {
+                let circuit = C::read_witness(&mut &task.witness[..])
+                    .map_err(|e| invalid_data(format!("invalid witness of {}: {}", C::NAME, e)))?;
+                let pk = keys.get(&task.key, &circuit)?;
+                let instances = task
+                    .instances
+                    .iter()
+                    .map(|column| &column[..])
+                    .collect::<Vec<_>>();
+                M::create_proof(params, &pk, &[circuit], &[&instances[..]]).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("proving {} failed: {}", C::NAME, e),
+                    )
+                })
+            },
+        );
+        self.provers.insert((C::NAME, M::NAME), prove);
+    }
+
+    /// Proves `task` and returns the proof.
+    ///
+    /// A circuit that is not registered fails with
+    /// [`io::ErrorKind::Unsupported`], a key that is not cached with
+    /// [`io::ErrorKind::NotFound`], a witness that cannot be decoded with
+    /// [`io::ErrorKind::InvalidData`], and a circuit that cannot be proven
+    /// with [`io::ErrorKind::Other`].
+    pub fn prove(
+        &self,
+        params: &S::ParamsProver,
+        keys: &KeyCache<S::Curve>,
+        task: &ProveTask<S::Scalar>,
+    ) -> io::Result<Vec<u8>> {
+        let prove = self
+            .provers
+            .iter()
+            .find(|((circuit, multiopen), _)| {
+                *circuit == task.circuit && *multiopen == task.multiopen
+            })
+            .map(|(_, prove)| prove)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "no circuit {} is registered with {}",
+                        task.circuit, task.multiopen
+                    ),
+                )
+            })?;
+        prove(params, keys, task)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        write_key_upload, BatchCircuit, KeyCache, KeyHash, Multiopen, ProveTask, ProverRegistry,
+        Shplonk,
+    };
+    use crate::{
+        circuit::{Layouter, SimpleFloorPlanner, Value},
+        distributed_util::net::{read_u64, write_u64},
+        distributed_util::params::{ContentHash, ParamsHash},
+        plonk::{
+            keygen_pk, keygen_vk, verify_proof, Advice, Circuit, Column, ConstraintSystem, Error,
+            Instance,
+        },
+        poly::{
+            commitment::ParamsProver,
+            kzg::{
+                commitment::{KZGCommitmentScheme, ParamsKZG},
+                multiopen::VerifierSHPLONK,
+                strategy::SingleStrategy,
+            },
+            VerificationStrategy,
+        },
+        transcript::{Blake2bRead, Challenge255, TranscriptReadBuffer},
+        SerdeFormat,
+    };
+    use halo2curves::bn256::{Bn256, Fr, G1Affine};
+    use rand_core::OsRng;
+    use std::io;
+
+    /// Exposes its witness as its single instance value.
+    #[derive(Clone, Default)]
+    struct Echo {
+        value: Option<u64>,
+    }
+
+    impl Circuit<Fr> for Echo {
+        type Config = (Column<Advice>, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+        #[cfg(feature = "circuit-params")]
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let advice = meta.advice_column();
+            let instance = meta.instance_column();
+            meta.enable_equality(advice);
+            meta.enable_equality(instance);
+            (advice, instance)
+        }
+
+        fn synthesize(
+            &self,
+            (advice, instance): Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            let cell = layouter.assign_region(
+                || "value",
+                |mut region| {
+                    let value = self
+                        .value
+                        .map_or(Value::unknown(), |v| Value::known(Fr::from(v)));
+                    region.assign_advice(|| "value", advice, 0, || value)
+                },
+            )?;
+            layouter.constrain_instance(cell.cell(), instance, 0)
+        }
+    }
+
+    impl BatchCircuit<Fr> for Echo {
+        const NAME: &'static str = "test/echo";
+
+        fn write_witness<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+            write_u64(writer, self.value.unwrap_or_default())
+        }
+
+        fn read_witness<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+            Ok(Echo {
+                value: Some(read_u64(reader)?),
+            })
+        }
+    }
+
+    #[test]
+    fn test_prove_task_roundtrip() {
+        let task = ProveTask {
+            params: ContentHash([1; 32]),
+            key: ContentHash([2; 32]),
+            circuit: Echo::NAME.to_string(),
+            multiopen: Shplonk::NAME.to_string(),
+            instances: vec![vec![Fr::from(7), Fr::from(8)], vec![]],
+            witness: vec![1, 2, 3],
+        };
+        for format in [SerdeFormat::Processed, SerdeFormat::RawBytes] {
+            let mut payload = vec![];
+            task.write(&mut payload, format).unwrap();
+            assert_eq!(ProveTask::<Fr>::read(&mut &payload[..]).unwrap(), task);
+        }
+    }
+
+    #[test]
+    fn test_registry_proves_registered_circuits() {
+        let params = ParamsKZG::<Bn256>::setup(4, OsRng);
+        let vk = keygen_vk(&params, &Echo::default()).unwrap();
+        let pk = keygen_pk(&params, vk, &Echo::default()).unwrap();
+
+        let keys = KeyCache::<G1Affine>::default();
+        let mut upload = vec![];
+        let key = write_key_upload(&mut upload, &pk, SerdeFormat::RawBytes).unwrap();
+        let mut task = ProveTask {
+            params: ParamsHash::of_params(&params),
+            key: ContentHash([0; 32]),
+            circuit: Echo::NAME.to_string(),
+            multiopen: Shplonk::NAME.to_string(),
+            instances: vec![vec![Fr::from(42)]],
+            witness: vec![],
+        };
+        Echo { value: Some(42) }
+            .write_witness(&mut task.witness)
+            .unwrap();
+
+        let mut registry = ProverRegistry::<KZGCommitmentScheme<Bn256>>::default();
+        let unsupported = registry.prove(&params, &keys, &task).unwrap_err();
+        assert_eq!(unsupported.kind(), io::ErrorKind::Unsupported);
+
+        registry.register::<Shplonk, Echo>();
+        assert!(registry.contains(Echo::NAME, Shplonk::NAME));
+        let unknown = registry.prove(&params, &keys, &task).unwrap_err();
+        assert_eq!(unknown.kind(), io::ErrorKind::NotFound);
+
+        assert_eq!(keys.insert(&upload).unwrap(), key);
+        assert!(keys.contains(&key));
+        task.key = key;
+        let proof = registry.prove(&params, &keys, &task).unwrap();
+
+        let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
+        let strategy = verify_proof::<_, VerifierSHPLONK<_>, _, _, _>(
+            params.verifier_params(),
+            pk.get_vk(),
+            SingleStrategy::new(&params),
+            &[&[&[Fr::from(42)][..]]],
+            &mut transcript,
+        )
+        .unwrap();
+        assert!(strategy.finalize());
+
+        let mut truncated = task.clone();
+        truncated.witness.pop();
+        let invalid = registry.prove(&params, &keys, &truncated).unwrap_err();
+        assert_eq!(invalid.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,176 @@
+//! What each worker can take on
+//!
+//! Workers report their [`Capacity`] in answer to a `Ping`, and the
+//! dispatcher gives each worker a share of every job proportional to its
+//! capacity times the weight the pool configuration gives it, see
+//! [`capacity_weights`]. Shares are only a first guess: a worker done early
+//! takes over the tasks the others have not started yet.
+use ff::Field;
+use group::{prime::PrimeCurveAffine, Curve, Group};
+use halo2curves::bn256::{Fr, G1Affine, G1};
+use rand_core::OsRng;
+use std::{fs, io, time::Instant};
+
+use super::net::{read_u32, read_u64, write_u32, write_u64};
+use crate::arithmetic::best_multiexp;
+
+/// Log size of the multiexp a worker times to measure its rate.
+const MEASURE_K: u32 = 12;
+
+/// Resolution of the shares computed by [`capacity_weights`].
+const SHARE_SCALE: u128 = 1 << 10;
+
+/// The resources of a worker.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capacity {
+    /// Threads the worker computes with.
+    pub cores: u32,
+    /// Bytes of memory available to the worker, zero if unknown.
+    pub memory: u64,
+    /// Terms of a multiexp over BN254 the worker computes per second, zero
+    /// if unknown.
+    pub msm_rate: u64,
+}
+
+impl Capacity {
+    /// Measures this machine. Timing the multiexp takes a fraction of a
+    /// second.
+    pub fn measure() -> Self {
+        Capacity {
+            cores: rayon::current_num_threads() as u32,
+            memory: available_memory().unwrap_or(0),
+            msm_rate: measure_msm_rate(),
+        }
+    }
+
+    /// Writes the capacity as `cores`, `memory` and `msm_rate`.
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u32(writer, self.cores)?;
+        write_u64(writer, self.memory)?;
+        write_u64(writer, self.msm_rate)
+    }
+
+    /// Reads a capacity written with [`Capacity::write`].
+    pub fn read<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Capacity {
+            cores: read_u32(reader)?,
+            memory: read_u64(reader)?,
+            msm_rate: read_u64(reader)?,
+        })
+    }
+}
+
+/// Returns the memory available on this machine, which only Linux tells.
+fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Times a multiexp of `2^MEASURE_K` terms and returns the terms computed
+/// per second.
+fn measure_msm_rate() -> u64 {
+    let n = 1 << MEASURE_K;
+    // Multiples of the generator make distinct bases for the price of an
+    // addition each.
+    let mut bases = vec![G1Affine::identity(); n];
+    let multiples = (0..n)
+        .scan(G1::identity(), |acc, _| {
+            *acc += G1::generator();
+            Some(*acc)
+        })
+        .collect::<Vec<_>>();
+    G1::batch_normalize(&multiples, &mut bases);
+    let coeffs = (0..n).map(|_| Fr::random(OsRng)).collect::<Vec<_>>();
+
+    let start = Instant::now();
+    best_multiexp(&coeffs, &bases);
+    let elapsed = start.elapsed().as_secs_f64();
+    (n as f64 / elapsed.max(1e-9)) as u64
+}
+
+/// Returns the share of every job each worker gets: its weight in the pool
+/// configuration, scaled by its multiexp rate relative to the fastest worker.
+///
+/// Workers that did not report a rate are taken to be as fast as the average
+/// of those that did, and when none did the weights are returned as they
+/// are.
+pub fn capacity_weights(weights: &[u32], capacities: &[Option<Capacity>]) -> Vec<u32> {
+    assert_eq!(weights.len(), capacities.len());
+    let rates = capacities
+        .iter()
+        .map(|capacity| {
+            capacity
+                .map(|capacity| capacity.msm_rate)
+                .filter(|&rate| rate > 0)
+        })
+        .collect::<Vec<_>>();
+    let known = rates
+        .iter()
+        .flatten()
+        .map(|&rate| rate as u128)
+        .collect::<Vec<_>>();
+    let fastest = match known.iter().max() {
+        Some(&fastest) => fastest,
+        None => return weights.to_vec(),
+    };
+    let average = known.iter().sum::<u128>() / known.len() as u128;
+
+    weights
+        .iter()
+        .zip(rates)
+        .map(|(&weight, rate)| {
+            let rate = rate.map_or(average, u128::from);
+            let share = (rate * SHARE_SCALE / fastest).max(1);
+            (weight as u128 * share).min(u32::MAX as u128) as u32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capacity_weights, Capacity};
+
+    fn rated(msm_rate: u64) -> Option<Capacity> {
+        Some(Capacity {
+            cores: 8,
+            memory: 1 << 34,
+            msm_rate,
+        })
+    }
+
+    #[test]
+    fn test_capacity_roundtrip() {
+        let capacity = rated(123_456).unwrap();
+        let mut buf = vec![];
+        capacity.write(&mut buf).unwrap();
+        assert_eq!(Capacity::read(&mut &buf[..]).unwrap(), capacity);
+    }
+
+    #[test]
+    fn test_capacity_weights() {
+        // Without any report the configured weights are used as they are.
+        assert_eq!(capacity_weights(&[1, 2], &[None, None]), vec![1, 2]);
+
+        // A machine four times as fast gets four times the work, times its
+        // weight.
+        let weights = capacity_weights(&[1, 1, 2], &[rated(4000), rated(1000), rated(1000)]);
+        assert_eq!(weights, vec![1024, 256, 512]);
+
+        // Workers that did not report are taken to be average, and a
+        // reported rate of zero is no report.
+        let weights = capacity_weights(&[1, 1, 1], &[rated(3000), None, rated(0)]);
+        assert_eq!(weights, vec![1024, 1024, 1024]);
+        let weights = capacity_weights(&[1, 1, 1], &[rated(3000), rated(1000), None]);
+        assert_eq!(weights, vec![1024, 341, 682]);
+
+        // Even a very slow worker keeps a share.
+        assert_eq!(
+            capacity_weights(&[1, 1], &[rated(u64::MAX), rated(1)]),
+            vec![1024, 1]
+        );
+    }
+}
@@ -0,0 +1,356 @@
+//! Worker pool configuration
+//!
+//! A pool is read either from a JSON file
+//!
+//! ```json
+//! { "workers": [{ "addr": "10.0.0.1:8081", "weight": 2 }, { "addr": "10.0.0.2:8081" }] }
+//! ```
+//!
+//! whose path is given by [`WORKERS_CONFIG_ENV`], or from a comma separated
+//! list of `addr[=weight]` entries in [`WORKERS_ENV`], for example
+//! `HALO2_WORKERS=10.0.0.1:8081=2,10.0.0.2:8081`. Addresses may use host names,
+//! they are resolved when the configuration is loaded. The JSON file may also
+//! set `task_timeout_secs`, the time a worker gets to answer a single task,
+//! and `key`, the secret shared with the workers, which [`WORKERS_KEY_ENV`]
+//! overrides. Without a secret, connections are not authenticated, which a
+//! pool only accepts when all its workers are on this host. Setting
+//! `check_commitments` checks the commitments the workers return, see
+//! [`verify`](super::verify), and `window_bytes` bounds how much of a request
+//! is in flight to a worker at once, see [`net`](super::net).
+
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    net::{SocketAddr, ToSocketAddrs},
+    path::Path,
+    time::Duration,
+};
+
+use super::net::{auth::PresharedKey, invalid_data, DEFAULT_WINDOW};
+
+/// Environment variable holding the path of a JSON pool configuration.
+pub const WORKERS_CONFIG_ENV: &str = "HALO2_WORKERS_CONFIG";
+
+/// Environment variable holding a comma separated list of workers.
+pub const WORKERS_ENV: &str = "HALO2_WORKERS";
+
+/// Environment variable holding the secret shared with the workers.
+pub const WORKERS_KEY_ENV: &str = "HALO2_WORKERS_KEY";
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_task_timeout_secs() -> u64 {
+    600
+}
+
+fn default_window_bytes() -> u64 {
+    DEFAULT_WINDOW as u64
+}
+
+/// A single worker of the pool.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerConfig {
+    /// Address the worker listens on.
+    pub addr: String,
+    /// Relative share of the work sent to this worker, further scaled by the
+    /// capacity it reports, see [`capacity`](super::capacity).
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+impl WorkerConfig {
+    /// Creates a worker entry with the default weight.
+    pub fn new(addr: impl Into<String>) -> Self {
+        WorkerConfig {
+            addr: addr.into(),
+            weight: default_weight(),
+        }
+    }
+
+    /// Sets the relative share of the work sent to this worker.
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Resolves the address of the worker.
+    pub fn socket_addr(&self) -> io::Result<SocketAddr> {
+        self.addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| invalid_data(format!("worker address {} did not resolve", self.addr)))
+    }
+}
+
+/// The set of workers a [`Dispatcher`](super::dispatcher::Dispatcher)
+/// distributes tasks to.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolConfig {
+    /// Workers of the pool.
+    pub workers: Vec<WorkerConfig>,
+    /// Seconds a worker gets to answer a single task before it is considered
+    /// dead and its task is handed to another worker.
+    #[serde(default = "default_task_timeout_secs")]
+    pub task_timeout_secs: u64,
+    /// Secret shared with the workers, see
+    /// [`PresharedKey`](super::net::auth::PresharedKey).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Whether the commitments returned by the workers are checked against
+    /// the polynomials they commit to. A worker failing the check fails the
+    /// job with an error naming it.
+    #[serde(default)]
+    pub check_commitments: bool,
+    /// Bytes of a request sent to a worker before it acknowledges them. A
+    /// larger window makes the most of fast links, a smaller one bounds the
+    /// memory a transfer takes on both ends.
+    #[serde(default = "default_window_bytes")]
+    pub window_bytes: u64,
+}
+
+impl fmt::Debug for PoolConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolConfig")
+            .field("workers", &self.workers)
+            .field("task_timeout_secs", &self.task_timeout_secs)
+            // Never print the secret itself.
+            .field("key", &self.key.as_ref().map(|_| ".."))
+            .field("check_commitments", &self.check_commitments)
+            .field("window_bytes", &self.window_bytes)
+            .finish()
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig::new(vec![])
+    }
+}
+
+impl PoolConfig {
+    /// Creates a pool from its workers.
+    pub fn new(workers: Vec<WorkerConfig>) -> Self {
+        PoolConfig {
+            workers,
+            task_timeout_secs: default_task_timeout_secs(),
+            key: None,
+            check_commitments: false,
+            window_bytes: default_window_bytes(),
+        }
+    }
+
+    /// Sets whether the commitments returned by the workers are checked.
+    pub fn with_commitment_checks(mut self, check: bool) -> Self {
+        self.check_commitments = check;
+        self
+    }
+
+    /// Sets the secret shared with the workers.
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Returns the key connections to the workers are authenticated with.
+    pub fn preshared_key(&self) -> PresharedKey {
+        PresharedKey::new(self.key.as_deref().unwrap_or_default().as_bytes())
+    }
+
+    /// Sets the time a worker gets to answer a single task.
+    pub fn with_task_timeout(mut self, timeout: Duration) -> Self {
+        self.task_timeout_secs = timeout.as_secs().max(1);
+        self
+    }
+
+    /// Returns the time a worker gets to answer a single task.
+    pub fn task_timeout(&self) -> Duration {
+        Duration::from_secs(self.task_timeout_secs)
+    }
+
+    /// Sets the bytes of a request sent to a worker before it acknowledges
+    /// them.
+    pub fn with_window(mut self, bytes: u64) -> Self {
+        self.window_bytes = bytes;
+        self
+    }
+
+    /// Returns the bytes of a request sent to a worker before it
+    /// acknowledges them.
+    pub fn window(&self) -> usize {
+        usize::try_from(self.window_bytes).unwrap_or(usize::MAX)
+    }
+
+    /// Loads the pool from the file named by [`WORKERS_CONFIG_ENV`] or, if
+    /// that is not set, from the list in [`WORKERS_ENV`]. The secret in
+    /// [`WORKERS_KEY_ENV`], if set, overrides the one of the file.
+    pub fn from_env() -> io::Result<Self> {
+        let config = if let Ok(path) = std::env::var(WORKERS_CONFIG_ENV) {
+            Self::from_file(path)?
+        } else {
+            match std::env::var(WORKERS_ENV) {
+                Ok(list) => Self::parse_list(&list)?,
+                Err(_) => {
+                    return Err(invalid_data(format!(
+                        "no worker pool configured, set {} or {}",
+                        WORKERS_CONFIG_ENV, WORKERS_ENV
+                    )))
+                }
+            }
+        };
+        let config = match std::env::var(WORKERS_KEY_ENV) {
+            Ok(key) => config.with_key(key),
+            Err(_) => config,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads the pool from a JSON file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let config: PoolConfig = serde_json::from_slice(&fs::read(path)?).map_err(invalid_data)?;
+        config.validate_workers()?;
+        Ok(config)
+    }
+
+    /// Parses a comma separated list of `addr[=weight]` entries.
+    pub fn parse_list(list: &str) -> io::Result<Self> {
+        let workers = list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| match entry.split_once('=') {
+                Some((addr, weight)) => weight
+                    .trim()
+                    .parse()
+                    .map(|weight| WorkerConfig::new(addr.trim()).with_weight(weight))
+                    .map_err(|_| invalid_data(format!("invalid worker weight in {}", entry))),
+                None => Ok(WorkerConfig::new(entry)),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let config = PoolConfig::new(workers);
+        config.validate_workers()?;
+        Ok(config)
+    }
+
+    /// Checks that the pool has at least one worker, that every worker has a
+    /// resolvable address and a nonzero weight, and that the pool has a
+    /// secret unless all its workers are on this host.
+    pub fn validate(&self) -> io::Result<()> {
+        self.validate_workers()?;
+        if self.key.as_deref().unwrap_or_default().is_empty() {
+            for worker in &self.workers {
+                if !worker.socket_addr()?.ip().is_loopback() {
+                    return Err(invalid_data(format!(
+                        "worker {} is not on this host, set {} or the key of the pool",
+                        worker.addr, WORKERS_KEY_ENV
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks everything [`PoolConfig::validate`] does but the secret, which
+    /// may still be set from [`WORKERS_KEY_ENV`].
+    fn validate_workers(&self) -> io::Result<()> {
+        if self.workers.is_empty() {
+            return Err(invalid_data("the worker pool is empty"));
+        }
+        if self.task_timeout_secs == 0 {
+            return Err(invalid_data("the task timeout must be at least one second"));
+        }
+        if self.window_bytes == 0 {
+            return Err(invalid_data("the window must be at least one byte"));
+        }
+        for worker in &self.workers {
+            if worker.weight == 0 {
+                return Err(invalid_data(format!(
+                    "worker {} has a weight of zero",
+                    worker.addr
+                )));
+            }
+            worker.socket_addr()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the weights of the workers, in order.
+    pub fn weights(&self) -> Vec<u32> {
+        self.workers.iter().map(|worker| worker.weight).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PoolConfig, WorkerConfig, DEFAULT_WINDOW};
+
+    #[test]
+    fn test_parse_list() {
+        let config = PoolConfig::parse_list("127.0.0.1:8081=3, 127.0.0.1:8082,").unwrap();
+        assert_eq!(
+            config,
+            PoolConfig::new(vec![
+                WorkerConfig::new("127.0.0.1:8081").with_weight(3),
+                WorkerConfig::new("127.0.0.1:8082"),
+            ])
+        );
+
+        assert!(PoolConfig::parse_list("").is_err());
+        assert!(PoolConfig::parse_list("127.0.0.1:8081=0").is_err());
+        assert!(PoolConfig::parse_list("127.0.0.1:8081=many").is_err());
+        assert!(PoolConfig::parse_list("not an address").is_err());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let config: PoolConfig = serde_json::from_str(
+            r#"{ "workers": [{ "addr": "127.0.0.1:8081", "weight": 2 }, { "addr": "127.0.0.1:8082" }] }"#,
+        )
+        .unwrap();
+        assert_eq!(config.weights(), vec![2, 1]);
+        assert_eq!(config.task_timeout_secs, 600);
+        assert_eq!(config.key, None);
+        assert!(!config.check_commitments);
+        assert_eq!(config.window(), DEFAULT_WINDOW);
+        config.validate().unwrap();
+
+        let config: PoolConfig = serde_json::from_str(
+            r#"{ "workers": [{ "addr": "127.0.0.1:8081" }], "key": "correct horse battery staple" }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.preshared_key(),
+            PoolConfig::default()
+                .with_key("correct horse battery staple")
+                .preshared_key()
+        );
+        assert_ne!(
+            config.preshared_key(),
+            PoolConfig::default().preshared_key()
+        );
+        assert!(!format!("{:?}", config).contains("horse"));
+
+        let config = PoolConfig::parse_list("10.0.0.1:8081, 127.0.0.1:8082").unwrap();
+        assert!(config.validate().is_err());
+        assert!(config.clone().with_key("").validate().is_err());
+        config
+            .with_key("correct horse battery staple")
+            .validate()
+            .unwrap();
+
+        let config: PoolConfig = serde_json::from_str(
+            r#"{ "workers": [{ "addr": "127.0.0.1:8081" }], "task_timeout_secs": 0 }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+
+        let config: PoolConfig = serde_json::from_str(
+            r#"{ "workers": [{ "addr": "127.0.0.1:8081" }], "window_bytes": 0 }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+}
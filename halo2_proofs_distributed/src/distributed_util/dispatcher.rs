@@ -1,123 +1,2449 @@
 //! Dispatcher api
 use futures::future::join_all;
+use group::{prime::PrimeCurveAffine, Group};
 use halo2curves::{
     bn256::{Bn256, G1Affine},
     CurveAffine,
 };
 use num_enum::{IntoPrimitive, TryFromPrimitive};
-use once_cell::sync::Lazy;
 use serde_derive::{Deserialize, Serialize};
-use std::{io, net::SocketAddr, thread, time::Duration};
-use stubborn_io::{tokio::StubbornIo, StubbornTcpStream};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    error, fmt, io,
+    net::SocketAddr,
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+    io::{BufReader, BufWriter},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    runtime::{Handle, RuntimeFlavor},
+    sync::{mpsc, Mutex as AsyncMutex, Notify},
+    task::{block_in_place, JoinHandle},
+    time::{sleep, timeout},
 };
 
+use ff::{Field, FromUniformBytes, WithSmallOrderMulGroup};
+
 use crate::{
+    arithmetic::best_multiexp,
+    helpers::{SerdeCurveAffine, SerdePrimeField},
     plonk::{
-        create_domain, permutation::Argument, permutation::ProvingKey, permutation::VerifyingKey,
+        create_domain,
+        evaluation::{Evaluator, HChallenges, HLayout, HPolys, RowWindow},
+        permutation::keygen::{commit_permutations, permutation_polys},
+        permutation::Argument,
+        permutation::ProvingKey,
+        permutation::VerifyingKey,
+        Error,
     },
-    poly::{commitment::Params, kzg::commitment::ParamsKZG, EvaluationDomain},
+    poly::{
+        commitment::{Blind, CommitmentScheme, Params, SrsBasis},
+        kzg::commitment::ParamsKZG,
+        Coeff, EvaluationDomain, ExtendedLagrangeCoeff, LagrangeCoeff, Polynomial,
+    },
+    SerdeFormat,
 };
 
-use super::{net::to_bytes, plonk::permutation::keygen::KeygenTaskKZG, utils::CastSlice};
+use super::{
+    batch::{write_key_upload, BatchCircuit, KeyHash, Multiopen, ProveTask},
+    capacity::{capacity_weights, Capacity},
+    config::PoolConfig,
+    fft::{four_step_split, merge_rows, powers, read_columns, split_columns, transpose, FftTask},
+    multiexp::MultiexpTask,
+    net::{
+        auth::{self, PresharedKey, Session},
+        invalid_data, read_bytes, read_points, read_response, read_scalars, write_bytes,
+        write_request_chunk, ChunkHeader, Payload, RequestId, Response, SerdeParams, CHUNK_LEN,
+        DEFAULT_WINDOW,
+    },
+    params::{write_upload, ParamsHash},
+    plonk::{
+        commit::{commit_lagrange, CommitTask},
+        evaluation::EvaluateHTask,
+        lookup::{
+            lookup_product, permute_lookup, read_permuted, read_products, LookupPermuteTask,
+            LookupProductTask, PermuteInput, PermuteOutput, ProductInput, ProductOutput,
+        },
+        permutation::keygen::{assemble_commitments, read_commitments, KeygenTask},
+        permutation::prover::{
+            chain_products, chunk_product, read_chunk_products, PermutationChunk,
+            PermutationProduct, PermutationProductTask,
+        },
+    },
+    shard::{ParamsShard, ShardMultiexpTask, ShardRows},
+    task::{write_task, Taskable},
+    utils::{split_range, split_weighted},
+    verify::check_commitments,
+};
 
-pub static WORKERS: Lazy<[SocketAddr; 1]> = Lazy::new(|| {
-    [
-        "127.0.0.1:8081".parse().unwrap(),
-        // "127.0.0.1:8082".parse().unwrap(),
-    ]
-});
+/// Format used for the params and points exchanged with the workers.
+const TASK_FORMAT: SerdeFormat = SerdeFormat::RawBytes;
 
 #[repr(u8)]
 #[derive(
-    Debug, Clone, Copy, strum::Display, TryFromPrimitive, IntoPrimitive, Serialize, Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    strum::Display,
+    TryFromPrimitive,
+    IntoPrimitive,
+    Serialize,
+    Deserialize,
 )]
 pub enum WorkerMethod {
     KeyGen = 0x00,
+    Ping = 0x01,
+    Commit = 0x02,
+    Multiexp = 0x03,
+    LoadShard = 0x04,
+    ShardMultiexp = 0x05,
+    Fft = 0x06,
+    EvaluateH = 0x07,
+    LookupPermute = 0x08,
+    LookupProduct = 0x09,
+    PermutationProduct = 0x0a,
+    UploadParams = 0x0b,
+    HasParams = 0x0c,
+    /// A task registered with the worker by name, see
+    /// [`task`](super::task).
+    Task = 0x0d,
+    /// A proving key, kept by the worker for the `Prove` requests referring
+    /// to it, see [`batch`](super::batch).
+    UploadKey = 0x0e,
+    /// A whole proof of a circuit registered with the worker.
+    Prove = 0x0f,
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, strum::Display, TryFromPrimitive, IntoPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, TryFromPrimitive, IntoPrimitive)]
 pub enum WorkerStatus {
     Ok = 0x00,
     ErrorInvalidMethod = 0x01,
     ErrorUnkown = 0x02,
+    ErrorInvalidPayload = 0x03,
+    /// The task refers to params the worker does not hold.
+    ErrorUnknownParams = 0x04,
+    /// The peer does not hold the pre-shared key, or a frame failed
+    /// authentication. The connection is closed after this answer.
+    ErrorUnauthenticated = 0x05,
+    /// The worker received part of a request, which it answers later. The
+    /// payload is the number of bytes of the request received so far.
+    Received = 0x06,
+}
+
+/// A failure while talking to a worker.
+#[derive(Debug)]
+pub enum WorkerError {
+    /// The connection to the worker failed.
+    Io {
+        /// Address of the worker.
+        addr: SocketAddr,
+        /// The underlying error.
+        error: io::Error,
+    },
+    /// The worker did not answer in time.
+    Timeout {
+        /// Address of the worker.
+        addr: SocketAddr,
+        /// How long the worker was waited for.
+        after: Duration,
+    },
+    /// The worker answered with an error status.
+    Status {
+        /// Address of the worker.
+        addr: SocketAddr,
+        /// The status the worker answered with.
+        status: WorkerStatus,
+        /// The message the worker attached to the status.
+        message: String,
+    },
+    /// The worker answered, but the answer is unusable.
+    InvalidResponse {
+        /// Address of the worker.
+        addr: SocketAddr,
+        /// What is wrong with the answer.
+        message: String,
+    },
+    /// The worker does not hold the pre-shared key of the pool, or a frame
+    /// failed authentication on either end.
+    Unauthenticated {
+        /// Address of the worker.
+        addr: SocketAddr,
+        /// Which end rejected what.
+        message: String,
+    },
+}
+
+impl WorkerError {
+    /// Address of the worker that failed.
+    pub fn addr(&self) -> SocketAddr {
+        match self {
+            WorkerError::Io { addr, .. }
+            | WorkerError::Timeout { addr, .. }
+            | WorkerError::Status { addr, .. }
+            | WorkerError::InvalidResponse { addr, .. }
+            | WorkerError::Unauthenticated { addr, .. } => *addr,
+        }
+    }
+
+    /// Returns whether the worker went away, as opposed to answering badly.
+    /// The task it was running can be handed to another worker.
+    pub fn is_disconnect(&self) -> bool {
+        matches!(self, WorkerError::Io { .. } | WorkerError::Timeout { .. })
+    }
+
+    /// Returns whether the worker does not hold the params of the task. The
+    /// task can be handed to another worker, which was sent them.
+    pub fn is_unknown_params(&self) -> bool {
+        matches!(
+            self,
+            WorkerError::Status {
+                status: WorkerStatus::ErrorUnknownParams,
+                ..
+            }
+        )
+    }
+
+    /// Returns whether the worker does not serve the method of the task, as
+    /// a worker without the task registered. The task can be handed to
+    /// another worker.
+    pub fn is_unsupported(&self) -> bool {
+        matches!(
+            self,
+            WorkerError::Status {
+                status: WorkerStatus::ErrorInvalidMethod,
+                ..
+            }
+        )
+    }
+
+    /// Returns whether the worker could not be trusted with the task. The
+    /// task can be handed to another worker.
+    pub fn is_unauthenticated(&self) -> bool {
+        matches!(self, WorkerError::Unauthenticated { .. })
+    }
+
+    /// Turns the errors of the authentication layer into
+    /// [`WorkerError::Unauthenticated`].
+    fn from_io(addr: SocketAddr, error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::PermissionDenied {
+            WorkerError::Unauthenticated {
+                addr,
+                message: error.to_string(),
+            }
+        } else {
+            WorkerError::Io { addr, error }
+        }
+    }
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkerError::Io { addr, error } => {
+                write!(f, "connection to worker {} failed: {}", addr, error)
+            }
+            WorkerError::Timeout { addr, after } => {
+                write!(f, "worker {} did not answer within {:?}", addr, after)
+            }
+            WorkerError::Status {
+                addr,
+                status,
+                message,
+            } => write!(f, "worker {} answered {}: {}", addr, status, message),
+            WorkerError::InvalidResponse { addr, message } => {
+                write!(f, "worker {} sent an invalid response: {}", addr, message)
+            }
+            WorkerError::Unauthenticated { addr, message } => {
+                write!(f, "worker {} failed authentication: {}", addr, message)
+            }
+        }
+    }
+}
+
+impl error::Error for WorkerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            WorkerError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// What a worker reports about itself in answer to a `Ping`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkerInfo {
+    /// Version of the worker binary.
+    pub version: String,
+    /// Methods the worker can serve.
+    pub capabilities: Vec<WorkerMethod>,
+    /// Resources of the worker.
+    pub capacity: Capacity,
+}
+
+impl WorkerInfo {
+    /// Writes the answer to a `Ping`.
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_bytes(writer, self.version.as_bytes())?;
+        let capabilities: Vec<u8> = self.capabilities.iter().map(|&m| m.into()).collect();
+        write_bytes(writer, &capabilities)?;
+        self.capacity.write(writer)
+    }
+
+    /// Reads the answer to a `Ping`. Methods this dispatcher does not know
+    /// about are skipped.
+    pub fn read<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let version = String::from_utf8(read_bytes(reader)?)
+            .map_err(|_| invalid_data("invalid worker version"))?;
+        let capabilities = read_bytes(reader)?
+            .into_iter()
+            .filter_map(|method| WorkerMethod::try_from(method).ok())
+            .collect();
+        let capacity = Capacity::read(reader)?;
+        Ok(WorkerInfo {
+            version,
+            capabilities,
+            capacity,
+        })
+    }
+}
+
+/// Time a worker gets to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of times each worker is tried while the pool is set up.
+const CONNECT_ATTEMPTS: usize = 5;
+
+/// The requests of a [`Link`] waiting for their answer.
+#[derive(Default)]
+struct Pending {
+    /// The ID the next request is sent with.
+    next_id: RequestId,
+    /// Where to pass the answers to each request on, the acknowledgements of
+    /// its chunks first.
+    waiting: HashMap<RequestId, mpsc::UnboundedSender<io::Result<Response>>>,
+    /// Why the link broke, once it has.
+    broken: Option<(io::ErrorKind, String)>,
+}
+
+impl Pending {
+    /// Fails every waiting request with `error`, and every later one too.
+    fn fail(&mut self, error: &io::Error) {
+        for (_, waiting) in self.waiting.drain() {
+            let _ = waiting.send(Err(io::Error::new(error.kind(), error.to_string())));
+        }
+        if self.broken.is_none() {
+            self.broken = Some((error.kind(), error.to_string()));
+        }
+    }
+}
+
+/// An open, authenticated connection to a worker.
+///
+/// Requests are written under a lock so that their frames do not interleave,
+/// and a task reads the answers and passes each on to the request it
+/// answers, so any number of requests can wait on the link at once.
+struct Link {
+    writer: AsyncMutex<(BufWriter<OwnedWriteHalf>, Session)>,
+    pending: Arc<Mutex<Pending>>,
+    router: JoinHandle<()>,
+}
+
+impl Link {
+    fn new(stream: TcpStream, session: Session) -> Self {
+        let (reader, writer) = stream.into_split();
+        let (sending, receiving) = session.split();
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let router = tokio::spawn(Link::route(
+            BufReader::new(reader),
+            receiving,
+            pending.clone(),
+        ));
+        Link {
+            writer: AsyncMutex::new((BufWriter::new(writer), sending)),
+            pending,
+            router,
+        }
+    }
+
+    /// Passes every answer read from `reader` on to the request it answers.
+    /// Once the connection breaks, every waiting request fails.
+    async fn route(
+        mut reader: BufReader<OwnedReadHalf>,
+        mut session: Session,
+        pending: Arc<Mutex<Pending>>,
+    ) {
+        let error = loop {
+            match read_response(&mut reader, &mut session).await {
+                Ok(response) => {
+                    // Nobody waits for the late answer to a request that
+                    // missed its deadline, it is dropped.
+                    let mut pending = pending.lock().unwrap();
+                    let waiting = match response.status {
+                        WorkerStatus::Received => pending.waiting.get(&response.id).cloned(),
+                        _ => pending.waiting.remove(&response.id),
+                    };
+                    if let Some(waiting) = waiting {
+                        let _ = waiting.send(Ok(response));
+                    }
+                }
+                Err(error) => break error,
+            }
+        };
+
+        pending.lock().unwrap().fail(&error);
+    }
+
+    fn is_broken(&self) -> bool {
+        self.pending.lock().unwrap().broken.is_some()
+    }
+
+    /// Stops reading answers and fails every waiting request.
+    fn close(&self) {
+        self.router.abort();
+        let error = io::Error::new(io::ErrorKind::ConnectionAborted, "connection dropped");
+        self.pending.lock().unwrap().fail(&error);
+    }
+
+    /// Sends `payload` for `method` and waits for the answer.
+    ///
+    /// The payload is encoded and goes out in chunks, with at most `window`
+    /// bytes the worker has not acknowledged yet, and `progress` is given the
+    /// number of bytes acknowledged after each acknowledgement.
+    async fn exchange(
+        &self,
+        method: WorkerMethod,
+        payload: &Payload<'_>,
+        window: usize,
+        progress: impl Fn(u64),
+    ) -> io::Result<Response> {
+        let (id, mut answers) = {
+            let mut pending = self.pending.lock().unwrap();
+            if let Some((kind, message)) = &pending.broken {
+                return Err(io::Error::new(*kind, message.clone()));
+            }
+            let id = pending.next_id;
+            pending.next_id += 1;
+            let (sender, answers) = mpsc::unbounded_channel();
+            pending.waiting.insert(id, sender);
+            (id, answers)
+        };
+
+        let chunk_len = CHUNK_LEN.min(window);
+        let mut chunks = payload.chunks(chunk_len);
+        let mut sent = 0;
+        let mut received = 0;
+        let mut last = false;
+        loop {
+            while !last && sent - received + chunk_len <= window {
+                let chunk = chunks
+                    .next()
+                    .expect("a payload has chunks up to its last one");
+                let written = match chunk {
+                    Ok(chunk) => {
+                        sent += chunk.len();
+                        last = sent == payload.len();
+                        let header = ChunkHeader {
+                            method: method.into(),
+                            id,
+                            last,
+                        };
+                        // Each chunk is written under the lock on its own, so
+                        // other requests get through while this one is sent.
+                        let mut writer = self.writer.lock().await;
+                        let (stream, session) = &mut *writer;
+                        write_request_chunk(stream, session, header, &chunk).await
+                    }
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = written {
+                    self.pending.lock().unwrap().waiting.remove(&id);
+                    return Err(error);
+                }
+            }
+
+            let response = match answers.recv().await {
+                Some(response) => response?,
+                None => return Err(io::ErrorKind::ConnectionAborted.into()),
+            };
+            if response.status != WorkerStatus::Received {
+                return Ok(response);
+            }
+            received = match <[u8; 8]>::try_from(&response.payload[..]) {
+                Ok(count) if u64::from_be_bytes(count) <= sent as u64 => {
+                    u64::from_be_bytes(count) as usize
+                }
+                _ => {
+                    return Err(invalid_data(
+                        "the worker acknowledged bytes it was not sent",
+                    ))
+                }
+            };
+            progress(received as u64);
+        }
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.router.abort();
+    }
+}
+
+/// How far the upload of a request to a worker got, as passed to the
+/// callback set with [`Dispatcher::with_progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// Address of the worker.
+    pub addr: SocketAddr,
+    /// The method of the request.
+    pub method: WorkerMethod,
+    /// Bytes of the request the worker acknowledged.
+    pub received: u64,
+    /// Bytes of the request.
+    pub total: u64,
+}
+
+/// A callback told how far uploads got.
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// An error the dispatcher worked around rather than failing the request at
+/// hand, as passed to the callback set with [`Dispatcher::with_notices`].
+#[derive(Debug)]
+pub struct Notice<'a> {
+    /// What went wrong.
+    pub error: &'a (dyn error::Error + 'a),
+    /// What was done about it, such as running the tasks of a worker on the
+    /// others.
+    pub fallback: String,
+}
+
+impl fmt::Display for Notice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.error, self.fallback)
+    }
 }
 
-pub trait Taskable {
-    fn method(&self) -> WorkerMethod;
-    fn to_bytes(&self) -> Vec<u8>;
-    fn from_bytes(&self, bytes: Vec<u8>) -> Self;
+/// A callback told about the errors worked around.
+pub type NoticeCallback = Arc<dyn Fn(&Notice<'_>) + Send + Sync>;
+
+/// A connection to a single worker of the pool.
+///
+/// The connection is opened lazily and shared by every request sent to the
+/// worker, each of which is told apart from the others by its ID, so several
+/// jobs can keep the worker busy at once. It is dropped as soon as an
+/// exchange fails or misses its deadline, failing the other requests waiting
+/// on it too. The next request reconnects, and authenticates again with
+/// `key`.
+///
+/// Requests are sent with at most `window` bytes in flight, see
+/// [`net`](super::net).
+#[allow(missing_debug_implementations)]
+pub struct WorkerConnection {
+    pub addr: SocketAddr,
+    key: PresharedKey,
+    link: Mutex<Option<Arc<Link>>>,
+    /// Held while connecting, so that concurrent requests open one
+    /// connection between them.
+    connecting: AsyncMutex<()>,
+    /// The rows of the SRS the worker holds, if it was sent a shard. A
+    /// worker may have restarted behind a dropped connection, so the shard
+    /// is forgotten along with the connection.
+    shard: Mutex<Option<ShardRows>>,
+    /// The hashes of the params the worker is known to hold, forgotten along
+    /// with the connection for the same reason.
+    params: Mutex<HashSet<ParamsHash>>,
+    /// The hashes of the proving keys the worker is known to hold, likewise.
+    keys: Mutex<HashSet<KeyHash>>,
+    /// Bytes of a request sent ahead of the acknowledgements of the worker.
+    window: usize,
+    /// Told how far each upload got, if set.
+    progress: Option<ProgressCallback>,
+    /// The resources the worker last reported. They belong to the machine
+    /// rather than the connection, so they are kept when it drops.
+    capacity: Mutex<Option<Capacity>>,
 }
 
+impl WorkerConnection {
+    /// Creates a connection to the worker at `addr`, without connecting yet.
+    pub fn new(addr: SocketAddr) -> Self {
+        WorkerConnection::with_key(addr, PresharedKey::default())
+    }
+
+    /// Creates a connection to the worker at `addr` authenticated with
+    /// `key`, without connecting yet.
+    pub fn with_key(addr: SocketAddr, key: PresharedKey) -> Self {
+        WorkerConnection {
+            addr,
+            key,
+            link: Mutex::new(None),
+            connecting: AsyncMutex::new(()),
+            shard: Mutex::new(None),
+            params: Mutex::new(HashSet::new()),
+            keys: Mutex::new(HashSet::new()),
+            window: DEFAULT_WINDOW,
+            progress: None,
+            capacity: Mutex::new(None),
+        }
+    }
+
+    /// Sends requests with at most `window` bytes in flight, at least one.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Returns whether the connection to the worker is currently open.
+    pub fn is_connected(&self) -> bool {
+        matches!(&*self.link.lock().unwrap(), Some(link) if !link.is_broken())
+    }
+
+    /// Returns the rows of the SRS the worker holds, if any.
+    pub fn shard(&self) -> Option<ShardRows> {
+        self.shard.lock().unwrap().clone()
+    }
+
+    /// Returns the resources the worker last reported, if it was asked.
+    pub fn capacity(&self) -> Option<Capacity> {
+        *self.capacity.lock().unwrap()
+    }
+
+    /// Asks the worker for its version, capabilities and resources, and
+    /// keeps the resources to size its share of the work.
+    pub async fn info(&self, deadline: Duration) -> Result<WorkerInfo, WorkerError> {
+        let info = self
+            .request(WorkerMethod::Ping, &Payload::default(), deadline)
+            .await?;
+        let info =
+            WorkerInfo::read(&mut &info[..]).map_err(|error| WorkerError::InvalidResponse {
+                addr: self.addr,
+                message: error.to_string(),
+            })?;
+        *self.capacity.lock().unwrap() = Some(info.capacity);
+        Ok(info)
+    }
+
+    /// Opens and authenticates the connection to the worker, unless it is
+    /// already open.
+    pub async fn connect(&self) -> Result<(), WorkerError> {
+        self.link().await.map(|_| ())
+    }
+
+    async fn link(&self) -> Result<Arc<Link>, WorkerError> {
+        let _connecting = self.connecting.lock().await;
+        if let Some(link) = &*self.link.lock().unwrap() {
+            if !link.is_broken() {
+                return Ok(link.clone());
+            }
+        }
+        self.disconnect(None);
+
+        let addr = self.addr;
+        let key = &self.key;
+        let open = async {
+            let mut stream = TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            let session = auth::connect(&mut stream, key).await?;
+            Ok((stream, session))
+        };
+        let (stream, session) = match timeout(CONNECT_TIMEOUT, open).await {
+            Ok(stream) => stream.map_err(|error| WorkerError::from_io(addr, error))?,
+            Err(_) => {
+                return Err(WorkerError::Timeout {
+                    addr,
+                    after: CONNECT_TIMEOUT,
+                })
+            }
+        };
+        let link = Arc::new(Link::new(stream, session));
+        *self.link.lock().unwrap() = Some(link.clone());
+        Ok(link)
+    }
+
+    /// Drops the connection, unless it was replaced by another one than
+    /// `link` already, and forgets what the worker holds.
+    fn disconnect(&self, link: Option<&Arc<Link>>) {
+        let mut current = self.link.lock().unwrap();
+        let replaced = match (&*current, link) {
+            (Some(current), Some(link)) => !Arc::ptr_eq(current, link),
+            _ => false,
+        };
+        if !replaced {
+            if let Some(link) = current.take() {
+                link.close();
+            }
+            *self.shard.lock().unwrap() = None;
+            self.params.lock().unwrap().clear();
+            self.keys.lock().unwrap().clear();
+        }
+    }
+
+    /// Sends `payload` for `method` and waits at most `deadline` for the
+    /// answer of the worker, returning the payload of the answer when the
+    /// worker reports success.
+    pub async fn request(
+        &self,
+        method: WorkerMethod,
+        payload: &Payload<'_>,
+        deadline: Duration,
+    ) -> Result<Vec<u8>, WorkerError> {
+        let addr = self.addr;
+        let link = self.link().await?;
+        let progress = |received| {
+            if let Some(progress) = &self.progress {
+                progress(&Progress {
+                    addr,
+                    method,
+                    received,
+                    total: payload.len() as u64,
+                });
+            }
+        };
+        let exchange = link.exchange(method, payload, self.window, progress);
+        let response = match timeout(deadline, exchange).await {
+            Ok(response) => response.map_err(|error| WorkerError::from_io(addr, error)),
+            Err(_) => Err(WorkerError::Timeout {
+                addr,
+                after: deadline,
+            }),
+        };
+
+        let response = response.and_then(|response| match response.status {
+            WorkerStatus::ErrorUnauthenticated => Err(WorkerError::Unauthenticated {
+                addr,
+                message: response.message,
+            }),
+            _ => Ok(response),
+        });
+
+        match response {
+            Ok(response) => match response.status {
+                WorkerStatus::Ok => Ok(response.payload),
+                status => Err(WorkerError::Status {
+                    addr,
+                    status,
+                    message: response.message,
+                }),
+            },
+            Err(error) => {
+                // The stream may be half way through a frame, don't reuse it.
+                self.disconnect(Some(&link));
+                Err(error)
+            }
+        }
+    }
+
+    fn holds_params(&self, hash: &ParamsHash) -> bool {
+        self.params.lock().unwrap().contains(hash)
+    }
+
+    fn add_params(&self, hash: ParamsHash) {
+        self.params.lock().unwrap().insert(hash);
+    }
+
+    fn holds_key(&self, hash: &KeyHash) -> bool {
+        self.keys.lock().unwrap().contains(hash)
+    }
+
+    fn add_key(&self, hash: KeyHash) {
+        self.keys.lock().unwrap().insert(hash);
+    }
+}
+
+/// Number of ranges the share of each worker is cut into by
+/// [`Dispatcher::split`].
+const STEAL_PARTS: usize = 4;
+
+/// The tasks of a job left to run, queued by the worker they are paired
+/// with, and the answers to those already run.
+struct Schedule<T> {
+    queues: Vec<VecDeque<usize>>,
+    alive: Vec<bool>,
+    /// Tasks handed to a worker and not finished yet.
+    running: usize,
+    /// Set once a task failed the job.
+    aborted: bool,
+    results: Vec<Option<T>>,
+}
+
+/// What a worker does next.
+enum Next {
+    Run(usize),
+    /// Waits for a running task to finish, as it may be handed back.
+    Wait,
+    Done,
+}
+
+impl<T> Schedule<T> {
+    fn new<P>(workers: usize, tasks: &[(usize, P)]) -> Self {
+        let mut queues = vec![VecDeque::new(); workers];
+        for (task, (worker, _)) in tasks.iter().enumerate() {
+            queues[*worker].push_back(task);
+        }
+        Schedule {
+            queues,
+            alive: vec![true; workers],
+            running: 0,
+            aborted: false,
+            results: tasks.iter().map(|_| None).collect(),
+        }
+    }
+
+    /// Hands `worker` the first task queued for it. Once it has none left,
+    /// portable tasks are taken from the back of the longest queue, the one
+    /// of a worker that failed included.
+    fn next(&mut self, worker: usize, portable: bool) -> Next {
+        if self.aborted || !self.alive[worker] {
+            return Next::Done;
+        }
+        let task = self.queues[worker].pop_front().or_else(|| {
+            if !portable {
+                return None;
+            }
+            let longest = self.queues.iter_mut().max_by_key(|queue| queue.len())?;
+            longest.pop_back()
+        });
+        match task {
+            Some(task) => {
+                self.running += 1;
+                Next::Run(task)
+            }
+            None if portable && self.running > 0 => Next::Wait,
+            None => Next::Done,
+        }
+    }
+
+    fn finish(&mut self, task: usize, result: T) {
+        self.running -= 1;
+        self.results[task] = Some(result);
+    }
+
+    /// Leaves `worker` out for the rest of the job and queues `task` back
+    /// first, returning the number of tasks the worker leaves unfinished.
+    fn hand_back(&mut self, worker: usize, task: usize) -> usize {
+        self.running -= 1;
+        self.alive[worker] = false;
+        self.queues[worker].push_front(task);
+        self.queues[worker].len()
+    }
+
+    fn abort(&mut self) {
+        self.running -= 1;
+        self.aborted = true;
+    }
+}
+
+/// Runs jobs on a pool of workers.
+///
+/// Jobs only borrow the dispatcher, so several keygens or proofs can run on
+/// the same pool at once, their tasks sharing the connections to the
+/// workers.
 #[allow(missing_debug_implementations)]
 pub struct Dispatcher {
-    pub workers: Vec<StubbornIo<TcpStream, SocketAddr>>,
+    pub config: PoolConfig,
+    pub workers: Vec<WorkerConnection>,
+    pub(super) notices: Option<NoticeCallback>,
 }
 
 impl Dispatcher {
-    pub async fn new() -> Self {
-        let mut dispatcher = Dispatcher {
-            workers: Vec::new(),
+    /// Connects to every worker of the pool described by `config`.
+    ///
+    /// Workers that cannot be reached are not an error: they are tried again
+    /// by the next job, and their share of the work goes to the others.
+    pub async fn new(config: PoolConfig) -> io::Result<Self> {
+        config.validate()?;
+
+        let key = config.preshared_key();
+        let workers = config
+            .workers
+            .iter()
+            .map(|worker| {
+                worker.socket_addr().map(|addr| {
+                    WorkerConnection::with_key(addr, key.clone()).with_window(config.window())
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let dispatcher = Dispatcher {
+            config,
+            workers,
+            notices: None,
         };
 
         // Set up the active worker connections
         dispatcher.init_worker_pool().await;
-        dispatcher
+        Ok(dispatcher)
+    }
+
+    /// Has `callback` told how far every request to a worker got, each time
+    /// the worker acknowledges part of it. Requests sent in a single chunk
+    /// are not reported.
+    pub fn with_progress(mut self, callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        let callback: ProgressCallback = Arc::new(callback);
+        for worker in &mut self.workers {
+            worker.progress = Some(callback.clone());
+        }
+        self
+    }
+
+    /// Has `callback` told about the errors of workers that are worked around
+    /// rather than returned, such as a worker dropping out of the pool in the
+    /// middle of a job. Nothing is reported without a callback.
+    ///
+    /// The workers that [`Dispatcher::new`] could not reach are not reported,
+    /// [`Dispatcher::ping`] returns their errors.
+    pub fn with_notices(mut self, callback: impl Fn(&Notice<'_>) + Send + Sync + 'static) -> Self {
+        self.notices = Some(Arc::new(callback));
+        self
+    }
+
+    /// Tells the callback set with [`Dispatcher::with_notices`] about
+    /// `error`, worked around as described by `fallback`.
+    fn notify(&self, error: &dyn error::Error, fallback: impl FnOnce() -> String) {
+        if let Some(notices) = &self.notices {
+            notices(&Notice {
+                error,
+                fallback: fallback(),
+            });
+        }
+    }
+
+    /// Asks every worker of the pool for its version, capabilities and
+    /// resources.
+    pub async fn ping(&self) -> Result<Vec<WorkerInfo>, Error> {
+        let deadline = self.config.task_timeout();
+        join_all(self.workers.iter().map(|worker| worker.info(deadline)))
+            .await
+            .into_iter()
+            .map(|info| info.map_err(Error::from))
+            .collect()
+    }
+
+    /// Returns the share of every job each worker gets: its weight in the
+    /// pool configuration scaled by the resources it reported, see
+    /// [`capacity_weights`].
+    pub fn weights(&self) -> Vec<u32> {
+        let capacities = self
+            .workers
+            .iter()
+            .map(|worker| worker.capacity())
+            .collect::<Vec<_>>();
+        capacity_weights(&self.config.weights(), &capacities)
+    }
+
+    /// Makes sure every worker holds `params`, and returns the hash tasks
+    /// refer to them by.
+    ///
+    /// Each worker is asked whether it holds them already, and is only sent
+    /// them if it does not. Workers that cannot be sent them are reported,
+    /// see [`Dispatcher::with_notices`], and answer the tasks referring to
+    /// them with
+    /// [`WorkerStatus::ErrorUnknownParams`], those tasks then go to the other
+    /// workers.
+    pub async fn upload_params<P: SerdeParams>(&self, params: &P) -> ParamsHash {
+        let hash = ParamsHash::of_params(params);
+        let deadline = self.config.task_timeout();
+        let missing = join_all(self.workers.iter().map(|worker| async move {
+            if worker.holds_params(&hash) {
+                return None;
+            }
+            match worker
+                .request(
+                    WorkerMethod::HasParams,
+                    &Payload::from(&hash.0[..]),
+                    deadline,
+                )
+                .await
+            {
+                Ok(answer) if answer[..] == [1] => {
+                    worker.add_params(hash);
+                    None
+                }
+                Ok(_) => Some(worker),
+                Err(error) => {
+                    self.notify(&error, || format!("params {} stay off it", hash));
+                    None
+                }
+            }
+        }))
+        .await;
+
+        // Only encode the params when a worker lacks them.
+        let missing = missing.into_iter().flatten().collect::<Vec<_>>();
+        if missing.is_empty() {
+            return hash;
+        }
+
+        // The params are encoded once for all the workers lacking them.
+        let mut payload = vec![];
+        write_upload(&mut payload, params, TASK_FORMAT).expect("writing to a Vec cannot fail");
+        let payload = &Payload::from(payload);
+        join_all(missing.into_iter().map(|worker| async move {
+            match worker
+                .request(WorkerMethod::UploadParams, payload, deadline)
+                .await
+            {
+                Ok(answer) if answer[..] == hash.0 => {
+                    worker.add_params(hash);
+                }
+                Ok(_) => self.notify(
+                    &WorkerError::InvalidResponse {
+                        addr: worker.addr,
+                        message: "params stored under another hash".to_string(),
+                    },
+                    || format!("params {} stay off it", hash),
+                ),
+                Err(error) => self.notify(&error, || format!("params {} stay off it", hash)),
+            }
+        }))
+        .await;
+
+        hash
     }
 
     /// Initiates the distributed keygen operation.
-    pub async fn keygen<'params, C: CurveAffine, P: Params<'params, C>>(
-        &mut self,
+    ///
+    /// The permutation columns are split into contiguous ranges, a few per
+    /// worker and sized by [`Dispatcher::weights`], and the commitments are
+    /// put back into column order once every range has been committed to.
+    ///
+    /// `params_hash` is the hash [`Dispatcher::upload_params`] returned for
+    /// `params`, as for every method computing with params on the workers.
+    pub async fn keygen<'params, C, P>(
+        &self,
+        params: &P,
+        params_hash: ParamsHash,
+        domain: &EvaluationDomain<C::Scalar>,
+        p: &Argument,
+        mapping: &[Vec<(usize, usize)>],
+    ) -> Result<Vec<C>, Error>
+    where
+        C: SerdeCurveAffine,
+        P: Params<'params, C> + SerdeParams,
+    {
+        let shards = self.split(p.ncolumns());
+
+        let tasks = shards
+            .iter()
+            .map(|(worker, columns)| {
+                let task = KeygenTask::<C, P>::new(
+                    params,
+                    params_hash,
+                    domain,
+                    p,
+                    mapping,
+                    columns.clone(),
+                );
+                (*worker, task)
+            })
+            .collect::<Vec<_>>();
+
+        let check = self.config.check_commitments;
+        let commitments = self
+            .dispatch(
+                WorkerMethod::KeyGen,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |shard, commitments| {
+                    // Check that the worker answered for exactly the columns of
+                    // the shard before the shards are stitched back together.
+                    let columns = &shards[shard].1;
+                    let commitments = read_commitments::<_, C>(&mut &commitments[..], TASK_FORMAT)
+                        .and_then(|commitments| {
+                            assemble_commitments(
+                                columns.len(),
+                                commitments.into_iter().map(|(column, commitment)| {
+                                    (column.wrapping_sub(columns.start), commitment)
+                                }),
+                            )
+                        })?;
+                    if check {
+                        let polys = permutation_polys(
+                            params.n() as usize,
+                            domain,
+                            p,
+                            columns.clone(),
+                            |i, j| mapping[i][j],
+                        );
+                        check_commitments(
+                            params,
+                            polys.iter().zip(&commitments).map(|(poly, &commitment)| {
+                                (&poly[..], Blind::default(), commitment)
+                            }),
+                        )?;
+                    }
+                    Ok(commitments)
+                },
+                |shard| {
+                    commit_permutations(params, domain, p, shards[shard].1.clone(), |i, j| {
+                        mapping[i][j]
+                    })
+                },
+            )
+            .await?;
+
+        // The shards cover consecutive column ranges, in order.
+        Ok(commitments.into_iter().flatten().collect())
+    }
+
+    /// Commits to the Lagrange polynomials `polys` with the matching `blinds`.
+    ///
+    /// The polynomials are split into contiguous batches, a few per worker
+    /// and sized by [`Dispatcher::weights`], and the commitments are returned
+    /// in the order of `polys`.
+    pub async fn commit_lagrange<'params, C, P>(
+        &self,
         params: &'params P,
-        domain: &'params EvaluationDomain<C::Scalar>,
-        p: &'params Argument,
-        mapping: &Vec<Vec<(usize, usize)>>,
-    ) -> Vec<C> {
-        let task = KeygenTaskKZG::<C, P>::new(params, domain, p, mapping.clone());
-        let commitments = join_all(self.workers.iter_mut().map(|worker| async {
-            // Dump the method over
-            worker.write_u8(WorkerMethod::KeyGen as u8).await.unwrap();
-
-            // Drop the payload
-            worker
-                .write_all(to_bytes(task.clone()).as_slice())
-                .await
-                .unwrap();
+        params_hash: ParamsHash,
+        polys: &[Polynomial<C::Scalar, LagrangeCoeff>],
+        blinds: &[Blind<C::Scalar>],
+    ) -> Result<Vec<C>, Error>
+    where
+        C: SerdeCurveAffine,
+        C::Scalar: SerdePrimeField,
+        P: Params<'params, C> + SerdeParams,
+    {
+        let batches = self.split(polys.len());
 
-            // Flush the buffer
-            worker.flush().await.unwrap();
+        let tasks = batches
+            .iter()
+            .map(|(worker, batch)| {
+                let task = CommitTask::<C, P>::new(
+                    params,
+                    params_hash,
+                    &polys[batch.clone()],
+                    &blinds[batch.clone()],
+                );
+                (*worker, task)
+            })
+            .collect::<Vec<_>>();
 
-            // Prepare to receive the commitments
-            let mut cs = [0u8; core::mem::size_of::<G1Affine>()];
+        let check = self.config.check_commitments;
+        let commitments = self
+            .dispatch(
+                WorkerMethod::Commit,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |batch, commitments| {
+                    let commitments = read_points::<_, C>(&mut &commitments[..], TASK_FORMAT)?;
+                    let batch = batches[batch].1.clone();
+                    if commitments.len() != batch.len() {
+                        return Err(invalid_data(format!(
+                            "expected {} commitments, got {}",
+                            batch.len(),
+                            commitments.len()
+                        )));
+                    }
+                    if check {
+                        check_commitments(
+                            params,
+                            polys[batch.clone()]
+                                .iter()
+                                .zip(&blinds[batch])
+                                .zip(&commitments)
+                                .map(|((poly, &blind), &commitment)| {
+                                    (&poly[..], blind, commitment)
+                                }),
+                        )?;
+                    }
+                    Ok(commitments)
+                },
+                |batch| {
+                    let batch = batches[batch].1.clone();
+                    commit_lagrange(params, &polys[batch.clone()], &blinds[batch])
+                },
+            )
+            .await?;
 
-            // Read the output from the worker
-            worker.read_exact(&mut cs).await.unwrap();
+        Ok(commitments.into_iter().flatten().collect())
+    }
 
-            // NOTE: This [0] will be removed later when we recieve from multiple sources
-            // This method will need to handle proper ordering as well of the commitments
-            cs.cast::<C>()[0]
-        }))
+    /// Permutes and commits to `lookups`, as
+    /// [`LookupCommitter::permute_lookups`](super::plonk::lookup::LookupCommitter::permute_lookups)
+    /// describes.
+    pub async fn permute_lookups<'params, C, P>(
+        &self,
+        params: &'params P,
+        params_hash: ParamsHash,
+        lookups: &[PermuteInput<C::Scalar>],
+    ) -> Result<Vec<Option<PermuteOutput<C>>>, Error>
+    where
+        C: SerdeCurveAffine,
+        C::Scalar: SerdePrimeField,
+        P: Params<'params, C> + SerdeParams,
+    {
+        let batches = self.split(lookups.len());
+
+        let tasks = batches
+            .iter()
+            .map(|(worker, batch)| {
+                let task =
+                    LookupPermuteTask::<C, P>::new(params, params_hash, &lookups[batch.clone()]);
+                (*worker, task)
+            })
+            .collect::<Vec<_>>();
+
+        let check = self.config.check_commitments;
+        let permuted = self
+            .dispatch(
+                WorkerMethod::LookupPermute,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |batch, permuted| {
+                    let permuted = read_permuted::<_, C>(&mut &permuted[..], TASK_FORMAT)?;
+                    let batch = &lookups[batches[batch].1.clone()];
+                    if permuted.len() != batch.len() {
+                        return Err(invalid_data(format!(
+                            "expected {} permuted lookups, got {}",
+                            batch.len(),
+                            permuted.len()
+                        )));
+                    }
+                    if check {
+                        check_commitments(
+                            params,
+                            batch.iter().zip(&permuted).flat_map(|(lookup, permuted)| {
+                                permuted.iter().flat_map(move |permuted| {
+                                    [
+                                        (
+                                            &permuted.permuted_input[..],
+                                            lookup.input_blind,
+                                            permuted.input_commitment,
+                                        ),
+                                        (
+                                            &permuted.permuted_table[..],
+                                            lookup.table_blind,
+                                            permuted.table_commitment,
+                                        ),
+                                    ]
+                                })
+                            }),
+                        )?;
+                    }
+                    Ok(permuted)
+                },
+                |batch| {
+                    lookups[batches[batch].1.clone()]
+                        .iter()
+                        .map(|lookup| permute_lookup(params, lookup))
+                        .collect()
+                },
+            )
+            .await?;
+
+        Ok(permuted.into_iter().flatten().collect())
+    }
+
+    /// Computes and commits to the grand products of `lookups`, as
+    /// [`LookupCommitter::lookup_products`](super::plonk::lookup::LookupCommitter::lookup_products)
+    /// describes.
+    pub async fn lookup_products<'params, C, P>(
+        &self,
+        params: &'params P,
+        params_hash: ParamsHash,
+        lookups: &[ProductInput<C::Scalar>],
+        beta: C::Scalar,
+        gamma: C::Scalar,
+    ) -> Result<Vec<ProductOutput<C>>, Error>
+    where
+        C: SerdeCurveAffine,
+        C::Scalar: SerdePrimeField,
+        P: Params<'params, C> + SerdeParams,
+    {
+        let batches = self.split(lookups.len());
+
+        let tasks = batches
+            .iter()
+            .map(|(worker, batch)| {
+                let task = LookupProductTask::<C, P>::new(
+                    params,
+                    params_hash,
+                    &lookups[batch.clone()],
+                    beta,
+                    gamma,
+                );
+                (*worker, task)
+            })
+            .collect::<Vec<_>>();
+
+        let check = self.config.check_commitments;
+        let products = self
+            .dispatch(
+                WorkerMethod::LookupProduct,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |batch, products| {
+                    let products = read_products::<_, C>(&mut &products[..], TASK_FORMAT)?;
+                    let batch = &lookups[batches[batch].1.clone()];
+                    if products.len() != batch.len() {
+                        return Err(invalid_data(format!(
+                            "expected {} lookup products, got {}",
+                            batch.len(),
+                            products.len()
+                        )));
+                    }
+                    if check {
+                        check_commitments(
+                            params,
+                            batch.iter().zip(&products).map(|(lookup, product)| {
+                                (&product.product[..], lookup.blind, product.commitment)
+                            }),
+                        )?;
+                    }
+                    Ok(products)
+                },
+                |batch| {
+                    lookups[batches[batch].1.clone()]
+                        .iter()
+                        .map(|lookup| lookup_product(params, lookup, beta, gamma))
+                        .collect()
+                },
+            )
+            .await?;
+
+        Ok(products.into_iter().flatten().collect())
+    }
+
+    /// Computes and commits to the grand products of the permutation argument,
+    /// as [`PermutationCommitter::permutation_products`](super::plonk::permutation::prover::PermutationCommitter::permutation_products)
+    /// describes.
+    ///
+    /// The column sets are computed independently of each other, and chained
+    /// once they are all back.
+    pub async fn permutation_products<'params, C, P>(
+        &self,
+        params: &'params P,
+        params_hash: ParamsHash,
+        chunks: &[PermutationChunk<'_, C::Scalar>],
+        sets: usize,
+        beta: C::Scalar,
+        gamma: C::Scalar,
+        omega: C::Scalar,
+    ) -> Result<Vec<PermutationProduct<C>>, Error>
+    where
+        C: SerdeCurveAffine,
+        C::Scalar: SerdePrimeField,
+        P: Params<'params, C> + SerdeParams,
+    {
+        let n = params.n() as usize;
+        let check = self.config.check_commitments;
+        let batches = self.split(chunks.len());
+
+        let tasks = batches
+            .iter()
+            .map(|(worker, batch)| {
+                let task = PermutationProductTask::<C, P>::new(
+                    params,
+                    params_hash,
+                    &chunks[batch.clone()],
+                    beta,
+                    gamma,
+                    omega,
+                );
+                (*worker, task)
+            })
+            .collect::<Vec<_>>();
+
+        let products = self
+            .dispatch(
+                WorkerMethod::PermutationProduct,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |batch, products| {
+                    let products = read_chunk_products::<_, C>(&mut &products[..], TASK_FORMAT)?;
+                    let batch = &chunks[batches[batch].1.clone()];
+                    if products.len() != batch.len() {
+                        return Err(invalid_data(format!(
+                            "expected {} permutation products, got {}",
+                            batch.len(),
+                            products.len()
+                        )));
+                    }
+                    for (chunk, product) in batch.iter().zip(products.iter()) {
+                        if product.product.len() != n - chunk.blinding.len() {
+                            return Err(invalid_data(format!(
+                                "permutation product of {} rows, expected {}",
+                                product.product.len(),
+                                n - chunk.blinding.len()
+                            )));
+                        }
+                    }
+                    if check {
+                        // The product is committed to without blind, and the
+                        // blinding rows apart, after as many zeros.
+                        check_commitments(
+                            params,
+                            batch.iter().zip(&products).flat_map(|(chunk, product)| {
+                                let mut blinding = vec![C::Scalar::ZERO; product.product.len()];
+                                blinding.extend_from_slice(&chunk.blinding);
+                                [
+                                    (
+                                        Cow::Borrowed(&product.product[..]),
+                                        Blind(C::Scalar::ZERO),
+                                        product.product_commitment,
+                                    ),
+                                    (
+                                        Cow::Owned(blinding),
+                                        chunk.blind,
+                                        product.blinding_commitment,
+                                    ),
+                                ]
+                            }),
+                        )?;
+                    }
+                    Ok(products)
+                },
+                |batch| {
+                    chunks[batches[batch].1.clone()]
+                        .iter()
+                        .map(|chunk| chunk_product(params, chunk, beta, gamma, omega))
+                        .collect()
+                },
+            )
+            .await?;
+
+        Ok(chain_products(
+            chunks,
+            products.into_iter().flatten().collect(),
+            sets,
+        ))
+    }
+
+    /// Computes the sum of `coeffs[i] * bases[i]`.
+    ///
+    /// The terms are split into contiguous ranges, a few per worker and sized
+    /// by [`Dispatcher::weights`], and the partial sums are added together.
+    pub async fn multiexp<C>(&self, coeffs: &[C::Scalar], bases: &[C]) -> Result<C::CurveExt, Error>
+    where
+        C: SerdeCurveAffine,
+        C::Scalar: SerdePrimeField,
+    {
+        assert_eq!(coeffs.len(), bases.len());
+        let ranges = self.split(coeffs.len());
+
+        let tasks = ranges
+            .iter()
+            .map(|(worker, range)| {
+                let task = MultiexpTask::<C>::new(&coeffs[range.clone()], &bases[range.clone()]);
+                (*worker, task)
+            })
+            .collect::<Vec<_>>();
+
+        let partials = self
+            .dispatch(
+                WorkerMethod::Multiexp,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |_, partial| {
+                    let partial = read_points::<_, C>(&mut &partial[..], TASK_FORMAT)?;
+                    if partial.len() != 1 {
+                        return Err(invalid_data(format!(
+                            "expected a single partial sum, got {}",
+                            partial.len()
+                        )));
+                    }
+                    Ok(partial[0].to_curve())
+                },
+                |range| {
+                    let range = ranges[range].1.clone();
+                    best_multiexp(&coeffs[range.clone()], &bases[range])
+                },
+            )
+            .await?;
+
+        Ok(partials
+            .into_iter()
+            .fold(C::CurveExt::identity(), |acc, partial| acc + partial))
+    }
+
+    /// Runs `tasks` on the pool, and returns their outputs in the order of
+    /// `tasks`.
+    ///
+    /// The tasks are shared between the workers like the columns of keygen,
+    /// and those a worker does not have registered go to the others. Tasks
+    /// no worker is left to run are run in this process.
+    pub async fn run_tasks<T: Taskable>(&self, tasks: &[T]) -> Result<Vec<T::Output>, Error> {
+        let tasks = self
+            .split(tasks.len())
+            .into_iter()
+            .flat_map(|(worker, range)| range.map(move |task| (worker, &tasks[task])))
+            .collect::<Vec<_>>();
+
+        let outputs = self
+            .dispatch(
+                WorkerMethod::Task,
+                &tasks,
+                true,
+                |task, payload| write_task(payload, *task),
+                |_, output| T::decode_output(&mut &output[..]).map(Ok),
+                |task| tasks[task].1.execute(),
+            )
+            .await?;
+        // Only the tasks run in this process can fail here.
+        Ok(outputs.into_iter().collect::<io::Result<_>>()?)
+    }
+
+    /// Makes sure every worker holds `pk`, and returns the hash `Prove`
+    /// requests refer to it by.
+    ///
+    /// The key is sent to each worker once per connection. Workers that
+    /// cannot be sent it are reported, see [`Dispatcher::with_notices`], and
+    /// answer the requests referring to it
+    /// with [`WorkerStatus::ErrorUnknownParams`], those requests then go to
+    /// the other workers.
+    pub async fn upload_key<C: SerdeCurveAffine>(&self, pk: &crate::plonk::ProvingKey<C>) -> KeyHash
+    where
+        C::Scalar: SerdePrimeField + FromUniformBytes<64>,
+    {
+        let mut payload = vec![];
+        let hash =
+            write_key_upload(&mut payload, pk, TASK_FORMAT).expect("writing to a Vec cannot fail");
+        let deadline = self.config.task_timeout();
+        let payload = &Payload::from(payload);
+        join_all(
+            self.workers
+                .iter()
+                .filter(|worker| !worker.holds_key(&hash))
+                .map(|worker| async move {
+                    match worker
+                        .request(WorkerMethod::UploadKey, payload, deadline)
+                        .await
+                    {
+                        Ok(answer) if answer[..] == hash.0 => worker.add_key(hash),
+                        Ok(_) => self.notify(
+                            &WorkerError::InvalidResponse {
+                                addr: worker.addr,
+                                message: "proving key stored under another hash".to_string(),
+                            },
+                            || format!("proving key {} stays off it", hash),
+                        ),
+                        Err(error) => {
+                            self.notify(&error, || format!("proving key {} stays off it", hash))
+                        }
+                    }
+                }),
+        )
         .await;
 
-        commitments
+        hash
     }
 
-    async fn init_worker_pool(&mut self) {
-        self.workers = join_all(WORKERS.iter().map(|worker| async move {
-            let stream = loop {
-                match StubbornTcpStream::connect(worker.clone()).await {
-                    Ok(stream) => break stream,
-                    Err(_) => {
-                        thread::sleep(Duration::from_secs(1));
+    /// Proves each of `circuits`, with the values of its instance columns
+    /// in `instances`, and returns the proofs in the order of `circuits`.
+    /// Each proof is made by [`Multiopen::create_proof`] for its circuit
+    /// alone.
+    ///
+    /// The params and `pk` are sent to the workers that do not hold them
+    /// yet, then each circuit is proven whole by a single worker. The
+    /// circuits are shared between the workers like the tasks of
+    /// [`Dispatcher::run_tasks`], and those a worker fails to prove because
+    /// it went away or does not have `C` registered with `M` go to the
+    /// others. Circuits no worker is left to prove are proven in this
+    /// process.
+    pub async fn prove_batch<S, M, C>(
+        &self,
+        params: &S::ParamsProver,
+        pk: &crate::plonk::ProvingKey<S::Curve>,
+        circuits: &[C],
+        instances: &[Vec<Vec<S::Scalar>>],
+    ) -> Result<Vec<Vec<u8>>, Error>
+    where
+        S: CommitmentScheme,
+        S::Curve: SerdeCurveAffine,
+        S::Scalar: SerdePrimeField + FromUniformBytes<64>,
+        S::ParamsProver: SerdeParams,
+        M: Multiopen<S>,
+        C: BatchCircuit<S::Scalar>,
+    {
+        if instances.len() != circuits.len() {
+            return Err(Error::InvalidInstances);
+        }
+        let params_hash = self.upload_params(params).await;
+        let key = self.upload_key(pk).await;
+
+        let tasks = self
+            .split(circuits.len())
+            .into_iter()
+            .flat_map(|(worker, range)| range.map(move |circuit| (worker, circuit)))
+            .map(|(worker, circuit)| {
+                let mut witness = vec![];
+                circuits[circuit].write_witness(&mut witness)?;
+                let task = ProveTask {
+                    params: params_hash,
+                    key,
+                    circuit: C::NAME.to_string(),
+                    multiopen: M::NAME.to_string(),
+                    instances: instances[circuit].clone(),
+                    witness,
+                };
+                Ok((worker, task))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let proofs = self
+            .dispatch(
+                WorkerMethod::Prove,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |_, proof| Ok(Ok(proof.to_vec())),
+                |circuit| {
+                    let columns = instances[circuit]
+                        .iter()
+                        .map(|column| &column[..])
+                        .collect::<Vec<_>>();
+                    M::create_proof(params, pk, &circuits[circuit..circuit + 1], &[&columns[..]])
+                },
+            )
+            .await?;
+        // Only the circuits proven in this process can fail here.
+        proofs.into_iter().collect()
+    }
+
+    /// Sends each worker its share of the rows of the SRS of size `2^k`, in
+    /// both bases, sized by [`Dispatcher::weights`].
+    ///
+    /// Workers that fail to load their shard are reported, see
+    /// [`Dispatcher::with_notices`], and left without one. The rows they were
+    /// meant to hold are then computed locally by
+    /// [`Dispatcher::multiexp_sharded`].
+    pub async fn load_shards<C: SerdeCurveAffine>(&self, k: u32, g: &[C], g_lagrange: &[C]) {
+        assert_eq!(g.len(), 1 << k);
+        assert_eq!(g_lagrange.len(), 1 << k);
+
+        let deadline = self.config.task_timeout();
+        let ranges = split_weighted(g.len(), &self.weights());
+        join_all(
+            self.workers
+                .iter()
+                .zip(ranges)
+                .map(|(worker, rows)| async move {
+                    *worker.shard.lock().unwrap() = None;
+                    if rows.is_empty() {
+                        return;
                     }
+
+                    let shard = ParamsShard::new(k, g, g_lagrange, rows);
+                    let mut payload = Payload::default();
+                    shard
+                        .write(&mut payload, TASK_FORMAT)
+                        .expect("writing to a payload cannot fail");
+                    match worker
+                        .request(WorkerMethod::LoadShard, &payload, deadline)
+                        .await
+                    {
+                        Ok(_) => *worker.shard.lock().unwrap() = Some(shard.rows()),
+                        Err(error) => self.notify(&error, || {
+                            format!("rows {:?} of the SRS stay local", shard.rows)
+                        }),
+                    }
+                }),
+        )
+        .await;
+    }
+
+    /// Returns whether any worker holds a shard of the SRS of size `2^k`.
+    pub fn has_shards(&self, k: u32) -> bool {
+        self.workers
+            .iter()
+            .any(|worker| matches!(worker.shard(), Some(shard) if shard.k == k))
+    }
+
+    /// Computes the sum of `coeffs[i] * bases[i]`, where `bases` is the start
+    /// of the SRS of size `2^k` in `basis`, without sending the bases.
+    ///
+    /// Each worker holding a shard of that SRS sums the terms of its rows.
+    /// The rows no worker holds, and those of a worker that fails, are
+    /// summed locally.
+    pub async fn multiexp_sharded<C>(
+        &self,
+        coeffs: &[C::Scalar],
+        k: u32,
+        basis: SrsBasis,
+        bases: &[C],
+    ) -> Result<C::CurveExt, Error>
+    where
+        C: SerdeCurveAffine,
+        C::Scalar: SerdePrimeField,
+    {
+        assert_eq!(coeffs.len(), bases.len());
+        let ranges = self
+            .workers
+            .iter()
+            .enumerate()
+            .filter_map(|(worker, connection)| match connection.shard() {
+                Some(shard) if shard.k == k => {
+                    let rows = shard.rows.start.min(coeffs.len())..shard.rows.end.min(coeffs.len());
+                    Some((worker, rows))
                 }
+                _ => None,
+            })
+            .filter(|(_, rows)| !rows.is_empty())
+            .collect::<Vec<_>>();
+
+        let tasks = ranges
+            .iter()
+            .map(|(worker, rows)| {
+                let task = ShardMultiexpTask::<C>::new(k, basis, coeffs, rows.clone());
+                (*worker, task)
+            })
+            .collect::<Vec<_>>();
+
+        let partials = self
+            .dispatch(
+                WorkerMethod::ShardMultiexp,
+                &tasks,
+                false,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |_, partial| {
+                    let partial = read_points::<_, C>(&mut &partial[..], TASK_FORMAT)?;
+                    if partial.len() != 1 {
+                        return Err(invalid_data(format!(
+                            "expected a single partial sum, got {}",
+                            partial.len()
+                        )));
+                    }
+                    Ok(partial[0].to_curve())
+                },
+                |range| {
+                    let rows = ranges[range].1.clone();
+                    best_multiexp(&coeffs[rows.clone()], &bases[rows])
+                },
+            )
+            .await?;
+
+        // The shards are disjoint, add the rows between them locally.
+        let mut covered = ranges
+            .iter()
+            .map(|(_, rows)| rows.clone())
+            .collect::<Vec<_>>();
+        covered.sort_by_key(|rows| rows.start);
+        let mut uncovered = vec![];
+        let mut next = 0;
+        for rows in covered {
+            if rows.start > next {
+                uncovered.push(next..rows.start);
+            }
+            next = next.max(rows.end);
+        }
+        if next < coeffs.len() {
+            uncovered.push(next..coeffs.len());
+        }
+
+        Ok(uncovered
+            .into_iter()
+            .map(|rows| best_multiexp(&coeffs[rows.clone()], &bases[rows]))
+            .chain(partials)
+            .fold(C::CurveExt::identity(), |acc, partial| acc + partial))
+    }
+
+    /// Runs an FFT of size `2^log_n` over `a` with the `2^log_n`-th root of
+    /// unity `omega`, giving the same result as [`best_fft`](crate::arithmetic::best_fft).
+    ///
+    /// The FFT is split with the [four-step algorithm](super::fft): the
+    /// columns and then the rows are spread across the workers by their
+    /// weights, and transposed here in between.
+    pub async fn fft<F: SerdePrimeField>(
+        &self,
+        a: &mut [F],
+        omega: F,
+        log_n: u32,
+    ) -> Result<(), Error> {
+        assert_eq!(a.len(), 1 << log_n);
+        let (log_columns, log_rows) = four_step_split(log_n);
+        let (columns, rows) = (1 << log_columns, 1 << log_rows);
+
+        let twiddles = powers(omega, columns);
+        let transformed = self
+            .fft_pass(
+                &split_columns(a, columns),
+                omega.pow_vartime([columns as u64]),
+                log_rows,
+                &twiddles,
+            )
+            .await?;
+
+        let transformed = self
+            .fft_pass(
+                &transpose(&transformed),
+                omega.pow_vartime([rows as u64]),
+                log_columns,
+                &[],
+            )
+            .await?;
+        merge_rows(a, transformed);
+        Ok(())
+    }
+
+    /// Distributed [`EvaluationDomain::coeff_to_extended`].
+    pub async fn coeff_to_extended<F>(
+        &self,
+        domain: &EvaluationDomain<F>,
+        a: Polynomial<F, Coeff>,
+    ) -> Result<Polynomial<F, ExtendedLagrangeCoeff>, Error>
+    where
+        F: WithSmallOrderMulGroup<3> + SerdePrimeField,
+    {
+        let mut values = domain.coeff_to_extended_input(a);
+        self.fft(
+            &mut values,
+            domain.get_extended_omega(),
+            domain.extended_k(),
+        )
+        .await?;
+        Ok(domain.extended_from_vec(values))
+    }
+
+    /// Distributed [`EvaluationDomain::extended_to_coeff`].
+    pub async fn extended_to_coeff<F>(
+        &self,
+        domain: &EvaluationDomain<F>,
+        mut a: Polynomial<F, ExtendedLagrangeCoeff>,
+    ) -> Result<Vec<F>, Error>
+    where
+        F: WithSmallOrderMulGroup<3> + SerdePrimeField,
+    {
+        assert_eq!(a.len(), domain.extended_len());
+        self.fft(&mut a, domain.get_extended_omega_inv(), domain.extended_k())
+            .await?;
+        Ok(domain.extended_to_coeff_output(a))
+    }
+
+    /// Evaluates h(X) over the extended `domain`, out of `polys`.
+    ///
+    /// The rows are split across the workers by weight, in a single range
+    /// per worker since each task carries the polynomials of the circuits
+    /// whole. Each worker is sent the cosets of the proving key over its rows
+    /// and the rows `evaluator` reaches around them, along with the
+    /// polynomials of the circuits in coefficient form, which it extends
+    /// itself. A range left to `local` is evaluated the same way, so no
+    /// extended coset of a circuit is ever held whole.
+    pub async fn evaluate_h<'a, C: CurveAffine>(
+        &self,
+        evaluator: &'a Evaluator<C>,
+        layout: &'a HLayout<C::ScalarExt>,
+        domain: &'a EvaluationDomain<C::ScalarExt>,
+        polys: &HPolys<'a, C::ScalarExt>,
+        challenges: &'a HChallenges<C::ScalarExt>,
+    ) -> Result<Vec<C::ScalarExt>, Error>
+    where
+        C::ScalarExt: SerdePrimeField,
+    {
+        let (before, after) = evaluator.reach(layout);
+        let ranges = split_weighted(layout.size, &self.weights())
+            .into_iter()
+            .enumerate()
+            .filter(|(_, rows)| !rows.is_empty())
+            .collect::<Vec<_>>();
+
+        let tasks = ranges
+            .iter()
+            .map(|(worker, rows)| {
+                let window = RowWindow::full(layout.size, rows.clone()).shrink(before, after);
+                (
+                    *worker,
+                    EvaluateHTask::new(evaluator, layout, challenges, domain, polys, window),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let values = self
+            .dispatch(
+                WorkerMethod::EvaluateH,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |range, values| {
+                    let values: Vec<C::ScalarExt> = read_scalars(&mut &values[..], TASK_FORMAT)?;
+                    if values.len() != ranges[range].1.len() {
+                        return Err(invalid_data(format!(
+                            "expected {} rows of h(X), got {}",
+                            ranges[range].1.len(),
+                            values.len()
+                        )));
+                    }
+                    Ok(values)
+                },
+                |range| tasks[range].1.eval(),
+            )
+            .await?;
+
+        Ok(values.into_iter().flatten().collect())
+    }
+
+    /// Runs one pass of [`Dispatcher::fft`]: FFTs of size `2^log_n` over
+    /// every column, followed by the twiddle factors if there are any.
+    async fn fft_pass<F: SerdePrimeField>(
+        &self,
+        columns: &[Vec<F>],
+        omega: F,
+        log_n: u32,
+        twiddles: &[F],
+    ) -> Result<Vec<Vec<F>>, Error> {
+        let batches = self.split(columns.len());
+        let task = |batch: &Range<usize>| {
+            let twiddles = if twiddles.is_empty() {
+                twiddles
+            } else {
+                &twiddles[batch.clone()]
             };
-            stream.set_nodelay(true).unwrap();
-            stream
+            FftTask::new(omega, log_n, &columns[batch.clone()], twiddles)
+        };
+
+        let tasks = batches
+            .iter()
+            .map(|(worker, batch)| (*worker, task(batch)))
+            .collect::<Vec<_>>();
+
+        let transformed = self
+            .dispatch(
+                WorkerMethod::Fft,
+                &tasks,
+                true,
+                |task, payload| task.write(payload, TASK_FORMAT),
+                |batch, transformed| {
+                    let transformed = read_columns(&mut &transformed[..], 1 << log_n, TASK_FORMAT)?;
+                    if transformed.len() != batches[batch].1.len() {
+                        return Err(invalid_data(format!(
+                            "expected {} columns, got {}",
+                            batches[batch].1.len(),
+                            transformed.len()
+                        )));
+                    }
+                    Ok(transformed)
+                },
+                |batch| task(&batches[batch].1).eval(),
+            )
+            .await?;
+
+        Ok(transformed.into_iter().flatten().collect())
+    }
+
+    /// Splits `len` items into contiguous ranges, in order, each paired with
+    /// the worker it is first sent to. The share of each worker is sized by
+    /// [`Dispatcher::weights`] and cut into [`STEAL_PARTS`] ranges, so that
+    /// workers done early have ranges left to take over from the others.
+    /// Empty ranges are left out.
+    fn split(&self, len: usize) -> Vec<(usize, Range<usize>)> {
+        split_weighted(len, &self.weights())
+            .into_iter()
+            .enumerate()
+            .flat_map(|(worker, share)| {
+                split_range(share.len(), STEAL_PARTS)
+                    .into_iter()
+                    .map(move |part| (worker, share.start + part.start..share.start + part.end))
+            })
+            .filter(|(_, range)| !range.is_empty())
+            .collect()
+    }
+
+    /// Runs `tasks` on the pool and returns their decoded answers, in the
+    /// order of `tasks`.
+    ///
+    /// A task is only encoded with `encode` when it is sent, and its payload
+    /// dropped once it is answered, so a job never holds more than the
+    /// payloads in flight.
+    ///
+    /// Each worker runs the tasks it is paired with one after the other, and
+    /// once it has none left takes over the last queued task of the worker
+    /// with the most left. A worker that drops its connection, misses the
+    /// task deadline or does not serve `method` is left out for the rest of
+    /// the job and its unfinished tasks are taken over by the remaining
+    /// workers, or run with `local` once no worker is left. A worker that
+    /// answers with an error, or with an answer `decode` rejects, fails the
+    /// job.
+    ///
+    /// Tasks that are not `portable` depend on state held by the worker they
+    /// are paired with, so they are never taken over and any failure runs
+    /// them with `local`.
+    async fn dispatch<T, O>(
+        &self,
+        method: WorkerMethod,
+        tasks: &[(usize, T)],
+        portable: bool,
+        encode: impl for<'t> Fn(&'t T, &mut Payload<'t>) -> io::Result<()>,
+        decode: impl Fn(usize, &[u8]) -> io::Result<O>,
+        local: impl Fn(usize) -> O,
+    ) -> Result<Vec<O>, Error> {
+        let deadline = self.config.task_timeout();
+        let schedule = Mutex::new(Schedule::new(self.workers.len(), tasks));
+        // Told whenever a task finishes, which may hand tasks back.
+        let changed = Notify::new();
+
+        let outcomes = {
+            let (schedule, changed, encode, decode) = (&schedule, &changed, &encode, &decode);
+            join_all(
+                self.workers
+                    .iter()
+                    .enumerate()
+                    .map(|(index, worker)| async move {
+                        loop {
+                            let notified = changed.notified();
+                            let next = schedule.lock().unwrap().next(index, portable);
+                            let task = match next {
+                                Next::Run(task) => task,
+                                Next::Wait => {
+                                    notified.await;
+                                    continue;
+                                }
+                                Next::Done => return Ok(()),
+                            };
+
+                            let mut payload = Payload::default();
+                            encode(&tasks[task].1, &mut payload)
+                                .expect("writing to a payload cannot fail");
+                            let answer = worker.request(method, &payload, deadline).await.and_then(
+                                |answer| {
+                                    decode(task, &answer).map_err(|error| {
+                                        WorkerError::InvalidResponse {
+                                            addr: worker.addr,
+                                            message: error.to_string(),
+                                        }
+                                    })
+                                },
+                            );
+                            let error = match answer {
+                                Ok(value) => {
+                                    schedule.lock().unwrap().finish(task, value);
+                                    changed.notify_waiters();
+                                    continue;
+                                }
+                                Err(error) => error,
+                            };
+
+                            let retry = error.is_disconnect()
+                                || error.is_unknown_params()
+                                || error.is_unsupported()
+                                || error.is_unauthenticated()
+                                || !portable;
+                            if retry {
+                                let unfinished = schedule.lock().unwrap().hand_back(index, task);
+                                self.notify(&error, || {
+                                    format!("rescheduling {} task(s)", unfinished)
+                                });
+                            } else {
+                                schedule.lock().unwrap().abort();
+                            }
+                            changed.notify_waiters();
+                            return if retry { Ok(()) } else { Err(error) };
+                        }
+                    }),
+            )
+            .await
+        };
+        for outcome in outcomes {
+            outcome?;
+        }
+
+        // Whatever is left was paired with workers that failed.
+        let schedule = schedule.into_inner().unwrap();
+        let mut results = schedule.results;
+        for task in schedule.queues.into_iter().flatten() {
+            results[task] = Some(run_blocking(|| local(task)));
+        }
+        Ok(results
+            .into_iter()
+            .map(|result| result.expect("every task is run"))
+            .collect())
+    }
+
+    async fn init_worker_pool(&self) {
+        join_all(self.workers.iter().map(|worker| async move {
+            for attempt in 1..=CONNECT_ATTEMPTS {
+                match worker.connect().await {
+                    // Without the info of the worker, its share is sized by its
+                    // weight alone.
+                    Ok(_) => {
+                        let _ = worker.info(CONNECT_TIMEOUT).await;
+                        return;
+                    }
+                    // Retrying does not give the worker the key.
+                    Err(error) if attempt == CONNECT_ATTEMPTS || error.is_unauthenticated() => {
+                        return;
+                    }
+                    Err(_) => sleep(Duration::from_secs(1)).await,
+                }
+            }
         }))
-        .await
+        .await;
+    }
+}
+
+/// Runs `f`, which takes a while, letting the runtime move its other tasks to
+/// another thread meanwhile. A runtime on a single thread has no other thread
+/// to move them to, and waits for `f` like any caller outside a runtime.
+fn run_blocking<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => block_in_place(f),
+        _ => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        capacity_weights, Dispatcher, Next, Progress, Schedule, WorkerConnection, WorkerError,
+        WorkerMethod, WorkerStatus,
+    };
+    use crate::{
+        distributed_util::{
+            config::{PoolConfig, WorkerConfig},
+            net::{
+                auth::{self, PresharedKey},
+                read_sealed_frame, write_response, ChunkHeader, Payload,
+            },
+            testing::{spawn_counted_worker, spawn_worker, spawn_worker_with_key, Behaviour},
+        },
+        plonk::{permutation::keygen::build_vk, permutation::Argument, Any, Column, Error},
+        poly::{commitment::ParamsProver, kzg::commitment::ParamsKZG, EvaluationDomain},
+    };
+    use group::prime::PrimeCurveAffine;
+    use halo2curves::bn256::{Bn256, G1Affine};
+    use rand_core::OsRng;
+    use std::{
+        sync::{atomic::Ordering, Arc, Mutex},
+        time::Duration,
+    };
+    use tokio::{io::AsyncReadExt, net::TcpListener, time::timeout};
+
+    const K: u32 = 4;
+
+    fn argument() -> Argument {
+        let mut p = Argument::new();
+        for index in 0..3 {
+            p.add_column(Column::new(index, Any::advice()));
+            p.add_column(Column::new(index, Any::Fixed));
+        }
+        p
+    }
+
+    fn mapping(ncolumns: usize, n: usize) -> Vec<Vec<(usize, usize)>> {
+        let mut mapping: Vec<Vec<_>> = (0..ncolumns)
+            .map(|i| (0..n).map(|j| (i, j)).collect())
+            .collect();
+        mapping[0][1] = (5, 3);
+        mapping[5][3] = (2, 0);
+        mapping[2][0] = (0, 1);
+        mapping
+    }
+
+    /// Returns a pool of fake workers behaving as `behaviours`.
+    async fn pool(behaviours: &[Behaviour]) -> PoolConfig {
+        let mut workers = vec![];
+        for &behaviour in behaviours {
+            workers.push(WorkerConfig::new(spawn_worker(behaviour).await.to_string()));
+        }
+        PoolConfig::new(workers)
+    }
+
+    /// Returns what the fake workers answer keygen with, which tells the
+    /// commitments of the pool from those computed locally.
+    fn generators() -> Vec<G1Affine> {
+        vec![G1Affine::generator(); argument().ncolumns()]
+    }
+
+    /// Runs keygen on the pool described by `config`, and locally.
+    async fn keygen_on(config: PoolConfig) -> (Result<Vec<G1Affine>, Error>, Vec<G1Affine>) {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let domain = EvaluationDomain::new(3, K);
+        let p = argument();
+        let mapping = mapping(p.ncolumns(), 1 << K);
+
+        let config = config.with_task_timeout(Duration::from_secs(1));
+        let dispatcher = Dispatcher::new(config).await.unwrap();
+
+        let params_hash = dispatcher.upload_params(&params).await;
+        let commitments = dispatcher
+            .keygen::<G1Affine, _>(&params, params_hash, &domain, &p, &mapping)
+            .await;
+        let local = build_vk::<G1Affine, _>(&params, &domain, &p, |i, j| mapping[i][j]);
+        (commitments, local.commitments().clone())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_keygen_survives_a_killed_worker() {
+        let config = pool(&[Behaviour::Corrupt, Behaviour::Die, Behaviour::Corrupt]).await;
+        let (commitments, _) = keygen_on(config).await;
+        assert_eq!(commitments.unwrap(), generators());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_keygen_reassigns_after_deadline() {
+        let config = pool(&[Behaviour::Hang, Behaviour::Corrupt, Behaviour::Corrupt]).await;
+        let (commitments, _) = keygen_on(config).await;
+        assert_eq!(commitments.unwrap(), generators());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_keygen_runs_locally_without_workers() {
+        let config = pool(&[Behaviour::Die, Behaviour::Hang, Behaviour::Die]).await;
+        let (commitments, local) = keygen_on(config).await;
+        assert_eq!(commitments.unwrap(), local);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_keygen_skips_workers_with_another_key() {
+        let mut workers = vec![];
+        for secret in ["pool", "intruder", "pool"] {
+            let key = PresharedKey::new(secret.as_bytes());
+            let addr = spawn_worker_with_key(Behaviour::Corrupt, key).await;
+            workers.push(WorkerConfig::new(addr.to_string()));
+        }
+        let config = PoolConfig::new(workers).with_key("pool");
+
+        let dispatcher = Dispatcher::new(config.clone()).await.unwrap();
+        let connected = dispatcher
+            .workers
+            .iter()
+            .map(|worker| worker.is_connected())
+            .collect::<Vec<_>>();
+        assert_eq!(connected, vec![true, false, true]);
+        let (commitments, _) = keygen_on(config.clone()).await;
+        assert_eq!(commitments.unwrap(), generators());
+
+        // Without the key no worker is trusted, and keygen runs locally.
+        let (commitments, local) = keygen_on(PoolConfig {
+            key: None,
+            ..config
+        })
+        .await;
+        assert_eq!(commitments.unwrap(), local);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_keygen_catches_wrong_commitments() {
+        let corrupt = spawn_worker(Behaviour::Corrupt).await;
+        let config = PoolConfig::new(vec![WorkerConfig::new(corrupt.to_string())]);
+
+        // Unchecked, the wrong commitments make it into the key.
+        let (commitments, local) = keygen_on(config.clone()).await;
+        assert_ne!(commitments.unwrap(), local);
+
+        let (commitments, _) = keygen_on(config.with_commitment_checks(true)).await;
+        match commitments {
+            Err(Error::Worker(WorkerError::InvalidResponse { addr, .. })) => {
+                assert_eq!(addr, corrupt)
+            }
+            other => panic!("expected the corrupt worker to be caught, got {:?}", other),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_shares_follow_reported_capacity() {
+        let behaviours = [Behaviour::Corrupt, Behaviour::Slow];
+        let mut workers = vec![];
+        let mut answered = vec![];
+        for behaviour in behaviours {
+            let (addr, count) = spawn_counted_worker(behaviour).await;
+            workers.push(WorkerConfig::new(addr.to_string()));
+            answered.push(count);
+        }
+        let config = PoolConfig::new(workers);
+        let capacities = behaviours.map(|behaviour| Some(behaviour.capacity()));
+        let weights = capacity_weights(&config.weights(), &capacities);
+        let dispatcher = Dispatcher::new(config).await.unwrap();
+        assert_eq!(dispatcher.weights(), weights);
+        assert!(weights[0] > weights[1]);
+
+        // The slow worker is first sent columns of its own, but keygen does
+        // not wait for it past the first one: the fast worker takes the
+        // others over.
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let domain = EvaluationDomain::new(3, K);
+        let p = argument();
+        let mapping = mapping(p.ncolumns(), 1 << K);
+        let tasks = dispatcher.split(p.ncolumns());
+        assert!(tasks.iter().filter(|(worker, _)| *worker == 1).count() > 1);
+
+        let params_hash = dispatcher.upload_params(&params).await;
+        let commitments = dispatcher
+            .keygen::<G1Affine, _>(&params, params_hash, &domain, &p, &mapping)
+            .await
+            .unwrap();
+        let answered = answered
+            .iter()
+            .map(|count| count.load(Ordering::SeqCst))
+            .collect::<Vec<_>>();
+        assert_eq!(answered, vec![tasks.len() - 1, 1]);
+        assert_eq!(commitments, generators());
+    }
+
+    #[test]
+    fn test_schedule_hands_over_tasks() {
+        let tasks = [0, 0, 0, 1, 1, 1].map(|worker| (worker, ()));
+        let mut schedule = Schedule::<()>::new(3, &tasks);
+        let run = |schedule: &mut Schedule<()>, worker| match schedule.next(worker, true) {
+            Next::Run(task) => Some(task),
+            Next::Wait => None,
+            Next::Done => panic!("worker {} has nothing left to wait for", worker),
+        };
+
+        // Workers start with their own tasks, then take the last of the
+        // longest queue.
+        assert_eq!(run(&mut schedule, 0), Some(0));
+        assert_eq!(run(&mut schedule, 2), Some(5));
+        assert_eq!(run(&mut schedule, 1), Some(3));
+        schedule.finish(5, ());
+        assert_eq!(run(&mut schedule, 2), Some(2));
+
+        // A failed task goes back to the front of its queue, for the others
+        // to take over.
+        assert_eq!(schedule.hand_back(1, 3), 2);
+        assert!(matches!(schedule.next(1, true), Next::Done));
+        assert_eq!(run(&mut schedule, 2), Some(4));
+        assert_eq!(run(&mut schedule, 0), Some(1));
+        assert_eq!(run(&mut schedule, 0), Some(3));
+        assert_eq!(run(&mut schedule, 0), None);
+
+        // Tasks that are not portable stay with their worker.
+        let mut schedule = Schedule::<()>::new(2, &tasks[..4]);
+        assert!(matches!(schedule.next(1, false), Next::Run(3)));
+        assert!(matches!(schedule.next(1, false), Next::Done));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_keygens_share_the_pool() {
+        let config = pool(&[Behaviour::Corrupt, Behaviour::Corrupt]).await;
+        let dispatcher = Dispatcher::new(config).await.unwrap();
+
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let domain = EvaluationDomain::new(3, K);
+        let p = argument();
+        let mapping = mapping(p.ncolumns(), 1 << K);
+        let params_hash = dispatcher.upload_params(&params).await;
+
+        let keygen =
+            || dispatcher.keygen::<G1Affine, _>(&params, params_hash, &domain, &p, &mapping);
+        let (first, second) = tokio::join!(keygen(), keygen());
+        assert_eq!(first.unwrap(), generators());
+        assert_eq!(second.unwrap(), generators());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_answers_are_routed_by_request_id() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut session = auth::accept(&mut stream, &PresharedKey::default())
+                .await
+                .unwrap();
+            // Echo both requests, answering the last one first.
+            let mut requests = vec![];
+            for _ in 0..2 {
+                let header = ChunkHeader::read(&mut stream).await.unwrap();
+                assert!(header.last);
+                let payload = read_sealed_frame(&mut stream, &mut session, &header.to_bytes())
+                    .await
+                    .unwrap();
+                requests.push((header.id, payload));
+            }
+            for (id, payload) in requests.into_iter().rev() {
+                write_response(
+                    &mut stream,
+                    &mut session,
+                    id,
+                    WorkerStatus::Ok,
+                    "",
+                    &payload,
+                )
+                .await
+                .unwrap();
+            }
+        });
+
+        let worker = WorkerConnection::new(addr);
+        let deadline = Duration::from_secs(5);
+        let (first, second) = tokio::join!(
+            worker.request(WorkerMethod::Ping, &Payload::from(&b"first"[..]), deadline),
+            worker.request(WorkerMethod::Ping, &Payload::from(&b"second"[..]), deadline),
+        );
+        assert_eq!(first.unwrap(), b"first");
+        assert_eq!(second.unwrap(), b"second");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_uploads_wait_for_acknowledgements() {
+        const WINDOW: usize = 64;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut session = auth::accept(&mut stream, &PresharedKey::default())
+                .await
+                .unwrap();
+            let mut payload = vec![];
+            loop {
+                let header = ChunkHeader::read(&mut stream).await.unwrap();
+                let chunk = read_sealed_frame(&mut stream, &mut session, &header.to_bytes())
+                    .await
+                    .unwrap();
+                assert!(chunk.len() <= WINDOW);
+                payload.extend_from_slice(&chunk);
+                if header.last {
+                    write_response(
+                        &mut stream,
+                        &mut session,
+                        header.id,
+                        WorkerStatus::Ok,
+                        "",
+                        &payload,
+                    )
+                    .await
+                    .unwrap();
+                    return;
+                }
+
+                // The window is full, nothing more comes until it is
+                // acknowledged.
+                let more = timeout(Duration::from_millis(100), stream.read_u8()).await;
+                assert!(more.is_err());
+                let received = (payload.len() as u64).to_be_bytes();
+                write_response(
+                    &mut stream,
+                    &mut session,
+                    header.id,
+                    WorkerStatus::Received,
+                    "",
+                    &received,
+                )
+                .await
+                .unwrap();
+            }
+        });
+
+        let reports = Arc::new(Mutex::new(vec![]));
+        let mut worker = WorkerConnection::new(addr).with_window(WINDOW);
+        let recorded = reports.clone();
+        worker.progress = Some(Arc::new(move |progress: &Progress| {
+            assert_eq!(progress.total, 200);
+            recorded.lock().unwrap().push(progress.received);
+        }));
+
+        let payload = (0..200).map(|i| i as u8).collect::<Vec<_>>();
+        let answer = worker
+            .request(
+                WorkerMethod::UploadParams,
+                &Payload::from(&payload[..]),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(answer, payload);
+        // This worker answers the last chunk without acknowledging it.
+        assert_eq!(*reports.lock().unwrap(), vec![64, 128, 192]);
     }
 }
@@ -0,0 +1,258 @@
+//! Distributed FFTs
+//!
+//! An FFT of size `n = n_1 n_2` is computed with the four-step algorithm. The
+//! input is viewed as `n_1` interleaved columns of `n_2` elements; every
+//! column goes through an FFT of size `n_2` and is multiplied by twiddle
+//! factors, then every row of the result goes through an FFT of size `n_1`.
+//! The dispatcher spreads the columns and then the rows across the workers,
+//! transposing the matrix in between. Field arithmetic is exact, so the result
+//! is the same as that of [`best_fft`] over the whole input.
+use ff::Field;
+use std::{borrow::Cow, io};
+
+use crate::{
+    arithmetic::{best_fft, parallelize},
+    helpers::SerdePrimeField,
+    SerdeFormat,
+};
+
+use super::net::{
+    invalid_data, read_header, read_scalars, read_u32, write_header, write_scalars, write_u32,
+    Encode,
+};
+
+/// Returns how many bits of the size of an FFT of size `2^log_n` go to the
+/// number of columns and to the number of rows of the four-step algorithm.
+pub fn four_step_split(log_n: u32) -> (u32, u32) {
+    let log_columns = log_n / 2;
+    (log_columns, log_n - log_columns)
+}
+
+/// Returns `[1, base, base^2, ...]`, `len` elements long.
+pub(crate) fn powers<F: Field>(base: F, len: usize) -> Vec<F> {
+    std::iter::successors(Some(F::ONE), |power| Some(*power * base))
+        .take(len)
+        .collect()
+}
+
+/// Splits `a` into `columns` interleaved columns, the `c`-th of which holds
+/// `a[c], a[c + columns], a[c + 2 * columns], ...`.
+pub(crate) fn split_columns<F: Copy>(a: &[F], columns: usize) -> Vec<Vec<F>> {
+    (0..columns)
+        .map(|c| a.iter().skip(c).step_by(columns).copied().collect())
+        .collect()
+}
+
+/// Returns the rows of the matrix made of `columns`.
+pub(crate) fn transpose<F: Copy>(columns: &[Vec<F>]) -> Vec<Vec<F>> {
+    let len = columns.first().map_or(0, Vec::len);
+    (0..len)
+        .map(|r| columns.iter().map(|column| column[r]).collect())
+        .collect()
+}
+
+/// Writes the output of the four-step algorithm back into `a`: the `k`-th
+/// element of the `r`-th row goes to `a[r + k * rows.len()]`.
+pub(crate) fn merge_rows<F>(a: &mut [F], rows: Vec<Vec<F>>) {
+    let stride = rows.len();
+    for (r, row) in rows.into_iter().enumerate() {
+        for (k, value) in row.into_iter().enumerate() {
+            a[r + k * stride] = value;
+        }
+    }
+}
+
+/// Distributed request to run FFTs of size `2^log_n` over a batch of columns
+///
+/// When `twiddles` is not empty, it holds one factor per column and the `i`-th
+/// output of a column is then multiplied by the `i`-th power of its factor.
+#[derive(Debug, Clone)]
+pub struct FftTask<'a, F: Clone> {
+    pub omega: F,
+    pub log_n: u32,
+    pub twiddles: Cow<'a, [F]>,
+    pub columns: Cow<'a, [Vec<F>]>,
+}
+
+impl<'a, F: Field> FftTask<'a, F> {
+    /// Builds the task transforming `columns` with the `2^log_n`-th root of
+    /// unity `omega`.
+    pub fn new(omega: F, log_n: u32, columns: &'a [Vec<F>], twiddles: &'a [F]) -> Self {
+        assert!(twiddles.is_empty() || twiddles.len() == columns.len());
+        assert!(columns.iter().all(|column| column.len() == 1 << log_n));
+        FftTask {
+            omega,
+            log_n,
+            twiddles: Cow::Borrowed(twiddles),
+            columns: Cow::Borrowed(columns),
+        }
+    }
+
+    /// Computes the transformed columns of the task, in order.
+    pub fn eval(&self) -> Vec<Vec<F>> {
+        let mut columns = self.columns.to_vec();
+        parallelize(&mut columns, |columns, start| {
+            for (i, column) in columns.iter_mut().enumerate() {
+                best_fft(column, self.omega, self.log_n);
+                if let Some(twiddle) = self.twiddles.get(start + i) {
+                    let mut factor = F::ONE;
+                    for value in column.iter_mut() {
+                        *value *= factor;
+                        factor *= twiddle;
+                    }
+                }
+            }
+        });
+        columns
+    }
+}
+
+impl<'a, F: SerdePrimeField> FftTask<'a, F> {
+    /// Encodes the task.
+    ///
+    /// The layout is the task header, `omega`, `log_n`, the count-prefixed
+    /// twiddle factors and the columns as written by [`write_columns`].
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, F>(writer, format)?;
+        SerdePrimeField::write(&self.omega, writer, format)?;
+        write_u32(writer, self.log_n)?;
+        write_scalars(writer, &self.twiddles, format)?;
+        write_columns(writer, &self.columns, format)
+    }
+
+    /// Decodes a task written with [`FftTask::write`], along with the format
+    /// the dispatcher used, which the answer is expected in.
+    pub fn read<R: io::Read>(reader: &mut R) -> io::Result<(FftTask<'static, F>, SerdeFormat)> {
+        let format = read_header::<_, F>(reader)?;
+
+        let omega = <F as SerdePrimeField>::read(reader, format)?;
+        let log_n = read_u32(reader)?;
+        if log_n > F::S {
+            return Err(invalid_data(format!(
+                "the field has no FFT of size 2^{}",
+                log_n
+            )));
+        }
+        let twiddles = read_scalars(reader, format)?;
+        let columns = read_columns(reader, 1 << log_n, format)?;
+        if !twiddles.is_empty() && twiddles.len() != columns.len() {
+            return Err(invalid_data(format!(
+                "{} twiddle factors for {} columns",
+                twiddles.len(),
+                columns.len()
+            )));
+        }
+
+        Ok((
+            FftTask {
+                omega,
+                log_n,
+                twiddles: Cow::Owned(twiddles),
+                columns: Cow::Owned(columns),
+            },
+            format,
+        ))
+    }
+}
+
+/// Writes a count-prefixed list of columns, each written with
+/// [`write_scalars`].
+pub fn write_columns<'a, W: Encode<'a>, F: SerdePrimeField>(
+    writer: &mut W,
+    columns: &'a [Vec<F>],
+    format: SerdeFormat,
+) -> io::Result<()> {
+    write_u32(writer, columns.len() as u32)?;
+    for column in columns {
+        writer.scalars(column, format)?;
+    }
+    Ok(())
+}
+
+/// Reads columns written with [`write_columns`], all of which must be `len`
+/// elements long.
+pub fn read_columns<R: io::Read, F: SerdePrimeField>(
+    reader: &mut R,
+    len: usize,
+    format: SerdeFormat,
+) -> io::Result<Vec<Vec<F>>> {
+    let count = read_u32(reader)?;
+    (0..count)
+        .map(|_| {
+            let column = read_scalars(reader, format)?;
+            if column.len() != len {
+                return Err(invalid_data(format!(
+                    "column of {} elements, expected {}",
+                    column.len(),
+                    len
+                )));
+            }
+            Ok(column)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{four_step_split, merge_rows, powers, split_columns, transpose, FftTask};
+    use crate::{arithmetic::best_fft, SerdeFormat};
+    use ff::{Field, PrimeField};
+    use halo2curves::bn256::Fr;
+    use rand_core::OsRng;
+
+    /// Runs the four-step algorithm in place, locally.
+    fn four_step_fft(a: &mut [Fr], omega: Fr, log_n: u32) {
+        let (log_columns, log_rows) = four_step_split(log_n);
+        let (columns, rows) = (1 << log_columns, 1 << log_rows);
+
+        let twiddles = powers(omega, columns);
+        let omega_columns = omega.pow_vartime([columns as u64]);
+        let input = split_columns(a, columns);
+        let transformed = FftTask::new(omega_columns, log_rows, &input, &twiddles).eval();
+
+        let omega_rows = omega.pow_vartime([rows as u64]);
+        let input = transpose(&transformed);
+        merge_rows(a, FftTask::new(omega_rows, log_columns, &input, &[]).eval());
+    }
+
+    fn omega(log_n: u32) -> Fr {
+        Fr::ROOT_OF_UNITY.pow_vartime([1u64 << (Fr::S - log_n)])
+    }
+
+    #[test]
+    fn test_four_step_matches_best_fft() {
+        for log_n in 0..=9 {
+            let a: Vec<_> = (0..1 << log_n).map(|_| Fr::random(OsRng)).collect();
+            let mut expected = a.clone();
+            best_fft(&mut expected, omega(log_n), log_n);
+
+            let mut actual = a;
+            four_step_fft(&mut actual, omega(log_n), log_n);
+            assert_eq!(actual, expected, "log_n = {}", log_n);
+        }
+    }
+
+    #[test]
+    fn test_fft_task_roundtrip() {
+        let columns: Vec<Vec<_>> = (0..3)
+            .map(|_| (0..8).map(|_| Fr::random(OsRng)).collect())
+            .collect();
+        let twiddles: Vec<_> = (0..3).map(|_| Fr::random(OsRng)).collect();
+        let task = FftTask::new(omega(3), 3, &columns, &twiddles);
+
+        let mut buf = vec![];
+        task.write(&mut buf, SerdeFormat::RawBytes).unwrap();
+        let (decoded, _) = FftTask::<Fr>::read(&mut &buf[..]).unwrap();
+        assert_eq!(decoded.eval(), task.eval());
+
+        // Columns must match the size of the FFT.
+        let task = FftTask { log_n: 2, ..task };
+        let mut buf = vec![];
+        task.write(&mut buf, SerdeFormat::RawBytes).unwrap();
+        assert!(FftTask::<Fr>::read(&mut &buf[..]).is_err());
+    }
+}
@@ -1,4 +1,17 @@
+pub mod backend;
+pub mod batch;
+pub mod capacity;
+pub mod config;
 pub mod dispatcher;
+pub mod fft;
+pub mod multiexp;
 pub mod net;
+pub mod params;
 pub mod plonk;
+pub mod scheme;
+pub mod shard;
+pub mod task;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod utils;
+pub mod verify;
@@ -0,0 +1,290 @@
+//! Distributed multi-scalar multiplication
+//!
+//! A [`DistributedMultiexp`] plugs into
+//! [`ParamsKZG::with_multiexp`](crate::poly::kzg::commitment::ParamsKZG::with_multiexp):
+//! the coefficients and bases of every large enough multi-scalar
+//! multiplication are split by index range across the workers, each worker
+//! sums its own range and the dispatcher adds the partial sums together.
+//!
+//! With [`DistributedMultiexp::with_sharded_params`] the workers also keep a
+//! [shard](super::shard) of the SRS, so commitments only ship coefficients.
+use group::Curve;
+use std::{borrow::Cow, fmt, io, sync::Arc};
+use tokio::runtime::{Builder, Runtime};
+
+use halo2curves::pairing::Engine;
+
+use crate::{
+    arithmetic::{best_multiexp, CurveAffine},
+    helpers::{SerdeCurveAffine, SerdePrimeField},
+    plonk::Error,
+    poly::{
+        commitment::{MultiexpEngine, SrsBasis},
+        kzg::commitment::ParamsKZG,
+    },
+    SerdeFormat,
+};
+
+use super::{
+    config::PoolConfig,
+    dispatcher::{Dispatcher, Notice, NoticeCallback},
+    net::{invalid_data, read_header, read_points, read_u32, write_header, Encode},
+};
+
+/// Multi-scalar multiplications shorter than this are computed locally by
+/// default, shipping them costs more than computing them.
+pub const DEFAULT_MIN_LEN: usize = 1 << 10;
+
+/// Distributed request to compute a multi-scalar multiplication over a range
+/// of the coefficients and bases
+#[derive(Debug, Clone)]
+pub struct MultiexpTask<'a, C: CurveAffine> {
+    pub coeffs: Cow<'a, [C::Scalar]>,
+    pub bases: Cow<'a, [C]>,
+}
+
+impl<'a, C: CurveAffine> MultiexpTask<'a, C> {
+    /// Builds the task summing `coeffs[i] * bases[i]`.
+    pub fn new(coeffs: &'a [C::Scalar], bases: &'a [C]) -> Self {
+        assert_eq!(coeffs.len(), bases.len());
+        MultiexpTask {
+            coeffs: Cow::Borrowed(coeffs),
+            bases: Cow::Borrowed(bases),
+        }
+    }
+
+    /// Computes the partial sum of the task.
+    pub fn eval(&self) -> C {
+        best_multiexp(&self.coeffs, &self.bases).to_affine()
+    }
+}
+
+impl<'a, C> MultiexpTask<'a, C>
+where
+    C: SerdeCurveAffine,
+    C::Scalar: SerdePrimeField,
+{
+    /// Encodes the task.
+    ///
+    /// The layout is the task header, the count-prefixed coefficients and the
+    /// count-prefixed bases.
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+
+        writer.scalars(&self.coeffs, format)?;
+        writer.points(&self.bases, format)
+    }
+
+    /// Decodes a task written with [`MultiexpTask::write`], along with the
+    /// format the dispatcher used, which the answer is expected in.
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+    ) -> io::Result<(MultiexpTask<'static, C>, SerdeFormat)> {
+        let format = read_header::<_, C::Scalar>(reader)?;
+
+        let len = read_u32(reader)?;
+        let coeffs = (0..len)
+            .map(|_| <C::Scalar as SerdePrimeField>::read(reader, format))
+            .collect::<io::Result<Vec<_>>>()?;
+        let bases = read_points(reader, format)?;
+        if bases.len() != coeffs.len() {
+            return Err(invalid_data(format!(
+                "{} coefficients for {} bases",
+                coeffs.len(),
+                bases.len()
+            )));
+        }
+
+        Ok((
+            MultiexpTask {
+                coeffs: Cow::Owned(coeffs),
+                bases: Cow::Owned(bases),
+            },
+            format,
+        ))
+    }
+}
+
+/// A [`MultiexpEngine`] backed by the workers of a [`Dispatcher`].
+///
+/// The engine drives the dispatcher on a runtime of its own, blocking the
+/// thread calling the synchronous `Params` methods, which must not be a thread
+/// of a runtime: async code calls them through
+/// [`spawn_blocking`](tokio::task::spawn_blocking). They panic otherwise, as
+/// do [`DistributedMultiexp::new`] and
+/// [`DistributedMultiexp::with_sharded_params`]. A multi-scalar
+/// multiplication that the pool fails to compute is computed locally instead.
+pub struct DistributedMultiexp {
+    runtime: Option<Runtime>,
+    dispatcher: Dispatcher,
+    min_len: usize,
+    notices: Option<NoticeCallback>,
+}
+
+impl DistributedMultiexp {
+    /// Connects to the workers of the pool described by `config`.
+    pub fn new(config: PoolConfig) -> io::Result<Self> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("halo2-multiexp")
+            .enable_all()
+            .build()?;
+        let dispatcher = runtime.block_on(Dispatcher::new(config))?;
+
+        Ok(DistributedMultiexp {
+            runtime: Some(runtime),
+            dispatcher,
+            min_len: DEFAULT_MIN_LEN,
+            notices: None,
+        })
+    }
+
+    /// Sets the length under which multi-scalar multiplications are computed
+    /// locally.
+    pub fn with_min_len(mut self, min_len: usize) -> Self {
+        self.min_len = min_len;
+        self
+    }
+
+    /// Has `callback` told about the multi-scalar multiplications computed
+    /// locally because the pool failed them, and about the errors of workers
+    /// the dispatcher worked around, see [`Dispatcher::with_notices`].
+    pub fn with_notices(mut self, callback: impl Fn(&Notice<'_>) + Send + Sync + 'static) -> Self {
+        let callback: NoticeCallback = Arc::new(callback);
+        self.dispatcher.notices = Some(callback.clone());
+        self.notices = Some(callback);
+        self
+    }
+
+    /// Sends each worker its shard of the SRS of `params`. Commitments
+    /// against that SRS then only send the coefficients to the workers.
+    pub fn with_sharded_params<E>(self, params: &ParamsKZG<E>) -> Self
+    where
+        E: Engine + fmt::Debug,
+        E::G1Affine: SerdeCurveAffine,
+    {
+        self.runtime
+            .as_ref()
+            .unwrap()
+            .block_on(
+                self.dispatcher
+                    .load_shards(params.k, &params.g, &params.g_lagrange),
+            );
+        self
+    }
+
+    /// Computes the multi-scalar multiplication locally after the pool
+    /// failed it with `error`.
+    fn local<C: CurveAffine>(
+        &self,
+        error: Error,
+        coeffs: &[C::Scalar],
+        bases: &[C],
+    ) -> C::CurveExt {
+        if let Some(notices) = &self.notices {
+            notices(&Notice {
+                error: &error,
+                fallback: "computing the multiexp locally".to_string(),
+            });
+        }
+        best_multiexp(coeffs, bases)
+    }
+}
+
+impl<C> MultiexpEngine<C> for DistributedMultiexp
+where
+    C: SerdeCurveAffine,
+    C::Scalar: SerdePrimeField,
+{
+    fn multiexp(&self, coeffs: &[C::Scalar], bases: &[C]) -> C::CurveExt {
+        if coeffs.len() < self.min_len {
+            return best_multiexp(coeffs, bases);
+        }
+
+        let runtime = self.runtime.as_ref().unwrap();
+        runtime
+            .block_on(self.dispatcher.multiexp(coeffs, bases))
+            .unwrap_or_else(|error| self.local(error, coeffs, bases))
+    }
+
+    fn multiexp_srs(
+        &self,
+        coeffs: &[C::Scalar],
+        k: u32,
+        basis: SrsBasis,
+        bases: &[C],
+    ) -> C::CurveExt {
+        if coeffs.len() < self.min_len {
+            return best_multiexp(coeffs, bases);
+        }
+
+        if !self.dispatcher.has_shards(k) {
+            return self.multiexp(coeffs, bases);
+        }
+
+        let runtime = self.runtime.as_ref().unwrap();
+        runtime
+            .block_on(self.dispatcher.multiexp_sharded(coeffs, k, basis, bases))
+            .unwrap_or_else(|error| self.local(error, coeffs, bases))
+    }
+}
+
+impl fmt::Debug for DistributedMultiexp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DistributedMultiexp")
+            .field("min_len", &self.min_len)
+            .finish()
+    }
+}
+
+impl Drop for DistributedMultiexp {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which panics when done from async code.
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MultiexpTask;
+    use crate::{
+        arithmetic::best_multiexp,
+        poly::{
+            commitment::{Params, ParamsProver},
+            kzg::commitment::ParamsKZG,
+        },
+        SerdeFormat,
+    };
+    use ff::Field;
+    use group::Curve;
+    use halo2curves::bn256::{Bn256, Fr, G1Affine};
+    use rand_core::OsRng;
+
+    const K: u32 = 6;
+
+    #[test]
+    fn test_multiexp_task_roundtrip() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let coeffs: Vec<_> = (0..params.n()).map(|_| Fr::random(OsRng)).collect();
+        let expected = best_multiexp(&coeffs, &params.g).to_affine();
+
+        for format in [
+            SerdeFormat::Processed,
+            SerdeFormat::RawBytes,
+            SerdeFormat::RawBytesUnchecked,
+        ] {
+            let mut buf = vec![];
+            MultiexpTask::<G1Affine>::new(&coeffs, &params.g)
+                .write(&mut buf, format)
+                .unwrap();
+            let (task, _) = MultiexpTask::<G1Affine>::read(&mut &buf[..]).unwrap();
+            assert_eq!(task.eval(), expected);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Authentication of the connections between the dispatcher and the workers
+//!
+//! The dispatcher and its workers share a [`PresharedKey`]. When a connection
+//! is opened, each end picks a random nonce and proves it holds the key by
+//! MACing both nonces, the worker first. Both ends then derive a session key
+//! from the nonces, and every frame sent afterwards is followed by a tag over
+//! the frame, the direction it travels in and its position in the stream, so
+//! a frame cannot be forged, altered, replayed or reordered without the
+//! receiving end noticing.
+//!
+//! The handshake messages have a fixed size, so nothing is allocated on
+//! behalf of a peer before it proved it holds the key. The MAC is keyed
+//! BLAKE2b-256.
+use rand_core::{OsRng, RngCore};
+use std::{fmt, io};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::distributed_util::dispatcher::WorkerStatus;
+
+/// Length of keys, nonces and tags.
+pub const TAG_LEN: usize = 32;
+
+/// Label of the frames the dispatcher sends.
+const TO_WORKER: u8 = 0;
+
+/// Label of the frames a worker sends.
+const TO_DISPATCHER: u8 = 1;
+
+/// The secret a deployment shares between its dispatcher and its workers.
+///
+/// Without a configured secret both ends use the empty one, which
+/// authenticates nothing but keeps a single protocol. The `worker` binary
+/// only accepts it with `--insecure`, and a pool only with workers on the
+/// same host, see [`PoolConfig::validate`](crate::distributed_util::config::PoolConfig::validate).
+#[derive(Clone, PartialEq, Eq)]
+pub struct PresharedKey([u8; TAG_LEN]);
+
+impl PresharedKey {
+    /// Derives the key from a secret of any length, such as a passphrase.
+    pub fn new(secret: &[u8]) -> Self {
+        let hash = blake2b_simd::Params::new()
+            .hash_length(TAG_LEN)
+            .personal(b"halo2_psk_derive")
+            .hash(secret);
+        let mut key = [0u8; TAG_LEN];
+        key.copy_from_slice(hash.as_bytes());
+        PresharedKey(key)
+    }
+
+    fn mac(&self, parts: &[&[u8]]) -> [u8; TAG_LEN] {
+        mac(&self.0, parts)
+    }
+}
+
+impl Default for PresharedKey {
+    fn default() -> Self {
+        PresharedKey::new(b"")
+    }
+}
+
+impl fmt::Debug for PresharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key itself.
+        f.write_str("PresharedKey(..)")
+    }
+}
+
+/// Computes the keyed BLAKE2b-256 of the concatenation of `parts`.
+fn mac(key: &[u8; TAG_LEN], parts: &[&[u8]]) -> [u8; TAG_LEN] {
+    let mut state = blake2b_simd::Params::new()
+        .hash_length(TAG_LEN)
+        .key(key)
+        .to_state();
+    for part in parts {
+        state.update(part);
+    }
+    let mut tag = [0u8; TAG_LEN];
+    tag.copy_from_slice(state.finalize().as_bytes());
+    tag
+}
+
+/// Compares two tags in time independent of where they differ.
+fn tags_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Builds the error of a peer or a frame that failed authentication.
+pub fn unauthenticated<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, error)
+}
+
+/// The state of an authenticated connection: its session key and the number
+/// of frames sent and received on it.
+pub struct Session {
+    key: [u8; TAG_LEN],
+    outgoing: u8,
+    sent: u64,
+    received: u64,
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("sent", &self.sent)
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Session {
+    fn new(key: &PresharedKey, nonces: &[u8], outgoing: u8) -> Self {
+        Session {
+            key: key.mac(&[b"session", nonces]),
+            outgoing,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    fn tag(&self, direction: u8, index: u64, header: &[u8], payload: &[u8]) -> [u8; TAG_LEN] {
+        let len = (payload.len() as u64).to_be_bytes();
+        mac(
+            &self.key,
+            &[&[direction], &index.to_be_bytes(), header, &len, payload],
+        )
+    }
+
+    /// Returns the tag of the next frame this end sends.
+    pub fn seal(&mut self, header: &[u8], payload: &[u8]) -> [u8; TAG_LEN] {
+        let tag = self.tag(self.outgoing, self.sent, header, payload);
+        self.sent += 1;
+        tag
+    }
+
+    /// Splits the session into the half sealing the frames this end sends
+    /// and the half opening those it receives, so that the two directions of
+    /// a connection can be driven by different tasks.
+    pub fn split(self) -> (Session, Session) {
+        let receiving = Session {
+            key: self.key,
+            outgoing: self.outgoing,
+            sent: 0,
+            received: self.received,
+        };
+        let sending = Session {
+            received: 0,
+            ..self
+        };
+        (sending, receiving)
+    }
+
+    /// Checks the tag of the next frame this end receives.
+    pub fn open(&mut self, header: &[u8], payload: &[u8], tag: &[u8]) -> io::Result<()> {
+        let expected = self.tag(self.outgoing ^ 1, self.received, header, payload);
+        if !tags_match(&expected, tag) {
+            return Err(unauthenticated("frame failed authentication"));
+        }
+        self.received += 1;
+        Ok(())
+    }
+}
+
+/// Authenticates a connection the dispatcher opened to a worker.
+pub async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    key: &PresharedKey,
+) -> io::Result<Session> {
+    let mut nonces = [0u8; 2 * TAG_LEN];
+    OsRng.fill_bytes(&mut nonces[..TAG_LEN]);
+    stream.write_all(&nonces[..TAG_LEN]).await?;
+    stream.flush().await?;
+
+    let mut proof = [0u8; TAG_LEN];
+    stream.read_exact(&mut nonces[TAG_LEN..]).await?;
+    stream.read_exact(&mut proof).await?;
+    if !tags_match(&proof, &key.mac(&[b"worker", &nonces])) {
+        return Err(unauthenticated(
+            "the worker does not hold the pre-shared key",
+        ));
+    }
+
+    stream
+        .write_all(&key.mac(&[b"dispatcher", &nonces]))
+        .await?;
+    stream.flush().await?;
+    match stream.read_u8().await? {
+        status if status == u8::from(WorkerStatus::Ok) => Ok(Session::new(key, &nonces, TO_WORKER)),
+        _ => Err(unauthenticated("the worker rejected the pre-shared key")),
+    }
+}
+
+/// Authenticates a connection a dispatcher opened to this worker. A
+/// dispatcher that does not hold the key is answered
+/// [`WorkerStatus::ErrorUnauthenticated`].
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    key: &PresharedKey,
+) -> io::Result<Session> {
+    let mut nonces = [0u8; 2 * TAG_LEN];
+    stream.read_exact(&mut nonces[..TAG_LEN]).await?;
+    OsRng.fill_bytes(&mut nonces[TAG_LEN..]);
+    stream.write_all(&nonces[TAG_LEN..]).await?;
+    stream.write_all(&key.mac(&[b"worker", &nonces])).await?;
+    stream.flush().await?;
+
+    let mut proof = [0u8; TAG_LEN];
+    stream.read_exact(&mut proof).await?;
+    if !tags_match(&proof, &key.mac(&[b"dispatcher", &nonces])) {
+        stream
+            .write_u8(WorkerStatus::ErrorUnauthenticated.into())
+            .await?;
+        stream.flush().await?;
+        return Err(unauthenticated(
+            "the dispatcher does not hold the pre-shared key",
+        ));
+    }
+    stream.write_u8(WorkerStatus::Ok.into()).await?;
+    stream.flush().await?;
+    Ok(Session::new(key, &nonces, TO_DISPATCHER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept, connect, PresharedKey, TAG_LEN};
+    use crate::distributed_util::{
+        dispatcher::WorkerStatus,
+        net::{read_response, read_sealed_frame, write_response, write_sealed_frame},
+    };
+    use std::io;
+    use tokio::io::duplex;
+
+    #[tokio::test]
+    async fn test_sealed_frames_roundtrip() {
+        let key = PresharedKey::new(b"correct horse battery staple");
+        let (mut dispatcher, mut worker) = duplex(1 << 16);
+        let (client, server) =
+            tokio::join!(connect(&mut dispatcher, &key), accept(&mut worker, &key));
+        let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+        for payload in [&b"first"[..], &b""[..], &b"third"[..]] {
+            write_sealed_frame(&mut dispatcher, &mut client, &[7], payload)
+                .await
+                .unwrap();
+            let received = read_sealed_frame(&mut worker, &mut server, &[7])
+                .await
+                .unwrap();
+            assert_eq!(received, payload);
+
+            write_response(&mut worker, &mut server, 0, WorkerStatus::Ok, "", payload)
+                .await
+                .unwrap();
+            let response = read_response(&mut dispatcher, &mut client).await.unwrap();
+            assert_eq!(response.payload, payload);
+        }
+
+        // The halves of a split session carry on where the session was.
+        let (mut client_sending, mut client_receiving) = client.split();
+        let (mut server_sending, mut server_receiving) = server.split();
+        write_sealed_frame(&mut dispatcher, &mut client_sending, &[7], b"fourth")
+            .await
+            .unwrap();
+        let received = read_sealed_frame(&mut worker, &mut server_receiving, &[7])
+            .await
+            .unwrap();
+        assert_eq!(received, b"fourth");
+        write_response(
+            &mut worker,
+            &mut server_sending,
+            1,
+            WorkerStatus::Ok,
+            "",
+            b"",
+        )
+        .await
+        .unwrap();
+        let response = read_response(&mut dispatcher, &mut client_receiving)
+            .await
+            .unwrap();
+        assert_eq!(response.id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key_is_rejected() {
+        let (mut dispatcher, mut worker) = duplex(1 << 16);
+        let (client, server) = tokio::join!(
+            // The stream is dropped along with the failed handshake.
+            async move { connect(&mut dispatcher, &PresharedKey::new(b"dispatcher")).await },
+            accept(&mut worker, &PresharedKey::new(b"worker")),
+        );
+        // The dispatcher gives up as soon as the worker fails to prove it
+        // holds the key, so the worker never sees a proof.
+        assert_eq!(client.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert!(server.is_err());
+
+        // A dispatcher without the key that skips checking the worker is
+        // rejected with a status byte.
+        let key = PresharedKey::new(b"worker");
+        let (mut dispatcher, mut worker) = duplex(1 << 16);
+        let forged = async {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            dispatcher.write_all(&[0u8; 32]).await.unwrap();
+            let mut hello = [0u8; 64];
+            dispatcher.read_exact(&mut hello).await.unwrap();
+            dispatcher.write_all(&[0u8; 32]).await.unwrap();
+            dispatcher.read_u8().await.unwrap()
+        };
+        let (status, server) = tokio::join!(forged, accept(&mut worker, &key));
+        assert_eq!(status, u8::from(WorkerStatus::ErrorUnauthenticated));
+        assert_eq!(server.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_tampered_frames_are_rejected() {
+        let key = PresharedKey::new(b"correct horse battery staple");
+        let payload = b"commit to these polynomials".to_vec();
+
+        // Flip every bit of a sealed request in turn: the length prefix, the
+        // payload and the tag.
+        for bit in 0..8 * (8 + payload.len() + TAG_LEN) {
+            let (mut dispatcher, mut worker) = duplex(1 << 16);
+            let (client, server) =
+                tokio::join!(connect(&mut dispatcher, &key), accept(&mut worker, &key));
+            let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+            let mut frame = vec![];
+            write_sealed_frame(&mut frame, &mut client, &[2], &payload)
+                .await
+                .unwrap();
+            frame[bit / 8] ^= 1 << (bit % 8);
+            assert!(read_sealed_frame(&mut &frame[..], &mut server, &[2])
+                .await
+                .is_err());
+        }
+
+        // A frame is only accepted once, under the header it was sealed with
+        // and on the connection it was sealed for.
+        let (mut dispatcher, mut worker) = duplex(1 << 16);
+        let (client, server) =
+            tokio::join!(connect(&mut dispatcher, &key), accept(&mut worker, &key));
+        let (mut client, mut server) = (client.unwrap(), server.unwrap());
+        let mut frame = vec![];
+        write_sealed_frame(&mut frame, &mut client, &[2], &payload)
+            .await
+            .unwrap();
+        assert!(read_sealed_frame(&mut &frame[..], &mut server, &[3])
+            .await
+            .is_err());
+        assert_eq!(
+            read_sealed_frame(&mut &frame[..], &mut server, &[2])
+                .await
+                .unwrap(),
+            payload
+        );
+        assert!(read_sealed_frame(&mut &frame[..], &mut server, &[2])
+            .await
+            .is_err());
+
+        let (mut dispatcher, mut worker) = duplex(1 << 16);
+        let (_, other) = tokio::join!(connect(&mut dispatcher, &key), accept(&mut worker, &key));
+        assert!(
+            read_sealed_frame(&mut &frame[..], &mut other.unwrap(), &[2])
+                .await
+                .is_err()
+        );
+    }
+}
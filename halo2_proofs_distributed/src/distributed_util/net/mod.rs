@@ -1,27 +1,1031 @@
-pub fn to_bytes<T>(data: T) -> Vec<u8> {
-    let struct_size = std::mem::size_of::<T>();
-    let mut bytes = vec![0; struct_size];
+//! Wire format shared by the dispatcher and the workers.
+//!
+//! Every request is sent as one or more chunks of at most [`CHUNK_LEN`] bytes,
+//! each a [`ChunkHeader`] followed by a length-prefixed frame, and every
+//! answer is the ID of the request it answers followed by a single frame that
+//! starts with a [`WorkerStatus`] and an error message, which is empty on
+//! success. A connection carries any number of requests at once, their
+//! chunks interleaved, and a worker answers them as they complete, in any
+//! order. Connections are authenticated first, and each frame is then
+//! followed by its tag, see [`auth`].
+//!
+//! A worker acknowledges every chunk of a request sent in several with a
+//! [`WorkerStatus::Received`] answer holding the number of bytes of the
+//! request it has read so far. The dispatcher does not get further ahead of
+//! these than its window, so a large upload never has more than the window
+//! in flight, and encodes a request a chunk at a time as it sends it, see
+//! [`Payload`].
+//!
+//! The contents of a frame are encoded with the helpers below, which only ever
+//! write integers in big-endian order so that the dispatcher and the workers
+//! do not need to share an architecture or an address space.
 
-    unsafe {
-        let struct_ptr = &data as *const T as *const u8;
-        std::ptr::copy_nonoverlapping(struct_ptr, bytes.as_mut_ptr(), struct_size);
+pub mod auth;
+
+use std::{borrow::Cow, io};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use self::auth::{Session, TAG_LEN};
+use super::{dispatcher::WorkerStatus, scheme::SchemeId};
+use crate::{
+    helpers::{SerdeCurveAffine, SerdePrimeField},
+    plonk::{Any, Column, FirstPhase, SecondPhase, ThirdPhase},
+    poly::{ipa::commitment::ParamsIPA, kzg::commitment::ParamsKZG, EvaluationDomain},
+    SerdeFormat,
+};
+use ff::{PrimeField, WithSmallOrderMulGroup};
+use halo2curves::pairing::Engine;
+use std::fmt::Debug;
+
+/// Version of the task encoding, bumped whenever the layout of a task changes.
+pub const WIRE_VERSION: u8 = 11;
+
+/// Parameters that can be shipped to a worker.
+pub trait SerdeParams: Sized {
+    /// The scalar field of the scheme the parameters are for.
+    type Scalar: PrimeField;
+
+    /// Writes the parameters using the given `format`.
+    fn write_custom<W: io::Write>(&self, writer: &mut W, format: SerdeFormat) -> io::Result<()>;
+
+    /// Reads parameters that were written with the given `format`.
+    fn read_custom<R: io::Read>(reader: &mut R, format: SerdeFormat) -> io::Result<Self>;
+}
+
+impl<E: Engine + Debug> SerdeParams for ParamsKZG<E>
+where
+    E::G1Affine: SerdeCurveAffine,
+    E::G2Affine: SerdeCurveAffine,
+{
+    type Scalar = E::Scalar;
+
+    fn write_custom<W: io::Write>(&self, writer: &mut W, format: SerdeFormat) -> io::Result<()> {
+        ParamsKZG::write_custom(self, writer, format)
     }
 
-    bytes
+    fn read_custom<R: io::Read>(reader: &mut R, format: SerdeFormat) -> io::Result<Self> {
+        ParamsKZG::read_custom(reader, format)
+    }
 }
 
-pub fn from_bytes<T: Default>(bytes: &[u8]) -> T {
-    assert_eq!(bytes.len(), std::mem::size_of::<T>(), "bytes don't match!");
+/// The layout is `k` followed by the points of `g` and `g_lagrange`, `w` and
+/// `u`, with the point encoding of `format` rather than the compressed one of
+/// [`Params::write`](crate::poly::commitment::Params::write).
+impl<C: SerdeCurveAffine> SerdeParams for ParamsIPA<C> {
+    type Scalar = C::Scalar;
 
-    // Create an empty instance.
-    let mut instance = T::default();
-    unsafe {
-        // Cast the instance over the bytes.
-        let struct_ptr = &mut instance as *mut T as *mut u8;
+    fn write_custom<W: io::Write>(&self, writer: &mut W, format: SerdeFormat) -> io::Result<()> {
+        write_u32(writer, self.k)?;
+        for point in self.g.iter().chain(&self.g_lagrange) {
+            point.write(writer, format)?;
+        }
+        self.w.write(writer, format)?;
+        self.u.write(writer, format)
+    }
 
-        // Pack the struct.
-        std::ptr::copy_nonoverlapping(bytes.as_ptr(), struct_ptr, bytes.len());
+    fn read_custom<R: io::Read>(reader: &mut R, format: SerdeFormat) -> io::Result<Self> {
+        let k = read_u32(reader)?;
+        if k >= 32 {
+            return Err(invalid_data(format!("params of 2^{} rows", k)));
+        }
+        let n = 1u64 << k;
+        let g = (0..n)
+            .map(|_| C::read(reader, format))
+            .collect::<io::Result<Vec<_>>>()?;
+        let g_lagrange = (0..n)
+            .map(|_| C::read(reader, format))
+            .collect::<io::Result<Vec<_>>>()?;
+        let w = C::read(reader, format)?;
+        let u = C::read(reader, format)?;
+        Ok(ParamsIPA {
+            k,
+            n,
+            g,
+            g_lagrange,
+            w,
+            u,
+        })
     }
+}
+
+/// Builds an `io::Error` for a malformed payload.
+pub fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Writes a `u32` in big-endian order.
+pub fn write_u32<W: io::Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_be_bytes())
+}
+
+/// Reads a big-endian `u32`.
+pub fn read_u32<R: io::Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+/// Writes a `u64` in big-endian order.
+pub fn write_u64<W: io::Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_be_bytes())
+}
+
+/// Reads a big-endian `u64`.
+pub fn read_u64<R: io::Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Writes `value` as an unsigned LEB128 varint, seven bits per byte with the
+/// least significant first, so that small values take a single byte.
+pub fn write_varint<W: io::Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Reads a varint written with [`write_varint`].
+pub fn read_varint<R: io::Read>(reader: &mut R) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        let bits = u64::from(byte[0] & 0x7f);
+        if shift == 63 && bits > 1 {
+            break;
+        }
+        value |= bits << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("varint does not fit in 64 bits"))
+}
+
+/// Writes a `u32` length prefix followed by the bytes themselves.
+pub fn write_bytes<W: io::Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_u32(writer, bytes.len() as u32)?;
+    writer.write_all(bytes)
+}
+
+/// Reads bytes that were written with [`write_bytes`].
+pub fn read_bytes<R: io::Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)?;
+    // The length comes from the peer, so the buffer only grows with the
+    // bytes that actually arrive.
+    let mut bytes = vec![];
+    io::Read::read_to_end(&mut io::Read::take(reader, len.into()), &mut bytes)?;
+    if bytes.len() as u64 != u64::from(len) {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+/// Writes a column as its type tag, followed by the phase of an advice
+/// column, and its index as a varint.
+pub fn write_column<W: io::Write>(writer: &mut W, column: &Column<Any>) -> io::Result<()> {
+    match column.column_type() {
+        Any::Advice(advice) => writer.write_all(&[0x00, advice.phase()])?,
+        Any::Fixed => writer.write_all(&[0x01])?,
+        Any::Instance => writer.write_all(&[0x02])?,
+    }
+    write_varint(writer, column.index() as u64)
+}
+
+/// Reads a column written with [`write_column`].
+pub fn read_column<R: io::Read>(reader: &mut R) -> io::Result<Column<Any>> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    let column_type = match tag[0] {
+        0x00 => {
+            reader.read_exact(&mut tag)?;
+            match tag[0] {
+                0 => Any::advice_in(FirstPhase),
+                1 => Any::advice_in(SecondPhase),
+                2 => Any::advice_in(ThirdPhase),
+                phase => return Err(invalid_data(format!("unknown advice phase {}", phase))),
+            }
+        }
+        0x01 => Any::Fixed,
+        0x02 => Any::Instance,
+        tag => return Err(invalid_data(format!("unknown column type {}", tag))),
+    };
+    let index = usize::try_from(read_varint(reader)?).map_err(invalid_data)?;
+    Ok(Column::new(index, column_type))
+}
+
+/// Writes a count-prefixed list of columns, see [`write_column`].
+pub fn write_columns<W: io::Write>(writer: &mut W, columns: &[Column<Any>]) -> io::Result<()> {
+    write_u32(writer, columns.len() as u32)?;
+    for column in columns {
+        write_column(writer, column)?;
+    }
+    Ok(())
+}
+
+/// Reads a list of columns written with [`write_columns`].
+pub fn read_columns<R: io::Read>(reader: &mut R) -> io::Result<Vec<Column<Any>>> {
+    let len = read_u32(reader)?;
+    (0..len).map(|_| read_column(reader)).collect()
+}
+
+/// Writes a domain as its `k` followed by its `j`, one more than the degree
+/// of the quotient polynomial.
+pub fn write_domain<W: io::Write, F: WithSmallOrderMulGroup<3>>(
+    writer: &mut W,
+    domain: &EvaluationDomain<F>,
+) -> io::Result<()> {
+    write_u32(writer, domain.k())?;
+    write_u32(writer, domain.get_quotient_poly_degree() as u32 + 1)
+}
 
-    instance
+/// Reads a domain written with [`write_domain`], and builds it.
+pub fn read_domain<R: io::Read, F: WithSmallOrderMulGroup<3>>(
+    reader: &mut R,
+) -> io::Result<EvaluationDomain<F>> {
+    let k = read_u32(reader)?;
+    let j = read_u32(reader)?;
+    // `EvaluationDomain::new` panics on an extended domain larger than the
+    // roots of unity of the field.
+    let degree = u64::from(j.max(2) - 1);
+    let extended_k = u64::from(k) + u64::from(64 - (degree - 1).leading_zeros());
+    if j < 2 || extended_k > u64::from(F::S) {
+        return Err(invalid_data(format!(
+            "no domain of k = {} and j = {} over the field",
+            k, j
+        )));
+    }
+    Ok(EvaluationDomain::new(j, k))
+}
+
+/// Writes the tag of a `SerdeFormat`.
+pub fn write_format<W: io::Write>(writer: &mut W, format: SerdeFormat) -> io::Result<()> {
+    let tag: u8 = match format {
+        SerdeFormat::Processed => 0x00,
+        SerdeFormat::RawBytes => 0x01,
+        SerdeFormat::RawBytesUnchecked => 0x02,
+    };
+    writer.write_all(&[tag])
+}
+
+/// Reads a `SerdeFormat` tag.
+pub fn read_format<R: io::Read>(reader: &mut R) -> io::Result<SerdeFormat> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    match tag[0] {
+        0x00 => Ok(SerdeFormat::Processed),
+        0x01 => Ok(SerdeFormat::RawBytes),
+        0x02 => Ok(SerdeFormat::RawBytesUnchecked),
+        tag => Err(invalid_data(format!("unknown serde format {}", tag))),
+    }
+}
+
+/// Writes the header every task starts with: the wire version, the scheme of
+/// the scalar field `F` and the serde format.
+pub fn write_header<W: io::Write, F: PrimeField>(
+    writer: &mut W,
+    format: SerdeFormat,
+) -> io::Result<()> {
+    writer.write_all(&[WIRE_VERSION, SchemeId::tag::<F>()])?;
+    write_format(writer, format)
+}
+
+/// Reads a header written with [`write_header`], checking that the task is
+/// over the scalar field `F`, and returns the format of the task.
+pub fn read_header<R: io::Read, F: PrimeField>(reader: &mut R) -> io::Result<SerdeFormat> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let [version, scheme] = header;
+    if version != WIRE_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported task version {}, expected {}",
+            version, WIRE_VERSION
+        )));
+    }
+    if scheme != SchemeId::tag::<F>() {
+        return Err(invalid_data(format!(
+            "task for scheme {}, expected {}",
+            scheme,
+            SchemeId::tag::<F>()
+        )));
+    }
+    read_format(reader)
+}
+
+/// Returns the scheme of a task without decoding it, so that a worker can
+/// pick the handler to pass the task to.
+pub fn read_scheme(task: &[u8]) -> io::Result<SchemeId> {
+    match task {
+        [version, ..] if *version != WIRE_VERSION => Err(invalid_data(format!(
+            "unsupported task version {}, expected {}",
+            version, WIRE_VERSION
+        ))),
+        [_, scheme, ..] => SchemeId::try_from(*scheme)
+            .map_err(|_| invalid_data(format!("unknown scheme {}", scheme))),
+        _ => Err(invalid_data("task without a header")),
+    }
+}
+
+/// Writes a count-prefixed list of curve points.
+pub fn write_points<W: io::Write, C: SerdeCurveAffine>(
+    writer: &mut W,
+    points: &[C],
+    format: SerdeFormat,
+) -> io::Result<()> {
+    write_u32(writer, points.len() as u32)?;
+    for point in points {
+        point.write(writer, format)?;
+    }
+    Ok(())
+}
+
+/// Reads a list of curve points written with [`write_points`].
+pub fn read_points<R: io::Read, C: SerdeCurveAffine>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<Vec<C>> {
+    let len = read_u32(reader)?;
+    (0..len).map(|_| C::read(reader, format)).collect()
+}
+
+/// Writes a count-prefixed list of field elements.
+pub fn write_scalars<W: io::Write, F: SerdePrimeField>(
+    writer: &mut W,
+    scalars: &[F],
+    format: SerdeFormat,
+) -> io::Result<()> {
+    write_u32(writer, scalars.len() as u32)?;
+    for scalar in scalars {
+        SerdePrimeField::write(scalar, writer, format)?;
+    }
+    Ok(())
+}
+
+/// Reads a list of field elements written with [`write_scalars`].
+pub fn read_scalars<R: io::Read, F: SerdePrimeField>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<Vec<F>> {
+    let len = read_u32(reader)?;
+    (0..len)
+        .map(|_| <F as SerdePrimeField>::read(reader, format))
+        .collect()
+}
+
+/// Where a task is encoded to. A buffer holds the whole encoding, while a
+/// [`Payload`] keeps the slices passed to [`Encode::scalars`] and
+/// [`Encode::points`] borrowed, and only encodes them as it is sent.
+pub trait Encode<'a>: io::Write {
+    /// Writes a count-prefixed list of field elements, see [`write_scalars`].
+    fn scalars<F: SerdePrimeField>(
+        &mut self,
+        scalars: &'a [F],
+        format: SerdeFormat,
+    ) -> io::Result<()>;
+
+    /// Writes a count-prefixed list of curve points, see [`write_points`].
+    fn points<C: SerdeCurveAffine>(
+        &mut self,
+        points: &'a [C],
+        format: SerdeFormat,
+    ) -> io::Result<()>;
+}
+
+impl<'a> Encode<'a> for Vec<u8> {
+    fn scalars<F: SerdePrimeField>(
+        &mut self,
+        scalars: &'a [F],
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_scalars(self, scalars, format)
+    }
+
+    fn points<C: SerdeCurveAffine>(
+        &mut self,
+        points: &'a [C],
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_points(self, points, format)
+    }
+}
+
+/// Sends `payload` as a single length-prefixed frame followed by the tag
+/// `session` computes over `header` and the frame, and flushes the stream.
+/// The `header` is the part of the message sent before the frame, such as
+/// the method byte of a request.
+pub async fn write_sealed_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    session: &mut Session,
+    header: &[u8],
+    payload: &[u8],
+) -> io::Result<()> {
+    let tag = session.seal(header, payload);
+    writer.write_u64(payload.len() as u64).await?;
+    writer.write_all(payload).await?;
+    writer.write_all(&tag).await?;
+    writer.flush().await
+}
+
+/// Receives a frame sent with [`write_sealed_frame`], failing with
+/// `PermissionDenied` if its tag does not match.
+pub async fn read_sealed_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    session: &mut Session,
+    header: &[u8],
+) -> io::Result<Vec<u8>> {
+    let len = reader.read_u64().await?;
+    // The length is not authenticated yet, so the buffer only grows with
+    // the bytes that actually arrive.
+    let mut payload = vec![];
+    (&mut *reader).take(len).read_to_end(&mut payload).await?;
+    if payload.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let mut tag = [0u8; TAG_LEN];
+    reader.read_exact(&mut tag).await?;
+    session.open(header, &payload, &tag)?;
+    Ok(payload)
+}
+
+/// The ID a dispatcher gives a request, which the answer carries back.
+pub type RequestId = u64;
+
+/// Largest piece of a request sent as a single frame.
+pub const CHUNK_LEN: usize = 1 << 20;
+
+/// Bytes of a request sent ahead of the acknowledgements of the worker,
+/// unless the pool sets its own window.
+pub const DEFAULT_WINDOW: usize = 16 << 20;
+
+/// The part of a request chunk sent before its frame, which is sealed along
+/// with the frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkHeader {
+    /// The method of the request.
+    pub method: u8,
+    /// The request the chunk is part of.
+    pub id: RequestId,
+    /// Whether the chunk is the last one of the request.
+    pub last: bool,
+}
+
+impl ChunkHeader {
+    /// Length of the encoded header.
+    pub const LEN: usize = 10;
+
+    /// Returns the encoding of the header.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut header = [0u8; Self::LEN];
+        header[0] = self.method;
+        header[1..9].copy_from_slice(&self.id.to_be_bytes());
+        header[9] = self.last as u8;
+        header
+    }
+
+    /// Reads a header written with [`ChunkHeader::to_bytes`].
+    pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Self> {
+        let mut header = [0u8; Self::LEN];
+        reader.read_exact(&mut header).await?;
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&header[1..9]);
+        let last = match header[9] {
+            0 => false,
+            1 => true,
+            flag => return Err(invalid_data(format!("invalid chunk flag {}", flag))),
+        };
+        Ok(ChunkHeader {
+            method: header[0],
+            id: RequestId::from_be_bytes(id),
+            last,
+        })
+    }
+}
+
+/// Sends `chunk`, the next piece of the request described by `header`.
+pub async fn write_request_chunk<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    session: &mut Session,
+    header: ChunkHeader,
+    chunk: &[u8],
+) -> io::Result<()> {
+    let header = header.to_bytes();
+    writer.write_all(&header).await?;
+    write_sealed_frame(writer, session, &header, chunk).await
+}
+
+/// Encodes the element of a slice at the given index into the buffer.
+type EncodeElement<'a> = Box<dyn Fn(usize, &mut Vec<u8>) -> io::Result<()> + Send + Sync + 'a>;
+
+/// A part of a [`Payload`].
+enum Part<'a> {
+    Bytes(Cow<'a, [u8]>),
+    /// The elements of a slice, each encoded to `len` bytes.
+    Elements {
+        count: usize,
+        len: usize,
+        encode: EncodeElement<'a>,
+    },
+}
+
+impl Part<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Part::Bytes(bytes) => bytes.len(),
+            Part::Elements { count, len, .. } => count * len,
+        }
+    }
+}
+
+/// A request to a worker, encoded as it is sent.
+///
+/// The bytes written to the payload are kept as they are, while the slices of
+/// field elements and curve points passed to [`Encode`] stay borrowed and are
+/// encoded a chunk at a time by [`Payload::chunks`], so that the large inputs
+/// of a task are never copied into a buffer whole.
+#[derive(Default)]
+#[allow(missing_debug_implementations)]
+pub struct Payload<'a> {
+    parts: Vec<Part<'a>>,
+    len: usize,
+}
+
+impl<'a> Payload<'a> {
+    /// Returns the number of bytes the payload encodes to.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the payload encodes to no bytes at all.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the chunks the payload is sent in, every one `chunk_len` bytes
+    /// long but the last. An empty payload is sent in a single empty chunk.
+    pub fn chunks(&self, chunk_len: usize) -> Chunks<'_, 'a> {
+        assert!(chunk_len > 0);
+        Chunks {
+            payload: self,
+            chunk_len,
+            part: 0,
+            offset: 0,
+            sent: None,
+        }
+    }
+
+    /// Writes the count of `count` elements, which `encode` encodes when the
+    /// payload is sent. The elements must all encode to the same length.
+    fn elements(&mut self, count: usize, encode: EncodeElement<'a>) -> io::Result<()> {
+        write_u32(self, count as u32)?;
+        if count == 0 {
+            return Ok(());
+        }
+        let mut first = vec![];
+        encode(0, &mut first)?;
+        let len = first.len();
+        self.len += count * len;
+        self.parts.push(Part::Elements { count, len, encode });
+        Ok(())
+    }
+}
+
+impl io::Write for Payload<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.parts.last_mut() {
+            Some(Part::Bytes(Cow::Owned(bytes))) => bytes.extend_from_slice(buf),
+            _ => self.parts.push(Part::Bytes(Cow::Owned(buf.to_vec()))),
+        }
+        self.len += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> Encode<'a> for Payload<'a> {
+    fn scalars<F: SerdePrimeField>(
+        &mut self,
+        scalars: &'a [F],
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        self.elements(
+            scalars.len(),
+            Box::new(move |index, buffer| SerdePrimeField::write(&scalars[index], buffer, format)),
+        )
+    }
+
+    fn points<C: SerdeCurveAffine>(
+        &mut self,
+        points: &'a [C],
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        self.elements(
+            points.len(),
+            Box::new(move |index, buffer| points[index].write(buffer, format)),
+        )
+    }
+}
+
+/// Sends bytes encoded up front, such as params encoded once for every worker.
+impl<'a> From<&'a [u8]> for Payload<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Payload {
+            len: bytes.len(),
+            parts: vec![Part::Bytes(Cow::Borrowed(bytes))],
+        }
+    }
+}
+
+impl From<Vec<u8>> for Payload<'static> {
+    fn from(bytes: Vec<u8>) -> Self {
+        Payload {
+            len: bytes.len(),
+            parts: vec![Part::Bytes(Cow::Owned(bytes))],
+        }
+    }
+}
+
+/// The chunks of a [`Payload`], see [`Payload::chunks`].
+#[allow(missing_debug_implementations)]
+pub struct Chunks<'p, 'a> {
+    payload: &'p Payload<'a>,
+    chunk_len: usize,
+    /// The part the next chunk starts in, and the byte of that part.
+    part: usize,
+    offset: usize,
+    /// Bytes sent so far, `None` before the first chunk.
+    sent: Option<usize>,
+}
+
+impl Chunks<'_, '_> {
+    /// Appends the next `len` bytes of the payload to `chunk`.
+    fn fill(&mut self, chunk: &mut Vec<u8>, mut len: usize) -> io::Result<()> {
+        let mut element = vec![];
+        while len > 0 {
+            let part = &self.payload.parts[self.part];
+            let take = len.min(part.len() - self.offset);
+            match part {
+                Part::Bytes(bytes) => chunk.extend_from_slice(&bytes[self.offset..][..take]),
+                Part::Elements {
+                    len: element_len,
+                    encode,
+                    ..
+                } => {
+                    // An element split across two chunks is encoded for both.
+                    let end = self.offset + take;
+                    let mut offset = self.offset;
+                    while offset < end {
+                        let (index, skip) = (offset / element_len, offset % element_len);
+                        element.clear();
+                        encode(index, &mut element)?;
+                        if element.len() != *element_len {
+                            return Err(invalid_data(format!(
+                                "element {} encodes to {} bytes rather than {}",
+                                index,
+                                element.len(),
+                                element_len
+                            )));
+                        }
+                        let until = (end - offset + skip).min(*element_len);
+                        chunk.extend_from_slice(&element[skip..until]);
+                        offset += until - skip;
+                    }
+                }
+            }
+            len -= take;
+            self.offset += take;
+            if self.offset == part.len() {
+                self.part += 1;
+                self.offset = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Chunks<'_, '_> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sent = match self.sent {
+            Some(sent) if sent == self.payload.len => return None,
+            sent => sent.unwrap_or(0),
+        };
+        let len = self.chunk_len.min(self.payload.len - sent);
+        let mut chunk = Vec::with_capacity(len);
+        if let Err(error) = self.fill(&mut chunk, len) {
+            // Nothing follows a chunk that could not be encoded.
+            self.sent = Some(self.payload.len);
+            return Some(Err(error));
+        }
+        self.sent = Some(sent + len);
+        Some(Ok(chunk))
+    }
+}
+
+/// A decoded answer of a worker.
+#[derive(Debug)]
+pub struct Response {
+    /// The request answered.
+    pub id: RequestId,
+    /// Status of the request.
+    pub status: WorkerStatus,
+    /// Error message, empty when the request succeeded.
+    pub message: String,
+    /// Result of the request.
+    pub payload: Vec<u8>,
+}
+
+/// Sends the answer to the request `id` as a single sealed frame.
+pub async fn write_response<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    session: &mut Session,
+    id: RequestId,
+    status: WorkerStatus,
+    message: &str,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(1 + 4 + message.len() + payload.len());
+    frame.push(status.into());
+    write_bytes(&mut frame, message.as_bytes())?;
+    frame.extend_from_slice(payload);
+    let id = id.to_be_bytes();
+    writer.write_all(&id).await?;
+    write_sealed_frame(writer, session, &id, &frame).await
+}
+
+/// Receives an answer sent with [`write_response`].
+pub async fn read_response<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    session: &mut Session,
+) -> io::Result<Response> {
+    let id = reader.read_u64().await?;
+    let frame = read_sealed_frame(reader, session, &id.to_be_bytes()).await?;
+    let (status, mut rest) = frame
+        .split_first()
+        .ok_or_else(|| invalid_data("empty response frame"))?;
+    let status = WorkerStatus::try_from(*status)
+        .map_err(|_| invalid_data(format!("unknown worker status {}", status)))?;
+    let message = String::from_utf8(read_bytes(&mut rest)?).map_err(invalid_data)?;
+    Ok(Response {
+        id,
+        status,
+        message,
+        payload: rest.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        auth::{accept, connect, PresharedKey, Session},
+        read_bytes, read_columns, read_header, read_response, read_scheme, read_sealed_frame,
+        read_varint, write_columns, write_header, write_request_chunk, write_response,
+        write_sealed_frame, write_u32, write_varint, ChunkHeader, Encode, Payload,
+    };
+    use crate::{
+        distributed_util::{
+            capacity::Capacity,
+            dispatcher::{WorkerInfo, WorkerMethod, WorkerStatus},
+            scheme::SchemeId,
+        },
+        plonk::{Any, Column, SecondPhase},
+        SerdeFormat,
+    };
+    use group::prime::PrimeCurveAffine;
+    use halo2curves::{bn256, pasta};
+    use std::io;
+    use tokio::io::duplex;
+
+    /// Returns the sessions of both ends of a connection.
+    async fn sessions() -> (Session, Session) {
+        let key = PresharedKey::default();
+        let (mut dispatcher, mut worker) = duplex(1 << 10);
+        let (client, server) =
+            tokio::join!(connect(&mut dispatcher, &key), accept(&mut worker, &key));
+        (client.unwrap(), server.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_response_roundtrip() {
+        let (mut client, mut server) = sessions().await;
+        let mut buf = vec![];
+        // Answers need not come in the order of the requests.
+        write_response(&mut buf, &mut server, 7, WorkerStatus::Ok, "", &[1, 2, 3])
+            .await
+            .unwrap();
+        write_response(
+            &mut buf,
+            &mut server,
+            3,
+            WorkerStatus::ErrorInvalidMethod,
+            "unknown method 0xff",
+            &[],
+        )
+        .await
+        .unwrap();
+
+        let mut reader = &buf[..];
+        let ok = read_response(&mut reader, &mut client).await.unwrap();
+        assert_eq!(ok.id, 7);
+        assert_eq!(ok.status, WorkerStatus::Ok);
+        assert!(ok.message.is_empty());
+        assert_eq!(ok.payload, vec![1, 2, 3]);
+
+        let err = read_response(&mut reader, &mut client).await.unwrap();
+        assert_eq!(err.id, 3);
+        assert_eq!(err.status, WorkerStatus::ErrorInvalidMethod);
+        assert_eq!(err.message, "unknown method 0xff");
+        assert!(err.payload.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_request_ids_are_authenticated() {
+        let (mut client, mut server) = sessions().await;
+        let header = ChunkHeader {
+            method: 2,
+            id: 41,
+            last: true,
+        };
+        let mut buf = vec![];
+        write_request_chunk(&mut buf, &mut client, header, b"task")
+            .await
+            .unwrap();
+        assert_eq!(buf[..ChunkHeader::LEN], header.to_bytes());
+        assert_eq!(ChunkHeader::read(&mut &buf[..]).await.unwrap(), header);
+
+        // A request cannot be passed off as another one, nor its chunks
+        // moved around.
+        let (head, frame) = buf.split_at(ChunkHeader::LEN);
+        for byte in [8, 9] {
+            let mut forged = head.to_vec();
+            forged[byte] ^= 1;
+            assert!(read_sealed_frame(&mut &frame[..], &mut server, &forged)
+                .await
+                .is_err());
+        }
+        assert_eq!(
+            read_sealed_frame(&mut &frame[..], &mut server, head)
+                .await
+                .unwrap(),
+            b"task"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chunk_header_rejects_unknown_flag() {
+        let mut header = ChunkHeader {
+            method: 0,
+            id: 0,
+            last: false,
+        }
+        .to_bytes();
+        header[9] = 2;
+        assert!(ChunkHeader::read(&mut &header[..]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_response_rejects_unknown_status() {
+        let (mut client, mut server) = sessions().await;
+        let mut buf = 0u64.to_be_bytes().to_vec();
+        write_sealed_frame(
+            &mut buf,
+            &mut server,
+            &0u64.to_be_bytes(),
+            &[0xff, 0, 0, 0, 0],
+        )
+        .await
+        .unwrap();
+        assert!(read_response(&mut &buf[..], &mut client).await.is_err());
+    }
+
+    #[test]
+    fn test_varint_roundtrip() {
+        for (value, len) in [
+            (0, 1),
+            (1, 1),
+            (127, 1),
+            (128, 2),
+            (300, 2),
+            (u32::MAX as u64, 5),
+            (u64::MAX, 10),
+        ] {
+            let mut buf = vec![];
+            write_varint(&mut buf, value).unwrap();
+            assert_eq!(buf.len(), len);
+            assert_eq!(read_varint(&mut &buf[..]).unwrap(), value);
+        }
+
+        // Varints past 64 bits are rejected rather than truncated.
+        let mut too_long = vec![0xff; 9];
+        too_long.push(0x02);
+        assert!(read_varint(&mut &too_long[..]).is_err());
+        assert!(read_varint(&mut &[0x80; 11][..]).is_err());
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+    }
+
+    #[test]
+    fn test_read_bytes_rejects_truncated_payload() {
+        let mut buf = vec![];
+        write_u32(&mut buf, u32::MAX).unwrap();
+        buf.extend_from_slice(b"short");
+        let error = read_bytes(&mut &buf[..]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_columns_roundtrip() {
+        let columns = vec![
+            Column::new(0, Any::advice()),
+            Column::new(300, Any::advice_in(SecondPhase)),
+            Column::new(2, Any::Fixed),
+            Column::new(1, Any::Instance),
+        ];
+        let mut buf = vec![];
+        write_columns(&mut buf, &columns).unwrap();
+        assert_eq!(read_columns(&mut &buf[..]).unwrap(), columns);
+
+        // An unknown column type or phase is rejected.
+        for byte in [4, 5] {
+            let mut forged = buf.clone();
+            forged[byte] = 3;
+            assert!(read_columns(&mut &forged[..]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_payload_chunks_match_the_eager_encoding() {
+        let scalars: Vec<_> = (0..10u64).map(bn256::Fr::from).collect();
+        let points = vec![bn256::G1Affine::generator(); 3];
+
+        let mut expected = vec![];
+        let mut payload = Payload::default();
+        write_u32(&mut expected, 7).unwrap();
+        write_u32(&mut payload, 7).unwrap();
+        expected.scalars(&scalars, SerdeFormat::RawBytes).unwrap();
+        payload.scalars(&scalars, SerdeFormat::RawBytes).unwrap();
+        write_u32(&mut expected, 8).unwrap();
+        write_u32(&mut payload, 8).unwrap();
+        expected.points(&points, SerdeFormat::Processed).unwrap();
+        payload.points(&points, SerdeFormat::Processed).unwrap();
+        assert_eq!(payload.len(), expected.len());
+
+        // Chunks split the elements anywhere and end with the payload.
+        for chunk_len in [1, 7, 32, 33, expected.len(), expected.len() + 1] {
+            let chunks = payload.chunks(chunk_len).collect::<io::Result<Vec<_>>>();
+            let chunks = chunks.unwrap();
+            assert!(chunks.iter().all(|chunk| chunk.len() <= chunk_len));
+            assert_eq!(chunks.concat(), expected);
+        }
+
+        // An empty payload is still sent, as a single empty chunk.
+        let empty = Payload::default();
+        let chunks: Vec<_> = empty.chunks(4).map(Result::unwrap).collect();
+        assert_eq!(chunks, vec![Vec::<u8>::new()]);
+    }
+
+    #[test]
+    fn test_header_names_the_scheme() {
+        let mut buf = vec![];
+        write_header::<_, pasta::Fp>(&mut buf, SerdeFormat::RawBytes).unwrap();
+        assert_eq!(read_scheme(&buf).unwrap(), SchemeId::IpaVesta);
+        assert!(matches!(
+            read_header::<_, pasta::Fp>(&mut &buf[..]).unwrap(),
+            SerdeFormat::RawBytes
+        ));
+        // A task over another field is rejected rather than misread.
+        assert!(read_header::<_, bn256::Fr>(&mut &buf[..]).is_err());
+
+        let mut unknown = vec![];
+        write_header::<_, bn256::Fq>(&mut unknown, SerdeFormat::RawBytes).unwrap();
+        assert!(read_scheme(&unknown).is_err());
+        assert!(read_scheme(&[]).is_err());
+    }
+
+    #[test]
+    fn test_worker_info_roundtrip() {
+        let info = WorkerInfo {
+            version: "0.1.0".to_string(),
+            capabilities: vec![WorkerMethod::KeyGen, WorkerMethod::Ping],
+            capacity: Capacity {
+                cores: 16,
+                memory: 64 << 30,
+                msm_rate: 1 << 20,
+            },
+        };
+        let mut buf = vec![];
+        info.write(&mut buf).unwrap();
+        assert_eq!(WorkerInfo::read(&mut &buf[..]).unwrap(), info);
+    }
 }
@@ -0,0 +1,251 @@
+//! Content-addressed parameters
+//!
+//! Tasks do not carry the params they are computed with, only the hash of
+//! their encoding. The dispatcher uploads params to the workers that do not
+//! hold them yet, and each worker keeps what it was sent in a
+//! [`ParamsCache`], in memory and optionally in a directory it loads again
+//! when it restarts. A worker provisioned with the SRS ahead of time never
+//! has it sent over the network.
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::net::{read_header, write_header, SerdeParams};
+use crate::SerdeFormat;
+
+/// Extension of the files a [`ParamsCache`] keeps params in.
+const PARAMS_EXTENSION: &str = "params";
+
+/// A BLAKE2b-256 of the encoding of what it refers to, so that the workers
+/// holding something and the dispatchers naming it agree without asking.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentHash(pub [u8; 32]);
+
+/// The hash params are referred to by, that of their `RawBytes` encoding.
+pub type ParamsHash = ContentHash;
+
+impl ContentHash {
+    /// Hashes `encoded`.
+    pub fn of(encoded: &[u8]) -> Self {
+        let hash = blake2b_simd::Params::new().hash_length(32).hash(encoded);
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(hash.as_bytes());
+        ContentHash(bytes)
+    }
+
+    /// Hashes `params`, encoding them straight into the hasher.
+    pub fn of_params<P: SerdeParams>(params: &P) -> Self {
+        let mut state = blake2b_simd::Params::new().hash_length(32).to_state();
+        params
+            .write_custom(&mut state, SerdeFormat::RawBytes)
+            .expect("writing to a hasher cannot fail");
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(state.finalize().as_bytes());
+        ContentHash(hash)
+    }
+
+    pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.0)
+    }
+
+    pub fn read<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let mut hash = [0u8; 32];
+        reader.read_exact(&mut hash)?;
+        Ok(ContentHash(hash))
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ContentHash({})", self)
+    }
+}
+
+/// Writes the payload of an `UploadParams` request: the task header and the
+/// params.
+pub fn write_upload<W: io::Write, P: SerdeParams>(
+    writer: &mut W,
+    params: &P,
+    format: SerdeFormat,
+) -> io::Result<()> {
+    write_header::<_, P::Scalar>(writer, format)?;
+    params.write_custom(writer, format)
+}
+
+/// Reads the params of an `UploadParams` request written with
+/// [`write_upload`].
+pub fn read_upload<R: io::Read, P: SerdeParams>(reader: &mut R) -> io::Result<P> {
+    let format = read_header::<_, P::Scalar>(reader)?;
+    P::read_custom(reader, format)
+}
+
+/// Params a worker holds, by hash.
+///
+/// A clone of the cache shares its params rather than copying them, so a
+/// worker clones its cache out of the lock guarding it and computes with the
+/// clone, letting uploads through meanwhile.
+#[derive(Debug)]
+pub struct ParamsCache<P> {
+    params: HashMap<ParamsHash, Arc<P>>,
+    dir: Option<PathBuf>,
+}
+
+impl<P> Clone for ParamsCache<P> {
+    fn clone(&self) -> Self {
+        ParamsCache {
+            params: self.params.clone(),
+            dir: self.dir.clone(),
+        }
+    }
+}
+
+impl<P> Default for ParamsCache<P> {
+    fn default() -> Self {
+        ParamsCache {
+            params: HashMap::new(),
+            dir: None,
+        }
+    }
+}
+
+impl<P: SerdeParams> ParamsCache<P> {
+    /// Opens the cache kept in `dir`, creating the directory if needed and
+    /// loading every params file in it. Params inserted later are written
+    /// there too.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut params = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != PARAMS_EXTENSION) {
+                continue;
+            }
+            let loaded = P::read_custom(
+                &mut BufReader::new(fs::File::open(&path)?),
+                SerdeFormat::RawBytes,
+            )
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            params.insert(ParamsHash::of_params(&loaded), Arc::new(loaded));
+        }
+
+        Ok(ParamsCache {
+            params,
+            dir: Some(dir),
+        })
+    }
+
+    /// Adds `params` to the cache, and to its directory if it has one,
+    /// returning their hash.
+    pub fn insert(&mut self, params: P) -> io::Result<ParamsHash> {
+        let hash = ParamsHash::of_params(&params);
+        if self.params.contains_key(&hash) {
+            return Ok(hash);
+        }
+        if let Some(dir) = &self.dir {
+            write_file(dir, &hash, &params)?;
+        }
+        self.params.insert(hash, Arc::new(params));
+        Ok(hash)
+    }
+}
+
+impl<P> ParamsCache<P> {
+    /// Returns whether the cache holds the params hashing to `hash`.
+    pub fn contains(&self, hash: &ParamsHash) -> bool {
+        self.params.contains_key(hash)
+    }
+
+    /// Returns the params hashing to `hash`, or a `NotFound` error when the
+    /// cache does not hold them.
+    pub fn get(&self, hash: &ParamsHash) -> io::Result<&P> {
+        self.params.get(hash).map(Arc::as_ref).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("params {} are not cached", hash),
+            )
+        })
+    }
+
+    /// Returns the hashes of the params in the cache.
+    pub fn hashes(&self) -> impl Iterator<Item = &ParamsHash> {
+        self.params.keys()
+    }
+}
+
+/// Writes `params` to their file in `dir`. The file is written under a
+/// temporary name first, so a worker that dies half way through never leaves
+/// a truncated file behind.
+fn write_file<P: SerdeParams>(dir: &Path, hash: &ParamsHash, params: &P) -> io::Result<()> {
+    let path = dir.join(format!("{}.{}", hash, PARAMS_EXTENSION));
+    let partial = path.with_extension("partial");
+    {
+        let mut writer = BufWriter::new(fs::File::create(&partial)?);
+        params.write_custom(&mut writer, SerdeFormat::RawBytes)?;
+        io::Write::flush(&mut writer)?;
+    }
+    fs::rename(partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_upload, write_upload, ContentHash, ParamsCache, ParamsHash};
+    use crate::{
+        poly::{commitment::ParamsProver, ipa::commitment::ParamsIPA, kzg::commitment::ParamsKZG},
+        SerdeFormat,
+    };
+    use halo2curves::{bn256::Bn256, pasta::EqAffine};
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_params_cache_directory() {
+        let dir = std::env::temp_dir().join(format!("params-cache-{}", std::process::id()));
+        let params = ParamsKZG::<Bn256>::setup(4, OsRng);
+        let hash = ParamsHash::of_params(&params);
+
+        // The hash does not depend on the format the params were sent in.
+        let mut upload = vec![];
+        write_upload(&mut upload, &params, SerdeFormat::Processed).unwrap();
+        let uploaded: ParamsKZG<Bn256> = read_upload(&mut &upload[..]).unwrap();
+
+        let mut cache = ParamsCache::<ParamsKZG<Bn256>>::open(&dir).unwrap();
+        assert!(!cache.contains(&hash));
+        assert_eq!(cache.insert(uploaded).unwrap(), hash);
+        assert!(cache.get(&ContentHash([0; 32])).is_err());
+
+        let reopened = ParamsCache::<ParamsKZG<Bn256>>::open(&dir).unwrap();
+        assert_eq!(reopened.hashes().collect::<Vec<_>>(), vec![&hash]);
+        assert_eq!(ParamsHash::of_params(reopened.get(&hash).unwrap()), hash);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_ipa_params_upload() {
+        let params = ParamsIPA::<EqAffine>::new(4);
+        let hash = ParamsHash::of_params(&params);
+        for format in [SerdeFormat::Processed, SerdeFormat::RawBytes] {
+            let mut upload = vec![];
+            write_upload(&mut upload, &params, format).unwrap();
+            let uploaded: ParamsIPA<EqAffine> = read_upload(&mut &upload[..]).unwrap();
+            assert_eq!(ParamsHash::of_params(&uploaded), hash);
+
+            // Params of one scheme are not taken for those of another.
+            assert!(read_upload::<_, ParamsKZG<Bn256>>(&mut &upload[..]).is_err());
+        }
+    }
+}
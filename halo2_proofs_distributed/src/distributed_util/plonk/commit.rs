@@ -0,0 +1,246 @@
+//! Distributed commitments to Lagrange polynomials
+use futures::future::LocalBoxFuture;
+use group::{prime::PrimeCurveAffine, Curve};
+use std::{borrow::Cow, io};
+
+use crate::{
+    arithmetic::CurveAffine,
+    distributed_util::{
+        dispatcher::Dispatcher,
+        net::{invalid_data, read_header, read_u32, write_header, write_u32, Encode, SerdeParams},
+        params::{ParamsCache, ParamsHash},
+    },
+    helpers::{SerdeCurveAffine, SerdePrimeField},
+    plonk::Error,
+    poly::{
+        commitment::{Blind, Params},
+        LagrangeCoeff, Polynomial,
+    },
+    SerdeFormat,
+};
+
+/// Commits to polynomials in Lagrange form on behalf of the prover.
+pub trait LagrangeCommitter<C: CurveAffine> {
+    /// Returns the commitments to `polys` with the matching `blinds`, in order.
+    fn commit_lagrange<'a>(
+        &'a mut self,
+        polys: &'a [Polynomial<C::Scalar, LagrangeCoeff>],
+        blinds: &'a [Blind<C::Scalar>],
+    ) -> LocalBoxFuture<'a, Result<Vec<C>, Error>>;
+}
+
+/// Commits through the workers of a [`Dispatcher`].
+#[allow(missing_debug_implementations)]
+pub struct DispatchedCommitter<'a, 'params, P> {
+    pub params: &'params P,
+    /// The hash of `params`, which the workers were sent by
+    /// [`Dispatcher::upload_params`].
+    pub params_hash: ParamsHash,
+    pub dispatcher: &'a Dispatcher,
+}
+
+impl<'a, 'params, P> DispatchedCommitter<'a, 'params, P> {
+    pub fn new(
+        params: &'params P,
+        params_hash: ParamsHash,
+        dispatcher: &'a Dispatcher,
+    ) -> Self {
+        DispatchedCommitter {
+            params,
+            params_hash,
+            dispatcher,
+        }
+    }
+}
+
+impl<'a, 'params, C, P> LagrangeCommitter<C> for DispatchedCommitter<'a, 'params, P>
+where
+    C: SerdeCurveAffine,
+    C::Scalar: SerdePrimeField,
+    P: Params<'params, C> + SerdeParams,
+{
+    fn commit_lagrange<'b>(
+        &'b mut self,
+        polys: &'b [Polynomial<C::Scalar, LagrangeCoeff>],
+        blinds: &'b [Blind<C::Scalar>],
+    ) -> LocalBoxFuture<'b, Result<Vec<C>, Error>> {
+        Box::pin(
+            self.dispatcher
+                .commit_lagrange(self.params, self.params_hash, polys, blinds),
+        )
+    }
+}
+
+/// Commits to `polys` with the matching `blinds` on this machine.
+pub(crate) fn commit_lagrange<'params, C: CurveAffine, P: Params<'params, C>>(
+    params: &P,
+    polys: &[Polynomial<C::Scalar, LagrangeCoeff>],
+    blinds: &[Blind<C::Scalar>],
+) -> Vec<C> {
+    let commitments_projective: Vec<_> = polys
+        .iter()
+        .zip(blinds.iter())
+        .map(|(poly, blind)| params.commit_lagrange(poly, *blind))
+        .collect();
+    let mut commitments = vec![C::identity(); commitments_projective.len()];
+    C::CurveExt::batch_normalize(&commitments_projective, &mut commitments);
+    commitments
+}
+
+/// Distributed request to commit to a batch of Lagrange polynomials
+///
+/// Like [`KeygenTask`](super::permutation::keygen::KeygenTask), the
+/// dispatcher builds the task from borrowed prover state and a worker decodes
+/// an owned copy of it with [`CommitTask::read`].
+#[derive(Debug, Clone)]
+pub struct CommitTask<'a, C: CurveAffine, P: Clone> {
+    pub params: Cow<'a, P>,
+    pub params_hash: ParamsHash,
+    pub polys: Cow<'a, [Polynomial<C::Scalar, LagrangeCoeff>]>,
+    pub blinds: Cow<'a, [Blind<C::Scalar>]>,
+}
+
+impl<'a, C: CurveAffine, P: Clone> CommitTask<'a, C, P> {
+    /// Builds the task committing to `polys` with the matching `blinds`.
+    pub fn new(
+        params: &'a P,
+        params_hash: ParamsHash,
+        polys: &'a [Polynomial<C::Scalar, LagrangeCoeff>],
+        blinds: &'a [Blind<C::Scalar>],
+    ) -> Self {
+        assert_eq!(polys.len(), blinds.len());
+        CommitTask {
+            params: Cow::Borrowed(params),
+            params_hash,
+            polys: Cow::Borrowed(polys),
+            blinds: Cow::Borrowed(blinds),
+        }
+    }
+}
+
+impl<'a, C, P> CommitTask<'a, C, P>
+where
+    C: CurveAffine,
+    C::Scalar: SerdePrimeField,
+    P: Clone + SerdeParams,
+{
+    /// Encodes the task.
+    ///
+    /// The layout is the task header, the hash of the params, and the
+    /// count-prefixed list of polynomials each followed by its blind.
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+        self.params_hash.write(writer)?;
+
+        write_u32(writer, self.polys.len() as u32)?;
+        for (poly, blind) in self.polys.iter().zip(self.blinds.iter()) {
+            writer.scalars(poly, format)?;
+            SerdePrimeField::write(&blind.0, writer, format)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a task written with [`CommitTask::write`], along with the
+    /// format the dispatcher used, which the answer is expected in. The params
+    /// are looked up in `cache`.
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+        cache: &'a ParamsCache<P>,
+    ) -> io::Result<(CommitTask<'a, C, P>, SerdeFormat)> {
+        let format = read_header::<_, C::Scalar>(reader)?;
+        let params_hash = ParamsHash::read(reader)?;
+        let params = cache.get(&params_hash)?;
+
+        let len = read_u32(reader)? as usize;
+        let mut polys = Vec::with_capacity(len);
+        let mut blinds = Vec::with_capacity(len);
+        for _ in 0..len {
+            polys.push(Polynomial::<C::Scalar, LagrangeCoeff>::read(
+                reader, format,
+            )?);
+            blinds.push(Blind(<C::Scalar as SerdePrimeField>::read(reader, format)?));
+        }
+
+        Ok((
+            CommitTask {
+                params: Cow::Borrowed(params),
+                params_hash,
+                polys: Cow::Owned(polys),
+                blinds: Cow::Owned(blinds),
+            },
+            format,
+        ))
+    }
+
+    /// Computes the commitments of the task, in order.
+    pub fn commitments(&self) -> io::Result<Vec<C>>
+    where
+        P: Params<'a, C>,
+    {
+        let n = self.params.n() as usize;
+        if self.polys.iter().any(|poly| poly.len() > n) {
+            return Err(invalid_data(format!(
+                "polynomial is longer than the {} bases of the params",
+                n
+            )));
+        }
+        Ok(commit_lagrange(&*self.params, &self.polys, &self.blinds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{commit_lagrange, CommitTask};
+    use crate::{
+        distributed_util::params::ParamsCache,
+        poly::{
+            commitment::{Blind, ParamsProver},
+            kzg::commitment::ParamsKZG,
+            EvaluationDomain,
+        },
+        SerdeFormat,
+    };
+    use ff::Field;
+    use halo2curves::bn256::{Bn256, Fr, G1Affine};
+    use rand_core::OsRng;
+
+    const K: u32 = 4;
+
+    #[test]
+    fn test_commit_task_roundtrip() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let domain = EvaluationDomain::<Fr>::new(1, K);
+        let polys: Vec<_> = (0..3)
+            .map(|_| {
+                let mut poly = domain.empty_lagrange();
+                for value in poly.iter_mut() {
+                    *value = Fr::random(OsRng);
+                }
+                poly
+            })
+            .collect();
+        let blinds: Vec<_> = polys.iter().map(|_| Blind(Fr::random(OsRng))).collect();
+        let local = commit_lagrange::<G1Affine, _>(&params, &polys, &blinds);
+        let mut cache = ParamsCache::default();
+        let params_hash = cache.insert(params.clone()).unwrap();
+
+        for format in [
+            SerdeFormat::Processed,
+            SerdeFormat::RawBytes,
+            SerdeFormat::RawBytesUnchecked,
+        ] {
+            let task = CommitTask::<G1Affine, _>::new(&params, params_hash, &polys, &blinds);
+            let mut buf = vec![];
+            task.write(&mut buf, format).unwrap();
+
+            let (decoded, _) =
+                CommitTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &cache).unwrap();
+            assert_eq!(&decoded.blinds[..], &blinds[..]);
+            assert_eq!(decoded.commitments().unwrap(), local);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Distributed moves between the coefficient form and the extended domain
+//!
+//! The prover extends the grand product of each permutation set, and brings
+//! h(X) back to coefficient form, with FFTs over the whole extended domain.
+//! These are spread across the workers with [`Dispatcher::fft`].
+//!
+//! [`Dispatcher::fft`]: crate::distributed_util::dispatcher::Dispatcher::fft
+use futures::future::LocalBoxFuture;
+
+use crate::{
+    arithmetic::CurveAffine,
+    distributed_util::plonk::commit::DispatchedCommitter,
+    helpers::SerdePrimeField,
+    plonk::Error,
+    poly::{Coeff, EvaluationDomain, ExtendedLagrangeCoeff, Polynomial},
+};
+
+/// Runs the FFTs over the extended domain on behalf of the prover.
+pub trait DomainTransformer<C: CurveAffine> {
+    /// Same as [`EvaluationDomain::coeff_to_extended`].
+    fn coeff_to_extended<'a>(
+        &'a mut self,
+        domain: &'a EvaluationDomain<C::Scalar>,
+        a: Polynomial<C::Scalar, Coeff>,
+    ) -> LocalBoxFuture<'a, Result<Polynomial<C::Scalar, ExtendedLagrangeCoeff>, Error>>;
+
+    /// Same as [`EvaluationDomain::extended_to_coeff`].
+    fn extended_to_coeff<'a>(
+        &'a mut self,
+        domain: &'a EvaluationDomain<C::Scalar>,
+        a: Polynomial<C::Scalar, ExtendedLagrangeCoeff>,
+    ) -> LocalBoxFuture<'a, Result<Vec<C::Scalar>, Error>>;
+}
+
+impl<'a, 'params, C, P> DomainTransformer<C> for DispatchedCommitter<'a, 'params, P>
+where
+    C: CurveAffine,
+    C::Scalar: SerdePrimeField,
+{
+    fn coeff_to_extended<'b>(
+        &'b mut self,
+        domain: &'b EvaluationDomain<C::Scalar>,
+        a: Polynomial<C::Scalar, Coeff>,
+    ) -> LocalBoxFuture<'b, Result<Polynomial<C::Scalar, ExtendedLagrangeCoeff>, Error>> {
+        Box::pin(self.dispatcher.coeff_to_extended(domain, a))
+    }
+
+    fn extended_to_coeff<'b>(
+        &'b mut self,
+        domain: &'b EvaluationDomain<C::Scalar>,
+        a: Polynomial<C::Scalar, ExtendedLagrangeCoeff>,
+    ) -> LocalBoxFuture<'b, Result<Vec<C::Scalar>, Error>> {
+        Box::pin(self.dispatcher.extended_to_coeff(domain, a))
+    }
+}
@@ -0,0 +1,882 @@
+//! Distributed evaluation of the quotient polynomial h(X)
+//!
+//! The dispatcher splits the rows of the extended domain into a range per
+//! worker. A worker is sent the cosets of the proving key over its range,
+//! widened by the rows the constraints reach before and after it, along with
+//! the polynomials of each circuit in coefficient form. It extends these one
+//! at a time, keeps their values over the widened range, and answers with
+//! h(X) over its range. The constraints are evaluated row by row, so the
+//! ranges put back together are the h(X) the prover computes locally.
+//!
+//! Every worker extends every polynomial of the circuits, so the FFTs are
+//! repeated on each worker and the coefficients sent to each of them. In
+//! exchange the dispatcher never holds an extended coset of a circuit, which
+//! is what bounds the memory of the prover.
+use futures::future::LocalBoxFuture;
+use std::{borrow::Borrow, borrow::Cow, io};
+
+use crate::{
+    arithmetic::CurveAffine,
+    distributed_util::net::{
+        invalid_data, read_columns, read_domain, read_header, read_scalars, read_u32, read_varint,
+        write_columns, write_domain, write_header, write_scalars, write_u32, write_varint, Encode,
+    },
+    helpers::SerdePrimeField,
+    plonk::{
+        evaluation::{
+            Calculation, CalculationInfo, CircuitCosets, Cosets, Evaluator, GraphEvaluator,
+            HChallenges, HLayout, HPolys, RowWindow, ValueSource,
+        },
+        Error,
+    },
+    poly::{Coeff, EvaluationDomain, ExtendedLagrangeCoeff, Polynomial, Rotation},
+    SerdeFormat,
+};
+
+use super::commit::DispatchedCommitter;
+
+type Coset<F> = Polynomial<F, ExtendedLagrangeCoeff>;
+
+/// Evaluates h(X) on behalf of the prover.
+pub trait QuotientEvaluator<C: CurveAffine> {
+    /// Returns h(X) over the extended `domain`, out of `polys`.
+    fn evaluate_h<'a>(
+        &'a mut self,
+        evaluator: &'a Evaluator<C>,
+        layout: &'a HLayout<C::ScalarExt>,
+        domain: &'a EvaluationDomain<C::ScalarExt>,
+        polys: &'a HPolys<'a, C::ScalarExt>,
+        challenges: &'a HChallenges<C::ScalarExt>,
+    ) -> LocalBoxFuture<'a, Result<Vec<C::ScalarExt>, Error>>;
+}
+
+impl<'a, 'params, C, P> QuotientEvaluator<C> for DispatchedCommitter<'a, 'params, P>
+where
+    C: CurveAffine,
+    C::ScalarExt: SerdePrimeField,
+{
+    fn evaluate_h<'b>(
+        &'b mut self,
+        evaluator: &'b Evaluator<C>,
+        layout: &'b HLayout<C::ScalarExt>,
+        domain: &'b EvaluationDomain<C::ScalarExt>,
+        polys: &'b HPolys<'b, C::ScalarExt>,
+        challenges: &'b HChallenges<C::ScalarExt>,
+    ) -> LocalBoxFuture<'b, Result<Vec<C::ScalarExt>, Error>> {
+        Box::pin(
+            self.dispatcher
+                .evaluate_h(evaluator, layout, domain, polys, challenges),
+        )
+    }
+}
+
+/// Cosets of the proving key, over a window of the extended domain, owned by
+/// the task
+#[derive(Debug, Clone)]
+pub struct WindowCosets<F> {
+    pub fixed: Vec<Coset<F>>,
+    pub l0: Coset<F>,
+    pub l_last: Coset<F>,
+    pub l_active_row: Coset<F>,
+    pub permutations: Vec<Coset<F>>,
+}
+
+impl<F: Copy> WindowCosets<F> {
+    /// Copies the values of the cosets of `polys` in `window`.
+    pub fn new(polys: &HPolys<F>, window: RowWindow) -> Self {
+        let slice_all = |polys: &[Coset<F>]| -> Vec<_> {
+            polys.iter().map(|poly| window.slice(poly)).collect()
+        };
+        WindowCosets {
+            fixed: slice_all(polys.fixed),
+            l0: window.slice(polys.l0),
+            l_last: window.slice(polys.l_last),
+            l_active_row: window.slice(polys.l_active_row),
+            permutations: slice_all(polys.permutations),
+        }
+    }
+}
+
+/// Polynomials of a single circuit, borrowed from the prover by the
+/// dispatcher and owned by the task once decoded
+#[derive(Debug, Clone)]
+pub struct TaskCircuit<'a, F: Clone> {
+    pub advice: Vec<Cow<'a, Polynomial<F, Coeff>>>,
+    pub instance: Vec<Cow<'a, Polynomial<F, Coeff>>>,
+    /// Product of each permutation set, over the window
+    pub permutation_products: Vec<Coset<F>>,
+    pub lookups: Vec<[Cow<'a, Polynomial<F, Coeff>>; 3]>,
+    pub shuffles: Vec<Cow<'a, Polynomial<F, Coeff>>>,
+}
+
+/// Values of the polynomials of a circuit over the window of a task
+struct ExtendedCircuit<F> {
+    advice: Vec<Coset<F>>,
+    instance: Vec<Coset<F>>,
+    lookups: Vec<[Coset<F>; 3]>,
+    shuffles: Vec<Coset<F>>,
+}
+
+/// Distributed request to evaluate h(X) over a range of rows
+///
+/// The dispatcher builds the task from the borrowed evaluator of the proving
+/// key and the polynomials of the prover, and a worker decodes an owned copy
+/// of it with [`EvaluateHTask::read`].
+#[derive(Debug, Clone)]
+pub struct EvaluateHTask<'a, C: CurveAffine> {
+    pub evaluator: Cow<'a, Evaluator<C>>,
+    pub layout: Cow<'a, HLayout<C::ScalarExt>>,
+    pub challenges: Cow<'a, HChallenges<C::ScalarExt>>,
+    pub domain: Cow<'a, EvaluationDomain<C::ScalarExt>>,
+    pub window: RowWindow,
+    pub cosets: WindowCosets<C::ScalarExt>,
+    pub circuits: Vec<TaskCircuit<'a, C::ScalarExt>>,
+}
+
+impl<'a, C: CurveAffine> EvaluateHTask<'a, C> {
+    /// Builds the task evaluating the rows of `window`, a window shrunk to
+    /// the reach of `evaluator`, out of `polys`.
+    pub fn new(
+        evaluator: &'a Evaluator<C>,
+        layout: &'a HLayout<C::ScalarExt>,
+        challenges: &'a HChallenges<C::ScalarExt>,
+        domain: &'a EvaluationDomain<C::ScalarExt>,
+        polys: &HPolys<'a, C::ScalarExt>,
+        window: RowWindow,
+    ) -> Self {
+        let borrow_all = |polys: &'a [Polynomial<C::ScalarExt, Coeff>]| -> Vec<_> {
+            polys.iter().map(Cow::Borrowed).collect()
+        };
+        EvaluateHTask {
+            evaluator: Cow::Borrowed(evaluator),
+            layout: Cow::Borrowed(layout),
+            challenges: Cow::Borrowed(challenges),
+            domain: Cow::Borrowed(domain),
+            window,
+            cosets: WindowCosets::new(polys, window),
+            circuits: polys
+                .circuits
+                .iter()
+                .map(|circuit| TaskCircuit {
+                    advice: borrow_all(circuit.advice),
+                    instance: borrow_all(circuit.instance),
+                    permutation_products: circuit
+                        .permutation_products
+                        .iter()
+                        .map(|poly| window.slice(poly))
+                        .collect(),
+                    lookups: circuit
+                        .lookups
+                        .iter()
+                        .map(|&polys| polys.map(Cow::Borrowed))
+                        .collect(),
+                    shuffles: circuit
+                        .shuffles
+                        .iter()
+                        .map(|&poly| Cow::Borrowed(poly))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Computes h(X) over the rows of the task, in order.
+    ///
+    /// The polynomials of the circuits are extended one at a time, and only
+    /// their values over the window are kept.
+    pub fn eval(&self) -> Vec<C::ScalarExt> {
+        let extend_all = |polys: &[Cow<Polynomial<C::ScalarExt, Coeff>>]| -> Vec<_> {
+            polys.iter().map(|poly| self.extend(poly)).collect()
+        };
+        let extended: Vec<_> = self
+            .circuits
+            .iter()
+            .map(|circuit| ExtendedCircuit {
+                advice: extend_all(&circuit.advice),
+                instance: extend_all(&circuit.instance),
+                lookups: circuit
+                    .lookups
+                    .iter()
+                    .map(|[product, input, table]| {
+                        [self.extend(product), self.extend(input), self.extend(table)]
+                    })
+                    .collect(),
+                shuffles: extend_all(&circuit.shuffles),
+            })
+            .collect();
+
+        let cosets = Cosets {
+            fixed: &self.cosets.fixed,
+            l0: &self.cosets.l0,
+            l_last: &self.cosets.l_last,
+            l_active_row: &self.cosets.l_active_row,
+            permutations: &self.cosets.permutations,
+            circuits: self
+                .circuits
+                .iter()
+                .zip(extended.iter())
+                .map(|(circuit, extended)| CircuitCosets {
+                    advice: &extended.advice,
+                    instance: &extended.instance,
+                    permutation_products: circuit.permutation_products.iter().collect(),
+                    lookups: extended
+                        .lookups
+                        .iter()
+                        .map(|[product, input, table]| [product, input, table])
+                        .collect(),
+                    shuffles: extended.shuffles.iter().collect(),
+                })
+                .collect(),
+        };
+        self.evaluator
+            .evaluate_h_rows(&self.layout, &cosets, &self.challenges, self.window)
+    }
+
+    /// Returns the values of `poly` over the window of the task.
+    fn extend(&self, poly: &Polynomial<C::ScalarExt, Coeff>) -> Coset<C::ScalarExt> {
+        self.window
+            .slice(&self.domain.coeff_to_extended(poly.clone()))
+    }
+}
+
+impl<'a, C> EvaluateHTask<'a, C>
+where
+    C: CurveAffine,
+    C::ScalarExt: SerdePrimeField,
+{
+    /// Encodes the task.
+    ///
+    /// The layout is the task header, the evaluator, the layout of the domain,
+    /// the challenges, `k` and `j` of the domain, the window, the cosets of
+    /// the proving key over the window, and the polynomials of each circuit.
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+
+        write_graph(writer, &self.evaluator.custom_gates, format)?;
+        write_u32(writer, self.evaluator.lookups.len() as u32)?;
+        for graph in self.evaluator.lookups.iter() {
+            write_graph(writer, graph, format)?;
+        }
+        write_u32(writer, self.evaluator.shuffles.len() as u32)?;
+        for graph in self.evaluator.shuffles.iter() {
+            write_graph(writer, graph, format)?;
+        }
+
+        write_u32(writer, self.layout.size as u32)?;
+        write_u32(writer, self.layout.rot_scale as u32)?;
+        SerdePrimeField::write(&self.layout.extended_omega, writer, format)?;
+        write_u32(writer, self.layout.last_rotation.0 as u32)?;
+        write_u32(writer, self.layout.chunk_len as u32)?;
+        write_columns(writer, &self.layout.permutation_columns)?;
+
+        write_scalars(writer, &self.challenges.challenges, format)?;
+        let challenges = &self.challenges;
+        for challenge in [
+            challenges.y,
+            challenges.beta,
+            challenges.gamma,
+            challenges.theta,
+        ] {
+            SerdePrimeField::write(&challenge, writer, format)?;
+        }
+
+        write_domain(writer, &self.domain)?;
+
+        let window = &self.window;
+        for value in [window.offset, window.len, window.first, window.rows] {
+            write_u32(writer, value as u32)?;
+        }
+
+        let cosets = &self.cosets;
+        write_polys(writer, &cosets.fixed, format)?;
+        for poly in [&cosets.l0, &cosets.l_last, &cosets.l_active_row] {
+            writer.scalars(poly, format)?;
+        }
+        write_polys(writer, &cosets.permutations, format)?;
+        write_u32(writer, self.circuits.len() as u32)?;
+        for circuit in self.circuits.iter() {
+            write_polys(writer, &circuit.advice, format)?;
+            write_polys(writer, &circuit.instance, format)?;
+            write_polys(writer, &circuit.permutation_products, format)?;
+            write_u32(writer, circuit.lookups.len() as u32)?;
+            for polys in circuit.lookups.iter() {
+                for poly in polys.iter() {
+                    writer.scalars(poly, format)?;
+                }
+            }
+            write_polys(writer, &circuit.shuffles, format)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a task written with [`EvaluateHTask::write`], along with the
+    /// format the dispatcher used, which the answer is expected in.
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+    ) -> io::Result<(EvaluateHTask<'static, C>, SerdeFormat)> {
+        let format = read_header::<_, C::Scalar>(reader)?;
+
+        let custom_gates = read_graph(reader, format)?;
+        let lookups = (0..read_u32(reader)?)
+            .map(|_| read_graph(reader, format))
+            .collect::<io::Result<Vec<_>>>()?;
+        let shuffles = (0..read_u32(reader)?)
+            .map(|_| read_graph(reader, format))
+            .collect::<io::Result<Vec<_>>>()?;
+        let evaluator = Evaluator {
+            custom_gates,
+            lookups,
+            shuffles,
+        };
+
+        let size = read_u32(reader)? as usize;
+        let rot_scale = read_u32(reader)? as i32;
+        let extended_omega = <C::ScalarExt as SerdePrimeField>::read(reader, format)?;
+        let last_rotation = Rotation(read_u32(reader)? as i32);
+        let chunk_len = read_u32(reader)? as usize;
+        let permutation_columns = read_columns(reader)?;
+        let layout = HLayout {
+            size,
+            rot_scale,
+            extended_omega,
+            last_rotation,
+            chunk_len,
+            permutation_columns,
+        };
+
+        let challenges = read_scalars(reader, format)?;
+        let mut read_challenge = || <C::ScalarExt as SerdePrimeField>::read(reader, format);
+        let challenges = HChallenges {
+            challenges,
+            y: read_challenge()?,
+            beta: read_challenge()?,
+            gamma: read_challenge()?,
+            theta: read_challenge()?,
+        };
+
+        let domain = read_domain::<_, C::ScalarExt>(reader)?;
+        if domain.extended_len() != size
+            || domain.get_extended_omega() != extended_omega
+            || 1 << (domain.extended_k() - domain.k()) != rot_scale
+        {
+            return Err(invalid_data(format!(
+                "domain of k = {} and j = {} does not match the layout",
+                domain.k(),
+                domain.get_quotient_poly_degree() + 1
+            )));
+        }
+
+        let window = RowWindow {
+            offset: read_u32(reader)? as usize,
+            len: read_u32(reader)? as usize,
+            first: read_u32(reader)? as usize,
+            rows: read_u32(reader)? as usize,
+        };
+        if window.len > size || window.offset >= size || window.first + window.rows > window.len {
+            return Err(invalid_data(format!(
+                "window {:?} does not fit a domain of {} rows",
+                window, size
+            )));
+        }
+
+        let fixed = read_polys(reader, format)?;
+        let l0 = Coset::read(reader, format)?;
+        let l_last = Coset::read(reader, format)?;
+        let l_active_row = Coset::read(reader, format)?;
+        let permutations = read_polys(reader, format)?;
+        let cosets = WindowCosets {
+            fixed,
+            l0,
+            l_last,
+            l_active_row,
+            permutations,
+        };
+        let circuits = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(TaskCircuit {
+                    advice: read_coeffs(reader, format)?,
+                    instance: read_coeffs(reader, format)?,
+                    permutation_products: read_polys(reader, format)?,
+                    lookups: (0..read_u32(reader)?)
+                        .map(|_| {
+                            Ok([
+                                Cow::Owned(Polynomial::read(reader, format)?),
+                                Cow::Owned(Polynomial::read(reader, format)?),
+                                Cow::Owned(Polynomial::read(reader, format)?),
+                            ])
+                        })
+                        .collect::<io::Result<Vec<_>>>()?,
+                    shuffles: read_coeffs(reader, format)?,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if layout.chunk_len == 0 || layout.permutation_columns.len() != cosets.permutations.len() {
+            return Err(invalid_data(format!(
+                "{} permutation polynomials for {} columns in sets of {}",
+                cosets.permutations.len(),
+                layout.permutation_columns.len(),
+                layout.chunk_len
+            )));
+        }
+        for circuit in circuits.iter() {
+            if circuit.lookups.len() != evaluator.lookups.len()
+                || circuit.shuffles.len() * 2 != evaluator.shuffles.len()
+            {
+                return Err(invalid_data(format!(
+                    "{} lookups and {} shuffles for {} and {} evaluators",
+                    circuit.lookups.len(),
+                    circuit.shuffles.len(),
+                    evaluator.lookups.len(),
+                    evaluator.shuffles.len()
+                )));
+            }
+        }
+
+        let windows = cosets
+            .fixed
+            .iter()
+            .chain([&cosets.l0, &cosets.l_last, &cosets.l_active_row])
+            .chain(cosets.permutations.iter())
+            .chain(
+                circuits
+                    .iter()
+                    .flat_map(|circuit| circuit.permutation_products.iter()),
+            );
+        for poly in windows {
+            if poly.len() != window.len {
+                return Err(invalid_data(format!(
+                    "coset of {} values in a window of {}",
+                    poly.len(),
+                    window.len
+                )));
+            }
+        }
+        let n = 1 << domain.k();
+        let coeffs = circuits.iter().flat_map(|circuit| {
+            circuit
+                .advice
+                .iter()
+                .chain(circuit.instance.iter())
+                .chain(circuit.lookups.iter().flatten())
+                .chain(circuit.shuffles.iter())
+        });
+        for poly in coeffs {
+            if poly.len() != n {
+                return Err(invalid_data(format!(
+                    "polynomial of {} coefficients in a domain of {}",
+                    poly.len(),
+                    n
+                )));
+            }
+        }
+
+        Ok((
+            EvaluateHTask {
+                evaluator: Cow::Owned(evaluator),
+                layout: Cow::Owned(layout),
+                challenges: Cow::Owned(challenges),
+                domain: Cow::Owned(domain),
+                window,
+                cosets,
+                circuits,
+            },
+            format,
+        ))
+    }
+}
+
+/// Writes the constants of `graph` with [`write_scalars`], then its
+/// rotations, its calculations, see [`write_calculation`], and its number of
+/// intermediates.
+fn write_graph<W: io::Write, C: CurveAffine>(
+    writer: &mut W,
+    graph: &GraphEvaluator<C>,
+    format: SerdeFormat,
+) -> io::Result<()>
+where
+    C::ScalarExt: SerdePrimeField,
+{
+    write_scalars(writer, &graph.constants, format)?;
+    write_u32(writer, graph.rotations.len() as u32)?;
+    for &rotation in graph.rotations.iter() {
+        write_u32(writer, rotation as u32)?;
+    }
+    write_u32(writer, graph.calculations.len() as u32)?;
+    for info in graph.calculations.iter() {
+        write_calculation(writer, &info.calculation)?;
+        write_varint(writer, info.target as u64)?;
+    }
+    write_u32(writer, graph.num_intermediates as u32)
+}
+
+/// Reads a graph written with [`write_graph`].
+fn read_graph<R: io::Read, C: CurveAffine>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<GraphEvaluator<C>>
+where
+    C::ScalarExt: SerdePrimeField,
+{
+    let constants = read_scalars(reader, format)?;
+    let rotations = (0..read_u32(reader)?)
+        .map(|_| Ok(read_u32(reader)? as i32))
+        .collect::<io::Result<Vec<_>>>()?;
+    let calculations = (0..read_u32(reader)?)
+        .map(|_| {
+            Ok(CalculationInfo {
+                calculation: read_calculation(reader)?,
+                target: read_index(reader)?,
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let num_intermediates = read_u32(reader)? as usize;
+    if calculations
+        .iter()
+        .any(|calculation| calculation.target >= num_intermediates)
+    {
+        return Err(invalid_data(format!(
+            "calculation target out of {} intermediates",
+            num_intermediates
+        )));
+    }
+    Ok(GraphEvaluator {
+        constants,
+        rotations,
+        calculations,
+        num_intermediates,
+    })
+}
+
+/// Writes a count-prefixed list of polynomials, owned or borrowed.
+fn write_polys<'a, W, F, B, P>(
+    writer: &mut W,
+    polys: &'a [P],
+    format: SerdeFormat,
+) -> io::Result<()>
+where
+    W: Encode<'a>,
+    F: SerdePrimeField,
+    B: 'a,
+    P: Borrow<Polynomial<F, B>>,
+{
+    write_u32(writer, polys.len() as u32)?;
+    for poly in polys {
+        let poly: &Polynomial<F, B> = poly.borrow();
+        writer.scalars(poly, format)?;
+    }
+    Ok(())
+}
+
+fn read_polys<R: io::Read, F: SerdePrimeField>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<Vec<Coset<F>>> {
+    (0..read_u32(reader)?)
+        .map(|_| Coset::read(reader, format))
+        .collect()
+}
+
+/// Reads polynomials in coefficient form written with [`write_polys`].
+fn read_coeffs<R: io::Read, F: SerdePrimeField>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<Vec<Cow<'static, Polynomial<F, Coeff>>>> {
+    (0..read_u32(reader)?)
+        .map(|_| Ok(Cow::Owned(Polynomial::read(reader, format)?)))
+        .collect()
+}
+
+/// Reads an index written as a varint.
+fn read_index<R: io::Read>(reader: &mut R) -> io::Result<usize> {
+    usize::try_from(read_varint(reader)?).map_err(invalid_data)
+}
+
+/// Writes a value source as its tag followed by its indices as varints.
+fn write_value_source<W: io::Write>(writer: &mut W, source: &ValueSource) -> io::Result<()> {
+    let mut write = |tag: u8, indices: &[usize]| -> io::Result<()> {
+        writer.write_all(&[tag])?;
+        for &index in indices {
+            write_varint(writer, index as u64)?;
+        }
+        Ok(())
+    };
+    match *source {
+        ValueSource::Constant(index) => write(0x00, &[index]),
+        ValueSource::Intermediate(index) => write(0x01, &[index]),
+        ValueSource::Fixed(column, rotation) => write(0x02, &[column, rotation]),
+        ValueSource::Advice(column, rotation) => write(0x03, &[column, rotation]),
+        ValueSource::Instance(column, rotation) => write(0x04, &[column, rotation]),
+        ValueSource::Challenge(index) => write(0x05, &[index]),
+        ValueSource::Beta() => write(0x06, &[]),
+        ValueSource::Gamma() => write(0x07, &[]),
+        ValueSource::Theta() => write(0x08, &[]),
+        ValueSource::Y() => write(0x09, &[]),
+        ValueSource::PreviousValue() => write(0x0a, &[]),
+    }
+}
+
+/// Reads a value source written with [`write_value_source`].
+fn read_value_source<R: io::Read>(reader: &mut R) -> io::Result<ValueSource> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    Ok(match tag[0] {
+        0x00 => ValueSource::Constant(read_index(reader)?),
+        0x01 => ValueSource::Intermediate(read_index(reader)?),
+        0x02 => ValueSource::Fixed(read_index(reader)?, read_index(reader)?),
+        0x03 => ValueSource::Advice(read_index(reader)?, read_index(reader)?),
+        0x04 => ValueSource::Instance(read_index(reader)?, read_index(reader)?),
+        0x05 => ValueSource::Challenge(read_index(reader)?),
+        0x06 => ValueSource::Beta(),
+        0x07 => ValueSource::Gamma(),
+        0x08 => ValueSource::Theta(),
+        0x09 => ValueSource::Y(),
+        0x0a => ValueSource::PreviousValue(),
+        tag => return Err(invalid_data(format!("unknown value source {}", tag))),
+    })
+}
+
+/// Writes a calculation as its tag followed by its operands, see
+/// [`write_value_source`]. The terms of a Horner calculation are prefixed
+/// with their number, as a varint.
+fn write_calculation<W: io::Write>(writer: &mut W, calculation: &Calculation) -> io::Result<()> {
+    let mut write = |tag: u8, operands: &[&ValueSource]| -> io::Result<()> {
+        writer.write_all(&[tag])?;
+        for operand in operands {
+            write_value_source(writer, operand)?;
+        }
+        Ok(())
+    };
+    match calculation {
+        Calculation::Add(a, b) => write(0x00, &[a, b]),
+        Calculation::Sub(a, b) => write(0x01, &[a, b]),
+        Calculation::Mul(a, b) => write(0x02, &[a, b]),
+        Calculation::Square(a) => write(0x03, &[a]),
+        Calculation::Double(a) => write(0x04, &[a]),
+        Calculation::Negate(a) => write(0x05, &[a]),
+        Calculation::Store(a) => write(0x06, &[a]),
+        Calculation::Horner(start, terms, factor) => {
+            write(0x07, &[start])?;
+            write_varint(writer, terms.len() as u64)?;
+            for term in terms {
+                write_value_source(writer, term)?;
+            }
+            write_value_source(writer, factor)
+        }
+    }
+}
+
+/// Reads a calculation written with [`write_calculation`].
+fn read_calculation<R: io::Read>(reader: &mut R) -> io::Result<Calculation> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    let mut source = || read_value_source(reader);
+    Ok(match tag[0] {
+        0x00 => Calculation::Add(source()?, source()?),
+        0x01 => Calculation::Sub(source()?, source()?),
+        0x02 => Calculation::Mul(source()?, source()?),
+        0x03 => Calculation::Square(source()?),
+        0x04 => Calculation::Double(source()?),
+        0x05 => Calculation::Negate(source()?),
+        0x06 => Calculation::Store(source()?),
+        0x07 => {
+            let start = source()?;
+            let terms = (0..read_varint(reader)?)
+                .map(|_| read_value_source(reader))
+                .collect::<io::Result<Vec<_>>>()?;
+            Calculation::Horner(start, terms, read_value_source(reader)?)
+        }
+        tag => return Err(invalid_data(format!("unknown calculation {}", tag))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EvaluateHTask;
+    use crate::{
+        plonk::{
+            evaluation::{
+                CircuitCosets, CircuitPolys, Cosets, Evaluator, HChallenges, HLayout, HPolys,
+                RowWindow,
+            },
+            ConstraintSystem,
+        },
+        poly::{Coeff, EvaluationDomain, ExtendedLagrangeCoeff, Polynomial, Rotation},
+        SerdeFormat,
+    };
+    use ff::Field;
+    use halo2curves::bn256::{Fr, G1Affine};
+    use rand_core::OsRng;
+
+    /// Size of the extended domain of [`domain`].
+    const SIZE: usize = 64;
+
+    /// Returns a domain of 32 rows extended to [`SIZE`].
+    fn domain() -> EvaluationDomain<Fr> {
+        EvaluationDomain::new(3, 5)
+    }
+
+    fn random_coset() -> Polynomial<Fr, ExtendedLagrangeCoeff> {
+        Polynomial::from_values((0..SIZE).map(|_| Fr::random(OsRng)).collect())
+    }
+
+    fn random_cosets(len: usize) -> Vec<Polynomial<Fr, ExtendedLagrangeCoeff>> {
+        (0..len).map(|_| random_coset()).collect()
+    }
+
+    fn random_polys(len: usize) -> Vec<Polynomial<Fr, Coeff>> {
+        (0..len)
+            .map(|_| Polynomial::from_values((0..SIZE / 2).map(|_| Fr::random(OsRng)).collect()))
+            .collect()
+    }
+
+    /// Returns an evaluator with a gate, a lookup and a shuffle reaching a few
+    /// rows both ways, along with a layout with a single permutation set.
+    fn evaluator() -> (Evaluator<G1Affine>, HLayout<Fr>) {
+        let mut meta = ConstraintSystem::<Fr>::default();
+        let (a, b) = (meta.advice_column(), meta.advice_column());
+        let f = meta.fixed_column();
+        let i = meta.instance_column();
+        meta.create_gate("gate", |meta| {
+            let a_next = meta.query_advice(a, Rotation::next());
+            let a_prev = meta.query_advice(a, Rotation::prev());
+            let b = meta.query_advice(b, Rotation::cur());
+            let f = meta.query_fixed(f, Rotation::cur());
+            let i = meta.query_instance(i, Rotation(-2));
+            vec![f * (a_next * b - a_prev) + i]
+        });
+        meta.lookup_any("lookup", |meta| {
+            let a = meta.query_advice(a, Rotation::cur());
+            let f = meta.query_fixed(f, Rotation::cur());
+            vec![(a, f)]
+        });
+        meta.shuffle("shuffle", |meta| {
+            let b = meta.query_advice(b, Rotation::cur());
+            let a = meta.query_advice(a, Rotation(3));
+            vec![(b, a)]
+        });
+
+        let layout = HLayout {
+            size: SIZE,
+            rot_scale: 2,
+            extended_omega: domain().get_extended_omega(),
+            last_rotation: Rotation(-4),
+            chunk_len: 1,
+            permutation_columns: vec![a.into()],
+        };
+        (Evaluator::new(&meta), layout)
+    }
+
+    #[test]
+    fn test_row_window_shrink() {
+        let window = RowWindow::full(16, 0..4).shrink(2, 3);
+        assert_eq!(
+            window,
+            RowWindow {
+                offset: 14,
+                len: 9,
+                first: 2,
+                rows: 4
+            }
+        );
+
+        // A window reaching over the whole domain is left whole.
+        let window = RowWindow::full(16, 4..12);
+        assert_eq!(window.shrink(4, 4), window);
+    }
+
+    #[test]
+    fn test_h_task_matches_local() {
+        let (evaluator, layout) = evaluator();
+        let challenges = HChallenges {
+            challenges: vec![],
+            y: Fr::random(OsRng),
+            beta: Fr::random(OsRng),
+            gamma: Fr::random(OsRng),
+            theta: Fr::random(OsRng),
+        };
+        let domain = domain();
+        let fixed = random_cosets(1);
+        let [l0, l_last, l_active_row] = [random_coset(), random_coset(), random_coset()];
+        let permutations = random_cosets(1);
+        let circuits: Vec<_> = (0..2)
+            .map(|_| {
+                (
+                    random_polys(2),
+                    random_polys(1),
+                    random_cosets(1),
+                    random_polys(4),
+                )
+            })
+            .collect();
+        let polys = HPolys {
+            fixed: &fixed,
+            l0: &l0,
+            l_last: &l_last,
+            l_active_row: &l_active_row,
+            permutations: &permutations,
+            circuits: circuits
+                .iter()
+                .map(|(advice, instance, products, polys)| CircuitPolys {
+                    advice,
+                    instance,
+                    permutation_products: vec![&products[0]],
+                    lookups: vec![[&polys[0], &polys[1], &polys[2]]],
+                    shuffles: vec![&polys[3]],
+                })
+                .collect(),
+        };
+
+        let extend_all = |polys: &[Polynomial<Fr, Coeff>]| -> Vec<_> {
+            polys
+                .iter()
+                .map(|poly| domain.coeff_to_extended(poly.clone()))
+                .collect()
+        };
+        let extended: Vec<_> = circuits
+            .iter()
+            .map(|(advice, instance, _, polys)| {
+                (extend_all(advice), extend_all(instance), extend_all(polys))
+            })
+            .collect();
+        let cosets = Cosets {
+            fixed: &fixed,
+            l0: &l0,
+            l_last: &l_last,
+            l_active_row: &l_active_row,
+            permutations: &permutations,
+            circuits: circuits
+                .iter()
+                .zip(extended.iter())
+                .map(
+                    |((_, _, products, _), (advice, instance, polys))| CircuitCosets {
+                        advice,
+                        instance,
+                        permutation_products: vec![&products[0]],
+                        lookups: vec![[&polys[0], &polys[1], &polys[2]]],
+                        shuffles: vec![&polys[3]],
+                    },
+                )
+                .collect(),
+        };
+        let expected = evaluator.evaluate_h_rows(
+            &layout,
+            &cosets,
+            &challenges,
+            RowWindow::full(SIZE, 0..SIZE),
+        );
+
+        // Every shrunk window evaluates the same rows as the whole cosets.
+        let (before, after) = evaluator.reach(&layout);
+        let window = RowWindow::full(SIZE, 60..64).shrink(before, after);
+        let task = EvaluateHTask::new(&evaluator, &layout, &challenges, &domain, &polys, window);
+        let mut payload = vec![];
+        task.write(&mut payload, SerdeFormat::RawBytes).unwrap();
+        let (task, _) = EvaluateHTask::<G1Affine>::read(&mut &payload[..]).unwrap();
+        assert_eq!(task.eval(), expected[60..]);
+    }
+}
@@ -0,0 +1,662 @@
+//! Distributed lookup arguments
+//!
+//! The prover compresses the input and table expressions of every lookup and
+//! draws all the randomness of the argument itself, in the order it would
+//! draw it when proving locally. The workers then permute the compressed
+//! expressions and compute the grand products, and commit to both, so the
+//! proof is the one the prover writes on its own.
+use futures::future::LocalBoxFuture;
+use group::Curve;
+use std::{borrow::Cow, io};
+
+use crate::{
+    arithmetic::CurveAffine,
+    distributed_util::{
+        net::{
+            invalid_data, read_header, read_points, read_scalars, read_u32, write_header,
+            write_points, write_scalars, write_u32, Encode, SerdeParams,
+        },
+        params::{ParamsCache, ParamsHash},
+    },
+    helpers::{SerdeCurveAffine, SerdePrimeField},
+    plonk::{
+        lookup::prover::{permute_values, product_values},
+        Error,
+    },
+    poly::{
+        commitment::{Blind, Params},
+        LagrangeCoeff, Polynomial,
+    },
+    SerdeFormat,
+};
+
+use super::commit::DispatchedCommitter;
+
+/// A lookup to permute, along with the randomness the prover drew for it
+#[derive(Debug, Clone)]
+pub struct PermuteInput<F> {
+    pub compressed_input: Polynomial<F, LagrangeCoeff>,
+    pub compressed_table: Polynomial<F, LagrangeCoeff>,
+    /// Values of the rows after the usable ones in the permuted input
+    pub input_blinding: Vec<F>,
+    /// Values of the rows after the usable ones in the permuted table
+    pub table_blinding: Vec<F>,
+    pub input_blind: Blind<F>,
+    pub table_blind: Blind<F>,
+}
+
+/// The permuted input and table of a lookup, and the commitments to them
+#[derive(Debug, Clone)]
+pub struct PermuteOutput<C: CurveAffine> {
+    pub permuted_input: Polynomial<C::Scalar, LagrangeCoeff>,
+    pub permuted_table: Polynomial<C::Scalar, LagrangeCoeff>,
+    pub input_commitment: C,
+    pub table_commitment: C,
+}
+
+/// A permuted lookup to compute the grand product of, along with the
+/// randomness the prover drew for it
+#[derive(Debug, Clone)]
+pub struct ProductInput<F> {
+    pub compressed_input: Polynomial<F, LagrangeCoeff>,
+    pub compressed_table: Polynomial<F, LagrangeCoeff>,
+    pub permuted_input: Polynomial<F, LagrangeCoeff>,
+    pub permuted_table: Polynomial<F, LagrangeCoeff>,
+    /// Values of the rows after the last one of the grand product
+    pub blinding: Vec<F>,
+    pub blind: Blind<F>,
+}
+
+/// The grand product of a lookup, and the commitment to it
+#[derive(Debug, Clone)]
+pub struct ProductOutput<C: CurveAffine> {
+    pub product: Polynomial<C::Scalar, LagrangeCoeff>,
+    pub commitment: C,
+}
+
+/// Runs the lookup arguments on behalf of the prover.
+pub trait LookupCommitter<C: CurveAffine> {
+    /// Permutes and commits to `lookups`, in order. A lookup whose input is
+    /// not in its table has no permutation and yields `None`.
+    fn permute_lookups<'a>(
+        &'a mut self,
+        lookups: &'a [PermuteInput<C::Scalar>],
+    ) -> LocalBoxFuture<'a, Result<Vec<Option<PermuteOutput<C>>>, Error>>;
+
+    /// Computes and commits to the grand products of `lookups`, in order.
+    fn lookup_products<'a>(
+        &'a mut self,
+        lookups: &'a [ProductInput<C::Scalar>],
+        beta: C::Scalar,
+        gamma: C::Scalar,
+    ) -> LocalBoxFuture<'a, Result<Vec<ProductOutput<C>>, Error>>;
+}
+
+impl<'a, 'params, C, P> LookupCommitter<C> for DispatchedCommitter<'a, 'params, P>
+where
+    C: SerdeCurveAffine,
+    C::Scalar: SerdePrimeField,
+    P: Params<'params, C> + SerdeParams,
+{
+    fn permute_lookups<'b>(
+        &'b mut self,
+        lookups: &'b [PermuteInput<C::Scalar>],
+    ) -> LocalBoxFuture<'b, Result<Vec<Option<PermuteOutput<C>>>, Error>> {
+        Box::pin(
+            self.dispatcher
+                .permute_lookups(self.params, self.params_hash, lookups),
+        )
+    }
+
+    fn lookup_products<'b>(
+        &'b mut self,
+        lookups: &'b [ProductInput<C::Scalar>],
+        beta: C::Scalar,
+        gamma: C::Scalar,
+    ) -> LocalBoxFuture<'b, Result<Vec<ProductOutput<C>>, Error>> {
+        Box::pin(self.dispatcher.lookup_products(
+            self.params,
+            self.params_hash,
+            lookups,
+            beta,
+            gamma,
+        ))
+    }
+}
+
+/// Permutes and commits to `lookup` on this machine.
+pub(crate) fn permute_lookup<'params, C: CurveAffine, P: Params<'params, C>>(
+    params: &P,
+    lookup: &PermuteInput<C::Scalar>,
+) -> Option<PermuteOutput<C>> {
+    let usable_rows = params.n() as usize - lookup.input_blinding.len();
+    let (mut permuted_input, mut permuted_table) = permute_values(
+        &lookup.compressed_input,
+        &lookup.compressed_table,
+        usable_rows,
+    )
+    .ok()?;
+    permuted_input.extend_from_slice(&lookup.input_blinding);
+    permuted_table.extend_from_slice(&lookup.table_blinding);
+
+    let permuted_input = Polynomial::from_values(permuted_input);
+    let permuted_table = Polynomial::from_values(permuted_table);
+    Some(PermuteOutput {
+        input_commitment: params
+            .commit_lagrange(&permuted_input, lookup.input_blind)
+            .to_affine(),
+        table_commitment: params
+            .commit_lagrange(&permuted_table, lookup.table_blind)
+            .to_affine(),
+        permuted_input,
+        permuted_table,
+    })
+}
+
+/// Computes and commits to the grand product of `lookup` on this machine.
+pub(crate) fn lookup_product<'params, C: CurveAffine, P: Params<'params, C>>(
+    params: &P,
+    lookup: &ProductInput<C::Scalar>,
+    beta: C::Scalar,
+    gamma: C::Scalar,
+) -> ProductOutput<C> {
+    let rows = params.n() as usize - lookup.blinding.len();
+    let mut product = product_values(
+        &lookup.compressed_input,
+        &lookup.compressed_table,
+        &lookup.permuted_input,
+        &lookup.permuted_table,
+        beta,
+        gamma,
+        rows,
+    );
+    product.extend_from_slice(&lookup.blinding);
+
+    let product = Polynomial::from_values(product);
+    ProductOutput {
+        commitment: params.commit_lagrange(&product, lookup.blind).to_affine(),
+        product,
+    }
+}
+
+/// Checks that every polynomial of a lookup task spans the `n` rows of the
+/// params and that the blinding rows leave at least one row to the argument.
+fn check_rows<'p, F: 'p>(
+    polys: impl IntoIterator<Item = &'p Polynomial<F, LagrangeCoeff>>,
+    blinding: &[usize],
+    n: usize,
+) -> io::Result<()> {
+    if let Some(poly) = polys.into_iter().find(|poly| poly.len() != n) {
+        return Err(invalid_data(format!(
+            "lookup column of {} rows, expected {}",
+            poly.len(),
+            n
+        )));
+    }
+    if blinding.iter().any(|&len| len >= n) {
+        return Err(invalid_data(format!(
+            "{:?} blinding rows out of {}",
+            blinding, n
+        )));
+    }
+    Ok(())
+}
+
+/// Distributed request to permute and commit to a batch of lookups
+#[derive(Debug, Clone)]
+pub struct LookupPermuteTask<'a, C: CurveAffine, P: Clone> {
+    pub params: Cow<'a, P>,
+    pub params_hash: ParamsHash,
+    pub lookups: Cow<'a, [PermuteInput<C::Scalar>]>,
+}
+
+impl<'a, C: CurveAffine, P: Clone> LookupPermuteTask<'a, C, P> {
+    /// Builds the task permuting `lookups`.
+    pub fn new(
+        params: &'a P,
+        params_hash: ParamsHash,
+        lookups: &'a [PermuteInput<C::Scalar>],
+    ) -> Self {
+        LookupPermuteTask {
+            params: Cow::Borrowed(params),
+            params_hash,
+            lookups: Cow::Borrowed(lookups),
+        }
+    }
+}
+
+impl<'a, C, P> LookupPermuteTask<'a, C, P>
+where
+    C: SerdeCurveAffine,
+    C::Scalar: SerdePrimeField,
+    P: Clone + SerdeParams,
+{
+    /// Encodes the task.
+    ///
+    /// The layout is the task header, the hash of the params, and the
+    /// count-prefixed list of lookups, each made of its compressed input and
+    /// table, the blinding rows of both and the two blinds.
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+        self.params_hash.write(writer)?;
+
+        write_u32(writer, self.lookups.len() as u32)?;
+        for lookup in self.lookups.iter() {
+            writer.scalars(&lookup.compressed_input, format)?;
+            writer.scalars(&lookup.compressed_table, format)?;
+            write_scalars(writer, &lookup.input_blinding, format)?;
+            write_scalars(writer, &lookup.table_blinding, format)?;
+            SerdePrimeField::write(&lookup.input_blind.0, writer, format)?;
+            SerdePrimeField::write(&lookup.table_blind.0, writer, format)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a task written with [`LookupPermuteTask::write`], along
+    /// with the format the dispatcher used, which the answer is expected in.
+    /// The params are looked up in `cache`.
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+        cache: &'a ParamsCache<P>,
+    ) -> io::Result<(LookupPermuteTask<'a, C, P>, SerdeFormat)> {
+        let format = read_header::<_, C::Scalar>(reader)?;
+        let params_hash = ParamsHash::read(reader)?;
+        let params = cache.get(&params_hash)?;
+
+        let lookups = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(PermuteInput {
+                    compressed_input: Polynomial::read(reader, format)?,
+                    compressed_table: Polynomial::read(reader, format)?,
+                    input_blinding: read_scalars(reader, format)?,
+                    table_blinding: read_scalars(reader, format)?,
+                    input_blind: Blind(<C::Scalar as SerdePrimeField>::read(reader, format)?),
+                    table_blind: Blind(<C::Scalar as SerdePrimeField>::read(reader, format)?),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok((
+            LookupPermuteTask {
+                params: Cow::Borrowed(params),
+                params_hash,
+                lookups: Cow::Owned(lookups),
+            },
+            format,
+        ))
+    }
+
+    /// Permutes and commits to the lookups of the task, in order.
+    pub fn outputs(&self) -> io::Result<Vec<Option<PermuteOutput<C>>>>
+    where
+        P: Params<'a, C>,
+    {
+        let n = self.params.n() as usize;
+        for lookup in self.lookups.iter() {
+            check_rows(
+                [&lookup.compressed_input, &lookup.compressed_table],
+                &[lookup.input_blinding.len(), lookup.table_blinding.len()],
+                n,
+            )?;
+            if lookup.input_blinding.len() != lookup.table_blinding.len() {
+                return Err(invalid_data(format!(
+                    "{} blinding rows for the input, {} for the table",
+                    lookup.input_blinding.len(),
+                    lookup.table_blinding.len()
+                )));
+            }
+        }
+        Ok(self
+            .lookups
+            .iter()
+            .map(|lookup| permute_lookup(&*self.params, lookup))
+            .collect())
+    }
+}
+
+/// Writes the answer to a [`LookupPermuteTask`]: for every lookup, a byte
+/// telling whether it has a permutation, followed by the permuted input and
+/// table and the commitments to them when it does.
+pub fn write_permuted<W: io::Write, C: SerdeCurveAffine>(
+    writer: &mut W,
+    outputs: &[Option<PermuteOutput<C>>],
+    format: SerdeFormat,
+) -> io::Result<()>
+where
+    C::Scalar: SerdePrimeField,
+{
+    write_u32(writer, outputs.len() as u32)?;
+    for output in outputs {
+        match output {
+            Some(output) => {
+                writer.write_all(&[1])?;
+                output.permuted_input.write(writer, format)?;
+                output.permuted_table.write(writer, format)?;
+                write_points(
+                    writer,
+                    &[output.input_commitment, output.table_commitment],
+                    format,
+                )?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+    }
+    Ok(())
+}
+
+/// Reads an answer written with [`write_permuted`].
+pub fn read_permuted<R: io::Read, C: SerdeCurveAffine>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<Vec<Option<PermuteOutput<C>>>>
+where
+    C::Scalar: SerdePrimeField,
+{
+    (0..read_u32(reader)?)
+        .map(|_| {
+            let mut permuted = [0u8; 1];
+            reader.read_exact(&mut permuted)?;
+            match permuted[0] {
+                0 => Ok(None),
+                1 => {
+                    let permuted_input = Polynomial::read(reader, format)?;
+                    let permuted_table = Polynomial::read(reader, format)?;
+                    match read_points(reader, format)?[..] {
+                        [input_commitment, table_commitment] => Ok(Some(PermuteOutput {
+                            permuted_input,
+                            permuted_table,
+                            input_commitment,
+                            table_commitment,
+                        })),
+                        _ => Err(invalid_data("expected two commitments per lookup")),
+                    }
+                }
+                tag => Err(invalid_data(format!("invalid permutation tag {}", tag))),
+            }
+        })
+        .collect()
+}
+
+/// Distributed request to compute and commit to the grand products of a batch
+/// of permuted lookups
+#[derive(Debug, Clone)]
+pub struct LookupProductTask<'a, C: CurveAffine, P: Clone> {
+    pub params: Cow<'a, P>,
+    pub params_hash: ParamsHash,
+    pub beta: C::Scalar,
+    pub gamma: C::Scalar,
+    pub lookups: Cow<'a, [ProductInput<C::Scalar>]>,
+}
+
+impl<'a, C: CurveAffine, P: Clone> LookupProductTask<'a, C, P> {
+    /// Builds the task computing the grand products of `lookups` with the
+    /// challenges `beta` and `gamma`.
+    pub fn new(
+        params: &'a P,
+        params_hash: ParamsHash,
+        lookups: &'a [ProductInput<C::Scalar>],
+        beta: C::Scalar,
+        gamma: C::Scalar,
+    ) -> Self {
+        LookupProductTask {
+            params: Cow::Borrowed(params),
+            params_hash,
+            beta,
+            gamma,
+            lookups: Cow::Borrowed(lookups),
+        }
+    }
+}
+
+impl<'a, C, P> LookupProductTask<'a, C, P>
+where
+    C: SerdeCurveAffine,
+    C::Scalar: SerdePrimeField,
+    P: Clone + SerdeParams,
+{
+    /// Encodes the task.
+    ///
+    /// The layout is the task header, the hash of the params, `beta` and
+    /// `gamma`, and the count-prefixed list of lookups, each made of its
+    /// compressed and permuted input and table, the blinding rows of the grand
+    /// product and its blind.
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+        self.params_hash.write(writer)?;
+        SerdePrimeField::write(&self.beta, writer, format)?;
+        SerdePrimeField::write(&self.gamma, writer, format)?;
+
+        write_u32(writer, self.lookups.len() as u32)?;
+        for lookup in self.lookups.iter() {
+            writer.scalars(&lookup.compressed_input, format)?;
+            writer.scalars(&lookup.compressed_table, format)?;
+            writer.scalars(&lookup.permuted_input, format)?;
+            writer.scalars(&lookup.permuted_table, format)?;
+            write_scalars(writer, &lookup.blinding, format)?;
+            SerdePrimeField::write(&lookup.blind.0, writer, format)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a task written with [`LookupProductTask::write`], along
+    /// with the format the dispatcher used, which the answer is expected in.
+    /// The params are looked up in `cache`.
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+        cache: &'a ParamsCache<P>,
+    ) -> io::Result<(LookupProductTask<'a, C, P>, SerdeFormat)> {
+        let format = read_header::<_, C::Scalar>(reader)?;
+        let params_hash = ParamsHash::read(reader)?;
+        let params = cache.get(&params_hash)?;
+        let beta = <C::Scalar as SerdePrimeField>::read(reader, format)?;
+        let gamma = <C::Scalar as SerdePrimeField>::read(reader, format)?;
+
+        let lookups = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(ProductInput {
+                    compressed_input: Polynomial::read(reader, format)?,
+                    compressed_table: Polynomial::read(reader, format)?,
+                    permuted_input: Polynomial::read(reader, format)?,
+                    permuted_table: Polynomial::read(reader, format)?,
+                    blinding: read_scalars(reader, format)?,
+                    blind: Blind(<C::Scalar as SerdePrimeField>::read(reader, format)?),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok((
+            LookupProductTask {
+                params: Cow::Borrowed(params),
+                params_hash,
+                beta,
+                gamma,
+                lookups: Cow::Owned(lookups),
+            },
+            format,
+        ))
+    }
+
+    /// Computes and commits to the grand products of the task, in order.
+    pub fn outputs(&self) -> io::Result<Vec<ProductOutput<C>>>
+    where
+        P: Params<'a, C>,
+    {
+        let n = self.params.n() as usize;
+        for lookup in self.lookups.iter() {
+            check_rows(
+                [
+                    &lookup.compressed_input,
+                    &lookup.compressed_table,
+                    &lookup.permuted_input,
+                    &lookup.permuted_table,
+                ],
+                &[lookup.blinding.len()],
+                n,
+            )?;
+        }
+        Ok(self
+            .lookups
+            .iter()
+            .map(|lookup| lookup_product(&*self.params, lookup, self.beta, self.gamma))
+            .collect())
+    }
+}
+
+/// Writes the answer to a [`LookupProductTask`]: the grand product of
+/// every lookup followed by the commitment to it.
+pub fn write_products<W: io::Write, C: SerdeCurveAffine>(
+    writer: &mut W,
+    outputs: &[ProductOutput<C>],
+    format: SerdeFormat,
+) -> io::Result<()>
+where
+    C::Scalar: SerdePrimeField,
+{
+    write_u32(writer, outputs.len() as u32)?;
+    for output in outputs {
+        output.product.write(writer, format)?;
+        write_points(writer, &[output.commitment], format)?;
+    }
+    Ok(())
+}
+
+/// Reads an answer written with [`write_products`].
+pub fn read_products<R: io::Read, C: SerdeCurveAffine>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<Vec<ProductOutput<C>>>
+where
+    C::Scalar: SerdePrimeField,
+{
+    (0..read_u32(reader)?)
+        .map(|_| {
+            let product = Polynomial::read(reader, format)?;
+            match read_points(reader, format)?[..] {
+                [commitment] => Ok(ProductOutput {
+                    product,
+                    commitment,
+                }),
+                _ => Err(invalid_data("expected one commitment per lookup")),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        lookup_product, permute_lookup, LookupPermuteTask, LookupProductTask, PermuteInput,
+        ProductInput,
+    };
+    use crate::{
+        distributed_util::params::ParamsCache,
+        poly::{
+            commitment::{Blind, ParamsProver},
+            kzg::commitment::ParamsKZG,
+            Polynomial,
+        },
+        SerdeFormat,
+    };
+    use ff::Field;
+    use halo2curves::bn256::{Bn256, Fr, G1Affine};
+    use rand_core::OsRng;
+
+    const K: u32 = 4;
+    const BLINDING_FACTORS: usize = 3;
+
+    fn random_values(len: usize) -> Vec<Fr> {
+        (0..len).map(|_| Fr::random(OsRng)).collect()
+    }
+
+    /// Returns a lookup whose input is drawn from its table, unless `valid`
+    /// is false, in which case one input value is missing from the table.
+    fn permute_input(valid: bool) -> PermuteInput<Fr> {
+        let n = 1 << K;
+        let table = random_values(n);
+        let mut input = (0..n).map(|row| table[(row * 7) % 5]).collect::<Vec<_>>();
+        if !valid {
+            input[0] = Fr::random(OsRng);
+        }
+        PermuteInput {
+            compressed_input: Polynomial::from_values(input),
+            compressed_table: Polynomial::from_values(table),
+            input_blinding: random_values(BLINDING_FACTORS + 1),
+            table_blinding: random_values(BLINDING_FACTORS + 1),
+            input_blind: Blind(Fr::random(OsRng)),
+            table_blind: Blind(Fr::random(OsRng)),
+        }
+    }
+
+    fn product_input(params: &ParamsKZG<Bn256>, lookup: PermuteInput<Fr>) -> ProductInput<Fr> {
+        let permuted = permute_lookup::<G1Affine, _>(params, &lookup).unwrap();
+        ProductInput {
+            compressed_input: lookup.compressed_input,
+            compressed_table: lookup.compressed_table,
+            permuted_input: permuted.permuted_input,
+            permuted_table: permuted.permuted_table,
+            blinding: random_values(BLINDING_FACTORS),
+            blind: Blind(Fr::random(OsRng)),
+        }
+    }
+
+    #[test]
+    fn test_lookup_tasks_roundtrip() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let lookups = vec![permute_input(true), permute_input(false)];
+        let products = vec![product_input(&params, permute_input(true))];
+        let (beta, gamma) = (Fr::random(OsRng), Fr::random(OsRng));
+        let mut cache = ParamsCache::default();
+        let params_hash = cache.insert(params.clone()).unwrap();
+
+        for format in [
+            SerdeFormat::Processed,
+            SerdeFormat::RawBytes,
+            SerdeFormat::RawBytesUnchecked,
+        ] {
+            let mut buf = vec![];
+            LookupPermuteTask::<G1Affine, _>::new(&params, params_hash, &lookups)
+                .write(&mut buf, format)
+                .unwrap();
+            let (task, _) =
+                LookupPermuteTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &cache)
+                    .unwrap();
+            let permuted = task.outputs().unwrap();
+            assert_eq!(permuted.len(), 2);
+            assert!(permuted[1].is_none());
+            let permuted = permuted[0].as_ref().unwrap();
+            let local = permute_lookup::<G1Affine, _>(&params, &lookups[0]).unwrap();
+            assert_eq!(
+                permuted.permuted_input.to_vec(),
+                local.permuted_input.to_vec()
+            );
+            assert_eq!(
+                permuted.permuted_table.to_vec(),
+                local.permuted_table.to_vec()
+            );
+            assert_eq!(permuted.input_commitment, local.input_commitment);
+            assert_eq!(permuted.table_commitment, local.table_commitment);
+
+            let mut buf = vec![];
+            LookupProductTask::<G1Affine, _>::new(&params, params_hash, &products, beta, gamma)
+                .write(&mut buf, format)
+                .unwrap();
+            let (task, _) =
+                LookupProductTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &cache)
+                    .unwrap();
+            let product = &task.outputs().unwrap()[0];
+            let local = lookup_product::<G1Affine, _>(&params, &products[0], beta, gamma);
+            assert_eq!(product.product.to_vec(), local.product.to_vec());
+            assert_eq!(product.commitment, local.commitment);
+            // The grand product of a valid lookup wraps around to one.
+            assert_eq!(product.product[(1 << K) - BLINDING_FACTORS - 1], Fr::ONE);
+        }
+    }
+}
@@ -1,2 +1,34 @@
 //! Plonkish distributed api
+pub mod commit;
+pub mod domain;
+pub mod evaluation;
+pub mod lookup;
 pub mod permutation;
+
+use crate::arithmetic::CurveAffine;
+
+use self::{
+    commit::LagrangeCommitter, domain::DomainTransformer, evaluation::QuotientEvaluator,
+    lookup::LookupCommitter, permutation::prover::PermutationCommitter,
+};
+
+/// The parts of the prover that can be handed to the workers.
+pub trait ProverOffload<C: CurveAffine>:
+    LagrangeCommitter<C>
+    + LookupCommitter<C>
+    + PermutationCommitter<C>
+    + QuotientEvaluator<C>
+    + DomainTransformer<C>
+{
+}
+
+impl<C, T> ProverOffload<C> for T
+where
+    C: CurveAffine,
+    T: LagrangeCommitter<C>
+        + LookupCommitter<C>
+        + PermutationCommitter<C>
+        + QuotientEvaluator<C>
+        + DomainTransformer<C>,
+{
+}
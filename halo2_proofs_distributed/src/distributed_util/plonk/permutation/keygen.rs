@@ -1,48 +1,547 @@
 //! Keygen Definitions
 
-use ff::{Field, PrimeField};
-use halo2curves::{bn256::Bn256, CurveExt};
-use serde_derive::{Deserialize, Serialize};
+use std::{borrow::Cow, io, ops::Range};
 
 use crate::{
-    arithmetic::{parallelize, CurveAffine},
+    arithmetic::CurveAffine,
     distributed_util::{
-        dispatcher::{Taskable, WorkerMethod},
-        net::{from_bytes, to_bytes},
+        net::{
+            invalid_data, read_columns, read_domain, read_header, read_u32, read_varint,
+            write_columns, write_domain, write_header, write_u32, write_varint, SerdeParams,
+        },
+        params::{ParamsCache, ParamsHash},
     },
-    plonk::permutation::Argument,
-    poly::{commitment::Params, kzg::commitment::ParamsKZG, EvaluationDomain},
+    helpers::SerdeCurveAffine,
+    plonk::{permutation::keygen::commit_permutations, permutation::Argument},
+    poly::{commitment::Params, EvaluationDomain},
+    SerdeFormat,
 };
 
 /// Distributed request to perform keygen
+///
+/// The dispatcher builds the task from borrowed keygen state, while a worker
+/// decodes an owned copy of it with [`KeygenTask::read`]. A task only
+/// covers the permutation columns in `columns`, and `mapping` only holds the
+/// rows of the mapping for those columns.
 #[derive(Debug, Clone)]
-pub struct KeygenTaskKZG<'a, C: CurveAffine, P: Params<'a, C>> {
+pub struct KeygenTask<'a, C: CurveAffine, P> {
     pub params: &'a P,
-    pub domain: &'a EvaluationDomain<C::Scalar>,
-    pub p: &'a Argument,
-    pub mapping: Vec<Vec<(usize, usize)>>,
+    pub params_hash: ParamsHash,
+    pub domain: Cow<'a, EvaluationDomain<C::Scalar>>,
+    pub p: Cow<'a, Argument>,
+    pub columns: Range<usize>,
+    pub mapping: Cow<'a, [Vec<(usize, usize)>]>,
 }
 
-impl<'a, C: CurveAffine, P: Params<'a, C>> KeygenTaskKZG<'a, C, P> {
+impl<'a, C: CurveAffine, P> KeygenTask<'a, C, P> {
+    /// Builds the task for the permutation columns in `columns` out of the
+    /// full `mapping` of the assembly.
     pub fn new(
         params: &'a P,
+        params_hash: ParamsHash,
         domain: &'a EvaluationDomain<C::Scalar>,
         p: &'a Argument,
-        mapping: Vec<Vec<(usize, usize)>>,
+        mapping: &'a [Vec<(usize, usize)>],
+        columns: Range<usize>,
     ) -> Self {
-        KeygenTaskKZG {
+        KeygenTask {
             params,
-            domain,
-            p,
-            mapping,
+            params_hash,
+            domain: Cow::Borrowed(domain),
+            p: Cow::Borrowed(p),
+            mapping: Cow::Borrowed(&mapping[columns.clone()]),
+            columns,
         }
     }
+}
+
+impl<'a, C: SerdeCurveAffine, P: SerdeParams> KeygenTask<'a, C, P> {
+    /// Writes the task to a buffer. The layout is
+    ///
+    /// - the task header, see [`write_header`],
+    /// - the hash of the params, see [`ParamsHash`],
+    /// - the domain, see [`write_domain`],
+    /// - the permutation columns, see [`write_columns`],
+    /// - the range of columns covered by the task as `(start, end)`,
+    /// - the mapping of those columns, see [`write_mapping`].
+    pub fn write<W: io::Write>(&self, writer: &mut W, format: SerdeFormat) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+
+        self.params_hash.write(writer)?;
+
+        write_domain(writer, &self.domain)?;
+
+        write_columns(writer, &self.p.get_columns())?;
+
+        write_u32(writer, self.columns.start as u32)?;
+        write_u32(writer, self.columns.end as u32)?;
+        write_mapping(writer, self.columns.start, &self.mapping)
+    }
+
+    /// Reads a task written by [`KeygenTask::write`], returning it together
+    /// with the serde format the dispatcher used. The params are looked up in
+    /// `cache`, and must be for the size of the domain.
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+        cache: &'a ParamsCache<P>,
+    ) -> io::Result<(KeygenTask<'a, C, P>, SerdeFormat)>
+    where
+        P: Params<'a, C>,
+    {
+        let format = read_header::<_, C::Scalar>(reader)?;
+
+        let params_hash = ParamsHash::read(reader)?;
+        let params = cache.get(&params_hash)?;
+
+        let domain = read_domain::<_, C::Scalar>(reader)?;
+        if domain.k() != params.k() {
+            return Err(invalid_data(format!(
+                "domain of k = {} for params of k = {}",
+                domain.k(),
+                params.k()
+            )));
+        }
+
+        let mut p = Argument::new();
+        for column in read_columns(reader)? {
+            p.add_column(column);
+        }
+
+        let start = read_u32(reader)? as usize;
+        let end = read_u32(reader)? as usize;
+        if start > end || end > p.ncolumns() {
+            return Err(invalid_data(format!(
+                "column range {}..{} is out of bounds for {} permutation columns",
+                start,
+                end,
+                p.ncolumns()
+            )));
+        }
+        let columns = start..end;
+        let mapping = read_mapping(reader, columns.clone(), p.ncolumns(), 1 << domain.k())?;
+
+        Ok((
+            KeygenTask {
+                params,
+                params_hash,
+                domain: Cow::Owned(domain),
+                p: Cow::Owned(p),
+                columns,
+                mapping: Cow::Owned(mapping),
+            },
+            format,
+        ))
+    }
+
+    /// Computes the commitments to the permutation polynomials of the task,
+    /// tagged with the index of their column.
+    pub fn commitments(&self) -> Vec<(usize, C)>
+    where
+        P: Params<'a, C>,
+    {
+        let start = self.columns.start;
+        let commitments = commit_permutations(
+            self.params,
+            &self.domain,
+            &self.p,
+            self.columns.clone(),
+            |i, j| self.mapping[i - start][j],
+        );
+        self.columns.clone().zip(commitments).collect()
+    }
+}
+
+/// Writes the mapping of the permutation columns from `start` on as a sparse
+/// diff from the identity, in which every cell maps to itself. Only the cells
+/// tied to others by copy constraints are written, so the encoding grows with
+/// the number of copies rather than with the size of the circuit.
+///
+/// The layout is, for each column, the number of cells that do not map to
+/// themselves followed by the rows skipped before each of them and the
+/// `(column, row)` it maps to, all as varints. The number of rows is not
+/// written, it follows from the domain.
+pub fn write_mapping<W: io::Write>(
+    writer: &mut W,
+    start: usize,
+    mapping: &[Vec<(usize, usize)>],
+) -> io::Result<()> {
+    let nrows = mapping.get(0).map_or(0, |column| column.len());
+    for (i, column) in (start..).zip(mapping) {
+        if column.len() != nrows {
+            return Err(invalid_data(format!(
+                "column {} of the mapping has {} rows instead of {}",
+                i,
+                column.len(),
+                nrows
+            )));
+        }
+        let moved = column
+            .iter()
+            .enumerate()
+            .filter(|&(j, &cell)| cell != (i, j))
+            .collect::<Vec<_>>();
+        write_varint(writer, moved.len() as u64)?;
+        let mut next = 0;
+        for (j, &(to_column, to_row)) in moved {
+            write_varint(writer, (j - next) as u64)?;
+            write_varint(writer, to_column as u64)?;
+            write_varint(writer, to_row as u64)?;
+            next = j + 1;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the mapping of the permutation `columns` written with
+/// [`write_mapping`] and expands it back into a table of `nrows` rows per
+/// column. Every cell must map to a cell of the `ncolumns` permutation
+/// columns.
+pub fn read_mapping<R: io::Read>(
+    reader: &mut R,
+    columns: Range<usize>,
+    ncolumns: usize,
+    nrows: usize,
+) -> io::Result<Vec<Vec<(usize, usize)>>> {
+    let out_of_bounds =
+        |cell| invalid_data(format!("cell {:?} of the mapping is out of bounds", cell));
+    columns
+        .map(|i| {
+            let mut column = (0..nrows).map(|j| (i, j)).collect::<Vec<_>>();
+            let moved = read_varint(reader)?;
+            let mut next = 0u64;
+            for _ in 0..moved {
+                let j = next.saturating_add(read_varint(reader)?);
+                let cell = (read_varint(reader)?, read_varint(reader)?);
+                if j >= nrows as u64 {
+                    return Err(out_of_bounds((i as u64, j)));
+                }
+                if cell.0 >= ncolumns as u64 || cell.1 >= nrows as u64 {
+                    return Err(out_of_bounds(cell));
+                }
+                column[j as usize] = (cell.0 as usize, cell.1 as usize);
+                next = j + 1;
+            }
+            Ok(column)
+        })
+        .collect()
+}
 
-    pub fn zero(&self) -> <<C as CurveAffine>::CurveExt as CurveExt>::ScalarExt {
-        C::Scalar::ZERO
+/// Writes the commitments computed for a keygen task as a count-prefixed list
+/// of `(column, commitment)` pairs.
+pub fn write_commitments<W: io::Write, C: SerdeCurveAffine>(
+    writer: &mut W,
+    commitments: &[(usize, C)],
+    format: SerdeFormat,
+) -> io::Result<()> {
+    write_u32(writer, commitments.len() as u32)?;
+    for (column, commitment) in commitments {
+        write_u32(writer, *column as u32)?;
+        commitment.write(writer, format)?;
     }
+    Ok(())
+}
+
+/// Reads the commitments returned for a keygen task.
+pub fn read_commitments<R: io::Read, C: SerdeCurveAffine>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<Vec<(usize, C)>> {
+    let len = read_u32(reader)?;
+    (0..len)
+        .map(|_| Ok((read_u32(reader)? as usize, C::read(reader, format)?)))
+        .collect()
+}
+
+/// Puts the commitments returned by the workers back into column order,
+/// checking that every one of the `ncolumns` columns was committed to exactly
+/// once.
+pub fn assemble_commitments<C: CurveAffine>(
+    ncolumns: usize,
+    shards: impl IntoIterator<Item = (usize, C)>,
+) -> io::Result<Vec<C>> {
+    let mut commitments = vec![None; ncolumns];
+    for (column, commitment) in shards {
+        match commitments.get_mut(column) {
+            Some(slot @ None) => *slot = Some(commitment),
+            Some(Some(_)) => {
+                return Err(invalid_data(format!(
+                    "column {} was committed to more than once",
+                    column
+                )))
+            }
+            None => {
+                return Err(invalid_data(format!(
+                    "column {} is out of bounds for {} permutation columns",
+                    column, ncolumns
+                )))
+            }
+        }
+    }
+    commitments
+        .into_iter()
+        .enumerate()
+        .map(|(column, commitment)| {
+            commitment.ok_or_else(|| {
+                invalid_data(format!("no commitment was returned for column {}", column))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        assemble_commitments, read_commitments, read_mapping, write_commitments, write_mapping,
+        KeygenTask,
+    };
+    use crate::{
+        distributed_util::{
+            net::{write_header, write_u32},
+            params::ParamsCache,
+            scheme::SchemeId,
+            utils::split_range,
+        },
+        plonk::{
+            permutation::keygen::{build_vk, Assembly},
+            permutation::Argument,
+            Any, Column,
+        },
+        poly::{commitment::ParamsProver, kzg::commitment::ParamsKZG, EvaluationDomain},
+        SerdeFormat,
+    };
+    use group::prime::PrimeCurveAffine;
+    use halo2curves::bn256::{Bn256, Fr, G1Affine};
+    use rand_core::OsRng;
+    use std::io::ErrorKind;
+
+    const K: u32 = 4;
 
-    pub fn delta(&self) -> <<C as CurveAffine>::CurveExt as CurveExt>::ScalarExt {
-        C::Scalar::DELTA
+    fn argument() -> Argument {
+        let mut p = Argument::new();
+        p.add_column(Column::new(0, Any::advice()));
+        p.add_column(Column::new(1, Any::advice()));
+        p.add_column(Column::new(0, Any::Fixed));
+        p.add_column(Column::new(0, Any::Instance));
+        p
+    }
+
+    fn mapping(ncolumns: usize, n: usize) -> Vec<Vec<(usize, usize)>> {
+        let mut mapping: Vec<Vec<_>> = (0..ncolumns)
+            .map(|i| (0..n).map(|j| (i, j)).collect())
+            .collect();
+        // Tie a few cells together so that the mapping is not the identity.
+        mapping[0][1] = (2, 3);
+        mapping[2][3] = (3, 0);
+        mapping[3][0] = (0, 1);
+        mapping[1][5] = (1, 7);
+        mapping[1][7] = (1, 5);
+        mapping
+    }
+
+    #[test]
+    fn test_keygen_task_roundtrip() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let domain = EvaluationDomain::new(3, K);
+        let p = argument();
+        let mapping = mapping(p.ncolumns(), 1 << K);
+        let mut cache = ParamsCache::default();
+        let params_hash = cache.insert(params.clone()).unwrap();
+
+        for format in [
+            SerdeFormat::Processed,
+            SerdeFormat::RawBytes,
+            SerdeFormat::RawBytesUnchecked,
+        ] {
+            let task = KeygenTask::<G1Affine, _>::new(
+                &params,
+                params_hash,
+                &domain,
+                &p,
+                &mapping,
+                0..p.ncolumns(),
+            );
+            let mut buf = vec![];
+            task.write(&mut buf, format).unwrap();
+
+            let (decoded, _) =
+                KeygenTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &cache).unwrap();
+            assert_eq!(decoded.params.g, params.g);
+            assert_eq!(decoded.params.g_lagrange, params.g_lagrange);
+            assert_eq!(decoded.domain.k(), domain.k());
+            assert_eq!(decoded.domain.extended_k(), domain.extended_k());
+            assert_eq!(decoded.domain.get_omega(), domain.get_omega());
+            assert_eq!(decoded.p.get_columns(), p.get_columns());
+            assert_eq!(&decoded.mapping[..], &mapping[..]);
+
+            let local = build_vk::<G1Affine, _>(&params, &domain, &p, |i, j| mapping[i][j]);
+            let commitments = assemble_commitments(p.ncolumns(), decoded.commitments()).unwrap();
+            assert_eq!(&commitments, local.commitments());
+        }
+    }
+
+    #[test]
+    fn test_keygen_sharded_matches_local() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let domain = EvaluationDomain::new(3, K);
+        let p = argument();
+        let mapping = mapping(p.ncolumns(), 1 << K);
+        let local = build_vk::<G1Affine, _>(&params, &domain, &p, |i, j| mapping[i][j]);
+        let mut cache = ParamsCache::default();
+        let params_hash = cache.insert(params.clone()).unwrap();
+
+        for workers in 1..=p.ncolumns() + 2 {
+            let mut shards = vec![];
+            for columns in split_range(p.ncolumns(), workers) {
+                if columns.is_empty() {
+                    continue;
+                }
+                let task = KeygenTask::<G1Affine, _>::new(
+                    &params,
+                    params_hash,
+                    &domain,
+                    &p,
+                    &mapping,
+                    columns,
+                );
+                let mut buf = vec![];
+                task.write(&mut buf, SerdeFormat::RawBytes).unwrap();
+                let (decoded, format) =
+                    KeygenTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &cache).unwrap();
+
+                let mut response = vec![];
+                write_commitments(&mut response, &decoded.commitments(), format).unwrap();
+                shards.push(read_commitments::<_, G1Affine>(&mut &response[..], format).unwrap());
+            }
+
+            // Answers may come back in any order.
+            shards.reverse();
+            let commitments =
+                assemble_commitments(p.ncolumns(), shards.into_iter().flatten()).unwrap();
+            assert_eq!(&commitments, local.commitments());
+        }
+    }
+
+    #[test]
+    fn test_mapping_roundtrip_after_copies() {
+        let p = argument();
+        let n = 1 << K;
+        let mut assembly = Assembly::new(n, &p);
+        let columns = p.get_columns();
+        for (left, left_row, right, right_row) in
+            [(0, 1, 2, 3), (2, 3, 3, 0), (1, 5, 1, 7), (0, 9, 1, 7)]
+        {
+            assembly
+                .copy(columns[left], left_row, columns[right], right_row)
+                .unwrap();
+        }
+        #[cfg(feature = "thread-safe-region")]
+        assembly.build_ordered_mapping();
+        let mapping = assembly
+            .mapping()
+            .map(|column| column.collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        for workers in 1..=p.ncolumns() {
+            for columns in split_range(p.ncolumns(), workers) {
+                let mut buf = vec![];
+                write_mapping(&mut buf, columns.start, &mapping[columns.clone()]).unwrap();
+                let decoded =
+                    read_mapping(&mut &buf[..], columns.clone(), p.ncolumns(), n).unwrap();
+                assert_eq!(&decoded[..], &mapping[columns]);
+            }
+        }
+
+        // Only the copied cells are written, a few bytes each.
+        let mut buf = vec![];
+        write_mapping(&mut buf, 0, &mapping).unwrap();
+        assert!(buf.len() < 4 * 6 + p.ncolumns());
+    }
+
+    #[test]
+    fn test_mapping_rejects_cells_out_of_bounds() {
+        let p = argument();
+        let n = 1 << K;
+        let mut mapping = mapping(p.ncolumns(), n);
+        let mut buf = vec![];
+        write_mapping(&mut buf, 0, &mapping).unwrap();
+        assert_eq!(
+            read_mapping(&mut &buf[..], 0..p.ncolumns(), p.ncolumns(), n).unwrap(),
+            mapping
+        );
+        // Fewer rows or columns than the mapping refers to are rejected.
+        assert!(read_mapping(&mut &buf[..], 0..p.ncolumns(), p.ncolumns() - 1, n).is_err());
+        assert!(read_mapping(&mut &buf[..], 0..p.ncolumns(), p.ncolumns(), 6).is_err());
+
+        mapping[0].push((0, n));
+        let mut buf = vec![];
+        assert!(write_mapping(&mut buf, 0, &mapping).is_err());
+    }
+
+    #[test]
+    fn test_assemble_commitments_rejects_missing_and_duplicate_columns() {
+        let g = G1Affine::generator();
+        assert!(assemble_commitments(2, vec![(0, g)]).is_err());
+        assert!(assemble_commitments(2, vec![(0, g), (0, g), (1, g)]).is_err());
+        assert!(assemble_commitments(2, vec![(0, g), (2, g)]).is_err());
+        assert_eq!(
+            assemble_commitments(2, vec![(1, g), (0, g)]).unwrap(),
+            vec![g, g]
+        );
+    }
+
+    #[test]
+    fn test_keygen_task_rejects_unknown_version_scheme_and_params() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let domain = EvaluationDomain::new(3, K);
+        let p = argument();
+        let mapping = mapping(p.ncolumns(), 1 << K);
+        let mut cache = ParamsCache::default();
+        let params_hash = cache.insert(params.clone()).unwrap();
+
+        let task = KeygenTask::<G1Affine, _>::new(
+            &params,
+            params_hash,
+            &domain,
+            &p,
+            &mapping,
+            0..p.ncolumns(),
+        );
+        let mut buf = vec![];
+        task.write(&mut buf, SerdeFormat::RawBytes).unwrap();
+
+        let empty = ParamsCache::default();
+        let error =
+            KeygenTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &empty).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+
+        // A task for another scheme is rejected like one of another version.
+        let mut other_scheme = buf.clone();
+        other_scheme[1] = SchemeId::IpaVesta.into();
+        assert!(
+            KeygenTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &other_scheme[..], &cache).is_err()
+        );
+
+        buf[0] = buf[0].wrapping_add(1);
+        assert!(KeygenTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &cache).is_err());
+    }
+
+    #[test]
+    fn test_keygen_task_rejects_domains_other_than_the_params() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let mut cache = ParamsCache::default();
+        let params_hash = cache.insert(params).unwrap();
+
+        // Neither a domain of another size than the params, nor one that does
+        // not exist, gets as far as the mapping.
+        for (k, j) in [(K - 1, 3), (K + 1, 3), (K, 0), (K, 1), (u32::MAX, 3)] {
+            let mut buf = vec![];
+            write_header::<_, Fr>(&mut buf, SerdeFormat::RawBytes).unwrap();
+            params_hash.write(&mut buf).unwrap();
+            write_u32(&mut buf, k).unwrap();
+            write_u32(&mut buf, j).unwrap();
+            let error =
+                KeygenTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &cache).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "k = {}, j = {}", k, j);
+        }
     }
 }
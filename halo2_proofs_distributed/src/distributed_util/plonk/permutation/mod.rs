@@ -1,2 +1,3 @@
 //! Permutation distributed api
 pub mod keygen;
+pub mod prover;
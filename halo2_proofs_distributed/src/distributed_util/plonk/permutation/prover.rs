@@ -0,0 +1,480 @@
+//! Distributed permutation argument
+//!
+//! Each set of permutation columns has its own grand product z, which starts
+//! from the last usable value of the grand product of the previous set. Since
+//! z is a running product, a worker computes the running product of its set
+//! as if it started from one, and the dispatcher scales it by the value it
+//! actually starts from once the sets before it are known. Commitments being
+//! linear, the worker commits to the usable rows and to the blinding rows
+//! separately, and the commitment to z is scaled the same way.
+use futures::future::LocalBoxFuture;
+use group::{prime::PrimeCurveAffine, Curve};
+use std::{borrow::Cow, io};
+
+use ff::{Field, PrimeField};
+
+use crate::{
+    arithmetic::CurveAffine,
+    distributed_util::{
+        net::{
+            invalid_data, read_header, read_points, read_scalars, read_u32, write_header,
+            write_points, write_scalars, write_u32, Encode, SerdeParams,
+        },
+        params::{ParamsCache, ParamsHash},
+        plonk::commit::DispatchedCommitter,
+    },
+    helpers::{SerdeCurveAffine, SerdePrimeField},
+    plonk::{permutation::prover::product_values, Error},
+    poly::{
+        commitment::{Blind, Params},
+        LagrangeCoeff, Polynomial,
+    },
+    SerdeFormat,
+};
+
+/// A set of permutation columns, along with the randomness the prover drew
+/// for its grand product
+#[derive(Debug, Clone)]
+pub struct PermutationChunk<'a, F: Clone> {
+    /// Values of the columns of the set
+    pub columns: Vec<Cow<'a, Polynomial<F, LagrangeCoeff>>>,
+    /// Values of the permutation polynomials of those columns
+    pub permutations: Vec<Cow<'a, Polynomial<F, LagrangeCoeff>>>,
+    /// The power of delta of the first column of the set
+    pub delta: F,
+    /// Values of the rows after the last usable one of the grand product
+    pub blinding: Vec<F>,
+    pub blind: Blind<F>,
+}
+
+/// The grand product of a set of permutation columns as if it started from
+/// one, split in two commitments that add up to the commitment to it
+#[derive(Debug, Clone)]
+pub struct ChunkProduct<C: CurveAffine> {
+    /// Values of the usable rows and of the last row
+    pub product: Vec<C::Scalar>,
+    /// Commitment to `product`, without blind
+    pub product_commitment: C,
+    /// Commitment to the blinding rows, with the blind
+    pub blinding_commitment: C,
+}
+
+/// The grand product of a set of permutation columns, and the commitment to it
+#[derive(Debug, Clone)]
+pub struct PermutationProduct<C: CurveAffine> {
+    pub z: Polynomial<C::Scalar, LagrangeCoeff>,
+    pub commitment: C,
+}
+
+/// Computes the grand products of the permutation argument on behalf of the
+/// prover.
+pub trait PermutationCommitter<C: CurveAffine> {
+    /// Computes and commits to the grand products of `chunks`, in order. The
+    /// chunks are the `sets` column sets of each circuit, one circuit after
+    /// the other, and the grand product of a set starts where the one of the
+    /// previous set of the same circuit ends.
+    fn permutation_products<'a>(
+        &'a mut self,
+        chunks: &'a [PermutationChunk<'a, C::Scalar>],
+        sets: usize,
+        beta: C::Scalar,
+        gamma: C::Scalar,
+        omega: C::Scalar,
+    ) -> LocalBoxFuture<'a, Result<Vec<PermutationProduct<C>>, Error>>;
+}
+
+impl<'a, 'params, C, P> PermutationCommitter<C> for DispatchedCommitter<'a, 'params, P>
+where
+    C: SerdeCurveAffine,
+    C::Scalar: SerdePrimeField,
+    P: Params<'params, C> + SerdeParams,
+{
+    fn permutation_products<'b>(
+        &'b mut self,
+        chunks: &'b [PermutationChunk<'b, C::Scalar>],
+        sets: usize,
+        beta: C::Scalar,
+        gamma: C::Scalar,
+        omega: C::Scalar,
+    ) -> LocalBoxFuture<'b, Result<Vec<PermutationProduct<C>>, Error>> {
+        Box::pin(self.dispatcher.permutation_products(
+            self.params,
+            self.params_hash,
+            chunks,
+            sets,
+            beta,
+            gamma,
+            omega,
+        ))
+    }
+}
+
+/// Computes and commits to the grand product of `chunk` on this machine, as
+/// if it started from one.
+pub(crate) fn chunk_product<'params, C: CurveAffine, P: Params<'params, C>>(
+    params: &P,
+    chunk: &PermutationChunk<'_, C::Scalar>,
+    beta: C::Scalar,
+    gamma: C::Scalar,
+    omega: C::Scalar,
+) -> ChunkProduct<C> {
+    let n = params.n() as usize;
+    let rows = n - chunk.blinding.len();
+    let columns = chunk
+        .columns
+        .iter()
+        .map(|column| &column[..])
+        .collect::<Vec<_>>();
+    let permutations = chunk
+        .permutations
+        .iter()
+        .map(|permutation| &permutation[..])
+        .collect::<Vec<_>>();
+    let product = product_values(
+        &columns,
+        &permutations,
+        beta,
+        gamma,
+        chunk.delta,
+        omega,
+        rows,
+    );
+
+    let mut values = product.clone();
+    values.resize(n, C::Scalar::ZERO);
+    let product_commitment = params
+        .commit_lagrange(&Polynomial::from_values(values), Blind(C::Scalar::ZERO))
+        .to_affine();
+
+    let mut values = vec![C::Scalar::ZERO; rows];
+    values.extend_from_slice(&chunk.blinding);
+    let blinding_commitment = params
+        .commit_lagrange(&Polynomial::from_values(values), chunk.blind)
+        .to_affine();
+
+    ChunkProduct {
+        product,
+        product_commitment,
+        blinding_commitment,
+    }
+}
+
+/// Chains the grand products of `chunks`, computed as if each one started
+/// from one, into the grand products of the permutation argument of every
+/// circuit, each having `sets` column sets.
+pub(crate) fn chain_products<C: CurveAffine>(
+    chunks: &[PermutationChunk<'_, C::Scalar>],
+    products: Vec<ChunkProduct<C>>,
+    sets: usize,
+) -> Vec<PermutationProduct<C>> {
+    let mut last_z = C::Scalar::ONE;
+    chunks
+        .iter()
+        .zip(products)
+        .enumerate()
+        .map(|(i, (chunk, product))| {
+            // The grand product of each circuit starts from one.
+            if i % sets == 0 {
+                last_z = C::Scalar::ONE;
+            }
+            let commitment = (product.product_commitment * last_z
+                + product.blinding_commitment.to_curve())
+            .to_affine();
+
+            let z = product
+                .product
+                .into_iter()
+                .map(|z| last_z * z)
+                .chain(chunk.blinding.iter().copied())
+                .collect::<Vec<_>>();
+            last_z = z[z.len() - chunk.blinding.len() - 1];
+
+            PermutationProduct {
+                z: Polynomial::from_values(z),
+                commitment,
+            }
+        })
+        .collect()
+}
+
+/// Distributed request to compute and commit to the grand products of a batch
+/// of permutation column sets
+#[derive(Debug, Clone)]
+pub struct PermutationProductTask<'a, C: CurveAffine, P: Clone> {
+    pub params: Cow<'a, P>,
+    pub params_hash: ParamsHash,
+    pub beta: C::Scalar,
+    pub gamma: C::Scalar,
+    pub omega: C::Scalar,
+    pub chunks: Cow<'a, [PermutationChunk<'a, C::Scalar>]>,
+}
+
+impl<'a, C: CurveAffine, P: Clone> PermutationProductTask<'a, C, P> {
+    /// Builds the task computing the grand products of `chunks` with the
+    /// challenges `beta` and `gamma`, over the domain generated by `omega`.
+    pub fn new(
+        params: &'a P,
+        params_hash: ParamsHash,
+        chunks: &'a [PermutationChunk<'a, C::Scalar>],
+        beta: C::Scalar,
+        gamma: C::Scalar,
+        omega: C::Scalar,
+    ) -> Self {
+        PermutationProductTask {
+            params: Cow::Borrowed(params),
+            params_hash,
+            beta,
+            gamma,
+            omega,
+            chunks: Cow::Borrowed(chunks),
+        }
+    }
+}
+
+impl<'a, C, P> PermutationProductTask<'a, C, P>
+where
+    C: SerdeCurveAffine,
+    C::Scalar: SerdePrimeField,
+    P: Clone + SerdeParams,
+{
+    /// Encodes the task.
+    ///
+    /// The layout is the task header, the hash of the params, `beta`, `gamma`
+    /// and `omega`, and the count-prefixed list of column sets, each made of
+    /// the count-prefixed columns and their permutation polynomials, the power
+    /// of delta of the first column, the blinding rows and the blind.
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+        self.params_hash.write(writer)?;
+        SerdePrimeField::write(&self.beta, writer, format)?;
+        SerdePrimeField::write(&self.gamma, writer, format)?;
+        SerdePrimeField::write(&self.omega, writer, format)?;
+
+        write_u32(writer, self.chunks.len() as u32)?;
+        for chunk in self.chunks.iter() {
+            write_u32(writer, chunk.columns.len() as u32)?;
+            for column in chunk.columns.iter() {
+                writer.scalars(column, format)?;
+            }
+            write_u32(writer, chunk.permutations.len() as u32)?;
+            for permutation in chunk.permutations.iter() {
+                writer.scalars(permutation, format)?;
+            }
+            SerdePrimeField::write(&chunk.delta, writer, format)?;
+            write_scalars(writer, &chunk.blinding, format)?;
+            SerdePrimeField::write(&chunk.blind.0, writer, format)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a task written with [`PermutationProductTask::write`], along
+    /// with the format the dispatcher used, which the answer is expected in.
+    /// The params are looked up in `cache`.
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+        cache: &'a ParamsCache<P>,
+    ) -> io::Result<(PermutationProductTask<'a, C, P>, SerdeFormat)> {
+        let format = read_header::<_, C::Scalar>(reader)?;
+        let params_hash = ParamsHash::read(reader)?;
+        let params = cache.get(&params_hash)?;
+        let beta = <C::Scalar as SerdePrimeField>::read(reader, format)?;
+        let gamma = <C::Scalar as SerdePrimeField>::read(reader, format)?;
+        let omega = <C::Scalar as SerdePrimeField>::read(reader, format)?;
+
+        let read_polys = |reader: &mut R| {
+            (0..read_u32(reader)?)
+                .map(|_| Polynomial::read(reader, format).map(Cow::Owned))
+                .collect::<io::Result<Vec<_>>>()
+        };
+        let chunks = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(PermutationChunk {
+                    columns: read_polys(reader)?,
+                    permutations: read_polys(reader)?,
+                    delta: <C::Scalar as SerdePrimeField>::read(reader, format)?,
+                    blinding: read_scalars(reader, format)?,
+                    blind: Blind(<C::Scalar as SerdePrimeField>::read(reader, format)?),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok((
+            PermutationProductTask {
+                params: Cow::Borrowed(params),
+                params_hash,
+                beta,
+                gamma,
+                omega,
+                chunks: Cow::Owned(chunks),
+            },
+            format,
+        ))
+    }
+
+    /// Computes and commits to the grand products of the task, in order.
+    pub fn outputs(&self) -> io::Result<Vec<ChunkProduct<C>>>
+    where
+        P: Params<'a, C>,
+    {
+        let n = self.params.n() as usize;
+        for chunk in self.chunks.iter() {
+            if chunk.columns.is_empty() || chunk.columns.len() != chunk.permutations.len() {
+                return Err(invalid_data(format!(
+                    "{} columns with {} permutation polynomials",
+                    chunk.columns.len(),
+                    chunk.permutations.len()
+                )));
+            }
+            if let Some(poly) = chunk
+                .columns
+                .iter()
+                .chain(chunk.permutations.iter())
+                .find(|poly| poly.len() != n)
+            {
+                return Err(invalid_data(format!(
+                    "permutation column of {} rows, expected {}",
+                    poly.len(),
+                    n
+                )));
+            }
+            if chunk.blinding.len() >= n {
+                return Err(invalid_data(format!(
+                    "{} blinding rows out of {}",
+                    chunk.blinding.len(),
+                    n
+                )));
+            }
+        }
+        Ok(self
+            .chunks
+            .iter()
+            .map(|chunk| chunk_product(&*self.params, chunk, self.beta, self.gamma, self.omega))
+            .collect())
+    }
+}
+
+/// Writes the answer to a [`PermutationProductTask`]: for every column set,
+/// its grand product followed by the two commitments.
+pub fn write_chunk_products<W: io::Write, C: SerdeCurveAffine>(
+    writer: &mut W,
+    products: &[ChunkProduct<C>],
+    format: SerdeFormat,
+) -> io::Result<()>
+where
+    C::Scalar: SerdePrimeField,
+{
+    write_u32(writer, products.len() as u32)?;
+    for product in products {
+        write_scalars(writer, &product.product, format)?;
+        write_points(
+            writer,
+            &[product.product_commitment, product.blinding_commitment],
+            format,
+        )?;
+    }
+    Ok(())
+}
+
+/// Reads an answer written with [`write_chunk_products`].
+pub fn read_chunk_products<R: io::Read, C: SerdeCurveAffine>(
+    reader: &mut R,
+    format: SerdeFormat,
+) -> io::Result<Vec<ChunkProduct<C>>>
+where
+    C::Scalar: SerdePrimeField,
+{
+    (0..read_u32(reader)?)
+        .map(|_| {
+            let product = read_scalars(reader, format)?;
+            match read_points(reader, format)?[..] {
+                [product_commitment, blinding_commitment] => Ok(ChunkProduct {
+                    product,
+                    product_commitment,
+                    blinding_commitment,
+                }),
+                _ => Err(invalid_data("expected two commitments per column set")),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunk_product, PermutationChunk, PermutationProductTask};
+    use crate::{
+        distributed_util::params::ParamsCache,
+        poly::{
+            commitment::{Blind, ParamsProver},
+            kzg::commitment::ParamsKZG,
+            LagrangeCoeff, Polynomial,
+        },
+        SerdeFormat,
+    };
+    use ff::{Field, PrimeField};
+    use halo2curves::bn256::{Bn256, Fr, G1Affine};
+    use rand_core::OsRng;
+    use std::borrow::Cow;
+
+    const K: u32 = 4;
+    const BLINDING_FACTORS: usize = 3;
+
+    fn random_poly() -> Cow<'static, Polynomial<Fr, LagrangeCoeff>> {
+        Cow::Owned(Polynomial::from_values(
+            (0..1 << K).map(|_| Fr::random(OsRng)).collect(),
+        ))
+    }
+
+    /// Returns the column sets of `circuits` circuits with `sets` sets of two
+    /// columns each.
+    fn chunks(circuits: usize, sets: usize) -> Vec<PermutationChunk<'static, Fr>> {
+        (0..circuits * sets)
+            .map(|i| PermutationChunk {
+                columns: vec![random_poly(), random_poly()],
+                permutations: vec![random_poly(), random_poly()],
+                delta: Fr::DELTA.pow_vartime([(2 * (i % sets)) as u64]),
+                blinding: (0..BLINDING_FACTORS).map(|_| Fr::random(OsRng)).collect(),
+                blind: Blind(Fr::random(OsRng)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_permutation_task_roundtrip() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let chunks = chunks(1, 2);
+        let (beta, gamma, omega) = (Fr::random(OsRng), Fr::random(OsRng), Fr::random(OsRng));
+        let mut cache = ParamsCache::default();
+        let params_hash = cache.insert(params.clone()).unwrap();
+
+        for format in [
+            SerdeFormat::Processed,
+            SerdeFormat::RawBytes,
+            SerdeFormat::RawBytesUnchecked,
+        ] {
+            let mut buf = vec![];
+            PermutationProductTask::<G1Affine, _>::new(
+                &params,
+                params_hash,
+                &chunks,
+                beta,
+                gamma,
+                omega,
+            )
+            .write(&mut buf, format)
+            .unwrap();
+            let (task, _) =
+                PermutationProductTask::<G1Affine, ParamsKZG<Bn256>>::read(&mut &buf[..], &cache)
+                    .unwrap();
+            for (chunk, product) in chunks.iter().zip(task.outputs().unwrap()) {
+                let local = chunk_product::<G1Affine, _>(&params, chunk, beta, gamma, omega);
+                assert_eq!(product.product, local.product);
+                assert_eq!(product.product_commitment, local.product_commitment);
+                assert_eq!(product.blinding_commitment, local.blinding_commitment);
+            }
+        }
+    }
+}
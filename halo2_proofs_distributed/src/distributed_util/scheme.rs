@@ -0,0 +1,66 @@
+//! Commitment schemes the workers serve
+//!
+//! Every task names the scheme it is computed over, so that a single worker
+//! can serve KZG over BN254 as well as IPA over either Pasta curve. A task
+//! only carries the scalar field of its curve, and each of these schemes uses
+//! a different one, so the scheme is found from the field alone.
+use ff::PrimeField;
+use halo2curves::{bn256, pasta};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+/// The curve and commitment scheme a task is computed over.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, TryFromPrimitive, IntoPrimitive,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[repr(u8)]
+pub enum SchemeId {
+    /// KZG over BN254.
+    KzgBn256 = 0x00,
+    /// IPA over Pallas.
+    IpaPallas = 0x01,
+    /// IPA over Vesta.
+    IpaVesta = 0x02,
+}
+
+impl SchemeId {
+    /// Tag written in place of a scheme for fields no worker serves.
+    pub const UNKNOWN: u8 = 0xff;
+
+    /// All the schemes a worker serves.
+    pub const ALL: [SchemeId; 3] = [SchemeId::KzgBn256, SchemeId::IpaPallas, SchemeId::IpaVesta];
+
+    /// Returns the scheme of tasks over the scalar field `F`, if any.
+    pub fn of<F: PrimeField>() -> Option<Self> {
+        if F::MODULUS == bn256::Fr::MODULUS {
+            Some(SchemeId::KzgBn256)
+        } else if F::MODULUS == pasta::pallas::Scalar::MODULUS {
+            Some(SchemeId::IpaPallas)
+        } else if F::MODULUS == pasta::vesta::Scalar::MODULUS {
+            Some(SchemeId::IpaVesta)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the tag of tasks over the scalar field `F`.
+    pub fn tag<F: PrimeField>() -> u8 {
+        Self::of::<F>().map_or(Self::UNKNOWN, u8::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SchemeId;
+    use halo2curves::{bn256, pasta};
+
+    #[test]
+    fn test_scheme_of_field() {
+        assert_eq!(SchemeId::of::<bn256::Fr>(), Some(SchemeId::KzgBn256));
+        assert_eq!(SchemeId::of::<pasta::Fq>(), Some(SchemeId::IpaPallas));
+        assert_eq!(SchemeId::of::<pasta::Fp>(), Some(SchemeId::IpaVesta));
+        // Base fields of the curves are not scalar fields of any scheme.
+        assert_eq!(SchemeId::of::<bn256::Fq>(), None);
+        assert_eq!(SchemeId::tag::<bn256::Fq>(), SchemeId::UNKNOWN);
+    }
+}
@@ -0,0 +1,274 @@
+//! Sharded KZG parameters
+//!
+//! A commitment in either basis is a sum over the rows of the polynomial, so a
+//! worker that holds rows `[a, b)` of `g` and `g_lagrange` can compute the part
+//! of any commitment that covers those rows, and the dispatcher adds the parts
+//! together. Each worker then only keeps its share of the SRS in memory.
+use group::Curve;
+use std::{borrow::Cow, io, ops::Range};
+
+use crate::{
+    arithmetic::{best_multiexp, CurveAffine},
+    helpers::{SerdeCurveAffine, SerdePrimeField},
+    poly::commitment::SrsBasis,
+    SerdeFormat,
+};
+
+use super::net::{
+    invalid_data, read_header, read_points, read_u32, write_header, write_u32, Encode,
+};
+
+/// The rows of the SRS of size `2^k` a worker holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardRows {
+    pub k: u32,
+    pub rows: Range<usize>,
+}
+
+fn write_basis<W: io::Write>(writer: &mut W, basis: SrsBasis) -> io::Result<()> {
+    let tag: u8 = match basis {
+        SrsBasis::Coeff => 0x00,
+        SrsBasis::Lagrange => 0x01,
+    };
+    writer.write_all(&[tag])
+}
+
+fn read_basis<R: io::Read>(reader: &mut R) -> io::Result<SrsBasis> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    match tag[0] {
+        0x00 => Ok(SrsBasis::Coeff),
+        0x01 => Ok(SrsBasis::Lagrange),
+        tag => Err(invalid_data(format!("unknown SRS basis {}", tag))),
+    }
+}
+
+/// Rows `[a, b)` of the `g` and `g_lagrange` of a `ParamsKZG`.
+#[derive(Debug, Clone)]
+pub struct ParamsShard<'a, C: CurveAffine> {
+    pub k: u32,
+    pub rows: Range<usize>,
+    pub g: Cow<'a, [C]>,
+    pub g_lagrange: Cow<'a, [C]>,
+}
+
+impl<'a, C: CurveAffine> ParamsShard<'a, C> {
+    /// Builds the shard holding `rows` out of the full `g` and `g_lagrange`
+    /// of the params of size `2^k`.
+    pub fn new(k: u32, g: &'a [C], g_lagrange: &'a [C], rows: Range<usize>) -> Self {
+        ParamsShard {
+            k,
+            g: Cow::Borrowed(&g[rows.clone()]),
+            g_lagrange: Cow::Borrowed(&g_lagrange[rows.clone()]),
+            rows,
+        }
+    }
+
+    /// Returns which rows of which SRS the shard holds.
+    pub fn rows(&self) -> ShardRows {
+        ShardRows {
+            k: self.k,
+            rows: self.rows.clone(),
+        }
+    }
+
+    /// Computes the part of a commitment covered by `task`, which must only
+    /// cover rows of this shard.
+    pub fn multiexp(&self, task: &ShardMultiexpTask<C>) -> io::Result<C> {
+        let rows = task.rows();
+        if task.k != self.k || rows.start < self.rows.start || rows.end > self.rows.end {
+            return Err(invalid_data(format!(
+                "rows {:?} of k = {} are not in the shard, which holds rows {:?} of k = {}",
+                rows, task.k, self.rows, self.k
+            )));
+        }
+
+        let bases = match task.basis {
+            SrsBasis::Coeff => &self.g,
+            SrsBasis::Lagrange => &self.g_lagrange,
+        };
+        let offset = rows.start - self.rows.start;
+        Ok(best_multiexp(&task.coeffs, &bases[offset..offset + rows.len()]).to_affine())
+    }
+}
+
+impl<'a, C: SerdeCurveAffine> ParamsShard<'a, C> {
+    /// Encodes the shard.
+    ///
+    /// The layout is the task header, `k`, the rows, and the rows of `g` and of
+    /// `g_lagrange` as count-prefixed lists.
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+        write_u32(writer, self.k)?;
+        write_u32(writer, self.rows.start as u32)?;
+        write_u32(writer, self.rows.end as u32)?;
+        writer.points(&self.g, format)?;
+        writer.points(&self.g_lagrange, format)
+    }
+
+    /// Decodes a shard written with [`ParamsShard::write`].
+    pub fn read<R: io::Read>(reader: &mut R) -> io::Result<ParamsShard<'static, C>> {
+        let format = read_header::<_, C::Scalar>(reader)?;
+        let k = read_u32(reader)?;
+        let rows = read_u32(reader)? as usize..read_u32(reader)? as usize;
+        let g = read_points(reader, format)?;
+        let g_lagrange = read_points(reader, format)?;
+
+        if rows.start > rows.end
+            || 1usize.checked_shl(k).map_or(true, |n| rows.end > n)
+            || g.len() != rows.len()
+            || g_lagrange.len() != rows.len()
+        {
+            return Err(invalid_data(format!(
+                "shard of rows {:?} of k = {} holds {} and {} points",
+                rows,
+                k,
+                g.len(),
+                g_lagrange.len()
+            )));
+        }
+
+        Ok(ParamsShard {
+            k,
+            rows,
+            g: Cow::Owned(g),
+            g_lagrange: Cow::Owned(g_lagrange),
+        })
+    }
+}
+
+/// Distributed request to compute the part of a commitment covering the rows
+/// `[start, start + coeffs.len())` against the shard of a worker
+#[derive(Debug, Clone)]
+pub struct ShardMultiexpTask<'a, C: CurveAffine> {
+    pub k: u32,
+    pub basis: SrsBasis,
+    pub start: usize,
+    pub coeffs: Cow<'a, [C::Scalar]>,
+}
+
+impl<'a, C: CurveAffine> ShardMultiexpTask<'a, C> {
+    /// Builds the task for the coefficients of `rows`.
+    pub fn new(k: u32, basis: SrsBasis, coeffs: &'a [C::Scalar], rows: Range<usize>) -> Self {
+        ShardMultiexpTask {
+            k,
+            basis,
+            start: rows.start,
+            coeffs: Cow::Borrowed(&coeffs[rows]),
+        }
+    }
+
+    /// Returns the rows the task covers.
+    pub fn rows(&self) -> Range<usize> {
+        self.start..self.start + self.coeffs.len()
+    }
+}
+
+impl<'a, C> ShardMultiexpTask<'a, C>
+where
+    C: CurveAffine,
+    C::Scalar: SerdePrimeField,
+{
+    /// Encodes the task.
+    ///
+    /// The layout is the task header, `k`, the basis, the first row and the
+    /// count-prefixed coefficients.
+    pub fn write<'b, W: Encode<'b>>(
+        &'b self,
+        writer: &mut W,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        write_header::<_, C::Scalar>(writer, format)?;
+        write_u32(writer, self.k)?;
+        write_basis(writer, self.basis)?;
+        write_u32(writer, self.start as u32)?;
+        writer.scalars(&self.coeffs, format)
+    }
+
+    /// Decodes a task written with [`ShardMultiexpTask::write`], along with
+    /// the format the dispatcher used, which the answer is expected in.
+    pub fn read<R: io::Read>(
+        reader: &mut R,
+    ) -> io::Result<(ShardMultiexpTask<'static, C>, SerdeFormat)> {
+        let format = read_header::<_, C::Scalar>(reader)?;
+        let k = read_u32(reader)?;
+        let basis = read_basis(reader)?;
+        let start = read_u32(reader)? as usize;
+        let len = read_u32(reader)?;
+        let coeffs = (0..len)
+            .map(|_| <C::Scalar as SerdePrimeField>::read(reader, format))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok((
+            ShardMultiexpTask {
+                k,
+                basis,
+                start,
+                coeffs: Cow::Owned(coeffs),
+            },
+            format,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParamsShard, ShardMultiexpTask};
+    use crate::{
+        arithmetic::best_multiexp,
+        distributed_util::utils::split_range,
+        poly::{
+            commitment::{Params, ParamsProver, SrsBasis},
+            kzg::commitment::ParamsKZG,
+        },
+        SerdeFormat,
+    };
+    use ff::Field;
+    use group::{Curve, Group};
+    use halo2curves::bn256::{Bn256, Fr, G1Affine, G1};
+    use rand_core::OsRng;
+
+    const K: u32 = 6;
+
+    #[test]
+    fn test_sharded_multiexp_matches_local() {
+        let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+        let coeffs: Vec<_> = (0..params.n()).map(|_| Fr::random(OsRng)).collect();
+
+        for (basis, bases) in [
+            (SrsBasis::Coeff, &params.g),
+            (SrsBasis::Lagrange, &params.g_lagrange),
+        ] {
+            let expected = best_multiexp(&coeffs, bases).to_affine();
+
+            let mut sum = G1::identity();
+            for rows in split_range(coeffs.len(), 3) {
+                let mut buf = vec![];
+                ParamsShard::new(K, &params.g, &params.g_lagrange, rows.clone())
+                    .write(&mut buf, SerdeFormat::RawBytes)
+                    .unwrap();
+                let shard = ParamsShard::<G1Affine>::read(&mut &buf[..]).unwrap();
+                assert_eq!(&shard.g[..], &params.g[rows.clone()]);
+
+                let mut buf = vec![];
+                ShardMultiexpTask::<G1Affine>::new(K, basis, &coeffs, rows.clone())
+                    .write(&mut buf, SerdeFormat::Processed)
+                    .unwrap();
+                let (task, _) = ShardMultiexpTask::<G1Affine>::read(&mut &buf[..]).unwrap();
+                assert_eq!(task.rows(), rows);
+                sum += shard.multiexp(&task).unwrap();
+
+                // A shard only serves its own rows of its own SRS.
+                let other = ShardMultiexpTask::<G1Affine>::new(K, basis, &coeffs, 0..coeffs.len());
+                assert!(shard.multiexp(&other).is_err());
+                let other = ShardMultiexpTask::<G1Affine>::new(K - 1, basis, &coeffs, rows);
+                assert!(shard.multiexp(&other).is_err());
+            }
+            assert_eq!(sum.to_affine(), expected);
+        }
+    }
+}
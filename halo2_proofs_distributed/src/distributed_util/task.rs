@@ -0,0 +1,187 @@
+//! Tasks defined outside this crate
+//!
+//! Besides the tasks of keygen and the prover, a worker runs the tasks
+//! registered with it, such as a witness generator or a multiexp specific to
+//! an application. Such a task implements [`Taskable`], which names it and
+//! tells how it is encoded and run. The same implementation is linked into
+//! the dispatcher, which sends tasks with
+//! [`Dispatcher::run_tasks`](super::dispatcher::Dispatcher::run_tasks), and
+//! into the worker, which runs those found in its [`TaskRegistry`].
+//!
+//! Tasks are sent as [`WorkerMethod::Task`](super::dispatcher::WorkerMethod::Task)
+//! requests, whose payload is the name of the task followed by the task as
+//! [`Taskable::encode`] writes it.
+use std::{collections::HashMap, fmt, io, sync::Arc};
+
+use super::net::{invalid_data, read_bytes, write_bytes};
+
+/// A task a worker runs once registered with it.
+pub trait Taskable: Sized + Send + Sync + 'static {
+    /// Name the task is registered and sent under, unique among the tasks of
+    /// a worker. Prefixing it with the name of the crate defining the task
+    /// keeps it so.
+    const NAME: &'static str;
+
+    /// What the task computes.
+    type Output: Send + 'static;
+
+    /// Writes the task, as sent to the worker.
+    fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<()>;
+
+    /// Reads a task written with [`Taskable::encode`].
+    fn decode<R: io::Read>(reader: &mut R) -> io::Result<Self>;
+
+    /// Runs the task. Workers run it on a blocking thread, and the
+    /// dispatcher runs it itself when no worker is left.
+    fn execute(&self) -> io::Result<Self::Output>;
+
+    /// Writes what the task computed, as sent back to the dispatcher.
+    fn encode_output<W: io::Write>(output: &Self::Output, writer: &mut W) -> io::Result<()>;
+
+    /// Reads an output written with [`Taskable::encode_output`].
+    fn decode_output<R: io::Read>(reader: &mut R) -> io::Result<Self::Output>;
+}
+
+/// Writes the payload of the request sending `task`.
+pub fn write_task<T: Taskable, W: io::Write>(writer: &mut W, task: &T) -> io::Result<()> {
+    write_bytes(writer, T::NAME.as_bytes())?;
+    task.encode(writer)
+}
+
+/// Runs an encoded task and returns its encoded output.
+type Handler = Arc<dyn Fn(&mut &[u8]) -> io::Result<Vec<u8>> + Send + Sync>;
+
+/// The tasks a worker runs, by name.
+#[derive(Clone, Default)]
+pub struct TaskRegistry {
+    handlers: HashMap<&'static str, Handler>,
+}
+
+impl TaskRegistry {
+    /// Runs the tasks of type `T` from now on, replacing any task registered
+    /// under the same name.
+    pub fn register<T: Taskable>(&mut self) {
+        let handler: Handler = Arc::new(|reader: &mut &[u8]| {
+            let task = T::decode(reader)
+                .map_err(|error| invalid_data(format!("invalid task {}: {}", T::NAME, error)))?;
+            if !reader.is_empty() {
+                return Err(invalid_data(format!(
+                    "{} trailing bytes after task {}",
+                    reader.len(),
+                    T::NAME
+                )));
+            }
+            let output = task.execute()?;
+            let mut payload = vec![];
+            T::encode_output(&output, &mut payload)?;
+            Ok(payload)
+        });
+        self.handlers.insert(T::NAME, handler);
+    }
+
+    /// Returns whether a task is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.handlers.contains_key(name)
+    }
+
+    /// Returns the names of the registered tasks.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.handlers.keys().copied()
+    }
+
+    /// Runs the task of a request written with [`write_task`], and returns
+    /// its encoded output.
+    ///
+    /// A task that is not registered fails with
+    /// [`io::ErrorKind::Unsupported`], one that cannot be decoded with
+    /// [`io::ErrorKind::InvalidData`], and one that fails to run with the
+    /// error of [`Taskable::execute`].
+    pub fn run(&self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = payload;
+        let name = read_bytes(&mut reader)?;
+        let name = String::from_utf8_lossy(&name);
+        match self.handlers.get(&*name) {
+            Some(handler) => handler(&mut reader),
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("no task {} is registered", name),
+            )),
+        }
+    }
+}
+
+impl fmt::Debug for TaskRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.names().collect::<Vec<_>>();
+        names.sort_unstable();
+        f.debug_struct("TaskRegistry")
+            .field("tasks", &names)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_task, TaskRegistry, Taskable};
+    use crate::distributed_util::net::{read_u64, write_u64};
+    use std::io;
+
+    /// Squares a number, failing on overflow.
+    struct Square(u64);
+
+    impl Taskable for Square {
+        const NAME: &'static str = "test/square";
+        type Output = u64;
+
+        fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+            write_u64(writer, self.0)
+        }
+
+        fn decode<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+            read_u64(reader).map(Square)
+        }
+
+        fn execute(&self) -> io::Result<u64> {
+            self.0
+                .checked_mul(self.0)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "overflow"))
+        }
+
+        fn encode_output<W: io::Write>(output: &u64, writer: &mut W) -> io::Result<()> {
+            write_u64(writer, *output)
+        }
+
+        fn decode_output<R: io::Read>(reader: &mut R) -> io::Result<u64> {
+            read_u64(reader)
+        }
+    }
+
+    fn request(task: &Square) -> Vec<u8> {
+        let mut payload = vec![];
+        write_task(&mut payload, task).unwrap();
+        payload
+    }
+
+    #[test]
+    fn test_registry_runs_registered_tasks() {
+        let mut registry = TaskRegistry::default();
+        let unsupported = registry.run(&request(&Square(3))).unwrap_err();
+        assert_eq!(unsupported.kind(), io::ErrorKind::Unsupported);
+
+        registry.register::<Square>();
+        assert!(registry.contains(Square::NAME));
+        let output = registry.run(&request(&Square(3))).unwrap();
+        assert_eq!(Square::decode_output(&mut &output[..]).unwrap(), 9);
+
+        let failed = registry.run(&request(&Square(u64::MAX))).unwrap_err();
+        assert_eq!(failed.kind(), io::ErrorKind::Other);
+
+        let mut trailing = request(&Square(3));
+        trailing.push(0);
+        let invalid = registry.run(&trailing).unwrap_err();
+        assert_eq!(invalid.kind(), io::ErrorKind::InvalidData);
+        let truncated = request(&Square(3));
+        let invalid = registry.run(&truncated[..truncated.len() - 1]).unwrap_err();
+        assert_eq!(invalid.kind(), io::ErrorKind::InvalidData);
+    }
+}
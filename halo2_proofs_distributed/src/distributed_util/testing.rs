@@ -0,0 +1,206 @@
+//! Fake workers for tests of the dispatcher
+//!
+//! The workers only know the keygen tasks of KZG over BN254, which they
+//! answer with the generator for every column rather than with the actual
+//! commitments. Tests thus tell the tasks the pool ran from those run
+//! locally. The tasks themselves are run on real workers by the integration
+//! tests, see `worker::harness`.
+use group::prime::PrimeCurveAffine;
+use halo2curves::bn256::{Fr, G1Affine};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::sleep,
+};
+
+use super::{
+    capacity::Capacity,
+    dispatcher::{WorkerInfo, WorkerMethod, WorkerStatus},
+    net::{
+        auth::{self, PresharedKey},
+        read_columns, read_header, read_sealed_frame, read_u32, write_response, ChunkHeader,
+        RequestId,
+    },
+    params::ParamsHash,
+    plonk::permutation::keygen::write_commitments,
+};
+
+/// How a fake worker behaves once it has received a task. Every worker
+/// answers pings, and claims to hold whatever params it is asked about.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Behaviour {
+    /// Dies without answering, closing its listener too.
+    Die,
+    /// Keeps the connection open but never answers.
+    Hang,
+    /// Answers every task with the generator for each of its columns.
+    Corrupt,
+    /// Answers like [`Behaviour::Corrupt`], but after [`SLOW_DELAY`], and
+    /// reports a quarter of the multiexp rate of the other workers.
+    Slow,
+}
+
+/// Time a slow fake worker takes to answer each task.
+const SLOW_DELAY: Duration = Duration::from_secs(1);
+
+impl Behaviour {
+    /// Returns the capacity the worker reports.
+    pub(crate) fn capacity(self) -> Capacity {
+        let msm_rate = match self {
+            Behaviour::Slow => 1 << 18,
+            _ => 1 << 20,
+        };
+        Capacity {
+            cores: 1,
+            memory: 1 << 30,
+            msm_rate,
+        }
+    }
+}
+
+/// Starts a fake worker on a free local port and returns its address.
+pub(crate) async fn spawn_worker(behaviour: Behaviour) -> SocketAddr {
+    spawn_worker_with_key(behaviour, PresharedKey::default()).await
+}
+
+/// Starts a fake worker that only serves dispatchers holding `key`.
+pub(crate) async fn spawn_worker_with_key(behaviour: Behaviour, key: PresharedKey) -> SocketAddr {
+    spawn(behaviour, key, Arc::default()).await
+}
+
+/// Starts a fake worker and returns its address, along with the number of
+/// tasks it has answered so far.
+pub(crate) async fn spawn_counted_worker(behaviour: Behaviour) -> (SocketAddr, Arc<AtomicUsize>) {
+    let answered = Arc::new(AtomicUsize::new(0));
+    let addr = spawn(behaviour, PresharedKey::default(), answered.clone()).await;
+    (addr, answered)
+}
+
+async fn spawn(behaviour: Behaviour, key: PresharedKey, answered: Arc<AtomicUsize>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            match behaviour {
+                Behaviour::Die => {
+                    serve(stream, key, behaviour, answered).await;
+                    return;
+                }
+                _ => {
+                    tokio::spawn(serve(stream, key.clone(), behaviour, answered.clone()));
+                }
+            }
+        }
+    });
+    addr
+}
+
+/// Serves a connection, answering its requests one after the other.
+async fn serve(
+    mut stream: TcpStream,
+    key: PresharedKey,
+    behaviour: Behaviour,
+    answered: Arc<AtomicUsize>,
+) {
+    let mut session = match auth::accept(&mut stream, &key).await {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+    let mut partial = HashMap::<RequestId, Vec<u8>>::new();
+    while let Ok(header) = ChunkHeader::read(&mut stream).await {
+        let chunk = match read_sealed_frame(&mut stream, &mut session, &header.to_bytes()).await {
+            Ok(chunk) => chunk,
+            Err(_) => return,
+        };
+        let mut payload = partial.remove(&header.id).unwrap_or_default();
+        payload.extend_from_slice(&chunk);
+        if !header.last {
+            let received = (payload.len() as u64).to_be_bytes();
+            partial.insert(header.id, payload);
+            let status = WorkerStatus::Received;
+            if write_response(&mut stream, &mut session, header.id, status, "", &received)
+                .await
+                .is_err()
+            {
+                return;
+            }
+            continue;
+        }
+
+        let (status, message, answer) = match WorkerMethod::try_from(header.method) {
+            Ok(WorkerMethod::Ping) => {
+                let info = WorkerInfo {
+                    version: "test".to_string(),
+                    capabilities: vec![],
+                    capacity: behaviour.capacity(),
+                };
+                let mut answer = vec![];
+                info.write(&mut answer).unwrap();
+                (WorkerStatus::Ok, String::new(), answer)
+            }
+            Ok(WorkerMethod::HasParams) => (WorkerStatus::Ok, String::new(), vec![1]),
+            Ok(WorkerMethod::KeyGen) => {
+                match behaviour {
+                    Behaviour::Die => return,
+                    Behaviour::Hang => std::future::pending::<()>().await,
+                    Behaviour::Corrupt => {}
+                    Behaviour::Slow => sleep(SLOW_DELAY).await,
+                }
+                // The task runs locally if it cannot be decoded, which the
+                // tests see as it gets the actual commitments.
+                match keygen(&payload) {
+                    Ok(answer) => {
+                        // Counted before the answer is sent, so that the
+                        // dispatcher always sees it.
+                        answered.fetch_add(1, Ordering::SeqCst);
+                        (WorkerStatus::Ok, String::new(), answer)
+                    }
+                    Err(error) => (WorkerStatus::ErrorInvalidPayload, error.to_string(), vec![]),
+                }
+            }
+            _ => (WorkerStatus::ErrorInvalidMethod, String::new(), vec![]),
+        };
+        if write_response(
+            &mut stream,
+            &mut session,
+            header.id,
+            status,
+            &message,
+            &answer,
+        )
+        .await
+        .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Answers a keygen task with the generator for each of its columns. Only
+/// the range of columns is read, the rest of the task is left alone.
+fn keygen(mut payload: &[u8]) -> io::Result<Vec<u8>> {
+    let reader = &mut payload;
+    let format = read_header::<_, Fr>(reader)?;
+    ParamsHash::read(reader)?;
+    read_u32(reader)?;
+    read_u32(reader)?;
+    read_columns(reader)?;
+    let start = read_u32(reader)? as usize;
+    let end = read_u32(reader)? as usize;
+
+    let commitments = (start..end)
+        .map(|column| (column, G1Affine::generator()))
+        .collect::<Vec<_>>();
+    let mut answer = vec![];
+    write_commitments(&mut answer, &commitments, format)?;
+    Ok(answer)
+}
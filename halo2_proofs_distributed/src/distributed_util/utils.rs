@@ -1,5 +1,6 @@
 use std::{
     mem::size_of,
+    ops::Range,
     slice::{from_raw_parts, from_raw_parts_mut},
 };
 
@@ -29,3 +30,72 @@ pub trait CastSlice<From>: AsRef<[From]> {
 
 impl<From> CastSlice<From> for &[From] {}
 impl<From> CastSlice<From> for [From] {}
+
+/// Splits `0..len` into `parts` contiguous ranges whose lengths differ by at
+/// most one. Trailing ranges are empty when there are more parts than items.
+pub fn split_range(len: usize, parts: usize) -> Vec<Range<usize>> {
+    assert!(parts > 0, "cannot split a range into zero parts");
+    let (size, rem) = (len / parts, len % parts);
+    let mut start = 0;
+    (0..parts)
+        .map(|part| {
+            let end = start + size + usize::from(part < rem);
+            let range = start..end;
+            start = end;
+            range
+        })
+        .collect()
+}
+
+/// Splits `0..len` into contiguous ranges, one per weight, whose lengths are
+/// proportional to the weights.
+pub fn split_weighted(len: usize, weights: &[u32]) -> Vec<Range<usize>> {
+    let total: u128 = weights.iter().map(|&w| w as u128).sum();
+    assert!(total > 0, "cannot split a range without any weight");
+    let mut acc = 0u128;
+    let mut start = 0;
+    weights
+        .iter()
+        .map(|&w| {
+            acc += w as u128;
+            let end = (len as u128 * acc / total) as usize;
+            let range = start..end;
+            start = end;
+            range
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_range, split_weighted};
+
+    #[test]
+    fn test_split_range() {
+        assert_eq!(split_range(10, 3), vec![0..4, 4..7, 7..10]);
+        assert_eq!(split_range(2, 4), vec![0..1, 1..2, 2..2, 2..2]);
+        assert_eq!(split_range(0, 2), vec![0..0, 0..0]);
+
+        for len in 0..20 {
+            for parts in 1..8 {
+                let ranges = split_range(len, parts);
+                assert_eq!(ranges.len(), parts);
+                assert_eq!(ranges.iter().map(|r| r.len()).sum::<usize>(), len);
+                assert!(ranges.windows(2).all(|w| w[0].end == w[1].start));
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_weighted() {
+        assert_eq!(split_weighted(12, &[1, 2, 3]), vec![0..2, 2..6, 6..12]);
+        assert_eq!(split_weighted(3, &[1, 0, 1]), vec![0..1, 1..1, 1..3]);
+        assert_eq!(split_weighted(1, &[1, 1, 1]), vec![0..0, 0..0, 0..1]);
+
+        for len in 0..20 {
+            let ranges = split_weighted(len, &[5, 1, 2]);
+            assert_eq!(ranges.iter().map(|r| r.len()).sum::<usize>(), len);
+            assert!(ranges.windows(2).all(|w| w[0].end == w[1].start));
+        }
+    }
+}
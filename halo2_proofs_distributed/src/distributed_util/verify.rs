@@ -0,0 +1,134 @@
+//! Checks of the commitments workers return
+//!
+//! Decoding an answer already rejects points that are not on the curve, and
+//! on the curves the workers compute over every such point is in the prime
+//! order subgroup. A buggy or compromised worker can still return the wrong
+//! point, so when
+//! [`PoolConfig::check_commitments`](super::config::PoolConfig::check_commitments)
+//! is set the dispatcher runs [`check_commitments`] on every answer holding
+//! commitments, which costs one local commitment per answer instead of one per
+//! polynomial.
+use ff::Field;
+use rand_core::OsRng;
+use std::io;
+
+use super::net::invalid_data;
+use crate::{
+    arithmetic::{best_multiexp, parallelize, CurveAffine},
+    poly::{
+        commitment::{Blind, Params},
+        Polynomial,
+    },
+};
+
+/// Checks `commitments`, each given along with the values of the Lagrange
+/// polynomial it commits to and its blind.
+///
+/// Commitments are linear, so for random `r_i` the sum of the `r_i * C_i`
+/// must be the commitment to the sum of the `r_i * p_i` with the sum of the
+/// `r_i * b_i` as blind. A set holding a wrong commitment passes with
+/// probability `1 / |F|`. Polynomials shorter than the params are padded with
+/// zeros.
+pub fn check_commitments<'params, C, P, V>(
+    params: &P,
+    commitments: impl IntoIterator<Item = (V, Blind<C::Scalar>, C)>,
+) -> io::Result<()>
+where
+    C: CurveAffine,
+    P: Params<'params, C>,
+    V: AsRef<[C::Scalar]>,
+{
+    let n = params.n() as usize;
+    let mut combined = vec![C::Scalar::ZERO; n];
+    let mut blind = C::Scalar::ZERO;
+    let mut coeffs = vec![];
+    let mut points = vec![];
+    for (values, Blind(b), commitment) in commitments {
+        let values = values.as_ref();
+        if values.len() > n {
+            return Err(invalid_data(format!(
+                "a polynomial of {} rows does not fit params of {} rows",
+                values.len(),
+                n
+            )));
+        }
+
+        let r = C::Scalar::random(OsRng);
+        parallelize(&mut combined[..values.len()], |acc, start| {
+            for (acc, value) in acc.iter_mut().zip(&values[start..]) {
+                *acc += r * value;
+            }
+        });
+        blind += r * b;
+        coeffs.push(r);
+        points.push(commitment);
+    }
+    if points.is_empty() {
+        return Ok(());
+    }
+
+    let claimed = best_multiexp(&coeffs, &points);
+    let expected = params.commit_lagrange(&Polynomial::from_values(combined), Blind(blind));
+    if claimed != expected {
+        return Err(invalid_data(
+            "the commitments do not match the polynomials they commit to",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_commitments;
+    use crate::poly::{
+        commitment::{Blind, Params, ParamsProver},
+        kzg::commitment::ParamsKZG,
+        Polynomial,
+    };
+    use ff::Field;
+    use group::{prime::PrimeCurveAffine, Curve};
+    use halo2curves::bn256::{Bn256, Fr, G1Affine};
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_check_commitments() {
+        let params = ParamsKZG::<Bn256>::setup(4, OsRng);
+        let polys = [16, 16, 10]
+            .iter()
+            .map(|&len| (0..len).map(|_| Fr::random(OsRng)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let blinds = polys
+            .iter()
+            .map(|_| Blind(Fr::random(OsRng)))
+            .collect::<Vec<_>>();
+        let mut commitments = polys
+            .iter()
+            .zip(&blinds)
+            .map(|(poly, &blind)| {
+                let mut values = poly.clone();
+                values.resize(16, Fr::ZERO);
+                params
+                    .commit_lagrange(&Polynomial::from_values(values), blind)
+                    .to_affine()
+            })
+            .collect::<Vec<_>>();
+        let claims = |commitments: &[G1Affine]| {
+            polys
+                .iter()
+                .zip(&blinds)
+                .zip(commitments)
+                .map(|((poly, &blind), &commitment)| (poly.clone(), blind, commitment))
+                .collect::<Vec<_>>()
+        };
+
+        check_commitments(&params, claims(&commitments)).unwrap();
+        check_commitments(&params, Vec::<(Vec<Fr>, _, G1Affine)>::new()).unwrap();
+
+        // Any commitment off by anything is caught.
+        commitments[1] = (commitments[1].to_curve() + G1Affine::generator()).to_affine();
+        assert!(check_commitments(&params, claims(&commitments)).is_err());
+
+        let too_long = vec![(vec![Fr::ONE; 17], Blind::default(), G1Affine::generator())];
+        assert!(check_commitments(&params, too_long).is_err());
+    }
+}
@@ -34,5 +34,5 @@ pub mod transcript;
 
 pub mod dev;
 mod helpers;
-pub use helpers::SerdeFormat;
+pub use helpers::{SerdeCurveAffine, SerdeFormat, SerdePrimeField};
 pub mod distributed_util;
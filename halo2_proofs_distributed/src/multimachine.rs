@@ -3,11 +3,9 @@
 ///
 use futures::future::join_all;
 use lazy_static::lazy_static;
-use once_cell::sync::Lazy;
 use std::net::TcpStream;
 use std::sync::Mutex;
 use std::{io, net::SocketAddr, thread, time::Duration};
-use stubborn_io::StubbornTcpStream;
 
 lazy_static! {
     static ref TCP_CONNECTIONS: Mutex<Vec<TcpStream>> = {
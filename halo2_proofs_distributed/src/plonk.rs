@@ -23,9 +23,9 @@ use crate::SerdeFormat;
 mod assigned;
 mod circuit;
 mod error;
-mod evaluation;
+pub mod evaluation;
 mod keygen;
-mod lookup;
+pub(crate) mod lookup;
 pub mod permutation;
 mod shuffle;
 mod vanishing;
@@ -4,6 +4,7 @@ use std::fmt;
 use std::io;
 
 use super::{Any, Column};
+use crate::distributed_util::dispatcher::WorkerError;
 
 /// This is an error that could occur during proving or circuit synthesis.
 // TODO: these errors need to be cleaned up
@@ -37,6 +38,8 @@ pub enum Error {
     /// The instance sets up a copy constraint involving a column that has not been
     /// included in the permutation.
     ColumnNotInPermutation(Column<Any>),
+    /// A worker of the distributed pool failed to complete its task.
+    Worker(WorkerError),
 }
 
 impl From<io::Error> for Error {
@@ -46,6 +49,12 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<WorkerError> for Error {
+    fn from(error: WorkerError) -> Self {
+        Error::Worker(error)
+    }
+}
+
 impl Error {
     /// Constructs an `Error::NotEnoughRowsAvailable`.
     pub(crate) fn not_enough_rows_available(current_k: u32) -> Self {
@@ -79,6 +88,7 @@ impl fmt::Display for Error {
                 "Column {:?} must be included in the permutation. Help: try applying `meta.enable_equalty` on the column",
                 column
             ),
+            Error::Worker(e) => write!(f, "Worker error: {}", e),
         }
     }
 }
@@ -87,6 +97,7 @@ impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
             Error::Transcript(e) => Some(e),
+            Error::Worker(e) => Some(e),
             _ => None,
         }
     }
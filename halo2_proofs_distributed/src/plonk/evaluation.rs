@@ -1,4 +1,5 @@
 use crate::arithmetic::distribute;
+use crate::distributed_util::plonk::evaluation::QuotientEvaluator;
 use crate::multicore;
 use crate::plonk::lookup::prover::Committed;
 use crate::plonk::permutation::Argument;
@@ -24,16 +25,187 @@ use std::slice;
 use std::{
     collections::BTreeMap,
     iter,
-    ops::{Index, Mul, MulAssign},
+    ops::{Index, Mul, MulAssign, Range},
 };
 
-use super::{shuffle, ConstraintSystem, Expression};
+use super::{shuffle, Column, ConstraintSystem, Error, Expression};
 
 /// Return the index in the polynomial of size `isize` after rotation `rot`.
 fn get_rotation_idx(idx: usize, rot: i32, rot_scale: i32, isize: i32) -> usize {
     (((idx as i32) + (rot * rot_scale)).rem_euclid(isize)) as usize
 }
 
+/// Rows of the extended domain h(X) is evaluated on, out of a window of the
+/// cosets.
+///
+/// The window holds the `len` values from row `offset` on, wrapping around the
+/// end of the domain, and the evaluated rows are the `rows` ones starting at
+/// index `first` of the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RowWindow {
+    /// Row of the first value of the window
+    pub offset: usize,
+    /// Number of values in the window
+    pub len: usize,
+    /// Index in the window of the first evaluated row
+    pub first: usize,
+    /// Number of evaluated rows
+    pub rows: usize,
+}
+
+impl RowWindow {
+    /// Returns the window holding all `size` rows of the domain, evaluating
+    /// `rows`.
+    pub fn full(size: usize, rows: Range<usize>) -> Self {
+        assert!(rows.start <= rows.end && rows.end <= size);
+        RowWindow {
+            offset: 0,
+            len: size,
+            first: rows.start,
+            rows: rows.len(),
+        }
+    }
+
+    /// Shrinks a [full](RowWindow::full) window to the evaluated rows, plus
+    /// the `before` rows before them and the `after` rows after them. The
+    /// window is left whole when these cover the domain.
+    pub fn shrink(self, before: usize, after: usize) -> Self {
+        debug_assert_eq!(self.offset, 0);
+        if before + self.rows + after >= self.len {
+            return self;
+        }
+        RowWindow {
+            offset: (self.first + self.len - before) % self.len,
+            len: before + self.rows + after,
+            first: before,
+            rows: self.rows,
+        }
+    }
+
+    /// Returns the values of `poly`, over the whole domain, in the window.
+    pub fn slice<F: Copy, B: Basis>(&self, poly: &Polynomial<F, B>) -> Polynomial<F, B> {
+        Polynomial::from_values(
+            (0..self.len)
+                .map(|i| poly[(self.offset + i) % poly.len()])
+                .collect(),
+        )
+    }
+}
+
+/// Shape of the domain and of the permutation argument h(X) is evaluated with
+#[derive(Clone, Debug, PartialEq)]
+pub struct HLayout<F> {
+    /// Size of the extended domain
+    pub size: usize,
+    /// Number of extended rows per row of the domain
+    pub rot_scale: i32,
+    /// Generator of the extended domain
+    pub extended_omega: F,
+    /// Rotation of the last usable row
+    pub last_rotation: Rotation,
+    /// Number of columns per permutation set
+    pub chunk_len: usize,
+    /// Columns of the permutation argument
+    pub permutation_columns: Vec<Column<Any>>,
+}
+
+impl<F: Field> HLayout<F> {
+    /// Returns the layout of the circuit of `pk`.
+    pub fn new<C: CurveAffine<ScalarExt = F>>(pk: &ProvingKey<C>) -> Self {
+        let domain = &pk.vk.domain;
+        let blinding_factors = pk.vk.cs.blinding_factors();
+        HLayout {
+            size: domain.extended_len(),
+            rot_scale: 1 << (domain.extended_k() - domain.k()),
+            extended_omega: domain.get_extended_omega(),
+            last_rotation: Rotation(-((blinding_factors + 1) as i32)),
+            chunk_len: pk.vk.cs.degree() - 2,
+            permutation_columns: pk.vk.cs.permutation.get_columns(),
+        }
+    }
+}
+
+/// Challenges h(X) is evaluated with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HChallenges<F> {
+    /// Challenges of the advice phases
+    pub challenges: Vec<F>,
+    /// y
+    pub y: F,
+    /// beta
+    pub beta: F,
+    /// gamma
+    pub gamma: F,
+    /// theta
+    pub theta: F,
+}
+
+/// Extended cosets h(X) is evaluated over, all of the same length
+#[derive(Clone, Debug)]
+pub struct Cosets<'a, F> {
+    /// Fixed columns
+    pub fixed: &'a [Polynomial<F, ExtendedLagrangeCoeff>],
+    /// l_0(X)
+    pub l0: &'a Polynomial<F, ExtendedLagrangeCoeff>,
+    /// l_last(X)
+    pub l_last: &'a Polynomial<F, ExtendedLagrangeCoeff>,
+    /// 1 - (l_last(X) + l_blind(X))
+    pub l_active_row: &'a Polynomial<F, ExtendedLagrangeCoeff>,
+    /// Permutation polynomials
+    pub permutations: &'a [Polynomial<F, ExtendedLagrangeCoeff>],
+    /// Cosets of each circuit
+    pub circuits: Vec<CircuitCosets<'a, F>>,
+}
+
+/// Extended cosets of a single circuit
+#[derive(Clone, Debug)]
+pub struct CircuitCosets<'a, F> {
+    /// Advice columns
+    pub advice: &'a [Polynomial<F, ExtendedLagrangeCoeff>],
+    /// Instance columns
+    pub instance: &'a [Polynomial<F, ExtendedLagrangeCoeff>],
+    /// Product of each permutation set
+    pub permutation_products: Vec<&'a Polynomial<F, ExtendedLagrangeCoeff>>,
+    /// Product, permuted input and permuted table of each lookup
+    pub lookups: Vec<[&'a Polynomial<F, ExtendedLagrangeCoeff>; 3]>,
+    /// Product of each shuffle
+    pub shuffles: Vec<&'a Polynomial<F, ExtendedLagrangeCoeff>>,
+}
+
+/// What h(X) is evaluated out of when it is offloaded: the cosets of the
+/// proving key, and the polynomials of each circuit in coefficient form, left
+/// to whoever evaluates a range of rows to extend
+#[derive(Clone, Debug)]
+pub struct HPolys<'a, F> {
+    /// Fixed columns
+    pub fixed: &'a [Polynomial<F, ExtendedLagrangeCoeff>],
+    /// l_0(X)
+    pub l0: &'a Polynomial<F, ExtendedLagrangeCoeff>,
+    /// l_last(X)
+    pub l_last: &'a Polynomial<F, ExtendedLagrangeCoeff>,
+    /// 1 - (l_last(X) + l_blind(X))
+    pub l_active_row: &'a Polynomial<F, ExtendedLagrangeCoeff>,
+    /// Permutation polynomials
+    pub permutations: &'a [Polynomial<F, ExtendedLagrangeCoeff>],
+    /// Polynomials of each circuit
+    pub circuits: Vec<CircuitPolys<'a, F>>,
+}
+
+/// Polynomials of a single circuit h(X) is evaluated out of
+#[derive(Clone, Debug)]
+pub struct CircuitPolys<'a, F> {
+    /// Advice columns
+    pub advice: &'a [Polynomial<F, Coeff>],
+    /// Instance columns
+    pub instance: &'a [Polynomial<F, Coeff>],
+    /// Product of each permutation set, which the prover extends anyway
+    pub permutation_products: Vec<&'a Polynomial<F, ExtendedLagrangeCoeff>>,
+    /// Product, permuted input and permuted table of each lookup
+    pub lookups: Vec<[&'a Polynomial<F, Coeff>; 3]>,
+    /// Product of each shuffle
+    pub shuffles: Vec<&'a Polynomial<F, Coeff>>,
+}
+
 /// Value used in a calculation
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd)]
 pub enum ValueSource {
@@ -329,15 +501,15 @@ impl<C: CurveAffine> Evaluator<C> {
     ) -> Polynomial<C::ScalarExt, ExtendedLagrangeCoeff> {
         let domain = &pk.vk.domain;
         let size = domain.extended_len();
-        let rot_scale = 1 << (domain.extended_k() - domain.k());
-        let fixed = &pk.fixed_cosets[..];
-        let extended_omega = domain.get_extended_omega();
-        let isize = size as i32;
-        let one = C::ScalarExt::ONE;
-        let l0 = &pk.l0;
-        let l_last = &pk.l_last;
-        let l_active_row = &pk.l_active_row;
-        let p = &pk.vk.cs.permutation;
+        let layout = HLayout::new(pk);
+        let challenges = HChallenges {
+            challenges: challenges.to_vec(),
+            y,
+            beta,
+            gamma,
+            theta,
+        };
+        let window = RowWindow::full(size, 0..size);
 
         // Calculate the advice and instance cosets
         let advice: Vec<Vec<Polynomial<C::Scalar, ExtendedLagrangeCoeff>>> = advice_polys
@@ -359,10 +531,20 @@ impl<C: CurveAffine> Evaluator<C> {
             })
             .collect();
 
+        // The lookup and shuffle cosets are computed one at a time below, so
+        // the cosets only hold what every circuit shares.
+        let cosets = Cosets {
+            fixed: &pk.fixed_cosets,
+            l0: &pk.l0,
+            l_last: &pk.l_last,
+            l_active_row: &pk.l_active_row,
+            permutations: &pk.permutation.cosets,
+            circuits: vec![],
+        };
+
         let mut values = domain.empty_extended();
 
         // Core expression evaluations
-        let num_threads = multicore::current_num_threads();
         for ((((advice, instance), lookups), shuffles), permutation) in advice
             .iter()
             .zip(instance.iter())
@@ -370,116 +552,23 @@ impl<C: CurveAffine> Evaluator<C> {
             .zip(shuffles.iter())
             .zip(permutations.iter())
         {
+            let circuit = CircuitCosets {
+                advice,
+                instance,
+                permutation_products: permutation
+                    .sets
+                    .iter()
+                    .map(|set| &set.permutation_product_coset)
+                    .collect(),
+                lookups: vec![],
+                shuffles: vec![],
+            };
+
             // Custom gates
-            multicore::scope(|scope| {
-                let chunk_size = (size + num_threads - 1) / num_threads;
-                for (thread_idx, values) in values.chunks_mut(chunk_size).enumerate() {
-                    let start = thread_idx * chunk_size;
-                    scope.spawn(move |_| {
-                        let mut eval_data = self.custom_gates.instance();
-                        for (i, value) in values.iter_mut().enumerate() {
-                            let idx = start + i;
-                            *value = self.custom_gates.evaluate(
-                                &mut eval_data,
-                                fixed,
-                                advice,
-                                instance,
-                                challenges,
-                                &beta,
-                                &gamma,
-                                &theta,
-                                &y,
-                                value,
-                                idx,
-                                rot_scale,
-                                isize,
-                            );
-                        }
-                    });
-                }
-            });
+            self.evaluate_gates(&mut values, window, &layout, &cosets, &circuit, &challenges);
 
             // Permutations
-            let sets = &permutation.sets;
-            if !sets.is_empty() {
-                let blinding_factors = pk.vk.cs.blinding_factors();
-                let last_rotation = Rotation(-((blinding_factors + 1) as i32));
-                let chunk_len = pk.vk.cs.degree() - 2;
-                let delta_start = beta * &C::Scalar::ZETA;
-
-                let first_set = sets.first().unwrap();
-                let last_set = sets.last().unwrap();
-
-                // Permutation constraints
-                parallelize(&mut values, |values, start| {
-                    let mut beta_term = extended_omega.pow_vartime(&[start as u64, 0, 0, 0]);
-                    for (i, value) in values.iter_mut().enumerate() {
-                        let idx = start + i;
-                        let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
-                        let r_last = get_rotation_idx(idx, last_rotation.0, rot_scale, isize);
-
-                        // Enforce only for the first set.
-                        // l_0(X) * (1 - z_0(X)) = 0
-                        *value = *value * y
-                            + ((one - first_set.permutation_product_coset[idx]) * l0[idx]);
-                        // Enforce only for the last set.
-                        // l_last(X) * (z_l(X)^2 - z_l(X)) = 0
-                        *value = *value * y
-                            + ((last_set.permutation_product_coset[idx]
-                                * last_set.permutation_product_coset[idx]
-                                - last_set.permutation_product_coset[idx])
-                                * l_last[idx]);
-                        // Except for the first set, enforce.
-                        // l_0(X) * (z_i(X) - z_{i-1}(\omega^(last) X)) = 0
-                        for (set_idx, set) in sets.iter().enumerate() {
-                            if set_idx != 0 {
-                                *value = *value * y
-                                    + ((set.permutation_product_coset[idx]
-                                        - permutation.sets[set_idx - 1].permutation_product_coset
-                                            [r_last])
-                                        * l0[idx]);
-                            }
-                        }
-                        // And for all the sets we enforce:
-                        // (1 - (l_last(X) + l_blind(X))) * (
-                        //   z_i(\omega X) \prod_j (p(X) + \beta s_j(X) + \gamma)
-                        // - z_i(X) \prod_j (p(X) + \delta^j \beta X + \gamma)
-                        // )
-                        let mut current_delta = delta_start * beta_term;
-                        for ((set, columns), cosets) in sets
-                            .iter()
-                            .zip(p.columns.chunks(chunk_len))
-                            .zip(pk.permutation.cosets.chunks(chunk_len))
-                        {
-                            let mut left = set.permutation_product_coset[r_next];
-                            for (values, permutation) in columns
-                                .iter()
-                                .map(|&column| match column.column_type() {
-                                    Any::Advice(_) => &advice[column.index()],
-                                    Any::Fixed => &fixed[column.index()],
-                                    Any::Instance => &instance[column.index()],
-                                })
-                                .zip(cosets.iter())
-                            {
-                                left *= values[idx] + beta * permutation[idx] + gamma;
-                            }
-
-                            let mut right = set.permutation_product_coset[idx];
-                            for values in columns.iter().map(|&column| match column.column_type() {
-                                Any::Advice(_) => &advice[column.index()],
-                                Any::Fixed => &fixed[column.index()],
-                                Any::Instance => &instance[column.index()],
-                            }) {
-                                right *= values[idx] + current_delta + gamma;
-                                current_delta *= &C::Scalar::DELTA;
-                            }
-
-                            *value = *value * y + ((left - right) * l_active_row[idx]);
-                        }
-                        beta_term *= &extended_omega;
-                    }
-                });
-            }
+            self.evaluate_permutation(&mut values, window, &layout, &cosets, &circuit, &challenges);
 
             // Lookups
             for (n, lookup) in lookups.iter().enumerate() {
@@ -496,129 +585,463 @@ impl<C: CurveAffine> Evaluator<C> {
                     .domain
                     .coeff_to_extended(lookup.permuted_table_poly.clone());
 
-                // Lookup constraints
-                parallelize(&mut values, |values, start| {
-                    let lookup_evaluator = &self.lookups[n];
-                    let mut eval_data = lookup_evaluator.instance();
+                self.evaluate_lookup(
+                    &mut values,
+                    window,
+                    &layout,
+                    &cosets,
+                    &circuit,
+                    n,
+                    [&product_coset, &permuted_input_coset, &permuted_table_coset],
+                    &challenges,
+                );
+            }
+
+            // Shuffle constraints
+            for (n, shuffle) in shuffles.iter().enumerate() {
+                let product_coset = pk.vk.domain.coeff_to_extended(shuffle.product_poly.clone());
+
+                self.evaluate_shuffle(
+                    &mut values,
+                    window,
+                    &layout,
+                    &cosets,
+                    &circuit,
+                    n,
+                    &product_coset,
+                    &challenges,
+                );
+            }
+        }
+        values
+    }
+
+    /// Same as [`Evaluator::evaluate_h`], but the evaluation is left to
+    /// `offload`. The advice, instance, lookup and shuffle polynomials are
+    /// handed over in coefficient form, so no coset of a circuit is computed
+    /// here.
+    pub(in crate::plonk) async fn evaluate_h_offloaded<Q: QuotientEvaluator<C> + ?Sized>(
+        &self,
+        pk: &ProvingKey<C>,
+        advice_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        instance_polys: &[&[Polynomial<C::ScalarExt, Coeff>]],
+        challenges: &[C::ScalarExt],
+        y: C::ScalarExt,
+        beta: C::ScalarExt,
+        gamma: C::ScalarExt,
+        theta: C::ScalarExt,
+        lookups: &[Vec<lookup::prover::Committed<C>>],
+        shuffles: &[Vec<shuffle::prover::Committed<C>>],
+        permutations: &[permutation::prover::Committed<C>],
+        offload: &mut Q,
+    ) -> Result<Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>, Error> {
+        let domain = &pk.vk.domain;
+        let layout = HLayout::new(pk);
+        let challenges = HChallenges {
+            challenges: challenges.to_vec(),
+            y,
+            beta,
+            gamma,
+            theta,
+        };
+
+        let polys = HPolys {
+            fixed: &pk.fixed_cosets,
+            l0: &pk.l0,
+            l_last: &pk.l_last,
+            l_active_row: &pk.l_active_row,
+            permutations: &pk.permutation.cosets,
+            circuits: advice_polys
+                .iter()
+                .zip(instance_polys.iter())
+                .zip(lookups.iter())
+                .zip(shuffles.iter())
+                .zip(permutations.iter())
+                .map(
+                    |((((advice, instance), lookups), shuffles), permutation)| CircuitPolys {
+                        advice,
+                        instance,
+                        permutation_products: permutation
+                            .sets
+                            .iter()
+                            .map(|set| &set.permutation_product_coset)
+                            .collect(),
+                        lookups: lookups
+                            .iter()
+                            .map(|lookup| {
+                                [
+                                    &lookup.product_poly,
+                                    &lookup.permuted_input_poly,
+                                    &lookup.permuted_table_poly,
+                                ]
+                            })
+                            .collect(),
+                        shuffles: shuffles
+                            .iter()
+                            .map(|shuffle| &shuffle.product_poly)
+                            .collect(),
+                    },
+                )
+                .collect(),
+        };
+
+        let values = offload
+            .evaluate_h(self, &layout, domain, &polys, &challenges)
+            .await?;
+        Ok(domain.extended_from_vec(values))
+    }
+
+    /// Evaluates h(X) on the rows of `window`, out of `cosets` which hold the
+    /// values of `window`. The result is the h(X) the prover evaluates,
+    /// restricted to those rows.
+    pub fn evaluate_h_rows(
+        &self,
+        layout: &HLayout<C::ScalarExt>,
+        cosets: &Cosets<C::ScalarExt>,
+        challenges: &HChallenges<C::ScalarExt>,
+        window: RowWindow,
+    ) -> Vec<C::ScalarExt> {
+        let mut values = vec![C::ScalarExt::ZERO; window.rows];
+        for circuit in cosets.circuits.iter() {
+            self.evaluate_gates(&mut values, window, layout, cosets, circuit, challenges);
+            self.evaluate_permutation(&mut values, window, layout, cosets, circuit, challenges);
+            for (n, lookup) in circuit.lookups.iter().enumerate() {
+                self.evaluate_lookup(
+                    &mut values,
+                    window,
+                    layout,
+                    cosets,
+                    circuit,
+                    n,
+                    *lookup,
+                    challenges,
+                );
+            }
+            for (n, product) in circuit.shuffles.iter().enumerate() {
+                self.evaluate_shuffle(
+                    &mut values,
+                    window,
+                    layout,
+                    cosets,
+                    circuit,
+                    n,
+                    product,
+                    challenges,
+                );
+            }
+        }
+        values
+    }
+
+    /// Returns how many extended-domain rows before and after a row the
+    /// constraints of h(X) read at that row.
+    pub fn reach(&self, layout: &HLayout<C::ScalarExt>) -> (usize, usize) {
+        // Besides the queries of the expressions, the permutation reads the
+        // next and the last rows, and the lookups the next and previous ones.
+        let rotations = iter::once(&self.custom_gates)
+            .chain(self.lookups.iter())
+            .chain(self.shuffles.iter())
+            .flat_map(|graph| graph.rotations.iter().copied())
+            .chain([1, -1, layout.last_rotation.0]);
+        let (min, max) = rotations.fold((0, 0), |(min, max), rotation| {
+            (min.min(rotation), max.max(rotation))
+        });
+        let rot_scale = layout.rot_scale as usize;
+        (
+            min.unsigned_abs() as usize * rot_scale,
+            max as usize * rot_scale,
+        )
+    }
+
+    /// Folds the custom gates of `circuit` into `values`.
+    fn evaluate_gates(
+        &self,
+        values: &mut [C::ScalarExt],
+        window: RowWindow,
+        layout: &HLayout<C::ScalarExt>,
+        cosets: &Cosets<C::ScalarExt>,
+        circuit: &CircuitCosets<C::ScalarExt>,
+        challenges: &HChallenges<C::ScalarExt>,
+    ) {
+        let isize = window.len as i32;
+        let num_threads = multicore::current_num_threads();
+        multicore::scope(|scope| {
+            let chunk_size = (values.len() + num_threads - 1) / num_threads;
+            for (thread_idx, values) in values.chunks_mut(chunk_size).enumerate() {
+                let start = window.first + thread_idx * chunk_size;
+                scope.spawn(move |_| {
+                    let mut eval_data = self.custom_gates.instance();
                     for (i, value) in values.iter_mut().enumerate() {
                         let idx = start + i;
-
-                        let table_value = lookup_evaluator.evaluate(
+                        *value = self.custom_gates.evaluate(
                             &mut eval_data,
-                            fixed,
-                            advice,
-                            instance,
-                            challenges,
-                            &beta,
-                            &gamma,
-                            &theta,
-                            &y,
-                            &C::ScalarExt::ZERO,
+                            cosets.fixed,
+                            circuit.advice,
+                            circuit.instance,
+                            &challenges.challenges,
+                            &challenges.beta,
+                            &challenges.gamma,
+                            &challenges.theta,
+                            &challenges.y,
+                            value,
                             idx,
-                            rot_scale,
+                            layout.rot_scale,
                             isize,
                         );
-
-                        let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
-                        let r_prev = get_rotation_idx(idx, -1, rot_scale, isize);
-
-                        let a_minus_s = permuted_input_coset[idx] - permuted_table_coset[idx];
-                        // l_0(X) * (1 - z(X)) = 0
-                        *value = *value * y + ((one - product_coset[idx]) * l0[idx]);
-                        // l_last(X) * (z(X)^2 - z(X)) = 0
-                        *value = *value * y
-                            + ((product_coset[idx] * product_coset[idx] - product_coset[idx])
-                                * l_last[idx]);
-                        // (1 - (l_last(X) + l_blind(X))) * (
-                        //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
-                        //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta)
-                        //          (\theta^{m-1} s_0(X) + ... + s_{m-1}(X) + \gamma)
-                        // ) = 0
-                        *value = *value * y
-                            + ((product_coset[r_next]
-                                * (permuted_input_coset[idx] + beta)
-                                * (permuted_table_coset[idx] + gamma)
-                                - product_coset[idx] * table_value)
-                                * l_active_row[idx]);
-                        // Check that the first values in the permuted input expression and permuted
-                        // fixed expression are the same.
-                        // l_0(X) * (a'(X) - s'(X)) = 0
-                        *value = *value * y + (a_minus_s * l0[idx]);
-                        // Check that each value in the permuted lookup input expression is either
-                        // equal to the value above it, or the value at the same index in the
-                        // permuted table expression.
-                        // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) = 0
-                        *value = *value * y
-                            + (a_minus_s
-                                * (permuted_input_coset[idx] - permuted_input_coset[r_prev])
-                                * l_active_row[idx]);
                     }
                 });
             }
+        });
+    }
 
-            // Shuffle constraints
-            for (n, shuffle) in shuffles.iter().enumerate() {
-                let product_coset = pk.vk.domain.coeff_to_extended(shuffle.product_poly.clone());
+    /// Folds the permutation constraints of `circuit` into `values`.
+    fn evaluate_permutation(
+        &self,
+        values: &mut [C::ScalarExt],
+        window: RowWindow,
+        layout: &HLayout<C::ScalarExt>,
+        cosets: &Cosets<C::ScalarExt>,
+        circuit: &CircuitCosets<C::ScalarExt>,
+        challenges: &HChallenges<C::ScalarExt>,
+    ) {
+        let sets = &circuit.permutation_products;
+        if sets.is_empty() {
+            return;
+        }
 
-                // Shuffle constraints
-                parallelize(&mut values, |values, start| {
-                    let input_evaluator = &self.shuffles[2 * n];
-                    let shuffle_evaluator = &self.shuffles[2 * n + 1];
-                    let mut eval_data_input = shuffle_evaluator.instance();
-                    let mut eval_data_shuffle = shuffle_evaluator.instance();
-                    for (i, value) in values.iter_mut().enumerate() {
-                        let idx = start + i;
+        let (fixed, advice, instance) = (cosets.fixed, circuit.advice, circuit.instance);
+        let (l0, l_last, l_active_row) = (cosets.l0, cosets.l_last, cosets.l_active_row);
+        let (y, beta, gamma) = (challenges.y, challenges.beta, challenges.gamma);
+        let one = C::ScalarExt::ONE;
+        let isize = window.len as i32;
+        let rot_scale = layout.rot_scale;
+        let extended_omega = layout.extended_omega;
+        let last_rotation = layout.last_rotation;
+        let chunk_len = layout.chunk_len;
+        let delta_start = beta * &C::Scalar::ZETA;
 
-                        let input_value = input_evaluator.evaluate(
-                            &mut eval_data_input,
-                            fixed,
-                            advice,
-                            instance,
-                            challenges,
-                            &beta,
-                            &gamma,
-                            &theta,
-                            &y,
-                            &C::ScalarExt::ZERO,
-                            idx,
-                            rot_scale,
-                            isize,
-                        );
+        let first_set = sets.first().unwrap();
+        let last_set = sets.last().unwrap();
 
-                        let shuffle_value = shuffle_evaluator.evaluate(
-                            &mut eval_data_shuffle,
-                            fixed,
-                            advice,
-                            instance,
-                            challenges,
-                            &beta,
-                            &gamma,
-                            &theta,
-                            &y,
-                            &C::ScalarExt::ZERO,
-                            idx,
-                            rot_scale,
-                            isize,
-                        );
+        // Permutation constraints
+        parallelize(values, |values, start| {
+            let start = window.first + start;
+            let row = (window.offset + start) % layout.size;
+            let mut beta_term = extended_omega.pow_vartime(&[row as u64, 0, 0, 0]);
+            for (i, value) in values.iter_mut().enumerate() {
+                let idx = start + i;
+                let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
+                let r_last = get_rotation_idx(idx, last_rotation.0, rot_scale, isize);
 
-                        let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
-
-                        // l_0(X) * (1 - z(X)) = 0
-                        *value = *value * y + ((one - product_coset[idx]) * l0[idx]);
-                        // l_last(X) * (z(X)^2 - z(X)) = 0
-                        *value = *value * y
-                            + ((product_coset[idx] * product_coset[idx] - product_coset[idx])
-                                * l_last[idx]);
-                        // (1 - (l_last(X) + l_blind(X))) * (z(\omega X) (s(X) + \gamma) - z(X) (a(X) + \gamma)) = 0
-                        *value = *value * y
-                            + l_active_row[idx]
-                                * (product_coset[r_next] * shuffle_value
-                                    - product_coset[idx] * input_value)
+                // Enforce only for the first set.
+                // l_0(X) * (1 - z_0(X)) = 0
+                *value = *value * y + ((one - first_set[idx]) * l0[idx]);
+                // Enforce only for the last set.
+                // l_last(X) * (z_l(X)^2 - z_l(X)) = 0
+                *value =
+                    *value * y + ((last_set[idx] * last_set[idx] - last_set[idx]) * l_last[idx]);
+                // Except for the first set, enforce.
+                // l_0(X) * (z_i(X) - z_{i-1}(\omega^(last) X)) = 0
+                for (set_idx, set) in sets.iter().enumerate() {
+                    if set_idx != 0 {
+                        *value = *value * y + ((set[idx] - sets[set_idx - 1][r_last]) * l0[idx]);
                     }
-                });
+                }
+                // And for all the sets we enforce:
+                // (1 - (l_last(X) + l_blind(X))) * (
+                //   z_i(\omega X) \prod_j (p(X) + \beta s_j(X) + \gamma)
+                // - z_i(X) \prod_j (p(X) + \delta^j \beta X + \gamma)
+                // )
+                let mut current_delta = delta_start * beta_term;
+                for ((set, columns), cosets) in sets
+                    .iter()
+                    .zip(layout.permutation_columns.chunks(chunk_len))
+                    .zip(cosets.permutations.chunks(chunk_len))
+                {
+                    let mut left = set[r_next];
+                    for (values, permutation) in columns
+                        .iter()
+                        .map(|&column| match column.column_type() {
+                            Any::Advice(_) => &advice[column.index()],
+                            Any::Fixed => &fixed[column.index()],
+                            Any::Instance => &instance[column.index()],
+                        })
+                        .zip(cosets.iter())
+                    {
+                        left *= values[idx] + beta * permutation[idx] + gamma;
+                    }
+
+                    let mut right = set[idx];
+                    for values in columns.iter().map(|&column| match column.column_type() {
+                        Any::Advice(_) => &advice[column.index()],
+                        Any::Fixed => &fixed[column.index()],
+                        Any::Instance => &instance[column.index()],
+                    }) {
+                        right *= values[idx] + current_delta + gamma;
+                        current_delta *= &C::Scalar::DELTA;
+                    }
+
+                    *value = *value * y + ((left - right) * l_active_row[idx]);
+                }
+                beta_term *= &extended_omega;
             }
-        }
-        values
+        });
+    }
+
+    /// Folds the constraints of the `n`-th lookup of `circuit` into `values`,
+    /// given its product, permuted input and permuted table cosets.
+    fn evaluate_lookup(
+        &self,
+        values: &mut [C::ScalarExt],
+        window: RowWindow,
+        layout: &HLayout<C::ScalarExt>,
+        cosets: &Cosets<C::ScalarExt>,
+        circuit: &CircuitCosets<C::ScalarExt>,
+        n: usize,
+        [product_coset, permuted_input_coset, permuted_table_coset]: [&Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>;
+            3],
+        challenges: &HChallenges<C::ScalarExt>,
+    ) {
+        let (l0, l_last, l_active_row) = (cosets.l0, cosets.l_last, cosets.l_active_row);
+        let (y, beta, gamma) = (challenges.y, challenges.beta, challenges.gamma);
+        let one = C::ScalarExt::ONE;
+        let isize = window.len as i32;
+        let rot_scale = layout.rot_scale;
+
+        // Lookup constraints
+        parallelize(values, |values, start| {
+            let lookup_evaluator = &self.lookups[n];
+            let mut eval_data = lookup_evaluator.instance();
+            for (i, value) in values.iter_mut().enumerate() {
+                let idx = window.first + start + i;
+
+                let table_value = lookup_evaluator.evaluate(
+                    &mut eval_data,
+                    cosets.fixed,
+                    circuit.advice,
+                    circuit.instance,
+                    &challenges.challenges,
+                    &beta,
+                    &gamma,
+                    &challenges.theta,
+                    &y,
+                    &C::ScalarExt::ZERO,
+                    idx,
+                    rot_scale,
+                    isize,
+                );
+
+                let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
+                let r_prev = get_rotation_idx(idx, -1, rot_scale, isize);
+
+                let a_minus_s = permuted_input_coset[idx] - permuted_table_coset[idx];
+                // l_0(X) * (1 - z(X)) = 0
+                *value = *value * y + ((one - product_coset[idx]) * l0[idx]);
+                // l_last(X) * (z(X)^2 - z(X)) = 0
+                *value = *value * y
+                    + ((product_coset[idx] * product_coset[idx] - product_coset[idx])
+                        * l_last[idx]);
+                // (1 - (l_last(X) + l_blind(X))) * (
+                //   z(\omega X) (a'(X) + \beta) (s'(X) + \gamma)
+                //   - z(X) (\theta^{m-1} a_0(X) + ... + a_{m-1}(X) + \beta)
+                //          (\theta^{m-1} s_0(X) + ... + s_{m-1}(X) + \gamma)
+                // ) = 0
+                *value = *value * y
+                    + ((product_coset[r_next]
+                        * (permuted_input_coset[idx] + beta)
+                        * (permuted_table_coset[idx] + gamma)
+                        - product_coset[idx] * table_value)
+                        * l_active_row[idx]);
+                // Check that the first values in the permuted input expression and permuted
+                // fixed expression are the same.
+                // l_0(X) * (a'(X) - s'(X)) = 0
+                *value = *value * y + (a_minus_s * l0[idx]);
+                // Check that each value in the permuted lookup input expression is either
+                // equal to the value above it, or the value at the same index in the
+                // permuted table expression.
+                // (1 - (l_last + l_blind)) * (a′(X) − s′(X))⋅(a′(X) − a′(\omega^{-1} X)) = 0
+                *value = *value * y
+                    + (a_minus_s
+                        * (permuted_input_coset[idx] - permuted_input_coset[r_prev])
+                        * l_active_row[idx]);
+            }
+        });
+    }
+
+    /// Folds the constraints of the `n`-th shuffle of `circuit` into
+    /// `values`, given its product coset.
+    fn evaluate_shuffle(
+        &self,
+        values: &mut [C::ScalarExt],
+        window: RowWindow,
+        layout: &HLayout<C::ScalarExt>,
+        cosets: &Cosets<C::ScalarExt>,
+        circuit: &CircuitCosets<C::ScalarExt>,
+        n: usize,
+        product_coset: &Polynomial<C::ScalarExt, ExtendedLagrangeCoeff>,
+        challenges: &HChallenges<C::ScalarExt>,
+    ) {
+        let (l0, l_last, l_active_row) = (cosets.l0, cosets.l_last, cosets.l_active_row);
+        let (y, beta, gamma) = (challenges.y, challenges.beta, challenges.gamma);
+        let one = C::ScalarExt::ONE;
+        let isize = window.len as i32;
+        let rot_scale = layout.rot_scale;
+
+        // Shuffle constraints
+        parallelize(values, |values, start| {
+            let input_evaluator = &self.shuffles[2 * n];
+            let shuffle_evaluator = &self.shuffles[2 * n + 1];
+            let mut eval_data_input = shuffle_evaluator.instance();
+            let mut eval_data_shuffle = shuffle_evaluator.instance();
+            for (i, value) in values.iter_mut().enumerate() {
+                let idx = window.first + start + i;
+
+                let input_value = input_evaluator.evaluate(
+                    &mut eval_data_input,
+                    cosets.fixed,
+                    circuit.advice,
+                    circuit.instance,
+                    &challenges.challenges,
+                    &beta,
+                    &gamma,
+                    &challenges.theta,
+                    &y,
+                    &C::ScalarExt::ZERO,
+                    idx,
+                    rot_scale,
+                    isize,
+                );
+
+                let shuffle_value = shuffle_evaluator.evaluate(
+                    &mut eval_data_shuffle,
+                    cosets.fixed,
+                    circuit.advice,
+                    circuit.instance,
+                    &challenges.challenges,
+                    &beta,
+                    &gamma,
+                    &challenges.theta,
+                    &y,
+                    &C::ScalarExt::ZERO,
+                    idx,
+                    rot_scale,
+                    isize,
+                );
+
+                let r_next = get_rotation_idx(idx, 1, rot_scale, isize);
+
+                // l_0(X) * (1 - z(X)) = 0
+                *value = *value * y + ((one - product_coset[idx]) * l0[idx]);
+                // l_last(X) * (z(X)^2 - z(X)) = 0
+                *value = *value * y
+                    + ((product_coset[idx] * product_coset[idx] - product_coset[idx])
+                        * l_last[idx]);
+                // (1 - (l_last(X) + l_blind(X))) * (z(\omega X) (s(X) + \gamma) - z(X) (a(X) + \gamma)) = 0
+                *value = *value * y
+                    + l_active_row[idx]
+                        * (product_coset[r_next] * shuffle_value - product_coset[idx] * input_value)
+            }
+        });
     }
 }
 
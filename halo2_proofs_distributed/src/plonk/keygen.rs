@@ -3,10 +3,8 @@
 use std::ops::Range;
 
 use ff::{Field, FromUniformBytes};
-use futures::future::join_all;
+use futures::executor::block_on;
 use group::Curve;
-use halo2curves::bn256::{Bn256, G1Affine};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use super::{
     circuit::{
@@ -20,16 +18,10 @@ use super::{
 use crate::{
     arithmetic::{parallelize, CurveAffine},
     circuit::{layouter::SyncDeps, Value},
-    distributed_util::{
-        dispatcher::{self, Dispatcher, WorkerMethod},
-        net::to_bytes,
-        plonk::permutation::keygen::KeygenTaskKZG,
-        utils::CastSlice,
-    },
+    distributed_util::backend::{ExecutionBackend, LocalBackend},
     poly::{
         batch_invert_assigned,
-        commitment::{Blind, Params, MSM},
-        kzg::commitment::ParamsKZG,
+        commitment::{Blind, Params},
         EvaluationDomain,
     },
     timer,
@@ -218,16 +210,35 @@ impl<F: Field> Assignment<F> for Assembly<F> {
 }
 
 /// Generate a `VerifyingKey` from an instance of `Circuit`.
-pub async fn keygen_vk<'params, C, P, ConcreteCircuit>(
-    params: &'params P,
+pub fn keygen_vk<'params, C, P, ConcreteCircuit>(
+    params: &P,
+    circuit: &ConcreteCircuit,
+) -> Result<VerifyingKey<C>, Error>
+where
+    C: CurveAffine,
+    P: Params<'params, C>,
+    ConcreteCircuit: Circuit<C::Scalar>,
+    C::Scalar: FromUniformBytes<64>,
+{
+    // The local backend awaits nothing, so this never blocks.
+    block_on(keygen_vk_with(params, circuit, &mut LocalBackend))
+}
+
+/// Same as [`keygen_vk`], but the permutation commitments are computed by
+/// `backend`, e.g. a
+/// [`RemoteBackend`](crate::distributed_util::backend::RemoteBackend) to
+/// have the workers of a dispatcher compute them.
+pub async fn keygen_vk_with<'params, C, P, ConcreteCircuit, B>(
+    params: &P,
     circuit: &ConcreteCircuit,
-    dispatcher: &mut Dispatcher,
+    backend: &mut B,
 ) -> Result<VerifyingKey<C>, Error>
 where
     C: CurveAffine,
     P: Params<'params, C>,
     ConcreteCircuit: Circuit<C::Scalar>,
     C::Scalar: FromUniformBytes<64>,
+    B: ExecutionBackend<C, P> + ?Sized,
 {
     let (domain, cs, config) = create_domain::<C, ConcreteCircuit>(
         params.k(),
@@ -267,9 +278,9 @@ where
     let permutation_vk = timer!("Generation of permutation vk", {
         assembly
             .permutation
-            .build_vk(params, &domain, &cs.permutation, dispatcher)
+            .build_vk(params, &domain, &cs.permutation, backend)
             .await
-    });
+    })?;
 
     let fixed_commitments = fixed
         .iter()
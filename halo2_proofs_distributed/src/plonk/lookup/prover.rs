@@ -3,6 +3,7 @@ use super::super::{
     ProvingKey,
 };
 use super::Argument;
+use crate::distributed_util::plonk::lookup::{LookupCommitter, PermuteInput, ProductInput};
 use crate::plonk::evaluation::evaluate;
 use crate::{
     arithmetic::{eval_polynomial, parallelize, CurveAffine},
@@ -87,32 +88,16 @@ impl<F: WithSmallOrderMulGroup<3>> Argument<F> {
         C: CurveAffine<ScalarExt = F>,
         C::Curve: Mul<F, Output = C::Curve> + MulAssign<F>,
     {
-        // Closure to get values of expressions and compress them
-        let compress_expressions = |expressions: &[Expression<C::Scalar>]| {
-            let compressed_expression = expressions
-                .iter()
-                .map(|expression| {
-                    pk.vk.domain.lagrange_from_vec(evaluate(
-                        expression,
-                        params.n() as usize,
-                        1,
-                        fixed_values,
-                        advice_values,
-                        instance_values,
-                        challenges,
-                    ))
-                })
-                .fold(domain.empty_lagrange(), |acc, expression| {
-                    acc * *theta + &expression
-                });
-            compressed_expression
-        };
-
-        // Get values of input expressions involved in the lookup and compress them
-        let compressed_input_expression = compress_expressions(&self.input_expressions);
-
-        // Get values of table expressions involved in the lookup and compress them
-        let compressed_table_expression = compress_expressions(&self.table_expressions);
+        let (compressed_input_expression, compressed_table_expression) = self.compress(
+            pk,
+            params,
+            domain,
+            theta,
+            advice_values,
+            fixed_values,
+            instance_values,
+            challenges,
+        );
 
         // Permute compressed (InputExpression, TableExpression) pair
         let (permuted_input_expression, permuted_table_expression) = permute_expression_pair(
@@ -159,6 +144,242 @@ impl<F: WithSmallOrderMulGroup<3>> Argument<F> {
     }
 }
 
+impl<F: WithSmallOrderMulGroup<3>> Argument<F> {
+    /// Returns the values of A_compressed and S_compressed, as described in
+    /// [`Argument::commit_permuted`].
+    pub(in crate::plonk) fn compress<'params, C, P: Params<'params, C>>(
+        &self,
+        pk: &ProvingKey<C>,
+        params: &P,
+        domain: &EvaluationDomain<C::Scalar>,
+        theta: ChallengeTheta<C>,
+        advice_values: &[Polynomial<C::Scalar, LagrangeCoeff>],
+        fixed_values: &[Polynomial<C::Scalar, LagrangeCoeff>],
+        instance_values: &[Polynomial<C::Scalar, LagrangeCoeff>],
+        challenges: &[C::Scalar],
+    ) -> ExpressionPair<F>
+    where
+        C: CurveAffine<ScalarExt = F>,
+    {
+        // Closure to get values of expressions and compress them
+        let compress_expressions = |expressions: &[Expression<C::Scalar>]| {
+            let compressed_expression = expressions
+                .iter()
+                .map(|expression| {
+                    pk.vk.domain.lagrange_from_vec(evaluate(
+                        expression,
+                        params.n() as usize,
+                        1,
+                        fixed_values,
+                        advice_values,
+                        instance_values,
+                        challenges,
+                    ))
+                })
+                .fold(domain.empty_lagrange(), |acc, expression| {
+                    acc * *theta + &expression
+                });
+            compressed_expression
+        };
+
+        // Get values of input expressions involved in the lookup and compress them
+        let compressed_input_expression = compress_expressions(&self.input_expressions);
+
+        // Get values of table expressions involved in the lookup and compress them
+        let compressed_table_expression = compress_expressions(&self.table_expressions);
+
+        (compressed_input_expression, compressed_table_expression)
+    }
+}
+
+/// Same as [`Argument::commit_permuted`] for every lookup of every circuit,
+/// but the permutations and the commitments to them are left to `offload`.
+/// The randomness is drawn from `rng` in the order the local prover draws it.
+pub(in crate::plonk) async fn commit_permuted_offloaded<
+    'params,
+    C,
+    P: Params<'params, C>,
+    E: EncodedChallenge<C>,
+    R: RngCore,
+    T: TranscriptWrite<C, E>,
+    Q: LookupCommitter<C> + ?Sized,
+>(
+    pk: &ProvingKey<C>,
+    params: &P,
+    domain: &EvaluationDomain<C::Scalar>,
+    theta: ChallengeTheta<C>,
+    circuits: &[(
+        &[Polynomial<C::Scalar, LagrangeCoeff>],
+        &[Polynomial<C::Scalar, LagrangeCoeff>],
+    )],
+    challenges: &[C::Scalar],
+    mut rng: R,
+    transcript: &mut T,
+    offload: &mut Q,
+) -> Result<Vec<Vec<Permuted<C>>>, Error>
+where
+    C: CurveAffine,
+{
+    let blinding_factors = pk.vk.cs.blinding_factors();
+    let random_rows = |rng: &mut R| -> Vec<_> {
+        (0..(blinding_factors + 1))
+            .map(|_| C::Scalar::random(&mut *rng))
+            .collect()
+    };
+
+    let inputs = circuits
+        .iter()
+        .flat_map(|&(advice_values, instance_values)| {
+            pk.vk
+                .cs
+                .lookups
+                .iter()
+                .map(move |lookup| (lookup, advice_values, instance_values))
+        })
+        .map(|(lookup, advice_values, instance_values)| {
+            let (compressed_input, compressed_table) = lookup.compress(
+                pk,
+                params,
+                domain,
+                theta,
+                advice_values,
+                &pk.fixed_values,
+                instance_values,
+                challenges,
+            );
+            PermuteInput {
+                compressed_input,
+                compressed_table,
+                input_blinding: random_rows(&mut rng),
+                table_blinding: random_rows(&mut rng),
+                input_blind: Blind(C::Scalar::random(&mut rng)),
+                table_blind: Blind(C::Scalar::random(&mut rng)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let outputs = offload.permute_lookups(&inputs).await?;
+    if outputs.len() != inputs.len() {
+        return Err(Error::ConstraintSystemFailure);
+    }
+
+    let mut permuted = inputs
+        .into_iter()
+        .zip(outputs)
+        .map(|(input, output)| -> Result<_, Error> {
+            let output = output.ok_or(Error::ConstraintSystemFailure)?;
+
+            // Hash permuted input commitment
+            transcript.write_point(output.input_commitment)?;
+
+            // Hash permuted table commitment
+            transcript.write_point(output.table_commitment)?;
+
+            Ok(Permuted {
+                compressed_input_expression: input.compressed_input,
+                permuted_input_poly: domain.lagrange_to_coeff(output.permuted_input.clone()),
+                permuted_input_expression: output.permuted_input,
+                permuted_input_blind: input.input_blind,
+                compressed_table_expression: input.compressed_table,
+                permuted_table_poly: domain.lagrange_to_coeff(output.permuted_table.clone()),
+                permuted_table_expression: output.permuted_table,
+                permuted_table_blind: input.table_blind,
+            })
+        });
+
+    let lookups_per_circuit = pk.vk.cs.lookups.len();
+    circuits
+        .iter()
+        .map(|_| {
+            permuted
+                .by_ref()
+                .take(lookups_per_circuit)
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect()
+}
+
+/// Same as [`Permuted::commit_product`] for every lookup of every circuit,
+/// but the grand products and the commitments to them are left to `offload`.
+/// The randomness is drawn from `rng` in the order the local prover draws it.
+pub(in crate::plonk) async fn commit_product_offloaded<
+    'params,
+    C: CurveAffine,
+    P: Params<'params, C>,
+    E: EncodedChallenge<C>,
+    R: RngCore,
+    T: TranscriptWrite<C, E>,
+    Q: LookupCommitter<C> + ?Sized,
+>(
+    lookups: Vec<Vec<Permuted<C>>>,
+    pk: &ProvingKey<C>,
+    params: &P,
+    beta: ChallengeBeta<C>,
+    gamma: ChallengeGamma<C>,
+    mut rng: R,
+    transcript: &mut T,
+    offload: &mut Q,
+) -> Result<Vec<Vec<Committed<C>>>, Error> {
+    let blinding_factors = pk.vk.cs.blinding_factors();
+    let lookups_per_circuit = lookups.iter().map(Vec::len).collect::<Vec<_>>();
+
+    let (inputs, permuted): (Vec<_>, Vec<_>) = lookups
+        .into_iter()
+        .flatten()
+        .map(|lookup| {
+            let input = ProductInput {
+                compressed_input: lookup.compressed_input_expression,
+                compressed_table: lookup.compressed_table_expression,
+                permuted_input: lookup.permuted_input_expression,
+                permuted_table: lookup.permuted_table_expression,
+                blinding: (0..blinding_factors)
+                    .map(|_| C::Scalar::random(&mut rng))
+                    .collect(),
+                blind: Blind(C::Scalar::random(&mut rng)),
+            };
+            let permuted = (
+                lookup.permuted_input_poly,
+                lookup.permuted_input_blind,
+                lookup.permuted_table_poly,
+                lookup.permuted_table_blind,
+            );
+            (input, permuted)
+        })
+        .unzip();
+
+    let outputs = offload.lookup_products(&inputs, *beta, *gamma).await?;
+    if outputs.len() != inputs.len() {
+        return Err(Error::ConstraintSystemFailure);
+    }
+
+    let mut committed = inputs.into_iter().zip(permuted).zip(outputs).map(
+        |((input, permuted), output)| -> Result<_, Error> {
+            // Hash product commitment
+            transcript.write_point(output.commitment)?;
+
+            let (
+                permuted_input_poly,
+                permuted_input_blind,
+                permuted_table_poly,
+                permuted_table_blind,
+            ) = permuted;
+            Ok(Committed {
+                permuted_input_poly,
+                permuted_input_blind,
+                permuted_table_poly,
+                permuted_table_blind,
+                product_poly: pk.vk.domain.lagrange_to_coeff(output.product),
+                product_blind: input.blind,
+            })
+        },
+    );
+
+    lookups_per_circuit
+        .into_iter()
+        .map(|len| committed.by_ref().take(len).collect::<Result<Vec<_>, _>>())
+        .collect()
+}
+
 impl<C: CurveAffine> Permuted<C> {
     /// Given a Lookup with input expressions, table expressions, and the permuted
     /// input expression and permuted table expression, this method constructs the
@@ -181,72 +402,19 @@ impl<C: CurveAffine> Permuted<C> {
         transcript: &mut T,
     ) -> Result<Committed<C>, Error> {
         let blinding_factors = pk.vk.cs.blinding_factors();
-        // Goal is to compute the products of fractions
-        //
-        // Numerator: (\theta^{m-1} a_0(\omega^i) + \theta^{m-2} a_1(\omega^i) + ... + \theta a_{m-2}(\omega^i) + a_{m-1}(\omega^i) + \beta)
-        //            * (\theta^{m-1} s_0(\omega^i) + \theta^{m-2} s_1(\omega^i) + ... + \theta s_{m-2}(\omega^i) + s_{m-1}(\omega^i) + \gamma)
-        // Denominator: (a'(\omega^i) + \beta) (s'(\omega^i) + \gamma)
-        //
-        // where a_j(X) is the jth input expression in this lookup,
-        // where a'(X) is the compression of the permuted input expressions,
-        // s_j(X) is the jth table expression in this lookup,
-        // s'(X) is the compression of the permuted table expressions,
-        // and i is the ith row of the expression.
-        let mut lookup_product = vec![C::Scalar::ZERO; params.n() as usize];
-        // Denominator uses the permuted input expression and permuted table expression
-        parallelize(&mut lookup_product, |lookup_product, start| {
-            for ((lookup_product, permuted_input_value), permuted_table_value) in lookup_product
-                .iter_mut()
-                .zip(self.permuted_input_expression[start..].iter())
-                .zip(self.permuted_table_expression[start..].iter())
-            {
-                *lookup_product = (*beta + permuted_input_value) * &(*gamma + permuted_table_value);
-            }
-        });
-
-        // Batch invert to obtain the denominators for the lookup product
-        // polynomials
-        lookup_product.iter_mut().batch_invert();
-
-        // Finish the computation of the entire fraction by computing the numerators
-        // (\theta^{m-1} a_0(\omega^i) + \theta^{m-2} a_1(\omega^i) + ... + \theta a_{m-2}(\omega^i) + a_{m-1}(\omega^i) + \beta)
-        // * (\theta^{m-1} s_0(\omega^i) + \theta^{m-2} s_1(\omega^i) + ... + \theta s_{m-2}(\omega^i) + s_{m-1}(\omega^i) + \gamma)
-        parallelize(&mut lookup_product, |product, start| {
-            for (i, product) in product.iter_mut().enumerate() {
-                let i = i + start;
-
-                *product *= &(self.compressed_input_expression[i] + &*beta);
-                *product *= &(self.compressed_table_expression[i] + &*gamma);
-            }
-        });
-
-        // The product vector is a vector of products of fractions of the form
-        //
-        // Numerator: (\theta^{m-1} a_0(\omega^i) + \theta^{m-2} a_1(\omega^i) + ... + \theta a_{m-2}(\omega^i) + a_{m-1}(\omega^i) + \beta)
-        //            * (\theta^{m-1} s_0(\omega^i) + \theta^{m-2} s_1(\omega^i) + ... + \theta s_{m-2}(\omega^i) + s_{m-1}(\omega^i) + \gamma)
-        // Denominator: (a'(\omega^i) + \beta) (s'(\omega^i) + \gamma)
-        //
-        // where there are m input expressions and m table expressions,
-        // a_j(\omega^i) is the jth input expression in this lookup,
-        // a'j(\omega^i) is the permuted input expression,
-        // s_j(\omega^i) is the jth table expression in this lookup,
-        // s'(\omega^i) is the permuted table expression,
-        // and i is the ith row of the expression.
-
-        // Compute the evaluations of the lookup product polynomial
-        // over our domain, starting with z[0] = 1
-        let z = iter::once(C::Scalar::ONE)
-            .chain(lookup_product)
-            .scan(C::Scalar::ONE, |state, cur| {
-                *state *= &cur;
-                Some(*state)
-            })
-            // Take all rows including the "last" row which should
-            // be a boolean (and ideally 1, else soundness is broken)
-            .take(params.n() as usize - blinding_factors)
-            // Chain random blinding factors.
-            .chain((0..blinding_factors).map(|_| C::Scalar::random(&mut rng)))
-            .collect::<Vec<_>>();
+        let z = product_values(
+            &self.compressed_input_expression,
+            &self.compressed_table_expression,
+            &self.permuted_input_expression,
+            &self.permuted_table_expression,
+            *beta,
+            *gamma,
+            params.n() as usize - blinding_factors,
+        )
+        .into_iter()
+        // Chain random blinding factors.
+        .chain((0..blinding_factors).map(|_| C::Scalar::random(&mut rng)))
+        .collect::<Vec<_>>();
         assert_eq!(z.len(), params.n() as usize);
         let z = pk.vk.domain.lagrange_from_vec(z);
 
@@ -381,6 +549,83 @@ impl<C: CurveAffine> Evaluated<C> {
     }
 }
 
+/// Returns the first `rows` values of the grand product polynomial of a
+/// lookup, given its compressed and permuted input and table expressions.
+pub(crate) fn product_values<F: Field>(
+    compressed_input_expression: &[F],
+    compressed_table_expression: &[F],
+    permuted_input_expression: &[F],
+    permuted_table_expression: &[F],
+    beta: F,
+    gamma: F,
+    rows: usize,
+) -> Vec<F> {
+    // Goal is to compute the products of fractions
+    //
+    // Numerator: (\theta^{m-1} a_0(\omega^i) + \theta^{m-2} a_1(\omega^i) + ... + \theta a_{m-2}(\omega^i) + a_{m-1}(\omega^i) + \beta)
+    //            * (\theta^{m-1} s_0(\omega^i) + \theta^{m-2} s_1(\omega^i) + ... + \theta s_{m-2}(\omega^i) + s_{m-1}(\omega^i) + \gamma)
+    // Denominator: (a'(\omega^i) + \beta) (s'(\omega^i) + \gamma)
+    //
+    // where a_j(X) is the jth input expression in this lookup,
+    // where a'(X) is the compression of the permuted input expressions,
+    // s_j(X) is the jth table expression in this lookup,
+    // s'(X) is the compression of the permuted table expressions,
+    // and i is the ith row of the expression.
+    let mut lookup_product = vec![F::ZERO; compressed_input_expression.len()];
+    // Denominator uses the permuted input expression and permuted table expression
+    parallelize(&mut lookup_product, |lookup_product, start| {
+        for ((lookup_product, permuted_input_value), permuted_table_value) in lookup_product
+            .iter_mut()
+            .zip(permuted_input_expression[start..].iter())
+            .zip(permuted_table_expression[start..].iter())
+        {
+            *lookup_product = (beta + permuted_input_value) * &(gamma + permuted_table_value);
+        }
+    });
+
+    // Batch invert to obtain the denominators for the lookup product
+    // polynomials
+    lookup_product.iter_mut().batch_invert();
+
+    // Finish the computation of the entire fraction by computing the numerators
+    // (\theta^{m-1} a_0(\omega^i) + \theta^{m-2} a_1(\omega^i) + ... + \theta a_{m-2}(\omega^i) + a_{m-1}(\omega^i) + \beta)
+    // * (\theta^{m-1} s_0(\omega^i) + \theta^{m-2} s_1(\omega^i) + ... + \theta s_{m-2}(\omega^i) + s_{m-1}(\omega^i) + \gamma)
+    parallelize(&mut lookup_product, |product, start| {
+        for (i, product) in product.iter_mut().enumerate() {
+            let i = i + start;
+
+            *product *= &(compressed_input_expression[i] + &beta);
+            *product *= &(compressed_table_expression[i] + &gamma);
+        }
+    });
+
+    // The product vector is a vector of products of fractions of the form
+    //
+    // Numerator: (\theta^{m-1} a_0(\omega^i) + \theta^{m-2} a_1(\omega^i) + ... + \theta a_{m-2}(\omega^i) + a_{m-1}(\omega^i) + \beta)
+    //            * (\theta^{m-1} s_0(\omega^i) + \theta^{m-2} s_1(\omega^i) + ... + \theta s_{m-2}(\omega^i) + s_{m-1}(\omega^i) + \gamma)
+    // Denominator: (a'(\omega^i) + \beta) (s'(\omega^i) + \gamma)
+    //
+    // where there are m input expressions and m table expressions,
+    // a_j(\omega^i) is the jth input expression in this lookup,
+    // a'j(\omega^i) is the permuted input expression,
+    // s_j(\omega^i) is the jth table expression in this lookup,
+    // s'(\omega^i) is the permuted table expression,
+    // and i is the ith row of the expression.
+
+    // Compute the evaluations of the lookup product polynomial
+    // over our domain, starting with z[0] = 1
+    iter::once(F::ONE)
+        .chain(lookup_product)
+        .scan(F::ONE, |state, cur| {
+            *state *= &cur;
+            Some(*state)
+        })
+        // Take all rows including the "last" row which should
+        // be a boolean (and ideally 1, else soundness is broken)
+        .take(rows)
+        .collect()
+}
+
 type ExpressionPair<F> = (Polynomial<F, LagrangeCoeff>, Polynomial<F, LagrangeCoeff>);
 
 /// Given a vector of input values A and a vector of table values S,
@@ -400,21 +645,60 @@ fn permute_expression_pair<'params, C: CurveAffine, P: Params<'params, C>, R: Rn
     let blinding_factors = pk.vk.cs.blinding_factors();
     let usable_rows = params.n() as usize - (blinding_factors + 1);
 
-    let mut permuted_input_expression: Vec<C::Scalar> = input_expression.to_vec();
+    let (mut permuted_input_expression, mut permuted_table_coeffs) =
+        permute_values(input_expression, table_expression, usable_rows)?;
+
+    permuted_input_expression
+        .extend((0..(blinding_factors + 1)).map(|_| C::Scalar::random(&mut rng)));
+    permuted_table_coeffs.extend((0..(blinding_factors + 1)).map(|_| C::Scalar::random(&mut rng)));
+    assert_eq!(permuted_input_expression.len(), params.n() as usize);
+    assert_eq!(permuted_table_coeffs.len(), params.n() as usize);
+
+    #[cfg(feature = "sanity-checks")]
+    {
+        let mut last = None;
+        for (a, b) in permuted_input_expression
+            .iter()
+            .zip(permuted_table_coeffs.iter())
+            .take(usable_rows)
+        {
+            if *a != *b {
+                assert_eq!(*a, last.unwrap());
+            }
+            last = Some(*a);
+        }
+    }
+
+    Ok((
+        domain.lagrange_from_vec(permuted_input_expression),
+        domain.lagrange_from_vec(permuted_table_coeffs),
+    ))
+}
+
+/// Permutes the first `usable_rows` values of a compressed input expression
+/// A and a compressed table expression S into A' and S', as described in
+/// [`permute_expression_pair`].
+pub(crate) fn permute_values<F: Field + Ord>(
+    input_expression: &[F],
+    table_expression: &[F],
+    usable_rows: usize,
+) -> Result<(Vec<F>, Vec<F>), Error> {
+    let mut permuted_input_expression: Vec<F> = input_expression.to_vec();
     permuted_input_expression.truncate(usable_rows);
 
     // Sort input lookup expression values
     permuted_input_expression.sort();
 
     // A BTreeMap of each unique element in the table expression and its count
-    let mut leftover_table_map: BTreeMap<C::Scalar, u32> = table_expression
-        .iter()
-        .take(usable_rows)
-        .fold(BTreeMap::new(), |mut acc, coeff| {
-            *acc.entry(*coeff).or_insert(0) += 1;
-            acc
-        });
-    let mut permuted_table_coeffs = vec![C::Scalar::ZERO; usable_rows];
+    let mut leftover_table_map: BTreeMap<F, u32> =
+        table_expression
+            .iter()
+            .take(usable_rows)
+            .fold(BTreeMap::new(), |mut acc, coeff| {
+                *acc.entry(*coeff).or_insert(0) += 1;
+                acc
+            });
+    let mut permuted_table_coeffs = vec![F::ZERO; usable_rows];
 
     let mut repeated_input_rows = permuted_input_expression
         .iter()
@@ -448,29 +732,5 @@ fn permute_expression_pair<'params, C: CurveAffine, P: Params<'params, C>, R: Rn
     }
     assert!(repeated_input_rows.is_empty());
 
-    permuted_input_expression
-        .extend((0..(blinding_factors + 1)).map(|_| C::Scalar::random(&mut rng)));
-    permuted_table_coeffs.extend((0..(blinding_factors + 1)).map(|_| C::Scalar::random(&mut rng)));
-    assert_eq!(permuted_input_expression.len(), params.n() as usize);
-    assert_eq!(permuted_table_coeffs.len(), params.n() as usize);
-
-    #[cfg(feature = "sanity-checks")]
-    {
-        let mut last = None;
-        for (a, b) in permuted_input_expression
-            .iter()
-            .zip(permuted_table_coeffs.iter())
-            .take(usable_rows)
-        {
-            if *a != *b {
-                assert_eq!(*a, last.unwrap());
-            }
-            last = Some(*a);
-        }
-    }
-
-    Ok((
-        domain.lagrange_from_vec(permuted_input_expression),
-        domain.lagrange_from_vec(permuted_table_coeffs),
-    ))
+    Ok((permuted_input_expression, permuted_table_coeffs))
 }
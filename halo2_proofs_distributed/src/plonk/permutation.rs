@@ -93,6 +93,10 @@ pub struct VerifyingKey<C: CurveAffine> {
 }
 
 impl<C: CurveAffine> VerifyingKey<C> {
+    pub(crate) fn from_commitments(commitments: Vec<C>) -> Self {
+        VerifyingKey { commitments }
+    }
+
     /// Returns commitments of sigma polynomials
     pub fn commitments(&self) -> &Vec<C> {
         &self.commitments
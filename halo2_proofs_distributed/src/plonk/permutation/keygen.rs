@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Range;
 
-use ff::{Field, PrimeField};
+use ff::{Field, WithSmallOrderMulGroup};
 use futures::future::join_all;
 use group::Curve;
 use rayon::prelude::{
@@ -13,7 +14,7 @@ use std::time::Instant;
 use super::{Argument, ProvingKey, VerifyingKey};
 use crate::{
     arithmetic::{parallelize, CurveAffine},
-    distributed_util::dispatcher::Dispatcher,
+    distributed_util::backend::ExecutionBackend,
     plonk::{Any, Column, Error},
     poly::{
         commitment::{Blind, CommitmentScheme, Params},
@@ -115,15 +116,21 @@ impl Assembly {
         Ok(())
     }
 
-    pub(crate) async fn build_vk<'params, C: CurveAffine, P: Params<'params, C>>(
+    pub(crate) async fn build_vk<C, P, B>(
         self,
-        params: &'params P,
-        domain: &'params EvaluationDomain<C::Scalar>,
-        p: &'params Argument,
-        dispatcher: &mut Dispatcher,
-    ) -> VerifyingKey<C> {
+        params: &P,
+        domain: &EvaluationDomain<C::Scalar>,
+        p: &Argument,
+        backend: &mut B,
+    ) -> Result<VerifyingKey<C>, Error>
+    where
+        C: CurveAffine,
+        B: ExecutionBackend<C, P> + ?Sized,
+    {
         timer!("keygen.rs build_vk", {
-            build_vk(params, domain, p, &self.mapping, dispatcher).await
+            backend
+                .permutation_vk(params, domain, p, &|i, j| self.mapping[i][j])
+                .await
         })
     }
 
@@ -287,14 +294,22 @@ impl Assembly {
         }
     }
 
-    pub(crate) fn build_vk<'params, C: CurveAffine, P: Params<'params, C>>(
+    pub(crate) async fn build_vk<C, P, B>(
         &mut self,
         params: &P,
         domain: &EvaluationDomain<C::Scalar>,
         p: &Argument,
-    ) -> VerifyingKey<C> {
+        backend: &mut B,
+    ) -> Result<VerifyingKey<C>, Error>
+    where
+        C: CurveAffine,
+        B: ExecutionBackend<C, P> + ?Sized,
+    {
         self.build_ordered_mapping();
-        build_vk(params, domain, p, |i, j| self.mapping_at_idx(i, j))
+        let assembly = &*self;
+        backend
+            .permutation_vk(params, domain, p, &|i, j| assembly.mapping_at_idx(i, j))
+            .await
     }
 
     pub(crate) fn build_pk<'params, C: CurveAffine, P: Params<'params, C>>(
@@ -400,13 +415,92 @@ pub(crate) fn build_pk<'params, C: CurveAffine, P: Params<'params, C>>(
     }
 }
 
-pub(crate) async fn build_vk<'params, C: CurveAffine, P: Params<'params, C>>(
-    params: &'params P,
-    domain: &'params EvaluationDomain<C::Scalar>,
-    p: &'params Argument,
-    mapping: &Vec<Vec<(usize, usize)>>,
-    dispatcher: &mut Dispatcher,
+pub(crate) fn build_vk<'params, C: CurveAffine, P: Params<'params, C>>(
+    params: &P,
+    domain: &EvaluationDomain<C::Scalar>,
+    p: &Argument,
+    mapping: impl Fn(usize, usize) -> (usize, usize) + Sync,
 ) -> VerifyingKey<C> {
-    let commitments = dispatcher.keygen(params, domain, p, mapping).await;
+    let commitments = commit_permutations(params, domain, p, 0..p.columns.len(), mapping);
     VerifyingKey { commitments }
 }
+
+/// Commits to the permutation polynomials of the columns in `columns`. The
+/// `mapping` is indexed by the absolute column index.
+pub(crate) fn commit_permutations<'params, C: CurveAffine, P: Params<'params, C>>(
+    params: &P,
+    domain: &EvaluationDomain<C::Scalar>,
+    p: &Argument,
+    columns: Range<usize>,
+    mapping: impl Fn(usize, usize) -> (usize, usize) + Sync,
+) -> Vec<C> {
+    let permutations = permutation_polys(params.n() as usize, domain, p, columns, mapping);
+
+    // TIME! This is the rate-limiting step
+    // Pre-compute commitments for the URS.
+    let mut commitments = Vec::with_capacity(permutations.len());
+    for permutation in &permutations {
+        // Compute commitment to permutation polynomial
+        commitments.push(
+            params
+                .commit_lagrange(permutation, Blind::default())
+                .to_affine(),
+        );
+    }
+
+    commitments
+}
+
+/// Computes the permutation polynomials, of `n` rows, of the columns in
+/// `columns`. The `mapping` is indexed by the absolute column index.
+pub(crate) fn permutation_polys<F: WithSmallOrderMulGroup<3>>(
+    n: usize,
+    domain: &EvaluationDomain<F>,
+    p: &Argument,
+    columns: Range<usize>,
+    mapping: impl Fn(usize, usize) -> (usize, usize) + Sync,
+) -> Vec<Polynomial<F, LagrangeCoeff>> {
+    // Compute [omega^0, omega^1, ..., omega^{params.n - 1}]
+    let mut omega_powers = vec![F::ZERO; n];
+    timer!("keygen.rs omega_powers", {
+        let omega = domain.get_omega();
+        parallelize(&mut omega_powers, |o, start| {
+            let mut cur = omega.pow_vartime(&[start as u64]);
+            for v in o.iter_mut() {
+                *v = cur;
+                cur *= &omega;
+            }
+        })
+    });
+
+    // Compute [omega_powers * \delta^0, omega_powers * \delta^1, ..., omega_powers * \delta^m]
+    let mut deltaomega = vec![omega_powers; p.columns.len()];
+    timer!("keygen.rs deltaomega", {
+        parallelize(&mut deltaomega, |o, start| {
+            let mut cur = F::DELTA.pow_vartime(&[start as u64]);
+            for omega_powers in o.iter_mut() {
+                for v in omega_powers {
+                    *v *= &cur;
+                }
+                cur *= &F::DELTA;
+            }
+        });
+    });
+
+    // Computes the permutation polynomial based on the permutation
+    // description in the assembly.
+    let mut permutations = vec![domain.empty_lagrange(); columns.len()];
+    timer!("keygen.rs permutations", {
+        parallelize(&mut permutations, |o, start| {
+            for (x, permutation_poly) in o.iter_mut().enumerate() {
+                let i = columns.start + start + x;
+                for (j, p) in permutation_poly.iter_mut().enumerate() {
+                    let (permuted_i, permuted_j) = mapping(i, j);
+                    *p = deltaomega[permuted_i][permuted_j];
+                }
+            }
+        });
+    });
+
+    permutations
+}
@@ -4,12 +4,19 @@ use group::{
     Curve,
 };
 use rand_core::RngCore;
-use std::iter::{self, ExactSizeIterator};
+use std::{
+    borrow::Cow,
+    iter::{self, ExactSizeIterator},
+};
 
 use super::super::{circuit::Any, ChallengeBeta, ChallengeGamma, ChallengeX};
 use super::{Argument, ProvingKey};
 use crate::{
     arithmetic::{eval_polynomial, parallelize, CurveAffine},
+    distributed_util::plonk::{
+        domain::DomainTransformer,
+        permutation::prover::{PermutationChunk, PermutationCommitter},
+    },
     plonk::{self, Error},
     poly::{
         self,
@@ -86,85 +93,42 @@ impl Argument {
             .chunks(chunk_len)
             .zip(pkey.permutations.chunks(chunk_len))
         {
-            // Goal is to compute the products of fractions
-            //
-            // (p_j(\omega^i) + \delta^j \omega^i \beta + \gamma) /
-            // (p_j(\omega^i) + \beta s_j(\omega^i) + \gamma)
-            //
-            // where p_j(X) is the jth column in this permutation,
-            // and i is the ith row of the column.
-
-            let mut modified_values = vec![C::Scalar::ONE; params.n() as usize];
-
-            // Iterate over each column of the permutation
-            for (&column, permuted_column_values) in columns.iter().zip(permutations.iter()) {
-                let values = match column.column_type() {
-                    Any::Advice(_) => advice,
-                    Any::Fixed => fixed,
-                    Any::Instance => instance,
-                };
-                parallelize(&mut modified_values, |modified_values, start| {
-                    for ((modified_values, value), permuted_value) in modified_values
-                        .iter_mut()
-                        .zip(values[column.index()][start..].iter())
-                        .zip(permuted_column_values[start..].iter())
-                    {
-                        *modified_values *= &(*beta * permuted_value + &*gamma + value);
-                    }
-                });
-            }
-
-            // Invert to obtain the denominator for the permutation product polynomial
-            modified_values.batch_invert();
-
-            // Iterate over each column again, this time finishing the computation
-            // of the entire fraction by computing the numerators
-            for &column in columns.iter() {
-                let omega = domain.get_omega();
-                let values = match column.column_type() {
-                    Any::Advice(_) => advice,
-                    Any::Fixed => fixed,
-                    Any::Instance => instance,
-                };
-                parallelize(&mut modified_values, |modified_values, start| {
-                    let mut deltaomega = deltaomega * &omega.pow_vartime(&[start as u64, 0, 0, 0]);
-                    for (modified_values, value) in modified_values
-                        .iter_mut()
-                        .zip(values[column.index()][start..].iter())
-                    {
-                        // Multiply by p_j(\omega^i) + \delta^j \omega^i \beta
-                        *modified_values *= &(deltaomega * &*beta + &*gamma + value);
-                        deltaomega *= &omega;
-                    }
-                });
-                deltaomega *= &<C::Scalar as PrimeField>::DELTA;
-            }
-
-            // The modified_values vector is a vector of products of fractions
-            // of the form
-            //
-            // (p_j(\omega^i) + \delta^j \omega^i \beta + \gamma) /
-            // (p_j(\omega^i) + \beta s_j(\omega^i) + \gamma)
-            //
-            // where i is the index into modified_values, for the jth column in
-            // the permutation
+            let values = columns
+                .iter()
+                .map(|column| {
+                    let values = match column.column_type() {
+                        Any::Advice(_) => advice,
+                        Any::Fixed => fixed,
+                        Any::Instance => instance,
+                    };
+                    &values[column.index()][..]
+                })
+                .collect::<Vec<_>>();
+            let permutations = permutations
+                .iter()
+                .map(|permutation| &permutation[..])
+                .collect::<Vec<_>>();
 
             // Compute the evaluations of the permutation product polynomial
-            // over our domain, starting with z[0] = 1
-            let mut z = vec![last_z];
-            for row in 1..(params.n() as usize) {
-                let mut tmp = z[row - 1];
-
-                tmp *= &modified_values[row - 1];
-                z.push(tmp);
-            }
-            let mut z = domain.lagrange_from_vec(z);
+            // over our domain, starting with z[0] = last_z
+            let z = product_values(
+                &values,
+                &permutations,
+                *beta,
+                *gamma,
+                deltaomega,
+                domain.get_omega(),
+                params.n() as usize - blinding_factors,
+            )
+            .into_iter()
+            .map(|z| last_z * z)
             // Set blinding factors
-            for z in &mut z[params.n() as usize - blinding_factors..] {
-                *z = C::Scalar::random(&mut rng);
-            }
+            .chain((0..blinding_factors).map(|_| C::Scalar::random(&mut rng)))
+            .collect::<Vec<_>>();
+            let z = domain.lagrange_from_vec(z);
             // Set new last_z
             last_z = z[params.n() as usize - (blinding_factors + 1)];
+            deltaomega *= <C::Scalar as PrimeField>::DELTA.pow_vartime([columns.len() as u64]);
 
             let blind = Blind(C::Scalar::random(&mut rng));
 
@@ -192,6 +156,174 @@ impl Argument {
     }
 }
 
+/// Same as [`Argument::commit`] for the permutation argument of every circuit,
+/// but the grand products of the column sets and the commitments to them are
+/// left to `offload`, as are the FFTs extending the grand products. The
+/// randomness is drawn from `rng` in the order the local prover draws it.
+pub(in crate::plonk) async fn commit_offloaded<
+    'params,
+    C: CurveAffine,
+    P: Params<'params, C>,
+    E: EncodedChallenge<C>,
+    R: RngCore,
+    T: TranscriptWrite<C, E>,
+    Q: PermutationCommitter<C> + DomainTransformer<C> + ?Sized,
+>(
+    params: &P,
+    pk: &plonk::ProvingKey<C>,
+    circuits: &[(
+        &[Polynomial<C::Scalar, LagrangeCoeff>],
+        &[Polynomial<C::Scalar, LagrangeCoeff>],
+    )],
+    beta: ChallengeBeta<C>,
+    gamma: ChallengeGamma<C>,
+    mut rng: R,
+    transcript: &mut T,
+    offload: &mut Q,
+) -> Result<Vec<Committed<C>>, Error> {
+    let domain = &pk.vk.domain;
+    let argument = &pk.vk.cs.permutation;
+    assert!(pk.vk.cs_degree >= 3);
+    let chunk_len = pk.vk.cs_degree - 2;
+    let blinding_factors = pk.vk.cs.blinding_factors();
+
+    let chunks = circuits
+        .iter()
+        .flat_map(|&(advice, instance)| {
+            argument
+                .columns
+                .chunks(chunk_len)
+                .zip(pk.permutation.permutations.chunks(chunk_len))
+                .enumerate()
+                .map(move |(set, chunk)| (set, chunk, advice, instance))
+        })
+        .map(|(set, (columns, permutations), advice, instance)| {
+            let columns = columns
+                .iter()
+                .map(|column| {
+                    let values = match column.column_type() {
+                        Any::Advice(_) => advice,
+                        Any::Fixed => &pk.fixed_values[..],
+                        Any::Instance => instance,
+                    };
+                    Cow::Borrowed(&values[column.index()])
+                })
+                .collect();
+            PermutationChunk {
+                columns,
+                permutations: permutations.iter().map(Cow::Borrowed).collect(),
+                // Each column gets its own delta power.
+                delta: <C::Scalar as PrimeField>::DELTA.pow_vartime([(set * chunk_len) as u64]),
+                blinding: (0..blinding_factors)
+                    .map(|_| C::Scalar::random(&mut rng))
+                    .collect(),
+                blind: Blind(C::Scalar::random(&mut rng)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let sets = argument.columns.chunks(chunk_len).len();
+    let products = offload
+        .permutation_products(&chunks, sets, *beta, *gamma, domain.get_omega())
+        .await?;
+    if products.len() != chunks.len() {
+        return Err(Error::ConstraintSystemFailure);
+    }
+
+    let mut committed = Vec::with_capacity(chunks.len());
+    for (chunk, product) in chunks.into_iter().zip(products) {
+        // Hash the permutation product commitment
+        transcript.write_point(product.commitment)?;
+
+        let z = domain.lagrange_to_coeff(product.z);
+        committed.push(CommittedSet {
+            permutation_product_coset: offload.coeff_to_extended(domain, z.clone()).await?,
+            permutation_product_poly: z,
+            permutation_product_blind: chunk.blind,
+        });
+    }
+
+    let mut committed = committed.into_iter();
+    Ok(circuits
+        .iter()
+        .map(|_| Committed {
+            sets: committed.by_ref().take(sets).collect(),
+        })
+        .collect())
+}
+
+/// Returns the first `rows` values of the grand product polynomial of a set
+/// of permutation columns, as if it started from one, given the values of the
+/// columns, of their permutation polynomials and the power of delta of the
+/// first column.
+pub(crate) fn product_values<F: PrimeField>(
+    columns: &[&[F]],
+    permutations: &[&[F]],
+    beta: F,
+    gamma: F,
+    delta: F,
+    omega: F,
+    rows: usize,
+) -> Vec<F> {
+    // Goal is to compute the products of fractions
+    //
+    // (p_j(\omega^i) + \delta^j \omega^i \beta + \gamma) /
+    // (p_j(\omega^i) + \beta s_j(\omega^i) + \gamma)
+    //
+    // where p_j(X) is the jth column in this permutation,
+    // and i is the ith row of the column.
+
+    let mut modified_values = vec![F::ONE; rows];
+
+    // Iterate over each column of the permutation
+    for (values, permuted_column_values) in columns.iter().zip(permutations.iter()) {
+        parallelize(&mut modified_values, |modified_values, start| {
+            for ((modified_values, value), permuted_value) in modified_values
+                .iter_mut()
+                .zip(values[start..].iter())
+                .zip(permuted_column_values[start..].iter())
+            {
+                *modified_values *= &(beta * permuted_value + &gamma + value);
+            }
+        });
+    }
+
+    // Invert to obtain the denominator for the permutation product polynomial
+    modified_values.batch_invert();
+
+    // Iterate over each column again, this time finishing the computation
+    // of the entire fraction by computing the numerators
+    let mut deltaomega = delta;
+    for values in columns.iter() {
+        parallelize(&mut modified_values, |modified_values, start| {
+            let mut deltaomega = deltaomega * &omega.pow_vartime(&[start as u64, 0, 0, 0]);
+            for (modified_values, value) in modified_values.iter_mut().zip(values[start..].iter()) {
+                // Multiply by p_j(\omega^i) + \delta^j \omega^i \beta
+                *modified_values *= &(deltaomega * &beta + &gamma + value);
+                deltaomega *= &omega;
+            }
+        });
+        deltaomega *= &F::DELTA;
+    }
+
+    // The modified_values vector is a vector of products of fractions
+    // of the form
+    //
+    // (p_j(\omega^i) + \delta^j \omega^i \beta + \gamma) /
+    // (p_j(\omega^i) + \beta s_j(\omega^i) + \gamma)
+    //
+    // where i is the index into modified_values, for the jth column in
+    // the permutation
+    iter::once(F::ONE)
+        .chain(modified_values)
+        .scan(F::ONE, |state, cur| {
+            *state *= &cur;
+            Some(*state)
+        })
+        .take(rows)
+        .collect()
+}
+
 impl<C: CurveAffine> Committed<C> {
     pub(in crate::plonk) fn construct(self) -> Constructed<C> {
         Constructed {
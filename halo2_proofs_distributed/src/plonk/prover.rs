@@ -1,4 +1,5 @@
 use ff::{Field, FromUniformBytes, PrimeField, WithSmallOrderMulGroup};
+use futures::executor::block_on;
 use group::Curve;
 use halo2curves::CurveExt;
 use rand_core::RngCore;
@@ -19,6 +20,12 @@ use super::{
     ChallengeX, ChallengeY, Error, Expression, ProvingKey,
 };
 use crate::circuit::layouter::SyncDeps;
+use crate::distributed_util::{
+    dispatcher::Dispatcher,
+    net::SerdeParams,
+    plonk::{commit::DispatchedCommitter, ProverOffload},
+};
+use crate::helpers::{SerdeCurveAffine, SerdePrimeField};
 use crate::timer;
 use crate::{
     arithmetic::{eval_polynomial, CurveAffine},
@@ -199,6 +206,73 @@ pub fn create_proof<
     R: RngCore,
     T: TranscriptWrite<Scheme::Curve, E>,
     ConcreteCircuit: Circuit<Scheme::Scalar>,
+>(
+    params: &'params Scheme::ParamsProver,
+    pk: &ProvingKey<Scheme::Curve>,
+    circuits: &[ConcreteCircuit],
+    instances: &[&[&[Scheme::Scalar]]],
+    rng: R,
+    transcript: &mut T,
+) -> Result<(), Error>
+where
+    Scheme::Scalar: WithSmallOrderMulGroup<3> + FromUniformBytes<64>,
+{
+    // Without an offload nothing is awaited, so this never blocks.
+    block_on(create_proof_with::<Scheme, P, _, _, _, _>(
+        params, pk, circuits, instances, rng, transcript, None,
+    ))
+}
+
+/// Same as [`create_proof`], but the advice columns, lookups and permutation
+/// grand products are committed to and h(X) is evaluated by the workers of
+/// `dispatcher`. For the same `rng` the proof is identical to the
+/// one [`create_proof`] writes.
+pub async fn create_proof_distributed<
+    'params,
+    Scheme: CommitmentScheme,
+    P: Prover<'params, Scheme>,
+    E: EncodedChallenge<Scheme::Curve>,
+    R: RngCore,
+    T: TranscriptWrite<Scheme::Curve, E>,
+    ConcreteCircuit: Circuit<Scheme::Scalar>,
+>(
+    params: &'params Scheme::ParamsProver,
+    pk: &ProvingKey<Scheme::Curve>,
+    circuits: &[ConcreteCircuit],
+    instances: &[&[&[Scheme::Scalar]]],
+    rng: R,
+    transcript: &mut T,
+    dispatcher: &Dispatcher,
+) -> Result<(), Error>
+where
+    Scheme::Curve: SerdeCurveAffine,
+    Scheme::Scalar: WithSmallOrderMulGroup<3> + FromUniformBytes<64> + SerdePrimeField,
+    Scheme::ParamsProver: SerdeParams,
+{
+    let params_hash = dispatcher.upload_params(params).await;
+    let mut offload = DispatchedCommitter::new(params, params_hash, dispatcher);
+    create_proof_with::<Scheme, P, _, _, _, _>(
+        params,
+        pk,
+        circuits,
+        instances,
+        rng,
+        transcript,
+        Some(&mut offload),
+    )
+    .await
+}
+
+/// Creates the proof, committing to the advice columns and evaluating h(X)
+/// with `offload` when one is given and locally otherwise.
+async fn create_proof_with<
+    'params,
+    Scheme: CommitmentScheme,
+    P: Prover<'params, Scheme>,
+    E: EncodedChallenge<Scheme::Curve>,
+    R: RngCore,
+    T: TranscriptWrite<Scheme::Curve, E>,
+    ConcreteCircuit: Circuit<Scheme::Scalar>,
 >(
     params: &'params Scheme::ParamsProver,
     pk: &ProvingKey<Scheme::Curve>,
@@ -206,6 +280,7 @@ pub fn create_proof<
     instances: &[&[&[Scheme::Scalar]]],
     mut rng: R,
     transcript: &mut T,
+    mut offload: Option<&mut dyn ProverOffload<Scheme::Curve>>,
 ) -> Result<(), Error>
 where
     Scheme::Scalar: WithSmallOrderMulGroup<3> + FromUniformBytes<64>,
@@ -364,19 +439,23 @@ where
                     .iter()
                     .map(|_| Blind(Scheme::Scalar::random(&mut rng)))
                     .collect();
-                let advice_commitments_projective: Vec<_> = advice_values
-                    .iter()
-                    .zip(blinds.iter())
-                    .map(|(poly, blind)| params.commit_lagrange(poly, *blind))
-                    .collect();
-                let mut advice_commitments =
-                    vec![Scheme::Curve::identity(); advice_commitments_projective.len()];
-                <Scheme::Curve as CurveAffine>::CurveExt::batch_normalize(
-                    &advice_commitments_projective,
-                    &mut advice_commitments,
-                );
-                let advice_commitments = advice_commitments;
-                drop(advice_commitments_projective);
+                let advice_commitments = match offload.as_mut() {
+                    Some(offload) => offload.commit_lagrange(&advice_values, &blinds).await?,
+                    None => {
+                        let advice_commitments_projective: Vec<_> = advice_values
+                            .iter()
+                            .zip(blinds.iter())
+                            .map(|(poly, blind)| params.commit_lagrange(poly, *blind))
+                            .collect();
+                        let mut advice_commitments =
+                            vec![Scheme::Curve::identity(); advice_commitments_projective.len()];
+                        <Scheme::Curve as CurveAffine>::CurveExt::batch_normalize(
+                            &advice_commitments_projective,
+                            &mut advice_commitments,
+                        );
+                        advice_commitments
+                    }
+                };
 
                 for commitment in &advice_commitments {
                     transcript.write_point(*commitment)?;
@@ -410,32 +489,58 @@ where
     let theta: ChallengeTheta<_> = transcript.squeeze_challenge_scalar();
 
     let lookups: Vec<Vec<lookup::prover::Permuted<Scheme::Curve>>> = timer!("lookups", {
-        instance
-            .iter()
-            .zip(advice.iter())
-            .map(|(instance, advice)| -> Result<Vec<_>, Error> {
-                // Construct and commit to permuted values for each lookup
-                pk.vk
-                    .cs
-                    .lookups
+        match offload.as_mut() {
+            Some(offload) => {
+                let circuits = instance
                     .iter()
-                    .map(|lookup| {
-                        lookup.commit_permuted(
-                            pk,
-                            params,
-                            domain,
-                            theta,
-                            &advice.advice_polys,
-                            &pk.fixed_values,
-                            &instance.instance_values,
-                            &challenges,
-                            &mut rng,
-                            transcript,
+                    .zip(advice.iter())
+                    .map(|(instance, advice)| {
+                        (
+                            advice.advice_polys.as_slice(),
+                            instance.instance_values.as_slice(),
                         )
                     })
-                    .collect()
-            })
-            .collect::<Result<Vec<_>, _>>()?
+                    .collect::<Vec<_>>();
+                lookup::prover::commit_permuted_offloaded(
+                    pk,
+                    params,
+                    domain,
+                    theta,
+                    &circuits,
+                    &challenges,
+                    &mut rng,
+                    transcript,
+                    &mut **offload,
+                )
+                .await?
+            }
+            None => instance
+                .iter()
+                .zip(advice.iter())
+                .map(|(instance, advice)| -> Result<Vec<_>, Error> {
+                    // Construct and commit to permuted values for each lookup
+                    pk.vk
+                        .cs
+                        .lookups
+                        .iter()
+                        .map(|lookup| {
+                            lookup.commit_permuted(
+                                pk,
+                                params,
+                                domain,
+                                theta,
+                                &advice.advice_polys,
+                                &pk.fixed_values,
+                                &instance.instance_values,
+                                &challenges,
+                                &mut rng,
+                                transcript,
+                            )
+                        })
+                        .collect()
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        }
     });
 
     // Sample beta challenge
@@ -447,39 +552,79 @@ where
     // Commit to permutations.
     let permutations: Vec<permutation::prover::Committed<Scheme::Curve>> =
         timer!("permutations", {
-            instance
-                .iter()
-                .zip(advice.iter())
-                .map(|(instance, advice)| {
-                    pk.vk.cs.permutation.commit(
+            match offload.as_mut() {
+                Some(offload) => {
+                    let circuits = instance
+                        .iter()
+                        .zip(advice.iter())
+                        .map(|(instance, advice)| {
+                            (
+                                advice.advice_polys.as_slice(),
+                                instance.instance_values.as_slice(),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    permutation::prover::commit_offloaded(
                         params,
                         pk,
-                        &pk.permutation,
-                        &advice.advice_polys,
-                        &pk.fixed_values,
-                        &instance.instance_values,
+                        &circuits,
                         beta,
                         gamma,
                         &mut rng,
                         transcript,
+                        &mut **offload,
                     )
-                })
-                .collect::<Result<Vec<_>, _>>()?
+                    .await?
+                }
+                None => instance
+                    .iter()
+                    .zip(advice.iter())
+                    .map(|(instance, advice)| {
+                        pk.vk.cs.permutation.commit(
+                            params,
+                            pk,
+                            &pk.permutation,
+                            &advice.advice_polys,
+                            &pk.fixed_values,
+                            &instance.instance_values,
+                            beta,
+                            gamma,
+                            &mut rng,
+                            transcript,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            }
         });
 
     let lookups: Vec<Vec<lookup::prover::Committed<Scheme::Curve>>> = timer!("lookups", {
-        lookups
-            .into_iter()
-            .map(|lookups| -> Result<Vec<_>, _> {
-                // Construct and commit to products for each lookup
-                lookups
-                    .into_iter()
-                    .map(|lookup| {
-                        lookup.commit_product(pk, params, beta, gamma, &mut rng, transcript)
-                    })
-                    .collect::<Result<Vec<_>, _>>()
-            })
-            .collect::<Result<Vec<_>, _>>()?
+        match offload.as_mut() {
+            Some(offload) => {
+                lookup::prover::commit_product_offloaded(
+                    lookups,
+                    pk,
+                    params,
+                    beta,
+                    gamma,
+                    &mut rng,
+                    transcript,
+                    &mut **offload,
+                )
+                .await?
+            }
+            None => lookups
+                .into_iter()
+                .map(|lookups| -> Result<Vec<_>, _> {
+                    // Construct and commit to products for each lookup
+                    lookups
+                        .into_iter()
+                        .map(|lookup| {
+                            lookup.commit_product(pk, params, beta, gamma, &mut rng, transcript)
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        }
     });
 
     let shuffles: Vec<Vec<shuffle::prover::Committed<Scheme::Curve>>> = timer!("shuffles", {
@@ -538,28 +683,57 @@ where
         .collect();
 
     // Evaluate the h(X) polynomial
-    let h_poly = pk.ev.evaluate_h(
-        pk,
-        &advice
-            .iter()
-            .map(|a| a.advice_polys.as_slice())
-            .collect::<Vec<_>>(),
-        &instance
-            .iter()
-            .map(|i| i.instance_polys.as_slice())
-            .collect::<Vec<_>>(),
-        &challenges,
-        *y,
-        *beta,
-        *gamma,
-        *theta,
-        &lookups,
-        &shuffles,
-        &permutations,
-    );
+    let advice_polys = advice
+        .iter()
+        .map(|a| a.advice_polys.as_slice())
+        .collect::<Vec<_>>();
+    let instance_polys = instance
+        .iter()
+        .map(|i| i.instance_polys.as_slice())
+        .collect::<Vec<_>>();
+    let h_poly = match offload.as_mut() {
+        Some(offload) => {
+            pk.ev
+                .evaluate_h_offloaded(
+                    pk,
+                    &advice_polys,
+                    &instance_polys,
+                    &challenges,
+                    *y,
+                    *beta,
+                    *gamma,
+                    *theta,
+                    &lookups,
+                    &shuffles,
+                    &permutations,
+                    &mut **offload,
+                )
+                .await?
+        }
+        None => pk.ev.evaluate_h(
+            pk,
+            &advice_polys,
+            &instance_polys,
+            &challenges,
+            *y,
+            *beta,
+            *gamma,
+            *theta,
+            &lookups,
+            &shuffles,
+            &permutations,
+        ),
+    };
 
     // Construct the vanishing argument's h(X) commitments
-    let vanishing = vanishing.construct(params, domain, h_poly, &mut rng, transcript)?;
+    let vanishing = match offload.as_mut() {
+        Some(offload) => {
+            vanishing
+                .construct_offloaded(params, domain, h_poly, &mut rng, transcript, &mut **offload)
+                .await?
+        }
+        None => vanishing.construct(params, domain, h_poly, &mut rng, transcript)?,
+    };
 
     let x: ChallengeX<_> = transcript.squeeze_challenge_scalar();
     let xn = x.pow(&[params.n() as u64, 0, 0, 0]);
@@ -9,6 +9,7 @@ use rayon::{current_num_threads, prelude::*};
 use super::Argument;
 use crate::{
     arithmetic::{eval_polynomial, CurveAffine},
+    distributed_util::plonk::domain::DomainTransformer,
     plonk::{ChallengeX, ChallengeY, Error},
     poly::{
         self,
@@ -100,7 +101,7 @@ impl<C: CurveAffine> Committed<C> {
         params: &P,
         domain: &EvaluationDomain<C::Scalar>,
         h_poly: Polynomial<C::Scalar, ExtendedLagrangeCoeff>,
-        mut rng: R,
+        rng: R,
         transcript: &mut T,
     ) -> Result<Constructed<C>, Error> {
         // Divide by t(X) = X^{params.n} - 1.
@@ -109,6 +110,50 @@ impl<C: CurveAffine> Committed<C> {
         // Obtain final h(X) polynomial
         let h_poly = domain.extended_to_coeff(h_poly);
 
+        self.commit_pieces(params, domain, h_poly, rng, transcript)
+    }
+
+    /// Same as [`Committed::construct`], but h(X) is brought back to
+    /// coefficient form by `offload`.
+    pub(in crate::plonk) async fn construct_offloaded<
+        'params,
+        P: ParamsProver<'params, C>,
+        E: EncodedChallenge<C>,
+        R: RngCore,
+        T: TranscriptWrite<C, E>,
+        Q: DomainTransformer<C> + ?Sized,
+    >(
+        self,
+        params: &P,
+        domain: &EvaluationDomain<C::Scalar>,
+        h_poly: Polynomial<C::Scalar, ExtendedLagrangeCoeff>,
+        rng: R,
+        transcript: &mut T,
+        offload: &mut Q,
+    ) -> Result<Constructed<C>, Error> {
+        // Divide by t(X) = X^{params.n} - 1.
+        let h_poly = domain.divide_by_vanishing_poly(h_poly);
+
+        let h_poly = offload.extended_to_coeff(domain, h_poly).await?;
+
+        self.commit_pieces(params, domain, h_poly, rng, transcript)
+    }
+
+    /// Splits h(X), in coefficient form, into pieces and commits to them.
+    fn commit_pieces<
+        'params,
+        P: ParamsProver<'params, C>,
+        E: EncodedChallenge<C>,
+        R: RngCore,
+        T: TranscriptWrite<C, E>,
+    >(
+        self,
+        params: &P,
+        domain: &EvaluationDomain<C::Scalar>,
+        h_poly: Vec<C::Scalar>,
+        mut rng: R,
+        transcript: &mut T,
+    ) -> Result<Constructed<C>, Error> {
         // Split h(X) up into pieces
         let h_pieces = h_poly
             .chunks_exact(params.n() as usize)
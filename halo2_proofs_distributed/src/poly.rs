@@ -144,6 +144,14 @@ impl<F, B> Polynomial<F, B> {
     pub fn num_coeffs(&self) -> usize {
         self.values.len()
     }
+
+    /// Wraps `values` without checking their length against a domain.
+    pub(crate) fn from_values(values: Vec<F>) -> Self {
+        Polynomial {
+            values,
+            _marker: PhantomData,
+        }
+    }
 }
 
 impl<F: SerdePrimeField, B> Polynomial<F, B> {
@@ -124,6 +124,35 @@ pub trait MSM<C: CurveAffine>: Clone + Debug + Send + Sync {
     fn scalars(&self) -> Vec<C::Scalar>;
 }
 
+/// The SRS points a commitment is computed against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SrsBasis {
+    /// The powers of the secret, used to commit to polynomials in coefficient form.
+    Coeff,
+    /// The Lagrange basis, used to commit to polynomials in evaluation form.
+    Lagrange,
+}
+
+/// Computes multi-scalar multiplications on behalf of [`Params`], for
+/// instance across several machines.
+pub trait MultiexpEngine<C: CurveAffine>: Debug + Send + Sync {
+    /// Returns the sum of `coeffs[i] * bases[i]`.
+    fn multiexp(&self, coeffs: &[C::Scalar], bases: &[C]) -> C::CurveExt;
+
+    /// Returns the sum of `coeffs[i] * bases[i]`, where `bases` is the start of
+    /// the SRS of size `2^k` in the given `basis`. Engines that keep the SRS
+    /// themselves can use it rather than `bases`.
+    fn multiexp_srs(
+        &self,
+        coeffs: &[C::Scalar],
+        _k: u32,
+        _basis: SrsBasis,
+        bases: &[C],
+    ) -> C::CurveExt {
+        self.multiexp(coeffs, bases)
+    }
+}
+
 /// Common multi-open prover interface for various commitment schemes
 pub trait Prover<'params, Scheme: CommitmentScheme> {
     /// Query instance or not
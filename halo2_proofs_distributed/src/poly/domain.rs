@@ -168,6 +168,18 @@ impl<F: WithSmallOrderMulGroup<3>> EvaluationDomain<F> {
         }
     }
 
+    /// Obtains a polynomial in the extended Lagrange basis when given a vector
+    /// of evaluations of size `extended_len()`; panics if the provided vector
+    /// is the wrong length.
+    pub fn extended_from_vec(&self, values: Vec<F>) -> Polynomial<F, ExtendedLagrangeCoeff> {
+        assert_eq!(values.len(), self.extended_len());
+
+        Polynomial {
+            values,
+            _marker: PhantomData,
+        }
+    }
+
     /// Returns an empty (zero) polynomial in the coefficient basis
     pub fn empty_coeff(&self) -> Polynomial<F, Coeff> {
         Polynomial {
@@ -239,20 +251,28 @@ impl<F: WithSmallOrderMulGroup<3>> EvaluationDomain<F> {
     /// evaluation domain, rotating by `rotation` if desired.
     pub fn coeff_to_extended(
         &self,
-        mut a: Polynomial<F, Coeff>,
+        a: Polynomial<F, Coeff>,
     ) -> Polynomial<F, ExtendedLagrangeCoeff> {
-        assert_eq!(a.values.len(), 1 << self.k);
-
-        self.distribute_powers_zeta(&mut a.values, true);
-        a.values.resize(self.extended_len(), F::ZERO);
-        best_fft(&mut a.values, self.extended_omega, self.extended_k);
+        let mut values = self.coeff_to_extended_input(a);
+        best_fft(&mut values, self.extended_omega, self.extended_k);
 
         Polynomial {
-            values: a.values,
+            values,
             _marker: PhantomData,
         }
     }
 
+    /// Moves `a` onto the coset and pads it to the size of the extended
+    /// domain. What is left of [`EvaluationDomain::coeff_to_extended`] is an
+    /// FFT with [`EvaluationDomain::get_extended_omega`].
+    pub(crate) fn coeff_to_extended_input(&self, mut a: Polynomial<F, Coeff>) -> Vec<F> {
+        assert_eq!(a.values.len(), 1 << self.k);
+
+        self.distribute_powers_zeta(&mut a.values, true);
+        a.values.resize(self.extended_len(), F::ZERO);
+        a.values
+    }
+
     /// Rotate the extended domain polynomial over the original domain.
     pub fn rotate_extended(
         &self,
@@ -282,24 +302,37 @@ impl<F: WithSmallOrderMulGroup<3>> EvaluationDomain<F> {
         assert_eq!(a.values.len(), self.extended_len());
 
         // Inverse FFT
-        Self::ifft(
-            &mut a.values,
-            self.extended_omega_inv,
-            self.extended_k,
-            self.extended_ifft_divisor,
-        );
+        best_fft(&mut a.values, self.extended_omega_inv, self.extended_k);
+
+        self.extended_to_coeff_output(a)
+    }
+
+    /// Finishes [`EvaluationDomain::extended_to_coeff`] on `a`, once an FFT
+    /// with [`EvaluationDomain::get_extended_omega_inv`] was run over it.
+    pub(crate) fn extended_to_coeff_output(
+        &self,
+        a: Polynomial<F, ExtendedLagrangeCoeff>,
+    ) -> Vec<F> {
+        let mut a = a.values;
+        assert_eq!(a.len(), self.extended_len());
+
+        // Finish iFFT
+        parallelize(&mut a, |a, _| {
+            for a in a {
+                *a *= &self.extended_ifft_divisor;
+            }
+        });
 
         // Distribute powers to move from coset; opposite from the
         // transformation we performed earlier.
-        self.distribute_powers_zeta(&mut a.values, false);
+        self.distribute_powers_zeta(&mut a, false);
 
         // Truncate it to match the size of the quotient polynomial; the
         // evaluation domain might be slightly larger than necessary because
         // it always lies on a power-of-two boundary.
-        a.values
-            .truncate((&self.n * self.quotient_poly_degree) as usize);
+        a.truncate((&self.n * self.quotient_poly_degree) as usize);
 
-        a.values
+        a
     }
 
     /// This divides the polynomial (in the extended domain) by the vanishing
@@ -391,6 +424,12 @@ impl<F: WithSmallOrderMulGroup<3>> EvaluationDomain<F> {
         self.extended_omega
     }
 
+    /// Get the inverse of the generator of the extended domain's
+    /// multiplicative subgroup.
+    pub fn get_extended_omega_inv(&self) -> F {
+        self.extended_omega_inv
+    }
+
     /// Multiplies a value by some power of $\omega$, essentially rotating over
     /// the domain.
     pub fn rotate_omega(&self, value: F, rotation: Rotation) -> F {
@@ -2,7 +2,9 @@ use crate::arithmetic::{
     best_fft, best_multiexp, g_to_lagrange, parallelize, CurveAffine, CurveExt,
 };
 use crate::helpers::SerdeCurveAffine;
-use crate::poly::commitment::{Blind, CommitmentScheme, Params, ParamsProver, ParamsVerifier, MSM};
+use crate::poly::commitment::{
+    Blind, CommitmentScheme, MultiexpEngine, Params, ParamsProver, ParamsVerifier, SrsBasis, MSM,
+};
 use crate::poly::{Coeff, LagrangeCoeff, Polynomial};
 use crate::SerdeFormat;
 
@@ -13,6 +15,7 @@ use rand_core::{OsRng, RngCore};
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::ops::{Add, AddAssign, Mul, MulAssign};
+use std::sync::Arc;
 
 use std::io;
 
@@ -27,6 +30,7 @@ pub struct ParamsKZG<E: Engine> {
     pub(crate) g_lagrange: Vec<E::G1Affine>,
     pub(crate) g2: E::G2Affine,
     pub(crate) s_g2: E::G2Affine,
+    pub(crate) multiexp: Option<Arc<dyn MultiexpEngine<E::G1Affine>>>,
 }
 
 /// Umbrella commitment scheme construction for all KZG variants
@@ -129,6 +133,7 @@ where
             g_lagrange,
             g2,
             s_g2,
+            multiexp: None,
         }
     }
 
@@ -153,6 +158,30 @@ where
             g,
             g2,
             s_g2,
+            multiexp: None,
+        }
+    }
+
+    /// Computes the multi-scalar multiplications of commitments with `engine`
+    /// instead of on this machine.
+    pub fn with_multiexp(mut self, engine: Arc<dyn MultiexpEngine<E::G1Affine>>) -> Self {
+        self.multiexp = Some(engine);
+        self
+    }
+
+    /// Returns the sum of `coeffs[i] * srs[i]` over the SRS in `basis`,
+    /// computed by the engine set with [`ParamsKZG::with_multiexp`] if there
+    /// is one.
+    pub(crate) fn multiexp(&self, coeffs: &[E::Scalar], basis: SrsBasis) -> E::G1 {
+        let bases = match basis {
+            SrsBasis::Coeff => &self.g,
+            SrsBasis::Lagrange => &self.g_lagrange,
+        };
+        assert!(bases.len() >= coeffs.len());
+        let bases = &bases[0..coeffs.len()];
+        match &self.multiexp {
+            Some(engine) => engine.multiexp_srs(coeffs, self.k, basis, bases),
+            None => best_multiexp(coeffs, bases),
         }
     }
 
@@ -268,6 +297,7 @@ where
             g_lagrange,
             g2,
             s_g2,
+            multiexp: None,
         })
     }
 }
@@ -304,7 +334,9 @@ where
     }
 
     fn empty_msm(&'params self) -> MSMKZG<E> {
-        MSMKZG::new()
+        let mut msm = MSMKZG::new();
+        msm.multiexp = self.multiexp.clone();
+        msm
     }
 
     fn commit_lagrange(
@@ -314,10 +346,7 @@ where
     ) -> E::G1 {
         let mut scalars = Vec::with_capacity(poly.len());
         scalars.extend(poly.iter());
-        let bases = &self.g_lagrange;
-        let size = scalars.len();
-        assert!(bases.len() >= size);
-        best_multiexp(&scalars, &bases[0..size])
+        self.multiexp(&scalars, SrsBasis::Lagrange)
     }
 
     /// Writes params to a buffer.
@@ -358,10 +387,7 @@ where
     fn commit(&self, poly: &Polynomial<E::Scalar, Coeff>, _: Blind<E::Scalar>) -> E::G1 {
         let mut scalars = Vec::with_capacity(poly.len());
         scalars.extend(poly.iter());
-        let bases = &self.g;
-        let size = scalars.len();
-        assert!(bases.len() >= size);
-        best_multiexp(&scalars, &bases[0..size])
+        self.multiexp(&scalars, SrsBasis::Coeff)
     }
 
     fn get_g(&self) -> &[E::G1Affine] {
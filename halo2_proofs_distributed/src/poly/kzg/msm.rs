@@ -1,9 +1,9 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 
 use super::commitment::{KZGCommitmentScheme, ParamsKZG};
 use crate::{
     arithmetic::{best_multiexp, parallelize, CurveAffine},
-    poly::commitment::MSM,
+    poly::commitment::{MultiexpEngine, MSM},
 };
 use group::{Curve, Group};
 use halo2curves::pairing::{Engine, MillerLoopResult, MultiMillerLoop};
@@ -13,6 +13,7 @@ use halo2curves::pairing::{Engine, MillerLoopResult, MultiMillerLoop};
 pub struct MSMKZG<E: Engine> {
     pub(crate) scalars: Vec<E::Scalar>,
     pub(crate) bases: Vec<E::G1>,
+    pub(crate) multiexp: Option<Arc<dyn MultiexpEngine<E::G1Affine>>>,
 }
 
 impl<E: Engine> MSMKZG<E> {
@@ -21,6 +22,7 @@ impl<E: Engine> MSMKZG<E> {
         MSMKZG {
             scalars: vec![],
             bases: vec![],
+            multiexp: None,
         }
     }
 
@@ -66,7 +68,10 @@ impl<E: Engine + Debug> MSM<E::G1Affine> for MSMKZG<E> {
         use group::prime::PrimeCurveAffine;
         let mut bases = vec![E::G1Affine::identity(); self.scalars.len()];
         E::G1::batch_normalize(&self.bases, &mut bases);
-        best_multiexp(&self.scalars, &bases)
+        match &self.multiexp {
+            Some(engine) => engine.multiexp(&self.scalars, &bases),
+            None => best_multiexp(&self.scalars, &bases),
+        }
     }
 
     fn bases(&self) -> Vec<E::G1> {
@@ -103,6 +108,7 @@ impl<E: Engine + Debug> PreMSM<E> {
         MSMKZG {
             scalars: scalars.into_iter().flatten().collect(),
             bases: bases.into_iter().flatten().collect(),
+            multiexp: None,
         }
     }
 
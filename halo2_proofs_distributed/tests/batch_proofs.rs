@@ -0,0 +1,180 @@
+//! Independent proofs sharing a proving key, each run whole on a worker.
+use halo2_proofs_distributed::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    distributed_util::{
+        batch::{BatchCircuit, Shplonk},
+        dispatcher::WorkerMethod,
+        net::{read_u64, write_u64},
+    },
+    plonk::{
+        keygen_pk, keygen_vk, verify_proof, Advice, Circuit, Column, ConstraintSystem, Error,
+        Instance, Selector, VerifyingKey,
+    },
+    poly::{
+        commitment::ParamsProver,
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::VerifierSHPLONK,
+            strategy::SingleStrategy,
+        },
+        Rotation, VerificationStrategy,
+    },
+    transcript::{Blake2bRead, Challenge255, TranscriptReadBuffer},
+};
+use halo2curves::bn256::{Bn256, Fr, G1Affine};
+use rand_core::OsRng;
+use std::{
+    collections::BTreeSet,
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use worker::harness::{LocalWorkers, Notices};
+
+const K: u32 = 4;
+
+/// Proves knowledge of a square root of its instance.
+#[derive(Clone, Default)]
+struct Square {
+    root: Option<u64>,
+}
+
+impl Circuit<Fr> for Square {
+    type Config = (Column<Advice>, Column<Advice>, Column<Instance>, Selector);
+    type FloorPlanner = SimpleFloorPlanner;
+    #[cfg(feature = "circuit-params")]
+    type Params = ();
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let root = meta.advice_column();
+        let square = meta.advice_column();
+        let instance = meta.instance_column();
+        meta.enable_equality(square);
+        meta.enable_equality(instance);
+        let s = meta.selector();
+        meta.create_gate("square", |meta| {
+            let s = meta.query_selector(s);
+            let root = meta.query_advice(root, Rotation::cur());
+            let square = meta.query_advice(square, Rotation::cur());
+            vec![s * (root.clone() * root - square)]
+        });
+        (root, square, instance, s)
+    }
+
+    fn synthesize(
+        &self,
+        (root, square, instance, s): Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        let value = self
+            .root
+            .map_or(Value::unknown(), |root| Value::known(Fr::from(root)));
+        let cell = layouter.assign_region(
+            || "square",
+            |mut region| {
+                s.enable(&mut region, 0)?;
+                region.assign_advice(|| "root", root, 0, || value)?;
+                region.assign_advice(|| "square", square, 0, || value * value)
+            },
+        )?;
+        layouter.constrain_instance(cell.cell(), instance, 0)
+    }
+}
+
+impl BatchCircuit<Fr> for Square {
+    const NAME: &'static str = "batch_proofs/square";
+
+    fn write_witness<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u64(writer, self.root.unwrap_or_default())
+    }
+
+    fn read_witness<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Square {
+            root: Some(read_u64(reader)?),
+        })
+    }
+}
+
+fn verify(params: &ParamsKZG<Bn256>, vk: &VerifyingKey<G1Affine>, proof: &[u8], square: u64) {
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+    let strategy = verify_proof::<_, VerifierSHPLONK<_>, _, _, _>(
+        params.verifier_params(),
+        vk,
+        SingleStrategy::new(params),
+        &[&[&[Fr::from(square)][..]]],
+        &mut transcript,
+    )
+    .unwrap();
+    assert!(strategy.finalize());
+}
+
+#[test]
+fn batches_are_proven_on_the_pool() {
+    let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+    let vk = keygen_vk(&params, &Square::default()).unwrap();
+    let pk = keygen_pk(&params, vk, &Square::default()).unwrap();
+
+    let roots = (1..=10).collect::<Vec<u64>>();
+    let circuits = roots
+        .iter()
+        .map(|&root| Square { root: Some(root) })
+        .collect::<Vec<_>>();
+    let instances = roots
+        .iter()
+        .map(|&root| vec![vec![Fr::from(root * root)]])
+        .collect::<Vec<_>>();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        // The first worker does not have the circuit registered, the others
+        // take over its share.
+        let started = AtomicUsize::new(0);
+        let mut workers =
+            LocalWorkers::spawn_with(3, |worker| match started.fetch_add(1, Ordering::SeqCst) {
+                0 => worker,
+                _ => worker.with_circuit::<KZGCommitmentScheme<Bn256>, Shplonk, Square>(),
+            })
+            .await
+            .unwrap();
+        let notices = Notices::default();
+        let dispatcher = workers
+            .dispatcher()
+            .await
+            .unwrap()
+            .with_notices(notices.recorder());
+        for info in dispatcher.ping().await.unwrap() {
+            assert!(info.capabilities.contains(&WorkerMethod::Prove));
+        }
+
+        let proofs = dispatcher
+            .prove_batch::<KZGCommitmentScheme<Bn256>, Shplonk, _>(
+                &params, &pk, &circuits, &instances,
+            )
+            .await
+            .unwrap();
+        assert_eq!(proofs.len(), roots.len());
+        for (proof, root) in proofs.iter().zip(&roots) {
+            verify(&params, pk.get_vk(), proof, root * root);
+        }
+        // Only the share of the first worker was taken over, none of the
+        // proofs was created in this process.
+        assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::from([0]));
+
+        // Without any worker able to prove them, the circuits are proven in
+        // this process.
+        workers.kill(1);
+        workers.kill(2);
+        let proofs = dispatcher
+            .prove_batch::<KZGCommitmentScheme<Bn256>, Shplonk, _>(
+                &params, &pk, &circuits, &instances,
+            )
+            .await
+            .unwrap();
+        for (proof, root) in proofs.iter().zip(&roots) {
+            verify(&params, pk.get_vk(), proof, root * root);
+        }
+    });
+}
@@ -0,0 +1,202 @@
+//! Proofs whose steps run on real workers, checked against the same proofs
+//! created in this process.
+use ff::{FromUniformBytes, PrimeField, WithSmallOrderMulGroup};
+use halo2_proofs_distributed::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    distributed_util::{backend::RemoteBackend, net::SerdeParams},
+    plonk::{
+        create_proof, create_proof_distributed, keygen_pk, keygen_vk, keygen_vk_with, Advice,
+        Circuit, Column, ConstraintSystem, Error, Fixed, Selector,
+    },
+    poly::{
+        commitment::{CommitmentScheme, ParamsProver, Prover},
+        ipa::{
+            commitment::{IPACommitmentScheme, ParamsIPA},
+            multiopen::ProverIPA,
+        },
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::ProverSHPLONK,
+        },
+        Rotation,
+    },
+    transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+    SerdeCurveAffine, SerdeFormat, SerdePrimeField,
+};
+use halo2curves::{bn256::Bn256, pasta::EqAffine};
+use rand_chacha::ChaCha20Rng;
+use rand_core::{OsRng, SeedableRng};
+use std::collections::BTreeSet;
+use worker::harness::{LocalWorkers, Notices};
+
+const K: u32 = 4;
+
+#[derive(Clone)]
+struct AddConfig {
+    advice: [Column<Advice>; 3],
+    table: Column<Fixed>,
+    s: Selector,
+}
+
+/// Adds `b` to `a` twice, with copies between the rows and a lookup of the
+/// sums in a small table.
+#[derive(Clone, Default)]
+struct AddCircuit<F> {
+    a: Value<F>,
+    b: Value<F>,
+}
+
+impl<F: PrimeField> Circuit<F> for AddCircuit<F> {
+    type Config = AddConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+    #[cfg(feature = "circuit-params")]
+    type Params = ();
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> AddConfig {
+        let advice = [
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+        ];
+        for column in advice {
+            meta.enable_equality(column);
+        }
+        let s = meta.complex_selector();
+        meta.create_gate("add", |meta| {
+            let s = meta.query_selector(s);
+            let [a, b, c] = advice.map(|column| meta.query_advice(column, Rotation::cur()));
+            vec![s * (a + b - c)]
+        });
+        let table = meta.fixed_column();
+        meta.lookup_any("small sums", |meta| {
+            let s = meta.query_selector(s);
+            let c = meta.query_advice(advice[2], Rotation::cur());
+            let table = meta.query_fixed(table, Rotation::cur());
+            vec![(s * c, table)]
+        });
+        AddConfig { advice, table, s }
+    }
+
+    fn synthesize(&self, config: AddConfig, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+        let [a, b, c] = config.advice;
+        layouter.assign_region(
+            || "add twice",
+            |mut region| {
+                config.s.enable(&mut region, 0)?;
+                let a0 = region.assign_advice(|| "a", a, 0, || self.a)?;
+                let b0 = region.assign_advice(|| "b", b, 0, || self.b)?;
+                let c0 = region.assign_advice(|| "c", c, 0, || self.a + self.b)?;
+
+                config.s.enable(&mut region, 1)?;
+                c0.copy_advice(|| "a", &mut region, a, 1)?;
+                b0.copy_advice(|| "b", &mut region, b, 1)?;
+                region.assign_advice(|| "c", c, 1, || self.a + self.b + self.b)?;
+                a0.copy_advice(|| "a", &mut region, a, 2)?;
+                Ok(())
+            },
+        )?;
+        layouter.assign_region(
+            || "small sums",
+            |mut region| {
+                for row in 0..10 {
+                    region.assign_fixed(
+                        || "table",
+                        config.table,
+                        row,
+                        || Value::known(F::from(row as u64)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Creates the same proof in this process and on three local workers, and
+/// checks both transcripts are the same, and that none of the steps sent to
+/// the workers fell back to this process.
+fn assert_distributed_proof_matches_local<'params, S, P>(params: &'params S::ParamsProver)
+where
+    S: CommitmentScheme,
+    P: Prover<'params, S>,
+    S::Curve: SerdeCurveAffine,
+    S::Scalar: WithSmallOrderMulGroup<3> + FromUniformBytes<64> + SerdePrimeField,
+    S::ParamsProver: SerdeParams,
+{
+    let circuit = AddCircuit {
+        a: Value::known(S::Scalar::from(2)),
+        b: Value::known(S::Scalar::from(3)),
+    };
+
+    let local_vk = keygen_vk::<S::Curve, _, _>(params, &circuit).unwrap();
+    let pk = keygen_pk(params, local_vk.clone(), &circuit).unwrap();
+    let mut transcript = Blake2bWrite::<_, S::Curve, Challenge255<_>>::init(vec![]);
+    create_proof::<S, P, _, _, _, _>(
+        params,
+        &pk,
+        &[circuit.clone()],
+        &[&[]],
+        ChaCha20Rng::seed_from_u64(0xdead),
+        &mut transcript,
+    )
+    .unwrap();
+    let local = transcript.finalize();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let workers = LocalWorkers::spawn(3).await.unwrap();
+        let notices = Notices::default();
+        let dispatcher = workers
+            .dispatcher()
+            .await
+            .unwrap()
+            .with_notices(notices.recorder());
+
+        let vk = keygen_vk_with::<S::Curve, _, _, _>(
+            params,
+            &circuit,
+            &mut RemoteBackend::new(&dispatcher),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            vk.to_bytes(SerdeFormat::RawBytes),
+            local_vk.to_bytes(SerdeFormat::RawBytes)
+        );
+
+        let mut transcript = Blake2bWrite::<_, S::Curve, Challenge255<_>>::init(vec![]);
+        create_proof_distributed::<S, P, _, _, _, _>(
+            params,
+            &pk,
+            &[circuit],
+            &[&[]],
+            ChaCha20Rng::seed_from_u64(0xdead),
+            &mut transcript,
+            &dispatcher,
+        )
+        .await
+        .unwrap();
+        assert_eq!(transcript.finalize(), local);
+        assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::new());
+    });
+}
+
+#[test]
+fn kzg_proof_matches_local() {
+    let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+    assert_distributed_proof_matches_local::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>>(
+        &params,
+    );
+}
+
+#[test]
+fn ipa_proof_matches_local() {
+    let params = ParamsIPA::<EqAffine>::new(K);
+    assert_distributed_proof_matches_local::<IPACommitmentScheme<EqAffine>, ProverIPA<'_, EqAffine>>(
+        &params,
+    );
+}
@@ -0,0 +1,425 @@
+//! Steps of the prover run on real workers, checked against the same steps
+//! run in this process.
+use ff::{Field, PrimeField};
+use group::Curve;
+use halo2_proofs_distributed::{
+    distributed_util::{
+        dispatcher::Dispatcher,
+        multiexp::DistributedMultiexp,
+        plonk::{
+            lookup::{PermuteInput, ProductInput},
+            permutation::prover::PermutationChunk,
+        },
+    },
+    plonk::{
+        evaluation::{CircuitPolys, Evaluator, HChallenges, HLayout, HPolys},
+        ConstraintSystem,
+    },
+    poly::{
+        commitment::{Blind, Params, ParamsProver, MSM},
+        kzg::commitment::ParamsKZG,
+        Coeff, EvaluationDomain, ExtendedLagrangeCoeff, LagrangeCoeff, Polynomial, Rotation,
+    },
+};
+use halo2curves::bn256::{Bn256, Fr, G1Affine};
+use rand_core::OsRng;
+use std::{borrow::Cow, collections::BTreeSet, sync::Arc};
+use worker::harness::{LocalWorkers, Notices};
+
+const K: u32 = 4;
+const BLINDING_FACTORS: usize = 3;
+
+fn random_values(len: usize) -> Vec<Fr> {
+    (0..len).map(|_| Fr::random(OsRng)).collect()
+}
+
+fn random_lagrange(domain: &EvaluationDomain<Fr>) -> Polynomial<Fr, LagrangeCoeff> {
+    domain.lagrange_from_vec(random_values(domain.empty_lagrange().len()))
+}
+
+fn random_coeff(domain: &EvaluationDomain<Fr>) -> Polynomial<Fr, Coeff> {
+    domain.coeff_from_vec(random_values(domain.empty_coeff().len()))
+}
+
+fn random_extended(domain: &EvaluationDomain<Fr>) -> Polynomial<Fr, ExtendedLagrangeCoeff> {
+    let mut poly = domain.empty_extended();
+    for value in poly.iter_mut() {
+        *value = Fr::random(OsRng);
+    }
+    poly
+}
+
+/// Starts three workers and a dispatcher recording its notices.
+async fn pool() -> (LocalWorkers, Dispatcher, Notices) {
+    let workers = LocalWorkers::spawn(3).await.unwrap();
+    let notices = Notices::default();
+    let dispatcher = workers
+        .dispatcher()
+        .await
+        .unwrap()
+        .with_notices(notices.recorder());
+    (workers, dispatcher, notices)
+}
+
+/// Checks the notices recorded so far are about the `i`-th worker alone, if
+/// about any.
+fn assert_only_about(notices: &Notices, workers: &LocalWorkers, i: usize) {
+    let named = notices.take_workers(workers.addrs());
+    assert!(named.iter().all(|&j| j == i), "notices about {:?}", named);
+}
+
+#[test]
+fn extended_domain_conversions_run_on_the_pool() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let (mut workers, dispatcher, notices) = pool().await;
+
+        for dead in [None, Some(2)] {
+            if let Some(i) = dead {
+                workers.kill(i);
+            }
+            for (j, k) in [(2, 1), (3, 3), (5, 4), (8, 5), (9, 6)] {
+                let domain = EvaluationDomain::<Fr>::new(j, k);
+                let poly = random_coeff(&domain);
+
+                let expected = domain.coeff_to_extended(poly.clone());
+                let extended = dispatcher.coeff_to_extended(&domain, poly).await.unwrap();
+                assert_eq!(extended[..], expected[..], "j = {}, k = {}", j, k);
+
+                let expected = domain.extended_to_coeff(extended.clone());
+                let coeffs = dispatcher
+                    .extended_to_coeff(&domain, extended)
+                    .await
+                    .unwrap();
+                assert_eq!(coeffs, expected, "j = {}, k = {}", j, k);
+            }
+            match dead {
+                None => assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::new()),
+                Some(i) => assert_only_about(&notices, &workers, i),
+            }
+        }
+    });
+}
+
+#[test]
+fn multiexps_run_on_the_pool() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let workers = runtime.block_on(LocalWorkers::spawn(3)).unwrap();
+    let notices = Notices::default();
+
+    // The engine runs its own runtime, so it is used from outside the one of
+    // the workers.
+    let engine = DistributedMultiexp::new(workers.config())
+        .unwrap()
+        .with_min_len(0)
+        .with_notices(notices.recorder());
+    let params = ParamsKZG::<Bn256>::setup(K + 2, OsRng);
+    let distributed = params.clone().with_multiexp(Arc::new(engine));
+
+    let domain = EvaluationDomain::<Fr>::new(1, K + 2);
+    let poly = random_lagrange(&domain);
+    let blind = Blind::default();
+    assert_eq!(
+        distributed.commit_lagrange(&poly, blind).to_affine(),
+        params.commit_lagrange(&poly, blind).to_affine()
+    );
+
+    let poly = domain.lagrange_to_coeff(poly);
+    assert_eq!(
+        distributed.commit(&poly, blind).to_affine(),
+        params.commit(&poly, blind).to_affine()
+    );
+
+    let mut local_msm = params.empty_msm();
+    let mut distributed_msm = distributed.empty_msm();
+    for base in params.g.iter().take(5) {
+        let scalar = Fr::random(OsRng);
+        local_msm.append_term(scalar, base.to_curve());
+        distributed_msm.append_term(scalar, base.to_curve());
+    }
+    assert_eq!(
+        distributed_msm.eval().to_affine(),
+        local_msm.eval().to_affine()
+    );
+    assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::new());
+}
+
+#[test]
+fn sharded_params_commitments_run_on_the_pool() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut workers = runtime.block_on(LocalWorkers::spawn(3)).unwrap();
+    let params = ParamsKZG::<Bn256>::setup(K + 2, OsRng);
+
+    for dead in [None, Some(2)] {
+        let notices = Notices::default();
+        let engine = DistributedMultiexp::new(workers.config())
+            .unwrap()
+            .with_min_len(0)
+            .with_notices(notices.recorder());
+        if let Some(i) = dead {
+            workers.kill(i);
+        }
+        let engine = engine.with_sharded_params(&params);
+        // A worker that died keeps its rows off the pool, and says so.
+        let named = notices.take_workers(workers.addrs());
+        assert_eq!(named, dead.into_iter().collect::<BTreeSet<_>>());
+        let distributed = params.clone().with_multiexp(Arc::new(engine));
+
+        let domain = EvaluationDomain::<Fr>::new(1, K + 2);
+        let poly = random_lagrange(&domain);
+        let blind = Blind::default();
+        assert_eq!(
+            distributed.commit_lagrange(&poly, blind).to_affine(),
+            params.commit_lagrange(&poly, blind).to_affine()
+        );
+
+        let poly = domain.lagrange_to_coeff(poly);
+        assert_eq!(
+            distributed.commit(&poly, blind).to_affine(),
+            params.commit(&poly, blind).to_affine()
+        );
+
+        // Commitments to shorter polynomials only involve the first rows.
+        let short = random_coeff(&EvaluationDomain::<Fr>::new(1, K));
+        assert_eq!(
+            distributed.commit(&short, blind).to_affine(),
+            params.commit(&short, blind).to_affine()
+        );
+        assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::new());
+    }
+}
+
+#[test]
+fn permutation_products_run_on_the_pool() {
+    let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+    let domain = EvaluationDomain::<Fr>::new(1, K);
+    let (circuits, sets) = (2, 3);
+    let chunks = (0..circuits * sets)
+        .map(|i| PermutationChunk {
+            columns: vec![
+                Cow::Owned(random_lagrange(&domain)),
+                Cow::Owned(random_lagrange(&domain)),
+            ],
+            permutations: vec![
+                Cow::Owned(random_lagrange(&domain)),
+                Cow::Owned(random_lagrange(&domain)),
+            ],
+            delta: Fr::DELTA.pow_vartime([(2 * (i % sets)) as u64]),
+            blinding: random_values(BLINDING_FACTORS),
+            blind: Blind(Fr::random(OsRng)),
+        })
+        .collect::<Vec<_>>();
+    let (beta, gamma, omega) = (Fr::random(OsRng), Fr::random(OsRng), domain.get_omega());
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let (mut workers, dispatcher, notices) = pool().await;
+        let params_hash = dispatcher.upload_params(&params).await;
+        let products = || {
+            dispatcher.permutation_products::<G1Affine, _>(
+                &params,
+                params_hash,
+                &chunks,
+                sets,
+                beta,
+                gamma,
+                omega,
+            )
+        };
+
+        let distributed = products().await.unwrap();
+        assert_eq!(distributed.len(), chunks.len());
+        assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::new());
+
+        // The workers that are left take over the sets of a dead one.
+        workers.kill(2);
+        let taken_over = products().await.unwrap();
+        assert_only_about(&notices, &workers, 2);
+
+        // Without any worker, the products are computed in this process.
+        workers.kill(0);
+        workers.kill(1);
+        let local = products().await.unwrap();
+        for products in [&distributed, &taken_over] {
+            for (product, local) in products.iter().zip(&local) {
+                assert_eq!(product.z[..], local.z[..]);
+                assert_eq!(product.commitment, local.commitment);
+            }
+        }
+    });
+}
+
+#[test]
+fn lookups_run_on_the_pool() {
+    let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+    let domain = EvaluationDomain::<Fr>::new(1, K);
+    let n = 1 << K;
+    // The input of the fourth lookup is not in its table.
+    let lookups = (0..5)
+        .map(|i| {
+            let table = random_values(n);
+            let mut input = (0..n).map(|row| table[(row * 7) % 5]).collect::<Vec<_>>();
+            if i == 3 {
+                input[0] = Fr::random(OsRng);
+            }
+            PermuteInput {
+                compressed_input: domain.lagrange_from_vec(input),
+                compressed_table: domain.lagrange_from_vec(table),
+                input_blinding: random_values(BLINDING_FACTORS + 1),
+                table_blinding: random_values(BLINDING_FACTORS + 1),
+                input_blind: Blind(Fr::random(OsRng)),
+                table_blind: Blind(Fr::random(OsRng)),
+            }
+        })
+        .collect::<Vec<_>>();
+    let (beta, gamma) = (Fr::random(OsRng), Fr::random(OsRng));
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let (mut workers, dispatcher, notices) = pool().await;
+        let params_hash = dispatcher.upload_params(&params).await;
+        let permute = || dispatcher.permute_lookups::<G1Affine, _>(&params, params_hash, &lookups);
+
+        let permuted = permute().await.unwrap();
+        assert_eq!(permuted.len(), lookups.len());
+        assert_eq!(
+            permuted.iter().map(Option::is_some).collect::<Vec<_>>(),
+            vec![true, true, true, false, true]
+        );
+        let products = lookups
+            .iter()
+            .zip(&permuted)
+            .filter_map(|(lookup, permuted)| {
+                let permuted = permuted.as_ref()?;
+                Some(ProductInput {
+                    compressed_input: lookup.compressed_input.clone(),
+                    compressed_table: lookup.compressed_table.clone(),
+                    permuted_input: permuted.permuted_input.clone(),
+                    permuted_table: permuted.permuted_table.clone(),
+                    blinding: random_values(BLINDING_FACTORS),
+                    blind: Blind(Fr::random(OsRng)),
+                })
+            })
+            .collect::<Vec<_>>();
+        let multiply = || {
+            dispatcher.lookup_products::<G1Affine, _>(&params, params_hash, &products, beta, gamma)
+        };
+        let multiplied = multiply().await.unwrap();
+        assert_eq!(multiplied.len(), products.len());
+        assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::new());
+
+        // Without any worker, the lookups run in this process.
+        for i in 0..3 {
+            workers.kill(i);
+        }
+        for (permuted, local) in permuted.iter().zip(permute().await.unwrap()) {
+            if let (Some(permuted), Some(local)) = (permuted, local) {
+                assert_eq!(permuted.permuted_input[..], local.permuted_input[..]);
+                assert_eq!(permuted.permuted_table[..], local.permuted_table[..]);
+                assert_eq!(permuted.input_commitment, local.input_commitment);
+                assert_eq!(permuted.table_commitment, local.table_commitment);
+            }
+        }
+        for (product, local) in multiplied.iter().zip(multiply().await.unwrap()) {
+            assert_eq!(product.product[..], local.product[..]);
+            assert_eq!(product.commitment, local.commitment);
+        }
+    });
+}
+
+#[test]
+fn h_evaluations_run_on_the_pool() {
+    // A domain of 32 rows extended to 64, and a circuit with a gate, a lookup
+    // and a shuffle reaching a few rows both ways.
+    let domain = EvaluationDomain::<Fr>::new(3, 5);
+    let mut meta = ConstraintSystem::<Fr>::default();
+    let (a, b) = (meta.advice_column(), meta.advice_column());
+    let f = meta.fixed_column();
+    let i = meta.instance_column();
+    meta.create_gate("gate", |meta| {
+        let a_next = meta.query_advice(a, Rotation::next());
+        let a_prev = meta.query_advice(a, Rotation::prev());
+        let b = meta.query_advice(b, Rotation::cur());
+        let f = meta.query_fixed(f, Rotation::cur());
+        let i = meta.query_instance(i, Rotation(-2));
+        vec![f * (a_next * b - a_prev) + i]
+    });
+    meta.lookup_any("lookup", |meta| {
+        let a = meta.query_advice(a, Rotation::cur());
+        let f = meta.query_fixed(f, Rotation::cur());
+        vec![(a, f)]
+    });
+    meta.shuffle("shuffle", |meta| {
+        let b = meta.query_advice(b, Rotation::cur());
+        let a = meta.query_advice(a, Rotation(3));
+        vec![(b, a)]
+    });
+    let evaluator = Evaluator::<G1Affine>::new(&meta);
+    let layout = HLayout {
+        size: domain.extended_len(),
+        rot_scale: 2,
+        extended_omega: domain.get_extended_omega(),
+        last_rotation: Rotation(-4),
+        chunk_len: 1,
+        permutation_columns: vec![a.into()],
+    };
+    let challenges = HChallenges {
+        challenges: vec![],
+        y: Fr::random(OsRng),
+        beta: Fr::random(OsRng),
+        gamma: Fr::random(OsRng),
+        theta: Fr::random(OsRng),
+    };
+
+    let fixed = vec![random_extended(&domain)];
+    let [l0, l_last, l_active_row] = [(); 3].map(|_| random_extended(&domain));
+    let permutations = vec![random_extended(&domain)];
+    let circuits = (0..2)
+        .map(|_| {
+            (
+                [(); 2].map(|_| random_coeff(&domain)),
+                [random_coeff(&domain)],
+                random_extended(&domain),
+                [(); 4].map(|_| random_coeff(&domain)),
+            )
+        })
+        .collect::<Vec<_>>();
+    let polys = HPolys {
+        fixed: &fixed,
+        l0: &l0,
+        l_last: &l_last,
+        l_active_row: &l_active_row,
+        permutations: &permutations,
+        circuits: circuits
+            .iter()
+            .map(|(advice, instance, product, polys)| CircuitPolys {
+                advice,
+                instance,
+                permutation_products: vec![product],
+                lookups: vec![[&polys[0], &polys[1], &polys[2]]],
+                shuffles: vec![&polys[3]],
+            })
+            .collect(),
+    };
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let (mut workers, dispatcher, notices) = pool().await;
+        let evaluate = || dispatcher.evaluate_h(&evaluator, &layout, &domain, &polys, &challenges);
+
+        let distributed = evaluate().await.unwrap();
+        assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::new());
+
+        // The workers that are left take over the rows of a dead one.
+        workers.kill(2);
+        let taken_over = evaluate().await.unwrap();
+        assert_only_about(&notices, &workers, 2);
+
+        // Without any worker, h(X) is evaluated in this process.
+        workers.kill(0);
+        workers.kill(1);
+        let local = evaluate().await.unwrap();
+        assert_eq!(distributed, local);
+        assert_eq!(taken_over, local);
+    });
+}
@@ -6,6 +6,7 @@ use ff::{FromUniformBytes, WithSmallOrderMulGroup};
 use halo2_proofs_distributed::arithmetic::Field;
 use halo2_proofs_distributed::circuit::{Cell, Layouter, SimpleFloorPlanner, Value};
 use halo2_proofs_distributed::dev::MockProver;
+use halo2_proofs_distributed::distributed_util::net::SerdeParams;
 use halo2_proofs_distributed::plonk::{
     create_proof as create_plonk_proof, keygen_pk, keygen_vk, verify_proof as verify_plonk_proof,
     Advice, Assigned, Circuit, Column, ConstraintSystem, Error, Fixed, ProvingKey, TableColumn,
@@ -20,6 +21,7 @@ use halo2_proofs_distributed::transcript::{
     Blake2bRead, Blake2bWrite, Challenge255, EncodedChallenge, TranscriptReadBuffer,
     TranscriptWriterBuffer,
 };
+use halo2_proofs_distributed::{SerdeCurveAffine, SerdePrimeField};
 use rand_core::{OsRng, RngCore};
 use std::marker::PhantomData;
 
@@ -1022,7 +1024,100 @@ fn plonk_api() {
         }
     }
 
+    fn distributed_keygen<Scheme: CommitmentScheme>()
+    where
+        Scheme::Curve: SerdeCurveAffine,
+        Scheme::Scalar: SerdePrimeField + FromUniformBytes<64> + WithSmallOrderMulGroup<3>,
+        Scheme::ParamsProver: SerdeParams,
+    {
+        use halo2_proofs_distributed::distributed_util::backend::RemoteBackend;
+        use halo2_proofs_distributed::plonk::keygen_vk_with;
+        use halo2_proofs_distributed::SerdeFormat;
+        use std::collections::BTreeSet;
+        use worker::harness::{LocalWorkers, Notices};
+
+        let (_, _, lookup_table) = common!(Scheme);
+        let empty_circuit: MyCircuit<<Scheme as CommitmentScheme>::Scalar> = MyCircuit {
+            a: Value::unknown(),
+            lookup_table,
+        };
+
+        let params = Scheme::new_params(K);
+        let local = keygen_vk(&params, &empty_circuit).expect("keygen_vk should not fail");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut workers = LocalWorkers::spawn_with_key(3, "plonk_api").await.unwrap();
+            let notices = Notices::default();
+            let dispatcher = workers
+                .dispatcher()
+                .await
+                .unwrap()
+                .with_notices(notices.recorder());
+
+            let distributed = keygen_vk_with(
+                &params,
+                &empty_circuit,
+                &mut RemoteBackend::new(&dispatcher),
+            )
+            .await
+            .expect("keygen_vk_with should not fail");
+            assert_eq!(
+                distributed.to_bytes(SerdeFormat::RawBytes),
+                local.to_bytes(SerdeFormat::RawBytes)
+            );
+            // Every worker answered, so none of the keygen ran in this process.
+            assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::new());
+
+            // The workers that are left take over the columns of a dead one.
+            workers.kill(1);
+            let distributed = keygen_vk_with(
+                &params,
+                &empty_circuit,
+                &mut RemoteBackend::new(&dispatcher),
+            )
+            .await
+            .expect("keygen_vk_with should not fail");
+            assert_eq!(
+                distributed.to_bytes(SerdeFormat::RawBytes),
+                local.to_bytes(SerdeFormat::RawBytes)
+            );
+            assert_eq!(notices.take_workers(workers.addrs()), BTreeSet::from([1]));
+
+            let slightly_too_small_params = Scheme::new_params(K - 1);
+            assert_matches!(
+                keygen_vk_with(
+                    &slightly_too_small_params,
+                    &empty_circuit,
+                    &mut RemoteBackend::new(&dispatcher)
+                )
+                .await,
+                Err(Error::NotEnoughRowsAvailable {
+                    current_k,
+                }) if current_k == K - 1
+            );
+        });
+    }
+
+    fn test_distributed_keygen_kzg() {
+        use halo2_proofs_distributed::poly::kzg::commitment::KZGCommitmentScheme;
+        use halo2curves::bn256::Bn256;
+
+        distributed_keygen::<KZGCommitmentScheme<Bn256>>();
+    }
+
+    fn test_distributed_keygen_ipa() {
+        use halo2_proofs_distributed::poly::ipa::commitment::IPACommitmentScheme;
+        use halo2curves::pasta::{EpAffine, EqAffine};
+
+        // The same workers serve both curves of the cycle.
+        distributed_keygen::<IPACommitmentScheme<EqAffine>>();
+        distributed_keygen::<IPACommitmentScheme<EpAffine>>();
+    }
+
     test_plonk_api_ipa();
     test_plonk_api_gwc();
     test_plonk_api_shplonk();
+    test_distributed_keygen_kzg();
+    test_distributed_keygen_ipa();
 }
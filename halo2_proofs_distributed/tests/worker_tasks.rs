@@ -0,0 +1,77 @@
+//! Tasks defined outside the prover, run on workers they are registered with.
+use halo2_proofs_distributed::distributed_util::{
+    dispatcher::WorkerMethod,
+    net::{read_u64, write_u64},
+    task::Taskable,
+};
+use std::{
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use worker::harness::LocalWorkers;
+
+/// Sums the squares of the numbers up to `n`, as a stand-in for the work of
+/// an application.
+struct SumOfSquares {
+    n: u64,
+}
+
+impl Taskable for SumOfSquares {
+    const NAME: &'static str = "worker_tasks/sum-of-squares";
+    type Output = u64;
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_u64(writer, self.n)
+    }
+
+    fn decode<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(SumOfSquares {
+            n: read_u64(reader)?,
+        })
+    }
+
+    fn execute(&self) -> io::Result<u64> {
+        Ok((1..=self.n).map(|i| i * i).sum())
+    }
+
+    fn encode_output<W: io::Write>(output: &u64, writer: &mut W) -> io::Result<()> {
+        write_u64(writer, *output)
+    }
+
+    fn decode_output<R: io::Read>(reader: &mut R) -> io::Result<u64> {
+        read_u64(reader)
+    }
+}
+
+#[test]
+fn registered_tasks_run_on_the_pool() {
+    let tasks = (0..20).map(|n| SumOfSquares { n }).collect::<Vec<_>>();
+    let expected = tasks
+        .iter()
+        .map(|task| task.execute().unwrap())
+        .collect::<Vec<_>>();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        // The first worker does not have the task registered, the others
+        // take over its share.
+        let started = AtomicUsize::new(0);
+        let mut workers =
+            LocalWorkers::spawn_with(3, |worker| match started.fetch_add(1, Ordering::SeqCst) {
+                0 => worker,
+                _ => worker.with_task::<SumOfSquares>(),
+            })
+            .await
+            .unwrap();
+        let dispatcher = workers.dispatcher().await.unwrap();
+        for info in dispatcher.ping().await.unwrap() {
+            assert!(info.capabilities.contains(&WorkerMethod::Task));
+        }
+        assert_eq!(dispatcher.run_tasks(&tasks).await.unwrap(), expected);
+
+        // Without any worker able to run them, the tasks run in this process.
+        workers.kill(1);
+        workers.kill(2);
+        assert_eq!(dispatcher.run_tasks(&tasks).await.unwrap(), expected);
+    });
+}
@@ -0,0 +1,177 @@
+//! Circuits the service proves
+//!
+//! A circuit is registered by a type implementing [`RegisteredCircuit`],
+//! which names it and builds it from the input of a job. The registry keeps
+//! one entry per name, which hides the type of the circuit from the queue.
+use futures::future::LocalBoxFuture;
+use halo2_proofs_distributed::{
+    distributed_util::{backend::RemoteBackend, dispatcher::Dispatcher},
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    plonk::{create_proof_distributed, keygen_pk, keygen_vk_with, Circuit, Error, ProvingKey},
+    poly::kzg::{
+        commitment::{KZGCommitmentScheme, ParamsKZG},
+        multiopen::ProverSHPLONK,
+    },
+    transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+};
+use rand_core::OsRng;
+use std::{collections::HashMap, fmt, io, marker::PhantomData};
+
+/// A circuit the service proves, registered under [`RegisteredCircuit::NAME`].
+pub trait RegisteredCircuit: 'static {
+    /// Name jobs refer to the circuit by.
+    const NAME: &'static str;
+
+    /// The circuit.
+    type Circuit: Circuit<Fr>;
+
+    /// Returns the circuit without any witness, to generate its keys from.
+    fn shape() -> Self::Circuit;
+
+    /// Builds the circuit to prove from the content of the input file of a
+    /// job.
+    fn from_input(input: &[u8]) -> io::Result<Self::Circuit>;
+}
+
+/// Why a job failed.
+#[derive(Debug)]
+pub enum JobError {
+    /// The job could not be read, or its circuit built from its input.
+    Input(io::Error),
+    /// The circuit could not be proven.
+    Proof(Error),
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Input(error) => write!(f, "invalid input: {}", error),
+            JobError::Proof(error) => write!(f, "proving failed: {}", error),
+        }
+    }
+}
+
+/// A registered circuit, whatever its type.
+pub(crate) trait Entry: Send + Sync {
+    /// Generates the proving key of the circuit on the workers.
+    fn keygen<'a>(
+        &'a self,
+        params: &'a ParamsKZG<Bn256>,
+        dispatcher: &'a Dispatcher,
+    ) -> LocalBoxFuture<'a, Result<ProvingKey<G1Affine>, Error>>;
+
+    /// Proves the circuit built from `input` on the workers, and returns the
+    /// proof.
+    fn prove<'a>(
+        &'a self,
+        params: &'a ParamsKZG<Bn256>,
+        pk: &'a ProvingKey<G1Affine>,
+        dispatcher: &'a Dispatcher,
+        instances: &'a [Vec<Fr>],
+        input: &'a [u8],
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, JobError>>;
+}
+
+/// The entry of the circuit registered by `T`.
+struct Registered<T>(PhantomData<fn() -> T>);
+
+impl<T: RegisteredCircuit> Entry for Registered<T> {
+    fn keygen<'a>(
+        &'a self,
+        params: &'a ParamsKZG<Bn256>,
+        dispatcher: &'a Dispatcher,
+    ) -> LocalBoxFuture<'a, Result<ProvingKey<G1Affine>, Error>> {
+        Box::pin(async move {
+            let circuit = T::shape();
+            let vk = keygen_vk_with::<G1Affine, _, _, _>(
+                params,
+                &circuit,
+                &mut RemoteBackend::new(dispatcher),
+            )
+            .await?;
+            keygen_pk(params, vk, &circuit)
+        })
+    }
+
+    fn prove<'a>(
+        &'a self,
+        params: &'a ParamsKZG<Bn256>,
+        pk: &'a ProvingKey<G1Affine>,
+        dispatcher: &'a Dispatcher,
+        instances: &'a [Vec<Fr>],
+        input: &'a [u8],
+    ) -> LocalBoxFuture<'a, Result<Vec<u8>, JobError>> {
+        Box::pin(async move {
+            let circuit = T::from_input(input).map_err(JobError::Input)?;
+            let instances = instances
+                .iter()
+                .map(|column| &column[..])
+                .collect::<Vec<_>>();
+            let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            create_proof_distributed::<
+                KZGCommitmentScheme<Bn256>,
+                ProverSHPLONK<'_, Bn256>,
+                _,
+                _,
+                _,
+                _,
+            >(
+                params,
+                pk,
+                &[circuit],
+                &[&instances[..]],
+                OsRng,
+                &mut transcript,
+                dispatcher,
+            )
+            .await
+            .map_err(JobError::Proof)?;
+            Ok(transcript.finalize())
+        })
+    }
+}
+
+/// The circuits a service proves, by name.
+#[derive(Default)]
+pub struct CircuitRegistry {
+    circuits: HashMap<&'static str, Box<dyn Entry>>,
+}
+
+impl CircuitRegistry {
+    /// Proves the circuit of `T` from now on, replacing any circuit
+    /// registered under the same name.
+    pub fn register<T: RegisteredCircuit>(&mut self) {
+        self.circuits
+            .insert(T::NAME, Box::new(Registered::<T>(PhantomData)));
+    }
+
+    /// Returns whether a circuit is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.circuits.contains_key(name)
+    }
+
+    /// Returns the names of the registered circuits.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.circuits.keys().copied()
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&'static str, &dyn Entry)> + '_ {
+        self.circuits
+            .iter()
+            .map(|(&name, entry)| (name, entry.as_ref()))
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&dyn Entry> {
+        self.circuits.get(name).map(|entry| entry.as_ref())
+    }
+}
+
+impl fmt::Debug for CircuitRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.names().collect::<Vec<_>>();
+        names.sort_unstable();
+        f.debug_struct("CircuitRegistry")
+            .field("circuits", &names)
+            .finish()
+    }
+}
@@ -0,0 +1,204 @@
+//! The command line of the service and of its clients
+//!
+//! Shared by the `prover_service` binary and the binaries of crates
+//! registering circuits of their own, see [`run`].
+use crate::{
+    circuit::CircuitRegistry,
+    protocol::{request, JobId, Request, Response},
+    service::Service,
+};
+use halo2_proofs_distributed::{
+    distributed_util::{config::PoolConfig, dispatcher::Dispatcher},
+    halo2curves::bn256::Bn256,
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+};
+use std::{
+    fmt, fs,
+    io::BufReader,
+    os::unix::{fs::FileTypeExt, net::UnixStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::net::UnixListener;
+
+fn help() -> &'static str {
+    "usage:\n  \
+     prover_service serve --socket <path> --params <file> --dir <dir> [--jobs <n>]\n  \
+     prover_service submit --socket <path> --circuit <name> --input <file> [--instance <v,v,...>]...\n  \
+     prover_service status --socket <path> <job>"
+}
+
+/// The command line, one variant per subcommand.
+enum Args {
+    /// Runs the service on the pool of [`PoolConfig::from_env`].
+    Serve {
+        socket: PathBuf,
+        /// The file holding the KZG params of the circuits.
+        params: PathBuf,
+        /// Where jobs and proofs are kept across restarts.
+        dir: PathBuf,
+        jobs: usize,
+    },
+    /// Submits a job, one `--instance` per instance column.
+    Submit {
+        socket: PathBuf,
+        circuit: String,
+        input: PathBuf,
+        instances: Vec<Vec<String>>,
+    },
+    /// Asks where a job is at.
+    Status { socket: PathBuf, job: JobId },
+}
+
+/// Splits `--name value` and `--name=value` alike.
+fn option(arg: &str, args: &mut impl Iterator<Item = String>) -> Option<(String, String)> {
+    let name = arg.strip_prefix("--")?;
+    match name.split_once('=') {
+        Some((name, value)) => Some((name.to_string(), value.to_string())),
+        None => Some((name.to_string(), args.next()?)),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Args> {
+    let command = args.next()?;
+    let mut socket = None;
+    let mut params = None;
+    let mut dir = None;
+    let mut jobs = 1;
+    let mut circuit = None;
+    let mut input = None;
+    let mut instances = vec![];
+    let mut job = None;
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            job = Some(arg.parse().ok()?);
+            continue;
+        }
+        let (name, value) = option(&arg, &mut args)?;
+        match name.as_str() {
+            "socket" => socket = Some(PathBuf::from(value)),
+            "params" => params = Some(PathBuf::from(value)),
+            "dir" => dir = Some(PathBuf::from(value)),
+            "jobs" => jobs = value.parse().ok()?,
+            "circuit" => circuit = Some(value),
+            "input" => input = Some(PathBuf::from(value)),
+            "instance" => instances.push(
+                value
+                    .split(',')
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            _ => return None,
+        }
+    }
+    match command.as_str() {
+        "serve" => Some(Args::Serve {
+            socket: socket?,
+            params: params?,
+            dir: dir?,
+            jobs,
+        }),
+        "submit" => Some(Args::Submit {
+            socket: socket?,
+            circuit: circuit?,
+            input: input?,
+            instances,
+        }),
+        "status" => Some(Args::Status {
+            socket: socket?,
+            job: job?,
+        }),
+        _ => None,
+    }
+}
+
+/// Prints `message` to stderr and ends the process with a failure.
+fn exit_with(message: impl fmt::Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
+/// Removes the socket a previous service left behind at `path`, which is
+/// in the way of binding a new one. Anything other than a socket, or a
+/// socket a service still answers on, is left alone.
+fn remove_stale_socket(path: &Path) {
+    let is_socket = fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket && UnixStream::connect(path).is_err() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Runs the subcommand of the command line. `serve` proves the circuits of
+/// `circuits` until the process ends, `submit` and `status` print the answer
+/// of the service.
+pub async fn run(circuits: CircuitRegistry) {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Some(args) => args,
+        None => exit_with(help()),
+    };
+    let (socket, req) = match args {
+        Args::Serve {
+            socket,
+            params,
+            dir,
+            jobs,
+        } => {
+            let params = fs::File::open(&params)
+                .and_then(|file| ParamsKZG::<Bn256>::read(&mut BufReader::new(file)))
+                .unwrap_or_else(|e| {
+                    exit_with(format!("could not read {}: {}", params.display(), e))
+                });
+            let config = PoolConfig::from_env().unwrap_or_else(|e| exit_with(e));
+            let dispatcher = Dispatcher::new(config)
+                .await
+                .unwrap_or_else(|e| exit_with(e))
+                .with_notices(|notice| eprintln!("{}", notice));
+            let service = Service::new(dir, params, circuits, dispatcher)
+                .await
+                .unwrap_or_else(|e| exit_with(e))
+                .with_max_jobs(jobs);
+            remove_stale_socket(&socket);
+            let listener = UnixListener::bind(&socket).unwrap_or_else(|e| {
+                exit_with(format!("could not listen on {}: {}", socket.display(), e))
+            });
+            println!("service listening on: {}", socket.display());
+            if let Err(e) = Arc::new(service).serve(listener).await {
+                exit_with(e);
+            }
+            return;
+        }
+        Args::Submit {
+            socket,
+            circuit,
+            input,
+            instances,
+        } => (
+            socket,
+            Request::Submit {
+                circuit,
+                instances,
+                // The service resolves paths from its own directory.
+                input: fs::canonicalize(&input).unwrap_or_else(|e| {
+                    exit_with(format!("could not read {}: {}", input.display(), e))
+                }),
+            },
+        ),
+        Args::Status { socket, job } => (socket, Request::Status { job }),
+    };
+
+    let response = request(&socket, &req).await.unwrap_or_else(|e| {
+        exit_with(format!(
+            "could not reach the service at {}: {}",
+            socket.display(),
+            e
+        ))
+    });
+    match response {
+        Response::Submitted { job } => println!("{}", job),
+        Response::Status { status, .. } => println!("{}", serde_json::to_string(&status).unwrap()),
+        Response::Error { message } => exit_with(message),
+    }
+}
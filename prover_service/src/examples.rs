@@ -0,0 +1,99 @@
+//! Circuits the `prover_service` binary registers
+use ff::PrimeField;
+use halo2_proofs_distributed::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    halo2curves::bn256::Fr,
+    plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Instance, Selector},
+    poly::Rotation,
+};
+use prover_service::RegisteredCircuit;
+use std::io;
+
+/// Proves knowledge of a square root of the single instance value. The
+/// input of a job is the root, in decimal.
+#[derive(Debug)]
+pub struct Square;
+
+#[derive(Clone, Debug)]
+pub struct SquareConfig {
+    x: Column<Advice>,
+    y: Column<Advice>,
+    square: Column<Instance>,
+    s: Selector,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SquareCircuit {
+    x: Value<Fr>,
+}
+
+impl SquareCircuit {
+    pub fn new(x: Fr) -> Self {
+        SquareCircuit { x: Value::known(x) }
+    }
+}
+
+impl Circuit<Fr> for SquareCircuit {
+    type Config = SquareConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+    #[cfg(feature = "circuit-params")]
+    type Params = ();
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> SquareConfig {
+        let x = meta.advice_column();
+        let y = meta.advice_column();
+        let square = meta.instance_column();
+        meta.enable_equality(y);
+        meta.enable_equality(square);
+        let s = meta.selector();
+        meta.create_gate("square", |meta| {
+            let s = meta.query_selector(s);
+            let x = meta.query_advice(x, Rotation::cur());
+            let y = meta.query_advice(y, Rotation::cur());
+            vec![s * (x.clone() * x - y)]
+        });
+        SquareConfig { x, y, square, s }
+    }
+
+    fn synthesize(
+        &self,
+        config: SquareConfig,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        let y = layouter.assign_region(
+            || "square",
+            |mut region| {
+                config.s.enable(&mut region, 0)?;
+                region.assign_advice(|| "x", config.x, 0, || self.x)?;
+                region.assign_advice(|| "y", config.y, 0, || self.x * self.x)
+            },
+        )?;
+        layouter.constrain_instance(y.cell(), config.square, 0)
+    }
+}
+
+impl RegisteredCircuit for Square {
+    const NAME: &'static str = "square";
+    type Circuit = SquareCircuit;
+
+    fn shape() -> SquareCircuit {
+        SquareCircuit::default()
+    }
+
+    fn from_input(input: &[u8]) -> io::Result<SquareCircuit> {
+        std::str::from_utf8(input)
+            .ok()
+            .and_then(|x| Fr::from_str_vartime(x.trim()))
+            .map(SquareCircuit::new)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected a field element in decimal",
+                )
+            })
+    }
+}
@@ -0,0 +1,34 @@
+//! Long-running proving service on top of a worker pool
+//!
+//! A [`Service`] proves the jobs clients submit over a Unix socket. Each job
+//! names a circuit registered at compile time in a [`CircuitRegistry`], gives
+//! the values of its instance columns and the file holding the input its
+//! witness is built from. Jobs are queued, proven on the workers of a
+//! `Dispatcher` at most a configured number at once, and the proof of each
+//! is written to the directory of the service next to the record of the
+//! job. Completed jobs thus outlive restarts of the service, and the jobs
+//! left unfinished are queued again.
+//!
+//! Proofs use KZG over BN254 with the SHPLONK multiopen argument and a
+//! Blake2b transcript. Clients and the service exchange the JSON messages of
+//! [`protocol`], one per line.
+//!
+//! Crates proving circuits of their own register them and run the service
+//! with [`cli::run`], which reads the same command line as the
+//! `prover_service` binary:
+//!
+//! ```ignore
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut circuits = CircuitRegistry::default();
+//!     circuits.register::<MyCircuit>();
+//!     prover_service::cli::run(circuits).await
+//! }
+//! ```
+pub mod circuit;
+pub mod cli;
+pub mod protocol;
+pub mod service;
+
+pub use circuit::{CircuitRegistry, RegisteredCircuit};
+pub use service::Service;
@@ -0,0 +1,11 @@
+use examples::Square;
+use prover_service::CircuitRegistry;
+
+mod examples;
+
+#[tokio::main]
+async fn main() {
+    let mut circuits = CircuitRegistry::default();
+    circuits.register::<Square>();
+    prover_service::cli::run(circuits).await
+}
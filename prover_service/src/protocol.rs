@@ -0,0 +1,158 @@
+//! What clients and the service exchange over the socket
+//!
+//! Each [`Request`] is a line of JSON, answered by a [`Response`] on a line
+//! of its own. A connection may carry any number of requests. Field elements
+//! are written as decimal strings.
+use ff::PrimeField;
+use halo2_proofs_distributed::halo2curves::bn256::Fr;
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+/// Identifies a job, in the order jobs were submitted.
+pub type JobId = u64;
+
+/// A request of a client.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    /// Queues the proof of the circuit registered as `circuit`, with the
+    /// values of its instance columns in order and the file its witness is
+    /// built from. The file is read by the service when the job runs, so
+    /// its path is best absolute.
+    Submit {
+        circuit: String,
+        instances: Vec<Vec<String>>,
+        input: PathBuf,
+    },
+    /// Asks where a job is at.
+    Status { job: JobId },
+}
+
+/// The answer of the service to a [`Request`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    /// The job was queued.
+    Submitted { job: JobId },
+    /// Where a job is at.
+    Status { job: JobId, status: JobStatus },
+    /// The request was refused.
+    Error { message: String },
+}
+
+/// Where a job is at.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for a free slot.
+    Queued,
+    /// Being proven.
+    Running,
+    /// Proven, the proof is in the file `proof`.
+    Done { proof: PathBuf },
+    /// Could not be proven.
+    Failed { message: String },
+}
+
+/// Parses the values of instance columns written as decimal strings.
+pub fn parse_instances(instances: &[Vec<String>]) -> io::Result<Vec<Vec<Fr>>> {
+    instances
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|value| {
+                    Fr::from_str_vartime(value).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("invalid instance value {:?}", value),
+                        )
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Writes `message` as a line of JSON.
+pub(crate) async fn write_line<W, T>(writer: &mut W, message: &T) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await
+}
+
+/// Sends `request` to the service listening on `socket`, and returns its
+/// answer.
+pub async fn request(socket: impl AsRef<Path>, request: &Request) -> io::Result<Response> {
+    let stream = UnixStream::connect(socket).await?;
+    let (read, mut write) = stream.into_split();
+    write_line(&mut write, request).await?;
+
+    let mut line = String::new();
+    if BufReader::new(read).read_line(&mut line).await? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the service closed the connection without answering",
+        ));
+    }
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_instances, JobStatus, Request, Response};
+    use halo2_proofs_distributed::halo2curves::bn256::Fr;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_messages_roundtrip() {
+        let request = Request::Submit {
+            circuit: "square".to_string(),
+            instances: vec![vec!["9".to_string()]],
+            input: PathBuf::from("/tmp/input"),
+        };
+        let line = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            line,
+            r#"{"submit":{"circuit":"square","instances":[["9"]],"input":"/tmp/input"}}"#
+        );
+        assert_eq!(serde_json::from_str::<Request>(&line).unwrap(), request);
+
+        let response = Response::Status {
+            job: 3,
+            status: JobStatus::Done {
+                proof: PathBuf::from("/tmp/3.proof"),
+            },
+        };
+        let line = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            line,
+            r#"{"status":{"job":3,"status":{"state":"done","proof":"/tmp/3.proof"}}}"#
+        );
+        assert_eq!(serde_json::from_str::<Response>(&line).unwrap(), response);
+    }
+
+    #[test]
+    fn test_parse_instances() {
+        let instances = vec![vec!["9".to_string(), "0".to_string()], vec![]];
+        assert_eq!(
+            parse_instances(&instances).unwrap(),
+            vec![vec![Fr::from(9), Fr::zero()], vec![]]
+        );
+        assert!(parse_instances(&[vec!["0x09".to_string()]]).is_err());
+        assert!(parse_instances(&[vec!["-1".to_string()]]).is_err());
+    }
+}
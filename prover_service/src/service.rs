@@ -0,0 +1,464 @@
+//! The job queue
+//!
+//! Every job has a record in the directory of the service, `<id>.json`,
+//! rewritten whenever its status changes, and the proof of a job that
+//! completed is kept next to it as `<id>.proof`. A service started on the
+//! directory of a previous one answers for its jobs, and queues again those
+//! it had not finished.
+use crate::{
+    circuit::CircuitRegistry,
+    protocol::{parse_instances, write_line, JobId, JobStatus, Request, Response},
+};
+use halo2_proofs_distributed::{
+    distributed_util::dispatcher::Dispatcher,
+    halo2curves::bn256::{Bn256, G1Affine},
+    plonk::ProvingKey,
+    poly::kzg::commitment::ParamsKZG,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::{UnixListener, UnixStream},
+    runtime::Handle,
+    sync::{Notify, Semaphore},
+    task::JoinSet,
+};
+
+/// A submitted job, as recorded in the directory of the service.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Job {
+    circuit: String,
+    instances: Vec<Vec<String>>,
+    input: PathBuf,
+    status: JobStatus,
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    records: BTreeMap<JobId, Job>,
+    /// The queued jobs, oldest first.
+    queue: VecDeque<JobId>,
+    next: JobId,
+}
+
+/// Proves the jobs submitted to it on the workers of a [`Dispatcher`].
+pub struct Service {
+    dir: PathBuf,
+    circuits: CircuitRegistry,
+    keys: HashMap<&'static str, ProvingKey<G1Affine>>,
+    params: ParamsKZG<Bn256>,
+    dispatcher: Dispatcher,
+    jobs: Mutex<Jobs>,
+    /// Woken when a job is queued.
+    queued: Notify,
+    /// One permit per job that may be proven at once.
+    slots: Arc<Semaphore>,
+}
+
+impl Service {
+    /// Sets up a service keeping its jobs in `dir`, and generates the keys of
+    /// the registered circuits on the workers of `dispatcher`. The jobs
+    /// recorded in `dir` by a previous service are taken over.
+    ///
+    /// At most one job is proven at once, see [`Service::with_max_jobs`].
+    pub async fn new(
+        dir: impl AsRef<Path>,
+        params: ParamsKZG<Bn256>,
+        circuits: CircuitRegistry,
+        dispatcher: Dispatcher,
+    ) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut keys = HashMap::new();
+        for (name, entry) in circuits.entries() {
+            let pk = entry.keygen(&params, &dispatcher).await.map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("keygen of circuit {} failed: {}", name, e),
+                )
+            })?;
+            println!("circuit {} ready", name);
+            keys.insert(name, pk);
+        }
+
+        let jobs = load_jobs(&dir)?;
+        if !jobs.queue.is_empty() {
+            println!("{} jobs left unfinished are queued again", jobs.queue.len());
+        }
+
+        Ok(Service {
+            dir,
+            circuits,
+            keys,
+            params,
+            dispatcher,
+            jobs: Mutex::new(jobs),
+            queued: Notify::new(),
+            slots: Arc::new(Semaphore::new(1)),
+        })
+    }
+
+    /// Proves up to `max_jobs` jobs at once, at least one. The jobs share
+    /// the workers.
+    pub fn with_max_jobs(mut self, max_jobs: usize) -> Self {
+        self.slots = Arc::new(Semaphore::new(max_jobs.max(1)));
+        self
+    }
+
+    /// Queues the proof of the circuit registered as `circuit`, with the
+    /// given instance values and witness input file, and returns the id of
+    /// the job.
+    ///
+    /// The circuit and the instance values are checked right away. The
+    /// input is only required to be a file, it is read when the job runs.
+    pub fn submit(
+        &self,
+        circuit: &str,
+        instances: Vec<Vec<String>>,
+        input: PathBuf,
+    ) -> io::Result<JobId> {
+        if !self.circuits.contains(circuit) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no circuit {} is registered", circuit),
+            ));
+        }
+        parse_instances(&instances)?;
+        let metadata = fs::metadata(&input).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("could not read {}: {}", input.display(), e),
+            )
+        })?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a file", input.display()),
+            ));
+        }
+
+        let job = Job {
+            circuit: circuit.to_string(),
+            instances,
+            input,
+            status: JobStatus::Queued,
+        };
+        let id = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let id = jobs.next;
+            self.write_record(id, &job)?;
+            jobs.next += 1;
+            jobs.records.insert(id, job);
+            jobs.queue.push_back(id);
+            id
+        };
+        println!("job {} queued: {}", id, circuit);
+        self.queued.notify_one();
+        Ok(id)
+    }
+
+    /// Returns the status of `job`, `None` if there is no such job.
+    pub fn status(&self, job: JobId) -> Option<JobStatus> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.records.get(&job).map(|job| job.status.clone())
+    }
+
+    /// Answers the clients connecting to `listener` and proves the jobs they
+    /// submit, until an error occurs.
+    pub async fn serve(self: Arc<Self>, listener: UnixListener) -> io::Result<()> {
+        let mut names = self.circuits.names().collect::<Vec<_>>();
+        names.sort_unstable();
+        println!("proving circuits {}", names.join(", "));
+
+        let jobs = self.clone().run_jobs();
+        tokio::pin!(jobs);
+        let mut clients = JoinSet::new();
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => accepted?.0,
+                // Reap the clients that left.
+                Some(_) = clients.join_next() => continue,
+                () = &mut jobs => unreachable!("jobs are run as long as the service"),
+            };
+            let service = self.clone();
+            clients.spawn(async move {
+                if let Err(e) = service.answer(stream).await {
+                    println!("Could not answer a client: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Answers the requests of a client until it disconnects.
+    async fn answer(&self, stream: UnixStream) -> io::Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => self.respond(request),
+                Err(e) => Response::Error {
+                    message: format!("invalid request: {}", e),
+                },
+            };
+            write_line(&mut write, &response).await?;
+        }
+        Ok(())
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        match request {
+            Request::Submit {
+                circuit,
+                instances,
+                input,
+            } => match self.submit(&circuit, instances, input) {
+                Ok(job) => Response::Submitted { job },
+                Err(e) => Response::Error {
+                    message: e.to_string(),
+                },
+            },
+            Request::Status { job } => match self.status(job) {
+                Some(status) => Response::Status { job, status },
+                None => Response::Error {
+                    message: format!("no job {}", job),
+                },
+            },
+        }
+    }
+
+    /// Proves the queued jobs as slots free up.
+    async fn run_jobs(self: Arc<Self>) {
+        loop {
+            let slot = self.slots.clone().acquire_owned().await.unwrap();
+            let id = self.next_job().await;
+            let service = self.clone();
+            let handle = Handle::current();
+            tokio::spawn(async move {
+                // Proofs are not `Send`, each is driven on a blocking thread
+                // of its own.
+                let prover = service.clone();
+                let proving =
+                    tokio::task::spawn_blocking(move || handle.block_on(prover.prove(id)));
+                if let Err(e) = proving.await {
+                    let message = match e.try_into_panic() {
+                        Ok(panic) => format!("proving panicked: {}", panic_message(&*panic)),
+                        Err(e) => e.to_string(),
+                    };
+                    println!("job {} failed: {}", id, message);
+                    service.set_status(id, JobStatus::Failed { message });
+                }
+                drop(slot);
+            });
+        }
+    }
+
+    /// Waits for a job to be queued, and takes it off the queue.
+    async fn next_job(&self) -> JobId {
+        loop {
+            let queued = self.queued.notified();
+            if let Some(id) = self.jobs.lock().unwrap().queue.pop_front() {
+                return id;
+            }
+            queued.await;
+        }
+    }
+
+    /// Proves `id` and records how it went.
+    async fn prove(&self, id: JobId) {
+        let job = match self.set_status(id, JobStatus::Running) {
+            Some(job) => job,
+            None => return,
+        };
+        println!("job {} running", id);
+        let status = match self.run(id, &job).await {
+            Ok(proof) => {
+                println!("job {} done", id);
+                JobStatus::Done { proof }
+            }
+            Err(message) => {
+                println!("job {} failed: {}", id, message);
+                JobStatus::Failed { message }
+            }
+        };
+        self.set_status(id, status);
+    }
+
+    /// Proves `job`, and returns the file its proof is written to.
+    async fn run(&self, id: JobId, job: &Job) -> Result<PathBuf, String> {
+        let entry = self
+            .circuits
+            .get(&job.circuit)
+            .ok_or_else(|| format!("no circuit {} is registered", job.circuit))?;
+        let instances = parse_instances(&job.instances).map_err(|e| e.to_string())?;
+        let input = fs::read(&job.input)
+            .map_err(|e| format!("could not read {}: {}", job.input.display(), e))?;
+        let proof = entry
+            .prove(
+                &self.params,
+                &self.keys[job.circuit.as_str()],
+                &self.dispatcher,
+                &instances,
+                &input,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let path = self.dir.join(format!("{}.proof", id));
+        write_atomically(&path, &proof)
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    /// Sets the status of `id` and records it, returning the job.
+    fn set_status(&self, id: JobId, status: JobStatus) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.records.get_mut(&id)?;
+        job.status = status;
+        if let Err(e) = self.write_record(id, job) {
+            // The job goes on, it is just run again after a restart.
+            eprintln!("could not record job {}: {}", id, e);
+        }
+        Some(job.clone())
+    }
+
+    fn write_record(&self, id: JobId, job: &Job) -> io::Result<()> {
+        let record = serde_json::to_vec_pretty(job)?;
+        write_atomically(&self.dir.join(format!("{}.json", id)), &record)
+    }
+}
+
+/// Returns the message a panic was raised with, if it has one.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<String>()
+            .map_or("no message", String::as_str),
+    }
+}
+
+/// Reads the records of `dir`, queueing the jobs that had not finished.
+fn load_jobs(dir: &Path) -> io::Result<Jobs> {
+    let mut jobs = Jobs::default();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map_or(true, |extension| extension != "json")
+        {
+            continue;
+        }
+        let id = match path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<JobId>().ok())
+        {
+            Some(id) => id,
+            None => continue,
+        };
+        let job: Job = serde_json::from_slice(&fs::read(&path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid record {}: {}", path.display(), e),
+            )
+        })?;
+        jobs.next = jobs.next.max(id + 1);
+        jobs.records.insert(id, job);
+    }
+    for (&id, job) in jobs.records.iter_mut() {
+        if let JobStatus::Queued | JobStatus::Running = job.status {
+            job.status = JobStatus::Queued;
+            jobs.queue.push_back(id);
+        }
+    }
+    Ok(jobs)
+}
+
+/// Writes `contents` to `path` through a temporary file, so that `path`
+/// never holds part of it.
+///
+/// The temporary file is named after the whole of `path`, as the record and
+/// the proof of a job only differ by their extension.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_jobs, write_atomically, Job};
+    use crate::protocol::JobStatus;
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn test_load_jobs_queues_unfinished_jobs() {
+        let dir = std::env::temp_dir().join(format!("prover_service-jobs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let statuses = [
+            JobStatus::Running,
+            JobStatus::Done {
+                proof: dir.join("1.proof"),
+            },
+            JobStatus::Queued,
+            JobStatus::Failed {
+                message: "invalid input".to_string(),
+            },
+            JobStatus::Running,
+        ];
+        for (id, status) in statuses.iter().enumerate() {
+            let job = Job {
+                circuit: "square".to_string(),
+                instances: vec![vec!["9".to_string()]],
+                input: PathBuf::from("/tmp/input"),
+                status: status.clone(),
+            };
+            let record = serde_json::to_vec_pretty(&job).unwrap();
+            write_atomically(&dir.join(format!("{}.json", id)), &record).unwrap();
+        }
+        // Neither the proofs nor the temporary files left by a crash are
+        // records.
+        fs::write(dir.join("1.proof"), b"proof").unwrap();
+        fs::write(dir.join("5.json.tmp"), b"{").unwrap();
+
+        let jobs = load_jobs(&dir).unwrap();
+        assert_eq!(jobs.next, 5);
+        assert_eq!(jobs.queue, [0, 2, 4]);
+        for (id, job) in &jobs.records {
+            let expected = match &statuses[*id as usize] {
+                JobStatus::Running => &JobStatus::Queued,
+                status => status,
+            };
+            assert_eq!(&job.status, expected);
+        }
+        assert_eq!(jobs.records.len(), statuses.len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_and_proof_do_not_share_a_temporary_file() {
+        let dir = std::env::temp_dir().join(format!("prover_service-tmp-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // The record of a job being written while its proof is.
+        fs::write(dir.join("0.json.tmp"), b"{}").unwrap();
+        write_atomically(&dir.join("0.proof"), b"proof").unwrap();
+        assert_eq!(fs::read(dir.join("0.json.tmp")).unwrap(), b"{}");
+        assert_eq!(fs::read(dir.join("0.proof")).unwrap(), b"proof");
+        assert!(!dir.join("0.proof.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+//! Jobs submitted over the socket, proven on local workers.
+use examples::Square;
+use halo2_proofs_distributed::{
+    halo2curves::bn256::{Bn256, Fr},
+    plonk::{keygen_vk, verify_proof},
+    poly::{
+        commitment::ParamsProver,
+        kzg::{commitment::ParamsKZG, multiopen::VerifierSHPLONK, strategy::SingleStrategy},
+        VerificationStrategy,
+    },
+    transcript::{Blake2bRead, Challenge255, TranscriptReadBuffer},
+};
+use prover_service::{
+    protocol::{request, JobId, JobStatus, Request, Response},
+    CircuitRegistry, RegisteredCircuit, Service,
+};
+use rand_core::OsRng;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{net::UnixListener, task::JoinHandle};
+use worker::harness::LocalWorkers;
+
+// The circuits of the binary, which the library does not export.
+#[path = "../src/examples.rs"]
+mod examples;
+
+const K: u32 = 4;
+
+fn circuits() -> CircuitRegistry {
+    let mut circuits = CircuitRegistry::default();
+    circuits.register::<Square>();
+    circuits
+}
+
+/// Starts a service on `dir` proving up to `max_jobs` jobs at once,
+/// listening on `<dir>/socket`.
+async fn start(
+    workers: &LocalWorkers,
+    params: &ParamsKZG<Bn256>,
+    dir: &Path,
+    max_jobs: usize,
+) -> (PathBuf, JoinHandle<std::io::Result<()>>) {
+    let service = Service::new(
+        dir,
+        params.clone(),
+        circuits(),
+        workers.dispatcher().await.unwrap(),
+    )
+    .await
+    .unwrap()
+    .with_max_jobs(max_jobs);
+    let socket = dir.join("socket");
+    let _ = fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+    (socket, tokio::spawn(Arc::new(service).serve(listener)))
+}
+
+async fn submit(socket: &Path, square: u64, input: &Path) -> Response {
+    request(
+        socket,
+        &Request::Submit {
+            circuit: Square::NAME.to_string(),
+            instances: vec![vec![square.to_string()]],
+            input: input.to_path_buf(),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+async fn status(socket: &Path, job: JobId) -> JobStatus {
+    match request(socket, &Request::Status { job }).await.unwrap() {
+        Response::Status { status, .. } => status,
+        response => panic!("unexpected answer {:?}", response),
+    }
+}
+
+/// Waits for `job` to be done or failed.
+async fn finished(socket: &Path, job: JobId) -> JobStatus {
+    for _ in 0..600 {
+        match status(socket, job).await {
+            JobStatus::Queued | JobStatus::Running => {
+                tokio::time::sleep(Duration::from_millis(100)).await
+            }
+            status => return status,
+        }
+    }
+    panic!("job {} did not finish", job);
+}
+
+#[test]
+fn jobs_are_proven_and_outlive_the_service() {
+    let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+    let vk = keygen_vk(&params, &Square::shape()).unwrap();
+    let dir = std::env::temp_dir().join(format!("prover_service-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let root = dir.join("root");
+    fs::write(&root, "3\n").unwrap();
+    let garbage = dir.join("garbage");
+    fs::write(&garbage, "three").unwrap();
+    let jobs_dir = dir.join("jobs");
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let workers = LocalWorkers::spawn(2).await.unwrap();
+        let (socket, service) = start(&workers, &params, &jobs_dir, 2).await;
+
+        let mut jobs = vec![];
+        for _ in 0..3 {
+            match submit(&socket, 9, &root).await {
+                Response::Submitted { job } => jobs.push(job),
+                response => panic!("unexpected answer {:?}", response),
+            }
+        }
+        let bad = match submit(&socket, 9, &garbage).await {
+            Response::Submitted { job } => job,
+            response => panic!("unexpected answer {:?}", response),
+        };
+
+        // Jobs naming no registered circuit or file are refused.
+        let unknown = request(
+            &socket,
+            &Request::Submit {
+                circuit: "cube".to_string(),
+                instances: vec![],
+                input: root.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(unknown, Response::Error { .. }));
+        let missing = submit(&socket, 9, &dir.join("missing")).await;
+        assert!(matches!(missing, Response::Error { .. }));
+
+        let mut proofs = vec![];
+        for &job in &jobs {
+            match finished(&socket, job).await {
+                JobStatus::Done { proof } => proofs.push(proof),
+                status => panic!("job {} ended {:?}", job, status),
+            }
+        }
+        assert!(matches!(
+            finished(&socket, bad).await,
+            JobStatus::Failed { .. }
+        ));
+
+        for proof in &proofs {
+            let proof = fs::read(proof).unwrap();
+            let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
+            let square = [Fr::from(9)];
+            let strategy = verify_proof::<_, VerifierSHPLONK<_>, _, _, _>(
+                params.verifier_params(),
+                &vk,
+                SingleStrategy::new(&params),
+                &[&[&square[..]]],
+                &mut transcript,
+            )
+            .unwrap();
+            assert!(strategy.finalize());
+        }
+
+        // A service started on the same directory answers for the jobs of
+        // the previous one.
+        service.abort();
+        let (socket, _service) = start(&workers, &params, &jobs_dir, 2).await;
+        for (&job, proof) in jobs.iter().zip(proofs) {
+            assert_eq!(status(&socket, job).await, JobStatus::Done { proof });
+        }
+        assert!(matches!(
+            status(&socket, bad).await,
+            JobStatus::Failed { .. }
+        ));
+        match submit(&socket, 9, &root).await {
+            Response::Submitted { job } => assert_eq!(job, bad + 1),
+            response => panic!("unexpected answer {:?}", response),
+        }
+    });
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn jobs_wait_for_a_free_slot() {
+    let params = ParamsKZG::<Bn256>::setup(K, OsRng);
+    let dir = std::env::temp_dir().join(format!("prover_service-slots-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let root = dir.join("root");
+    fs::write(&root, "3\n").unwrap();
+    let jobs_dir = dir.join("jobs");
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let workers = LocalWorkers::spawn(2).await.unwrap();
+        let (socket, _service) = start(&workers, &params, &jobs_dir, 1).await;
+
+        let mut jobs = vec![];
+        for _ in 0..3 {
+            match submit(&socket, 9, &root).await {
+                Response::Submitted { job } => jobs.push(job),
+                response => panic!("unexpected answer {:?}", response),
+            }
+        }
+
+        // With a single slot jobs run in the order they were submitted, one
+        // after the other. Asking for the latest jobs first, a job is thus
+        // only seen running along another if both ran at once.
+        for _ in 0..600 {
+            let mut statuses = vec![];
+            for &job in jobs.iter().rev() {
+                statuses.push(status(&socket, job).await);
+            }
+            let running = statuses
+                .iter()
+                .filter(|status| matches!(status, JobStatus::Running))
+                .count();
+            assert!(running <= 1, "{} jobs running at once", running);
+            if statuses
+                .iter()
+                .all(|status| matches!(status, JobStatus::Done { .. }))
+            {
+                fs::remove_dir_all(&dir).unwrap();
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("jobs did not finish");
+    });
+}
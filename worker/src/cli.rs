@@ -0,0 +1,110 @@
+//! The command line of a worker
+//!
+//! Shared by the `worker` binary and the binaries of crates registering
+//! tasks of their own, see [`run`].
+use halo2_proofs_distributed::distributed_util::{
+    config::WORKERS_KEY_ENV, net::auth::PresharedKey,
+};
+use std::{
+    fs,
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+};
+
+use crate::Worker;
+
+fn help() -> &'static str {
+    "usage: worker --listen <addr> [--params-dir <dir>] [--key-file <path>] [--insecure]"
+}
+
+/// The command line of a worker.
+struct Args {
+    listen: SocketAddr,
+    /// Where params sent to the worker are kept across restarts, in one
+    /// subdirectory per scheme.
+    params_dir: Option<PathBuf>,
+    /// The file holding the secret shared with the dispatcher.
+    key_file: Option<PathBuf>,
+    /// Whether the worker may serve unauthenticated connections when no
+    /// secret is configured.
+    insecure: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Args> {
+    let mut listen = None;
+    let mut params_dir = None;
+    let mut key_file = None;
+    let mut insecure = false;
+    while let Some(arg) = args.next() {
+        if arg == "--listen" {
+            listen = args.next()?.to_socket_addrs().ok()?.next();
+        } else if let Some(addr) = arg.strip_prefix("--listen=") {
+            listen = addr.to_socket_addrs().ok()?.next();
+        } else if arg == "--params-dir" {
+            params_dir = Some(PathBuf::from(args.next()?));
+        } else if let Some(dir) = arg.strip_prefix("--params-dir=") {
+            params_dir = Some(PathBuf::from(dir));
+        } else if arg == "--key-file" {
+            key_file = Some(PathBuf::from(args.next()?));
+        } else if let Some(path) = arg.strip_prefix("--key-file=") {
+            key_file = Some(PathBuf::from(path));
+        } else if arg == "--insecure" {
+            insecure = true;
+        } else {
+            return None;
+        }
+    }
+    Some(Args {
+        listen: listen?,
+        params_dir,
+        key_file,
+        insecure,
+    })
+}
+
+/// Reads the secret shared with the dispatcher from the key file or, if none
+/// is given, from [`WORKERS_KEY_ENV`]. A trailing newline in the key file is
+/// not part of it. Without a secret, the worker only starts with
+/// `--insecure`.
+fn read_key(args: &Args) -> Result<PresharedKey, String> {
+    let secret = match &args.key_file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|error| format!("cannot read {}: {}", path.display(), error))?
+            .trim_end_matches(&['\r', '\n'][..])
+            .to_string(),
+        None => std::env::var(WORKERS_KEY_ENV).unwrap_or_default(),
+    };
+    if secret.is_empty() {
+        if !args.insecure {
+            return Err(format!(
+                "no key configured, set {} or --key-file, or pass --insecure to serve \
+                 unauthenticated connections",
+                WORKERS_KEY_ENV
+            ));
+        }
+        println!("no key configured, connections are not authenticated");
+    }
+    Ok(PresharedKey::new(secret.as_bytes()))
+}
+
+/// Starts the worker described by the command line, once `configure` has
+/// registered its tasks with it, and serves dispatchers until the process
+/// ends.
+pub async fn run(configure: impl FnOnce(Worker) -> Worker) {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Some(args) => args,
+        None => panic!("{}", help()),
+    };
+    let key = match read_key(&args) {
+        Ok(key) => key,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    let mut w = configure(Worker::new(args.listen).with_key(key));
+    if let Some(dir) = &args.params_dir {
+        w = w.with_params_dir(dir).unwrap();
+    }
+    w.start().await.unwrap();
+}
@@ -0,0 +1,138 @@
+//! In-process workers for tests
+//!
+//! [`LocalWorkers`] runs [`Worker`]s on tasks of the current tokio
+//! runtime, each listening on an ephemeral port of the loopback interface, so
+//! tests can drive a real [`Dispatcher`] without any process or fixed port
+//! set up ahead of time. [`Notices`] tells which of them the dispatcher had
+//! to work around.
+use halo2_proofs_distributed::distributed_util::{
+    config::{PoolConfig, WorkerConfig},
+    dispatcher::{Dispatcher, Notice},
+    net::auth::PresharedKey,
+};
+use std::{
+    collections::BTreeSet,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use crate::Worker;
+
+/// Workers running in this process, shut down when dropped.
+#[derive(Debug)]
+pub struct LocalWorkers {
+    addrs: Vec<SocketAddr>,
+    /// The secret the workers share with their dispatchers.
+    key: Option<String>,
+    tasks: Vec<JoinHandle<io::Result<()>>>,
+}
+
+impl LocalWorkers {
+    /// Starts `n` workers, each on a free port of `127.0.0.1`.
+    pub async fn spawn(n: usize) -> io::Result<Self> {
+        Self::start(n, None, |worker| worker).await
+    }
+
+    /// Starts `n` workers that only serve dispatchers holding `key`.
+    pub async fn spawn_with_key(n: usize, key: &str) -> io::Result<Self> {
+        Self::start(n, Some(key.to_string()), |worker| worker).await
+    }
+
+    /// Starts `n` workers, each set up by `configure`, for example to
+    /// register tasks with them.
+    pub async fn spawn_with(n: usize, configure: impl Fn(Worker) -> Worker) -> io::Result<Self> {
+        Self::start(n, None, configure).await
+    }
+
+    async fn start(
+        n: usize,
+        key: Option<String>,
+        configure: impl Fn(Worker) -> Worker,
+    ) -> io::Result<Self> {
+        let preshared_key = PresharedKey::new(key.as_deref().unwrap_or_default().as_bytes());
+        let mut addrs = Vec::with_capacity(n);
+        let mut tasks = Vec::with_capacity(n);
+        for _ in 0..n {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let worker = configure(Worker::new(addr).with_key(preshared_key.clone()));
+            tasks.push(tokio::spawn(async move { worker.serve(listener).await }));
+            addrs.push(addr);
+        }
+        Ok(LocalWorkers { addrs, key, tasks })
+    }
+
+    /// Returns the addresses the workers listen on.
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// Returns a pool made of the workers, all weighted alike, holding
+    /// their key.
+    pub fn config(&self) -> PoolConfig {
+        let config = PoolConfig::new(
+            self.addrs
+                .iter()
+                .map(|addr| WorkerConfig::new(addr.to_string()))
+                .collect(),
+        );
+        match &self.key {
+            Some(key) => config.with_key(key.clone()),
+            None => config,
+        }
+    }
+
+    /// Connects a dispatcher to the workers.
+    pub async fn dispatcher(&self) -> io::Result<Dispatcher> {
+        Dispatcher::new(self.config()).await
+    }
+
+    /// Stops the `i`-th worker, dropping its connections. The dispatcher sees
+    /// it as dead from its next task on.
+    pub fn kill(&mut self, i: usize) {
+        self.tasks[i].abort();
+    }
+}
+
+impl Drop for LocalWorkers {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// The notices of a dispatcher, recorded as they are told.
+#[derive(Clone, Debug, Default)]
+pub struct Notices(Arc<Mutex<Vec<String>>>);
+
+impl Notices {
+    /// Returns a callback for [`Dispatcher::with_notices`] recording the
+    /// notices here.
+    pub fn recorder(&self) -> impl Fn(&Notice<'_>) + Send + Sync + 'static {
+        let notices = self.0.clone();
+        move |notice| notices.lock().unwrap().push(notice.to_string())
+    }
+
+    /// Takes the notices recorded so far, and returns the index in `addrs` of
+    /// the workers they are about. Nothing ran in this process in place of a
+    /// worker if the set is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a notice is about none of `addrs`.
+    pub fn take_workers(&self, addrs: &[SocketAddr]) -> BTreeSet<usize> {
+        let notices = std::mem::take(&mut *self.0.lock().unwrap());
+        notices
+            .iter()
+            .map(|notice| {
+                addrs
+                    .iter()
+                    .position(|addr| notice.contains(&format!("worker {} ", addr)))
+                    .unwrap_or_else(|| panic!("notice about no known worker: {}", notice))
+            })
+            .collect()
+    }
+}
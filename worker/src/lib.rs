@@ -0,0 +1,937 @@
+//! Worker answering the tasks of a `Dispatcher`
+//!
+//! A single [`Worker`] serves every scheme of [`SchemeId`]: each task names
+//! the scheme it is computed over, and is passed to the [`SchemeWorker`]
+//! holding the params and the shard of the SRS of that scheme.
+//!
+//! Crates defining tasks of their own, see [`Taskable`], register them with
+//! [`Worker::with_task`] and run the worker with [`cli::run`], which reads
+//! the same command line as the `worker` binary:
+//!
+//! ```ignore
+//! #[tokio::main]
+//! async fn main() {
+//!     worker::cli::run(|worker| worker.with_task::<MyTask>()).await
+//! }
+//! ```
+//!
+//! Circuits proven whole on the worker, see [`BatchCircuit`], are registered
+//! the same way with [`Worker::with_circuit`].
+use ff::FromUniformBytes;
+use halo2_proofs_distributed::distributed_util::batch::{
+    BatchCircuit, KeyCache, Multiopen, ProveTask, ProverRegistry,
+};
+use halo2_proofs_distributed::distributed_util::capacity::Capacity;
+use halo2_proofs_distributed::distributed_util::dispatcher::{
+    WorkerInfo, WorkerMethod, WorkerStatus,
+};
+use halo2_proofs_distributed::distributed_util::fft::{write_columns, FftTask};
+use halo2_proofs_distributed::distributed_util::multiexp::MultiexpTask;
+use halo2_proofs_distributed::distributed_util::net::{
+    auth::{self, PresharedKey, Session},
+    invalid_data, read_scheme, read_sealed_frame, write_points, write_response, write_scalars,
+    ChunkHeader, RequestId, SerdeParams, DEFAULT_WINDOW,
+};
+use halo2_proofs_distributed::distributed_util::params::{read_upload, ParamsCache, ParamsHash};
+use halo2_proofs_distributed::distributed_util::plonk::commit::CommitTask;
+use halo2_proofs_distributed::distributed_util::plonk::evaluation::EvaluateHTask;
+use halo2_proofs_distributed::distributed_util::plonk::lookup::{
+    write_permuted, write_products, LookupPermuteTask, LookupProductTask,
+};
+use halo2_proofs_distributed::distributed_util::plonk::permutation::keygen::{
+    write_commitments, KeygenTask,
+};
+use halo2_proofs_distributed::distributed_util::plonk::permutation::prover::{
+    write_chunk_products, PermutationProductTask,
+};
+use halo2_proofs_distributed::distributed_util::scheme::SchemeId;
+use halo2_proofs_distributed::distributed_util::shard::{ParamsShard, ShardMultiexpTask};
+use halo2_proofs_distributed::distributed_util::task::{TaskRegistry, Taskable};
+use halo2_proofs_distributed::halo2curves::bn256::Bn256;
+use halo2_proofs_distributed::halo2curves::pasta::{EpAffine, EqAffine};
+use halo2_proofs_distributed::poly::commitment::CommitmentScheme;
+use halo2_proofs_distributed::poly::ipa::commitment::IPACommitmentScheme;
+use halo2_proofs_distributed::poly::kzg::commitment::KZGCommitmentScheme;
+use halo2_proofs_distributed::{timer, SerdeCurveAffine, SerdePrimeField};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+use tokio::io::{BufReader, BufWriter};
+use tokio::net::{tcp::OwnedReadHalf, TcpListener, TcpStream};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+
+pub mod cli;
+pub mod harness;
+
+/// Returns the name of the scheme `S` serves, for the logs.
+fn scheme_name<S: CommitmentScheme>() -> String {
+    SchemeId::of::<S::Scalar>().map_or_else(|| "unknown".to_string(), |id| id.to_string())
+}
+
+#[derive(Clone, Debug)]
+pub struct Worker {
+    listen: SocketAddr,
+    /// The key dispatchers must hold to be served.
+    key: PresharedKey,
+    /// Bytes of requests a connection holds before their handlers read them.
+    window: usize,
+    /// Resources reported to dispatchers, measured when the worker starts.
+    capacity: Capacity,
+    /// The tasks registered with the worker, besides those of the prover.
+    tasks: TaskRegistry,
+    kzg_bn256: SchemeWorker<KZGCommitmentScheme<Bn256>>,
+    ipa_pallas: SchemeWorker<IPACommitmentScheme<EpAffine>>,
+    ipa_vesta: SchemeWorker<IPACommitmentScheme<EqAffine>>,
+}
+
+impl Worker {
+    /// Creates a worker holding no params yet, and measures the machine it
+    /// runs on.
+    pub fn new(listen: SocketAddr) -> Self {
+        Self {
+            listen,
+            key: PresharedKey::default(),
+            window: DEFAULT_WINDOW,
+            capacity: Capacity::measure(),
+            tasks: TaskRegistry::default(),
+            kzg_bn256: SchemeWorker::default(),
+            ipa_pallas: SchemeWorker::default(),
+            ipa_vesta: SchemeWorker::default(),
+        }
+    }
+
+    /// Keeps the params sent to the worker in `dir`, one subdirectory per
+    /// scheme, and loads those already there.
+    pub fn with_params_dir(mut self, dir: &Path) -> io::Result<Self> {
+        self.kzg_bn256 = SchemeWorker::open(dir)?;
+        self.ipa_pallas = SchemeWorker::open(dir)?;
+        self.ipa_vesta = SchemeWorker::open(dir)?;
+        Ok(self)
+    }
+
+    /// Only serves the dispatchers holding `key`.
+    pub fn with_key(mut self, key: PresharedKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Holds at most `window` bytes of the requests of a connection that
+    /// their handlers have not read yet, and stops reading the connection
+    /// until they do.
+    pub fn with_window(mut self, window: usize) -> Self {
+        assert!(window > 0);
+        self.window = window;
+        self
+    }
+
+    /// Runs the tasks of type `T` sent by dispatchers.
+    pub fn with_task<T: Taskable>(mut self) -> Self {
+        self.tasks.register::<T>();
+        self
+    }
+
+    /// Proves the circuits of type `C` over the scheme `S` with the
+    /// multiopen argument `M`, as sent by `Dispatcher::prove_batch`.
+    ///
+    /// Panics if `S` is not one of the schemes the worker serves.
+    pub fn with_circuit<S, M, C>(mut self) -> Self
+    where
+        S: CommitmentScheme + 'static,
+        S::Curve: SerdeCurveAffine,
+        S::Scalar: SerdePrimeField + FromUniformBytes<64>,
+        M: Multiopen<S>,
+        C: BatchCircuit<S::Scalar>,
+    {
+        let schemes: [&mut dyn Any; 3] = [
+            &mut self.kzg_bn256,
+            &mut self.ipa_pallas,
+            &mut self.ipa_vesta,
+        ];
+        schemes
+            .into_iter()
+            .find_map(|scheme| scheme.downcast_mut::<SchemeWorker<S>>())
+            .unwrap_or_else(|| panic!("the worker serves no scheme {}", scheme_name::<S>()))
+            .provers
+            .register::<M, C>();
+        self
+    }
+
+    pub async fn start(&self) -> io::Result<()> {
+        let listener = TcpListener::bind(self.listen).await?;
+        self.serve(listener).await
+    }
+
+    /// Answers the dispatchers connecting to `listener`. The connections are
+    /// served by tasks owned by the returned future, so dropping or aborting
+    /// it shuts the whole worker down.
+    pub async fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        let addr = listener.local_addr()?;
+
+        println!("worker listening on: {}", addr);
+        println!(
+            "{} threads, {} MiB available, {} multiexp terms per second",
+            self.capacity.cores,
+            self.capacity.memory >> 20,
+            self.capacity.msm_rate
+        );
+        let mut tasks = self.tasks.names().collect::<Vec<_>>();
+        if !tasks.is_empty() {
+            tasks.sort_unstable();
+            println!("running tasks {}", tasks.join(", "));
+        }
+        let mut circuits = self
+            .kzg_bn256
+            .provers
+            .names()
+            .chain(self.ipa_pallas.provers.names())
+            .chain(self.ipa_vesta.provers.names())
+            .collect::<Vec<_>>();
+        if !circuits.is_empty() {
+            circuits.sort_unstable();
+            println!("proving circuits {}", circuits.join(", "));
+        }
+
+        let mut connections = JoinSet::new();
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(_) => break,
+                },
+                // Reap the connections that ended.
+                Some(_) = connections.join_next() => continue,
+            };
+            let peer_addr = peer.ip();
+            println!("Connection from {}", peer_addr);
+
+            // Set nodelay to always just send whatever data is available.
+            stream.set_nodelay(true).unwrap();
+
+            connections.spawn(self.clone().connection(stream, peer_addr, addr));
+        }
+
+        Ok(())
+    }
+
+    /// Answers the requests of the dispatcher at the other end of `stream`.
+    /// Each request runs in a task of its own and is answered as soon as it
+    /// is done, so a long task does not hold up the ones sent after it.
+    async fn connection(self, mut stream: TcpStream, peer_addr: IpAddr, addr: SocketAddr) {
+        let session = match auth::accept(&mut stream, &self.key).await {
+            Ok(session) => session,
+            Err(e) => {
+                println!("Connection from {} rejected: {}", peer_addr, e);
+                return;
+            }
+        };
+        let (mut sending, receiving) = session.split();
+        let (read, write) = stream.into_split();
+        let mut res = BufWriter::new(write);
+
+        // Requests are read on the side, so that answering never leaves a
+        // request half read.
+        let (requests, mut incoming) = mpsc::channel(QUEUED_REQUESTS);
+        let reading = read_requests(
+            BufReader::new(read),
+            receiving,
+            requests,
+            self.window,
+            peer_addr,
+        );
+        tokio::pin!(reading);
+        let mut reading_done = false;
+
+        let mut tasks = JoinSet::new();
+        loop {
+            let (id, result) = tokio::select! {
+                () = &mut reading, if !reading_done => {
+                    reading_done = true;
+                    continue;
+                }
+                request = incoming.recv() => match request {
+                    Some(Incoming::Task(id, method, body)) => {
+                        println!("{} -> {}: {} #{}", peer_addr, addr, method, id);
+                        let worker = self.clone();
+                        tasks.spawn(async move { (id, worker.handle(method, body).await) });
+                        continue;
+                    }
+                    Some(Incoming::Received(id, received)) => {
+                        let received = received.to_be_bytes();
+                        let status = WorkerStatus::Received;
+                        match write_response(&mut res, &mut sending, id, status, "", &received)
+                            .await
+                        {
+                            Ok(()) => continue,
+                            Err(e) => {
+                                println!("Could not answer {}: {}", peer_addr, e);
+                                break;
+                            }
+                        }
+                    }
+                    Some(Incoming::Refused(id, e)) => (id, Err(e)),
+                    // The dispatcher is gone, so the tasks still running have
+                    // no one to answer to and are dropped with `tasks`.
+                    None => break,
+                },
+                Some(done) = tasks.join_next() => match done {
+                    Ok(answer) => answer,
+                    Err(e) => {
+                        println!("A task of {} failed: {}", peer_addr, e);
+                        continue;
+                    }
+                },
+            };
+
+            let sent = match result {
+                Ok(answer) => {
+                    write_response(&mut res, &mut sending, id, WorkerStatus::Ok, "", &answer).await
+                }
+                Err(e) => {
+                    println!(
+                        "{} -> {}: #{}: {}: {}",
+                        peer_addr, addr, id, e.status, e.message
+                    );
+                    write_response(&mut res, &mut sending, id, e.status, &e.message, &[]).await
+                }
+            };
+            if let Err(e) = sent {
+                println!("Could not answer {}: {}", peer_addr, e);
+                break;
+            }
+        }
+    }
+
+    async fn handle(&self, method: WorkerMethod, body: Body) -> Result<Vec<u8>, TaskError> {
+        match method {
+            WorkerMethod::Ping => self.ping(),
+            WorkerMethod::HasParams => self.has_params(body),
+            WorkerMethod::Task => self.task(body).await,
+            // Every other request is a task, which names its scheme in its
+            // first bytes.
+            method => match read_scheme(body.head()).map_err(TaskError::invalid_payload)? {
+                SchemeId::KzgBn256 => self.kzg_bn256.handle(method, body).await,
+                SchemeId::IpaPallas => self.ipa_pallas.handle(method, body).await,
+                SchemeId::IpaVesta => self.ipa_vesta.handle(method, body).await,
+            },
+        }
+    }
+
+    fn ping(&self) -> Result<Vec<u8>, TaskError> {
+        let info = WorkerInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: vec![
+                WorkerMethod::KeyGen,
+                WorkerMethod::Ping,
+                WorkerMethod::Commit,
+                WorkerMethod::Multiexp,
+                WorkerMethod::LoadShard,
+                WorkerMethod::ShardMultiexp,
+                WorkerMethod::Fft,
+                WorkerMethod::EvaluateH,
+                WorkerMethod::LookupPermute,
+                WorkerMethod::LookupProduct,
+                WorkerMethod::PermutationProduct,
+                WorkerMethod::UploadParams,
+                WorkerMethod::HasParams,
+                WorkerMethod::Task,
+                WorkerMethod::UploadKey,
+                WorkerMethod::Prove,
+            ],
+            capacity: self.capacity,
+        };
+        let mut payload = vec![];
+        info.write(&mut payload).map_err(TaskError::unknown)?;
+        Ok(payload)
+    }
+
+    fn has_params(&self, body: Body) -> Result<Vec<u8>, TaskError> {
+        // The hash fits in the first chunk, so this does not wait on the
+        // connection.
+        let hash = ParamsHash::read(&mut body.head()).map_err(TaskError::invalid_payload)?;
+        let held = self.kzg_bn256.has_params(&hash)
+            || self.ipa_pallas.has_params(&hash)
+            || self.ipa_vesta.has_params(&hash);
+        Ok(vec![held as u8])
+    }
+
+    async fn task(&self, body: Body) -> Result<Vec<u8>, TaskError> {
+        let tasks = self.tasks.clone();
+        tokio::task::spawn_blocking(move || {
+            let task = body.into_bytes().map_err(TaskError::invalid_payload)?;
+            timer!("worker registered task", { tasks.run(&task) }).map_err(TaskError::task)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+}
+
+/// Requests and acknowledgements of a connection waiting to be answered
+/// before its reading side waits too.
+const QUEUED_REQUESTS: usize = 64;
+
+/// What the reading side of a connection, and the handlers reading the
+/// requests, pass on to be answered.
+enum Incoming {
+    /// A request to run, with the part of its body received so far.
+    Task(RequestId, WorkerMethod, Body),
+    /// A chunk of a request sent in several was read by its handler, to be
+    /// acknowledged with the number of bytes of the request read so far.
+    Received(RequestId, u64),
+    /// A request answered with an error straight away.
+    Refused(RequestId, TaskError),
+}
+
+/// A chunk of a request, along with the share of the window of its
+/// connection it holds until it is read, and whether it is the last one.
+type Chunk = (Vec<u8>, OwnedSemaphorePermit, bool);
+
+/// The body of a request, which its handler reads as the chunks arrive.
+///
+/// Once a chunk is read, it gives its share of the window back and, when the
+/// request came in several chunks, is acknowledged to the dispatcher. Reading
+/// past the first chunk waits for the next ones, so it must be done off the
+/// runtime, like the decoding of the tasks.
+struct Body {
+    id: RequestId,
+    chunk: Vec<u8>,
+    /// Position of the next byte to read in `chunk`.
+    offset: usize,
+    permit: Option<OwnedSemaphorePermit>,
+    /// The chunks still to come, and where to acknowledge the ones read, until
+    /// the last chunk arrives.
+    rest: Option<(mpsc::UnboundedReceiver<Chunk>, mpsc::Sender<Incoming>)>,
+    /// Bytes of the request read so far.
+    read: u64,
+}
+
+impl Body {
+    /// Returns the first chunk, which holds the header of a task, before any
+    /// of the body is read.
+    fn head(&self) -> &[u8] {
+        &self.chunk[self.offset..]
+    }
+
+    /// Reads the whole body.
+    fn into_bytes(mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        io::Read::read_to_end(&mut self, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Gives back the window held by the chunk just read, and acknowledges
+    /// it.
+    fn release(&mut self) {
+        if self.permit.take().is_none() {
+            return;
+        }
+        self.read += self.chunk.len() as u64;
+        if let Some((_, acks)) = &self.rest {
+            let _ = acks.blocking_send(Incoming::Received(self.id, self.read));
+        }
+    }
+}
+
+impl io::Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.chunk.len() {
+            self.release();
+            let next = match &mut self.rest {
+                Some((chunks, _)) => chunks.blocking_recv(),
+                None => None,
+            };
+            match next {
+                Some((chunk, permit, last)) => {
+                    self.chunk = chunk;
+                    self.offset = 0;
+                    self.permit = Some(permit);
+                    // The dispatcher has sent it all and only waits for the
+                    // answer.
+                    if last {
+                        self.rest = None;
+                    }
+                }
+                // The request ended, or the connection dropped half way
+                // through it.
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.offset);
+        buf[..len].copy_from_slice(&self.chunk[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+/// Reads the requests of a dispatcher into `requests` until the connection
+/// ends, passing each request on with its first chunk and the following
+/// chunks to its body as they arrive.
+///
+/// The chunks not read by their handlers yet hold at most `window` bytes,
+/// give or take the last chunk read, and nothing more is read until they do.
+async fn read_requests(
+    mut req: BufReader<OwnedReadHalf>,
+    mut session: Session,
+    requests: mpsc::Sender<Incoming>,
+    window: usize,
+    peer_addr: IpAddr,
+) {
+    let budget = Arc::new(Semaphore::new(window));
+    let mut partial: HashMap<RequestId, mpsc::UnboundedSender<Chunk>> = HashMap::new();
+    loop {
+        let header = match ChunkHeader::read(&mut req).await {
+            Ok(header) => header,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                println!("Connection from {} disconnected", peer_addr);
+                return;
+            }
+            Err(e) => {
+                println!("Connection from {} dropped: {}", peer_addr, e);
+                return;
+            }
+        };
+        let id = header.id;
+
+        // Every request carries frames, even one we cannot serve, so read
+        // them to stay in step with the dispatcher.
+        let chunk = match read_sealed_frame(&mut req, &mut session, &header.to_bytes()).await {
+            Ok(chunk) => chunk,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                // Nothing that follows on this connection can be trusted
+                // either.
+                println!("Connection from {}: {}", peer_addr, e);
+                let e = TaskError::new(WorkerStatus::ErrorUnauthenticated, e.to_string());
+                let _ = requests.send(Incoming::Refused(id, e)).await;
+                return;
+            }
+            Err(e) => {
+                println!("Connection from {} dropped mid-request: {}", peer_addr, e);
+                return;
+            }
+        };
+
+        let share = u32::try_from(chunk.len().min(window)).unwrap_or(u32::MAX);
+        let permit = match budget.clone().acquire_many_owned(share).await {
+            Ok(permit) => permit,
+            Err(_) => return,
+        };
+
+        if let Some(chunks) = partial.get(&id) {
+            // The handler is gone once it answered, and the rest of its
+            // request is dropped.
+            let _ = chunks.send((chunk, permit, header.last));
+            if header.last {
+                partial.remove(&id);
+            }
+            continue;
+        }
+
+        // Most requests fit in a single chunk, which goes without an
+        // acknowledgement.
+        let rest = if header.last {
+            None
+        } else {
+            let (chunks, receiver) = mpsc::unbounded_channel();
+            partial.insert(id, chunks);
+            Some((receiver, requests.clone()))
+        };
+        let body = Body {
+            id,
+            chunk,
+            offset: 0,
+            permit: Some(permit),
+            rest,
+            read: 0,
+        };
+
+        let request = match WorkerMethod::try_from(header.method) {
+            Ok(method) => Incoming::Task(id, method, body),
+            Err(_) => Incoming::Refused(
+                id,
+                TaskError::new(
+                    WorkerStatus::ErrorInvalidMethod,
+                    format!("unknown method {:#04x}", header.method),
+                ),
+            ),
+        };
+        if requests.send(request).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// The part of a [`Worker`] serving the tasks of the commitment scheme `S`.
+pub struct SchemeWorker<S: CommitmentScheme> {
+    /// The rows of the SRS this worker was sent, shared by all connections.
+    shard: Arc<RwLock<Option<ParamsShard<'static, S::Curve>>>>,
+    /// The params this worker holds, by hash, shared by all connections.
+    params: Arc<RwLock<ParamsCache<S::ParamsProver>>>,
+    /// The proving keys this worker was sent, shared by all connections.
+    keys: Arc<KeyCache<S::Curve>>,
+    /// The circuits this worker proves whole.
+    provers: ProverRegistry<S>,
+}
+
+impl<S: CommitmentScheme> Clone for SchemeWorker<S> {
+    fn clone(&self) -> Self {
+        SchemeWorker {
+            shard: self.shard.clone(),
+            params: self.params.clone(),
+            keys: self.keys.clone(),
+            provers: self.provers.clone(),
+        }
+    }
+}
+
+impl<S: CommitmentScheme> Default for SchemeWorker<S> {
+    fn default() -> Self {
+        SchemeWorker {
+            shard: Arc::default(),
+            params: Arc::default(),
+            keys: Arc::default(),
+            provers: ProverRegistry::default(),
+        }
+    }
+}
+
+impl<S: CommitmentScheme> fmt::Debug for SchemeWorker<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self.params.read().unwrap();
+        f.debug_struct("SchemeWorker")
+            .field("scheme", &scheme_name::<S>())
+            .field("params", &params.hashes().collect::<Vec<_>>())
+            .field("provers", &self.provers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> SchemeWorker<S>
+where
+    S: CommitmentScheme + 'static,
+    S::Curve: SerdeCurveAffine,
+    S::Scalar: SerdePrimeField + FromUniformBytes<64>,
+    S::ParamsProver: SerdeParams + Send + Sync + 'static,
+{
+    /// Opens the cache of the params of `S` kept in `dir`.
+    fn open(dir: &Path) -> io::Result<Self> {
+        let dir = dir.join(scheme_name::<S>());
+        let params = ParamsCache::open(&dir)?;
+        for hash in params.hashes() {
+            println!("holding params {} from {}", hash, dir.display());
+        }
+        Ok(SchemeWorker {
+            params: Arc::new(RwLock::new(params)),
+            ..SchemeWorker::default()
+        })
+    }
+
+    /// Returns whether the worker holds the params hashing to `hash`.
+    pub fn has_params(&self, hash: &ParamsHash) -> bool {
+        self.params.read().unwrap().contains(hash)
+    }
+
+    async fn handle(&self, method: WorkerMethod, body: Body) -> Result<Vec<u8>, TaskError> {
+        match method {
+            WorkerMethod::KeyGen => self.keygen(body).await,
+            WorkerMethod::Commit => self.commit(body).await,
+            WorkerMethod::Multiexp => self.multiexp(body).await,
+            WorkerMethod::LoadShard => self.load_shard(body).await,
+            WorkerMethod::ShardMultiexp => self.shard_multiexp(body).await,
+            WorkerMethod::Fft => self.fft(body).await,
+            WorkerMethod::EvaluateH => self.evaluate_h(body).await,
+            WorkerMethod::LookupPermute => self.lookup_permute(body).await,
+            WorkerMethod::LookupProduct => self.lookup_product(body).await,
+            WorkerMethod::PermutationProduct => self.permutation_product(body).await,
+            WorkerMethod::UploadParams => self.upload_params(body).await,
+            WorkerMethod::UploadKey => self.upload_key(body).await,
+            WorkerMethod::Prove => self.prove(body).await,
+            WorkerMethod::Ping | WorkerMethod::HasParams | WorkerMethod::Task => {
+                unreachable!("{} is answered by the worker itself", method)
+            }
+        }
+    }
+
+    async fn upload_params(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        let params = self.params.clone();
+        let hash = tokio::task::spawn_blocking(move || {
+            timer!("worker upload params", {
+                let uploaded = read_upload::<_, S::ParamsProver>(&mut body)
+                    .map_err(TaskError::invalid_payload)?;
+                params
+                    .write()
+                    .unwrap()
+                    .insert(uploaded)
+                    .map_err(TaskError::unknown)
+            })
+        })
+        .await
+        .map_err(TaskError::unknown)??;
+
+        println!("holding {} params {}", scheme_name::<S>(), hash);
+        Ok(hash.0.to_vec())
+    }
+
+    async fn upload_key(&self, body: Body) -> Result<Vec<u8>, TaskError> {
+        let keys = self.keys.clone();
+        let hash = tokio::task::spawn_blocking(move || {
+            let upload = body.into_bytes().map_err(TaskError::invalid_payload)?;
+            timer!("worker upload key", { keys.insert(&upload) })
+                .map_err(TaskError::invalid_payload)
+        })
+        .await
+        .map_err(TaskError::unknown)??;
+
+        println!("holding {} proving key {}", scheme_name::<S>(), hash);
+        Ok(hash.0.to_vec())
+    }
+
+    async fn prove(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        let params = self.params.clone();
+        let keys = self.keys.clone();
+        let provers = self.provers.clone();
+        tokio::task::spawn_blocking(move || {
+            let task =
+                ProveTask::<S::Scalar>::read(&mut body).map_err(TaskError::invalid_payload)?;
+            let params = params.read().unwrap().clone();
+            let params = params.get(&task.params).map_err(TaskError::read)?;
+            timer!("worker prove", { provers.prove(params, &keys, &task) })
+                .map_err(TaskError::prove)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn keygen(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        // Decode and handle the payload off the runtime, so a panic fails this
+        // task only. The task borrows its params from a clone of the cache,
+        // so uploads are not held up meanwhile.
+        let params = self.params.clone();
+        tokio::task::spawn_blocking(move || {
+            let params = params.read().unwrap().clone();
+            let (task, format) = KeygenTask::<S::Curve, S::ParamsProver>::read(&mut body, &params)
+                .map_err(TaskError::read)?;
+            let commitments = timer!("worker keygen commitments", { task.commitments() });
+
+            let mut payload = vec![];
+            write_commitments(&mut payload, &commitments, format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn commit(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        let params = self.params.clone();
+        tokio::task::spawn_blocking(move || {
+            let params = params.read().unwrap().clone();
+            let (task, format) = CommitTask::<S::Curve, S::ParamsProver>::read(&mut body, &params)
+                .map_err(TaskError::read)?;
+            let commitments = timer!("worker lagrange commitments", { task.commitments() })
+                .map_err(TaskError::invalid_payload)?;
+
+            let mut payload = vec![];
+            write_points(&mut payload, &commitments, format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn multiexp(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        // The body is read as it arrives, so decoding happens off the runtime
+        // too.
+        tokio::task::spawn_blocking(move || {
+            let (task, format) =
+                MultiexpTask::<S::Curve>::read(&mut body).map_err(TaskError::invalid_payload)?;
+            let partial = timer!("worker multiexp", { task.eval() });
+
+            let mut payload = vec![];
+            write_points(&mut payload, &[partial], format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn load_shard(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        // Decoding checks every point, keep it off the runtime too.
+        let shard = tokio::task::spawn_blocking(move || {
+            timer!("worker load shard", {
+                ParamsShard::<S::Curve>::read(&mut body)
+            })
+        })
+        .await
+        .map_err(TaskError::unknown)?
+        .map_err(TaskError::invalid_payload)?;
+
+        println!(
+            "holding rows {:?} of the SRS of k = {}",
+            shard.rows, shard.k
+        );
+        *self.shard.write().unwrap() = Some(shard);
+        Ok(vec![])
+    }
+
+    async fn shard_multiexp(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        let shard = self.shard.clone();
+        tokio::task::spawn_blocking(move || {
+            let (task, format) = ShardMultiexpTask::<S::Curve>::read(&mut body)
+                .map_err(TaskError::invalid_payload)?;
+            let partial = timer!("worker shard multiexp", {
+                match shard.read().unwrap().as_ref() {
+                    Some(shard) => shard.multiexp(&task),
+                    None => Err(invalid_data("no shard of the SRS is loaded")),
+                }
+            })
+            .map_err(TaskError::invalid_payload)?;
+
+            let mut payload = vec![];
+            write_points(&mut payload, &[partial], format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn fft(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        tokio::task::spawn_blocking(move || {
+            let (task, format) =
+                FftTask::<S::Scalar>::read(&mut body).map_err(TaskError::invalid_payload)?;
+            let columns = timer!("worker fft", { task.eval() });
+
+            let mut payload = vec![];
+            write_columns(&mut payload, &columns, format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn evaluate_h(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        tokio::task::spawn_blocking(move || {
+            let (task, format) =
+                EvaluateHTask::<S::Curve>::read(&mut body).map_err(TaskError::invalid_payload)?;
+            let values = timer!("worker evaluate h", { task.eval() });
+
+            let mut payload = vec![];
+            write_scalars(&mut payload, &values, format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn lookup_permute(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        let params = self.params.clone();
+        tokio::task::spawn_blocking(move || {
+            let params = params.read().unwrap().clone();
+            let (task, format) =
+                LookupPermuteTask::<S::Curve, S::ParamsProver>::read(&mut body, &params)
+                    .map_err(TaskError::read)?;
+            let permuted = timer!("worker lookup permute", { task.outputs() })
+                .map_err(TaskError::invalid_payload)?;
+
+            let mut payload = vec![];
+            write_permuted(&mut payload, &permuted, format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn lookup_product(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        let params = self.params.clone();
+        tokio::task::spawn_blocking(move || {
+            let params = params.read().unwrap().clone();
+            let (task, format) =
+                LookupProductTask::<S::Curve, S::ParamsProver>::read(&mut body, &params)
+                    .map_err(TaskError::read)?;
+            let products = timer!("worker lookup product", { task.outputs() })
+                .map_err(TaskError::invalid_payload)?;
+
+            let mut payload = vec![];
+            write_products(&mut payload, &products, format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+
+    async fn permutation_product(&self, mut body: Body) -> Result<Vec<u8>, TaskError> {
+        let params = self.params.clone();
+        tokio::task::spawn_blocking(move || {
+            let params = params.read().unwrap().clone();
+            let (task, format) =
+                PermutationProductTask::<S::Curve, S::ParamsProver>::read(&mut body, &params)
+                    .map_err(TaskError::read)?;
+            let products = timer!("worker permutation product", { task.outputs() })
+                .map_err(TaskError::invalid_payload)?;
+
+            let mut payload = vec![];
+            write_chunk_products(&mut payload, &products, format).map_err(TaskError::unknown)?;
+            Ok(payload)
+        })
+        .await
+        .map_err(TaskError::unknown)?
+    }
+}
+
+/// A task that could not be completed, reported back to the dispatcher.
+#[derive(Debug)]
+struct TaskError {
+    status: WorkerStatus,
+    message: String,
+}
+
+impl TaskError {
+    fn new(status: WorkerStatus, message: String) -> Self {
+        TaskError { status, message }
+    }
+
+    fn invalid_payload(e: impl std::fmt::Display) -> Self {
+        Self::new(WorkerStatus::ErrorInvalidPayload, e.to_string())
+    }
+
+    fn unknown(e: impl std::fmt::Display) -> Self {
+        Self::new(WorkerStatus::ErrorUnkown, e.to_string())
+    }
+
+    /// Reports a task that could not be decoded, telling the dispatcher when
+    /// it only failed because its params are not cached here.
+    fn read(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => Self::new(WorkerStatus::ErrorUnknownParams, e.to_string()),
+            _ => Self::invalid_payload(e),
+        }
+    }
+
+    /// Reports a registered task that could not be run, see
+    /// [`TaskRegistry::run`].
+    fn task(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::Unsupported => {
+                Self::new(WorkerStatus::ErrorInvalidMethod, e.to_string())
+            }
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => Self::invalid_payload(e),
+            _ => Self::unknown(e),
+        }
+    }
+
+    /// Reports a circuit that could not be proven, see
+    /// [`ProverRegistry::prove`]. A proving key that is not cached here is
+    /// reported like missing params, so the dispatcher sends the circuit to
+    /// another worker.
+    fn prove(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => Self::new(WorkerStatus::ErrorUnknownParams, e.to_string()),
+            _ => Self::task(e),
+        }
+    }
+}